@@ -1,8 +1,25 @@
-use crate::utils::{parse_address, parse_u256, parse_uopool_mode};
+use crate::utils::{
+    parse_address, parse_block_watcher_mode, parse_code_hash_signature_shape,
+    parse_deadline_policy, parse_dummy_signature, parse_factory_signature_shape,
+    parse_ordering_policy, parse_p2p_ops_policy, parse_skipped_entities, parse_submission_mode,
+    parse_tracer_mode, parse_u256, parse_uopool_mode,
+};
 use clap::Parser;
-use ethers::types::{Address, U256};
-use silius_primitives::UoPoolMode;
-use std::net::SocketAddr;
+use ethers::types::{Address, BlockNumber, Bytes, H256, U256};
+use silius_bundler::DEFAULT_FLASHBOTS_MAX_BLOCKS;
+use silius_grpc::UoPoolServiceConfig;
+use silius_primitives::{
+    bundler::{BlockWatcherMode, DeadlinePolicy, SubmissionMode},
+    sanity::SignatureShape,
+    simulation::{EnabledEntities, TracerMode},
+    uopool::{OrderingPolicy, P2pOpsPolicy},
+    Chain, ChainSpec, DummySignatures, UoPoolMode,
+};
+use silius_rpc::{
+    limits::RequestLimits,
+    rate_limit::{RateLimiter, SenderStake, TokenBucket},
+};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 
 #[derive(Clone, Debug, Parser, PartialEq)]
 pub struct UoPoolServiceOpts {
@@ -23,12 +40,212 @@ pub struct UoPoolServiceOpts {
 
     #[clap(long, default_value = "standard", value_parser=parse_uopool_mode)]
     pub uo_pool_mode: UoPoolMode,
+
+    #[clap(long, default_value = "1800")]
+    pub uo_ttl: u64,
+
+    #[clap(long, default_value = "0")]
+    pub sender_penalty_window: u64,
+
+    #[clap(long, default_value = "1")]
+    pub sender_penalty_max_failures: usize,
+
+    #[clap(long, default_value = "0")]
+    pub min_sender_code_size: usize,
+
+    #[clap(long, value_parser=parse_u256, default_value = "10")]
+    pub gas_increase_perc: U256,
+
+    #[clap(long, value_parser=parse_address)]
+    pub operator_paymaster: Option<Address>,
+
+    #[clap(long, value_parser=parse_address)]
+    pub operator_paymaster_signer: Option<Address>,
+
+    #[clap(long, default_value = "10")]
+    pub min_inclusion_denominator: u64,
+
+    #[clap(long, default_value = "10")]
+    pub throttling_slack: u64,
+
+    #[clap(long, default_value = "50")]
+    pub ban_slack: u64,
+
+    #[clap(long, default_value = "24")]
+    pub hourly_decay_denominator: u64,
+
+    #[clap(long)]
+    pub max_inner_calls: Option<usize>,
+
+    #[clap(long)]
+    pub time_range_buffer: Option<u64>,
+
+    #[clap(long, value_parser=parse_u256, default_value = "100")]
+    pub prefund_safety_factor_perc: U256,
+
+    #[clap(long, default_value = "bundle", value_parser=parse_p2p_ops_policy)]
+    pub p2p_ops_policy: P2pOpsPolicy,
+
+    #[clap(long, value_parser=parse_u256, default_value = "0")]
+    pub call_gas_safety_margin_perc: U256,
+
+    #[clap(long)]
+    pub mempool_db: Option<PathBuf>,
+
+    #[clap(long, default_value = "4")]
+    pub max_user_operations_per_sender: usize,
+
+    #[clap(long)]
+    pub max_mempool_size: Option<usize>,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub max_priority_fee_per_gas: Option<U256>,
+
+    #[clap(long)]
+    pub max_revalidations: Option<usize>,
+
+    #[clap(long, default_value = "65536")]
+    pub max_init_code_size: usize,
+
+    #[clap(long, default_value = "65536")]
+    pub max_call_data_size: usize,
+
+    #[clap(long, default_value = "16384")]
+    pub max_paymaster_and_data_size: usize,
+
+    #[clap(long, default_value = "131072")]
+    pub max_user_operation_size: usize,
+
+    #[clap(long)]
+    pub revert_penalty: Option<u64>,
+
+    #[clap(long, value_parser=parse_u256, default_value = "15000000")]
+    pub max_bundle_gas: U256,
+
+    #[clap(long)]
+    pub max_ops_per_bundle: Option<usize>,
+
+    #[clap(long, value_delimiter=',', value_parser = parse_address)]
+    pub allowlist: Vec<Address>,
+
+    #[clap(long, value_delimiter=',', value_parser = parse_address)]
+    pub denylist: Vec<Address>,
+
+    #[clap(long, default_value = "10000")]
+    pub simulation_timeout: u64,
+
+    #[clap(long, default_value = "3")]
+    pub simulation_max_retries: u32,
+
+    #[clap(long)]
+    pub max_verification_gas_overdeclare_perc: Option<u64>,
+
+    #[clap(long, default_value = "", value_parser=parse_skipped_entities)]
+    pub skip_opcode_rules: EnabledEntities,
+
+    #[clap(long, default_value = "", value_parser=parse_skipped_entities)]
+    pub skip_storage_rules: EnabledEntities,
+
+    #[clap(long, default_value = "auto", value_parser=parse_tracer_mode)]
+    pub tracer: TracerMode,
+
+    #[clap(long)]
+    pub simulation_block: Option<BlockNumber>,
+
+    #[clap(long, default_value = "0")]
+    pub confirmation_blocks: u64,
+
+    #[clap(long, value_delimiter = ',', value_parser = parse_factory_signature_shape)]
+    pub factory_signature_shapes: Vec<(Address, SignatureShape)>,
+
+    #[clap(long, value_delimiter = ',', value_parser = parse_code_hash_signature_shape)]
+    pub code_hash_signature_shapes: Vec<(H256, SignatureShape)>,
+
+    #[clap(long, default_value = "effective-gas-price", value_parser=parse_ordering_policy)]
+    pub bundle_ordering: OrderingPolicy,
+
+    #[clap(long)]
+    pub enforce_unstaked_entity_caps: bool,
+
+    #[clap(long, default_value = "1")]
+    pub unstaked_factory_bundle_cap: usize,
+
+    #[clap(long, default_value = "1")]
+    pub unstaked_paymaster_bundle_cap: usize,
+}
+
+impl UoPoolServiceOpts {
+    /// Settings of the uopool service with a uopool for each of the entry points
+    pub fn service_config(
+        &self,
+        entry_points: Vec<Address>,
+        chain: Chain,
+        chain_spec: ChainSpec,
+        max_verification_gas: U256,
+    ) -> UoPoolServiceConfig {
+        UoPoolServiceConfig {
+            grpc_listen_address: self.uopool_grpc_listen_address,
+            entry_points,
+            chain,
+            chain_spec,
+            max_verification_gas,
+            min_stake: self.min_stake,
+            min_unstake_delay: self.min_unstake_delay,
+            min_priority_fee_per_gas: self.min_priority_fee_per_gas,
+            whitelist: self.whitelist.clone(),
+            uo_pool_mode: self.uo_pool_mode,
+            uo_ttl: self.uo_ttl,
+            sender_penalty_window: self.sender_penalty_window,
+            sender_penalty_max_failures: self.sender_penalty_max_failures,
+            min_sender_code_size: self.min_sender_code_size,
+            gas_increase_perc: self.gas_increase_perc,
+            operator_paymaster: self.operator_paymaster,
+            operator_paymaster_signer: self.operator_paymaster_signer,
+            min_inclusion_denominator: self.min_inclusion_denominator,
+            throttling_slack: self.throttling_slack,
+            ban_slack: self.ban_slack,
+            hourly_decay_denominator: self.hourly_decay_denominator,
+            max_inner_calls: self.max_inner_calls,
+            time_range_buffer: self.time_range_buffer,
+            prefund_safety_factor_perc: self.prefund_safety_factor_perc,
+            p2p_ops_policy: self.p2p_ops_policy,
+            call_gas_safety_margin_perc: self.call_gas_safety_margin_perc,
+            mempool_db: self.mempool_db.clone(),
+            max_user_operations_per_sender: self.max_user_operations_per_sender,
+            max_mempool_size: self.max_mempool_size,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            max_revalidations: self.max_revalidations,
+            max_init_code_size: self.max_init_code_size,
+            max_call_data_size: self.max_call_data_size,
+            max_paymaster_and_data_size: self.max_paymaster_and_data_size,
+            max_user_operation_size: self.max_user_operation_size,
+            revert_penalty: self.revert_penalty,
+            max_bundle_gas: self.max_bundle_gas,
+            max_ops_per_bundle: self.max_ops_per_bundle,
+            allowlist: self.allowlist.clone(),
+            denylist: self.denylist.clone(),
+            simulation_timeout: self.simulation_timeout,
+            simulation_max_retries: self.simulation_max_retries,
+            max_verification_gas_overdeclare_perc: self.max_verification_gas_overdeclare_perc,
+            opcode_rules_entities: self.skip_opcode_rules,
+            storage_rules_entities: self.skip_storage_rules,
+            tracer: self.tracer,
+            simulation_block: self.simulation_block,
+            confirmation_blocks: self.confirmation_blocks,
+            factory_signature_shapes: self.factory_signature_shapes.clone(),
+            code_hash_signature_shapes: self.code_hash_signature_shapes.clone(),
+            bundle_ordering: self.bundle_ordering,
+            enforce_unstaked_entity_caps: self.enforce_unstaked_entity_caps,
+            unstaked_factory_bundle_cap: self.unstaked_factory_bundle_cap,
+            unstaked_paymaster_bundle_cap: self.unstaked_paymaster_bundle_cap,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Parser, PartialEq)]
 pub struct BundlerServiceOpts {
     #[clap(long, value_parser=parse_address)]
-    pub beneficiary: Address,
+    pub beneficiary: Option<Address>,
 
     #[clap(long, default_value = "1", value_parser=parse_u256)]
     pub gas_factor: U256,
@@ -41,6 +258,45 @@ pub struct BundlerServiceOpts {
 
     #[clap(long, default_value = "10")]
     pub bundle_interval: u64,
+
+    #[clap(long, default_value = "0")]
+    pub deadline_margin: u64,
+
+    #[clap(long, default_value = "prioritize", value_parser=parse_deadline_policy)]
+    pub deadline_policy: DeadlinePolicy,
+
+    #[clap(long)]
+    pub conditional_rpc: bool,
+
+    #[clap(long)]
+    pub flashbots_relay: Option<String>,
+
+    #[clap(long)]
+    pub flashbots_searcher_key: Option<String>,
+
+    #[clap(long, default_value_t = DEFAULT_FLASHBOTS_MAX_BLOCKS)]
+    pub flashbots_max_blocks: u64,
+
+    #[clap(long, default_value = "50")]
+    pub priority_fee_percentile: f64,
+
+    #[clap(long, default_value = "25")]
+    pub base_fee_buffer_perc: u64,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub fixed_gas_price: Option<U256>,
+
+    #[clap(long, default_value = "drop-invalid", value_parser=parse_submission_mode)]
+    pub submission_mode: SubmissionMode,
+
+    #[clap(long, default_value = "auto", value_parser=parse_block_watcher_mode)]
+    pub block_watcher: BlockWatcherMode,
+
+    #[clap(long, default_value = "1000")]
+    pub block_poll_interval: u64,
+
+    #[clap(long, default_value = "10")]
+    pub bundle_gas_limit_buffer_perc: u64,
 }
 
 #[derive(Clone, Debug, Parser, PartialEq)]
@@ -53,6 +309,77 @@ pub struct RpcServiceOpts {
 
     #[clap(long, value_delimiter = ',', default_value = "*")]
     pub cors_domain: Vec<String>,
+
+    #[clap(long)]
+    pub dummy_signature: Option<Bytes>,
+
+    #[clap(long, value_delimiter = ',', value_parser = parse_dummy_signature)]
+    pub entry_point_dummy_signatures: Vec<(Address, Bytes)>,
+
+    #[clap(long, value_delimiter = ',', value_parser = parse_dummy_signature)]
+    pub factory_dummy_signatures: Vec<(Address, Bytes)>,
+
+    #[clap(long)]
+    pub peer_rate_limit: Option<f64>,
+
+    #[clap(long)]
+    pub sender_rate_limit: Option<f64>,
+
+    #[clap(long)]
+    pub staked_sender_rate_limit: Option<f64>,
+
+    #[clap(long, default_value = "10")]
+    pub rate_limit_burst: u32,
+
+    #[clap(long, default_value = "10485760")]
+    pub rpc_max_request_body_size: u32,
+
+    #[clap(long, default_value = "100")]
+    pub rpc_max_batch_len: u32,
+}
+
+impl RpcServiceOpts {
+    pub fn dummy_signatures(&self) -> DummySignatures {
+        let mut dummy_signatures = DummySignatures::default();
+        if let Some(signature) = self.dummy_signature.clone() {
+            dummy_signatures = dummy_signatures.with_default(signature);
+        }
+        for (ep, signature) in self.entry_point_dummy_signatures.iter().cloned() {
+            dummy_signatures = dummy_signatures.with_entry_point(ep, signature);
+        }
+        for (factory, signature) in self.factory_dummy_signatures.iter().cloned() {
+            dummy_signatures = dummy_signatures.with_factory(factory, signature);
+        }
+        dummy_signatures
+    }
+
+    /// Rate limiter of `eth_sendUserOperation` (the rates are in requests per second), none if no rate is set
+    pub fn rate_limiter(&self, sender_stake: Arc<dyn SenderStake>) -> Option<RateLimiter> {
+        let bucket = |rate: Option<f64>| rate.map(|r| TokenBucket::new(self.rate_limit_burst, r));
+
+        if self.peer_rate_limit.is_none()
+            && self.sender_rate_limit.is_none()
+            && self.staked_sender_rate_limit.is_none()
+        {
+            return None;
+        }
+
+        Some(
+            RateLimiter::new(
+                bucket(self.peer_rate_limit),
+                bucket(self.sender_rate_limit),
+                bucket(self.staked_sender_rate_limit),
+            )
+            .with_sender_stake(sender_stake),
+        )
+    }
+
+    pub fn request_limits(&self) -> RequestLimits {
+        RequestLimits {
+            max_request_body_size: self.rpc_max_request_body_size,
+            max_batch_len: self.rpc_max_batch_len,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -80,8 +407,9 @@ mod tests {
         ];
         assert_eq!(
             BundlerServiceOpts {
-                beneficiary: Address::from_str("0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990")
-                    .unwrap(),
+                beneficiary: Some(
+                    Address::from_str("0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990").unwrap()
+                ),
                 gas_factor: U256::from(600),
                 min_balance: U256::from(1),
                 bundler_grpc_listen_address: SocketAddr::new(
@@ -89,6 +417,19 @@ mod tests {
                     3002
                 ),
                 bundle_interval: 10,
+                deadline_margin: 0,
+                deadline_policy: DeadlinePolicy::Prioritize,
+                conditional_rpc: false,
+                flashbots_relay: None,
+                flashbots_searcher_key: None,
+                flashbots_max_blocks: DEFAULT_FLASHBOTS_MAX_BLOCKS,
+                priority_fee_percentile: 50.0,
+                base_fee_buffer_perc: 25,
+                fixed_gas_price: None,
+                submission_mode: SubmissionMode::DropInvalid,
+                block_watcher: BlockWatcherMode::Auto,
+                block_poll_interval: 1000,
+                bundle_gas_limit_buffer_perc: 10,
             },
             BundlerServiceOpts::try_parse_from(args).unwrap()
         );
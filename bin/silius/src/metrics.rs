@@ -0,0 +1,32 @@
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use silius_primitives::metrics::METRICS;
+use std::{convert::Infallible, net::SocketAddr};
+use tracing::{error, info};
+
+/// Serves the metrics in the Prometheus text exposition format at `/metrics`
+pub fn metrics_server_run(listen_address: SocketAddr) {
+    let make_svc = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+            if req.uri().path() == "/metrics" {
+                Response::builder()
+                    .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(Body::from(METRICS.render()))
+            } else {
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+            }
+        }))
+    });
+
+    tokio::spawn(async move {
+        info!("Metrics server starting on {listen_address}");
+        if let Err(err) = Server::bind(&listen_address).serve(make_svc).await {
+            error!("Metrics server failed: {err:?}");
+        }
+    });
+}
@@ -1,14 +1,20 @@
 use anyhow::Result;
 use clap::Parser;
-use silius::cli::RpcServiceOpts;
+use ethers::{
+    providers::{Http, Provider},
+    types::U256,
+};
+use silius::{cli::RpcServiceOpts, utils::parse_u256};
 use silius_grpc::{bundler_client::BundlerClient, uo_pool_client::UoPoolClient};
 use silius_rpc::{
     debug_api::{DebugApiServer, DebugApiServerImpl},
     eth_api::{EthApiServer, EthApiServerImpl},
+    rate_limit::EntryPointStake,
+    utils::supported_entry_points,
     web3_api::{Web3ApiServer, Web3ApiServerImpl},
     JsonRpcServer,
 };
-use std::{collections::HashSet, future::pending};
+use std::{collections::HashSet, future::pending, sync::Arc};
 use tracing::info;
 
 #[derive(Parser)]
@@ -29,6 +35,13 @@ pub struct Opt {
 
     #[clap(long, default_value = "127.0.0.1:3002")]
     pub bundler_grpc_listen_address: String,
+
+    // stake of the senders getting the staked sender rate limit
+    #[clap(long, value_parser=parse_u256, default_value = "1")]
+    pub min_stake: U256,
+
+    #[clap(long, value_parser=parse_u256, default_value = "0")]
+    pub min_unstake_delay: U256,
 }
 
 #[tokio::main]
@@ -40,10 +53,23 @@ async fn main() -> Result<()> {
     info!("Starting bundler JSON-RPC server...");
 
     let api: HashSet<String> = HashSet::from_iter(opt.rpc_opts.rpc_api.iter().cloned());
+    let dummy_signatures = opt.rpc_opts.dummy_signatures();
+    let request_limits = opt.rpc_opts.request_limits();
+
+    let rate_limiter = opt.rpc_opts.rate_limiter(Arc::new(EntryPointStake {
+        eth_client: Arc::new(Provider::<Http>::try_from(opt.eth_client_address.clone())?),
+        min_stake: opt.min_stake,
+        min_unstake_delay: opt.min_unstake_delay,
+    }));
 
     let mut server = JsonRpcServer::new(opt.rpc_opts.rpc_listen_address.clone())
         .with_proxy(opt.eth_client_address)
-        .with_cors(opt.rpc_opts.cors_domain);
+        .with_cors(opt.rpc_opts.cors_domain)
+        .with_request_limits(request_limits);
+
+    if let Some(rate_limiter) = rate_limiter {
+        server = server.with_rate_limit(rate_limiter);
+    }
 
     if api.contains("web3") {
         server.add_method(Web3ApiServerImpl {}.into_rpc())?;
@@ -51,11 +77,14 @@ async fn main() -> Result<()> {
 
     let uopool_grpc_client =
         UoPoolClient::connect(format!("http://{}", opt.uopool_grpc_listen_address)).await?;
+    let entry_points = supported_entry_points(&uopool_grpc_client).await?;
 
     if api.contains("eth") {
         server.add_method(
             EthApiServerImpl {
                 uopool_grpc_client: uopool_grpc_client.clone(),
+                dummy_signatures,
+                entry_points: entry_points.clone(),
             }
             .into_rpc(),
         )?;
@@ -68,6 +97,7 @@ async fn main() -> Result<()> {
             DebugApiServerImpl {
                 uopool_grpc_client,
                 bundler_grpc_client,
+                entry_points,
             }
             .into_rpc(),
         )?;
@@ -4,14 +4,16 @@ use ethers::{
     providers::{Http, Middleware, Provider},
     types::{Address, U256},
 };
+use expanded_pathbuf::ExpandedPathBuf;
 use silius::{
     cli::UoPoolServiceOpts,
-    utils::{parse_address, parse_u256},
+    metrics::metrics_server_run,
+    utils::{parse_address, parse_u256, run_until_ctrl_c},
 };
-use silius_grpc::uopool_service_run;
-use silius_primitives::{chain::SUPPORTED_CHAINS, Chain};
-use std::{future::pending, sync::Arc};
-use tracing::info;
+use silius_grpc::{uopool_service_run, Shutdown};
+use silius_primitives::{chain::SUPPORTED_CHAINS, Chain, ChainSpec};
+use std::{future::pending, net::SocketAddr, sync::Arc, time::Duration};
+use tracing::{info, warn};
 
 #[derive(Parser)]
 #[clap(
@@ -28,12 +30,23 @@ pub struct Opt {
     #[clap(long, default_value= "dev", value_parser = SUPPORTED_CHAINS)]
     pub chain: Option<String>,
 
+    // chain spec (pre-verification gas overhead and fees) of a custom network, selected by the chain id otherwise
+    #[clap(long)]
+    pub chain_spec: Option<ExpandedPathBuf>,
+
     // execution client rpc endpoint
     #[clap(long, default_value = "127.0.0.1:8545")]
     pub eth_client_address: String,
 
     #[clap(long, value_parser=parse_u256)]
     pub max_verification_gas: U256,
+
+    #[clap(long)]
+    pub metrics_listen_address: Option<SocketAddr>,
+
+    // time given to the service to flush its state on shutdown (in seconds)
+    #[clap(long, default_value = "30")]
+    pub shutdown_timeout: u64,
 }
 
 #[tokio::main]
@@ -62,19 +75,28 @@ async fn main() -> Result<()> {
         }
     }
 
+    let chain_spec = match opt.chain_spec {
+        Some(path) => ChainSpec::from_file(path)
+            .map_err(|error| format_err!("Could not load chain spec: {}", error))?,
+        None => ChainSpec::for_chain(&chain),
+    };
+
+    if let Some(metrics_listen_address) = opt.metrics_listen_address {
+        metrics_server_run(metrics_listen_address);
+    }
+
     info!("Starting uopool gRPC service...");
 
+    let shutdown = Shutdown::default();
     uopool_service_run(
-        opt.uopool_opts.uopool_grpc_listen_address,
-        opt.entry_points,
+        opt.uopool_opts.service_config(
+            opt.entry_points,
+            chain,
+            chain_spec,
+            opt.max_verification_gas,
+        ),
         eth_client,
-        chain,
-        opt.max_verification_gas,
-        opt.uopool_opts.min_stake,
-        opt.uopool_opts.min_unstake_delay,
-        opt.uopool_opts.min_priority_fee_per_gas,
-        opt.uopool_opts.whitelist,
-        opt.uopool_opts.uo_pool_mode,
+        shutdown.signal(),
     )
     .await?;
 
@@ -83,5 +105,12 @@ async fn main() -> Result<()> {
         opt.uopool_opts.uopool_grpc_listen_address
     );
 
-    pending().await
+    run_until_ctrl_c(pending::<Result<()>>()).await?;
+
+    info!("Shutting down");
+    let shutdown_timeout = Duration::from_secs(opt.shutdown_timeout);
+    if !shutdown.shutdown(shutdown_timeout).await {
+        warn!("UoPool service didn't shut down in {shutdown_timeout:?}");
+    }
+    Ok(())
 }
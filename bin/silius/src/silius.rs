@@ -2,32 +2,45 @@ use anyhow::{format_err, Result};
 use clap::Parser;
 use ethers::{
     providers::{Http, Middleware, Provider},
+    signers::LocalWallet,
     types::{Address, U256},
 };
 use expanded_pathbuf::ExpandedPathBuf;
 use silius::{
     cli::{BundlerServiceOpts, RpcServiceOpts, UoPoolServiceOpts},
+    metrics::metrics_server_run,
     utils::{parse_address, parse_u256, run_until_ctrl_c},
 };
+#[cfg(feature = "aws-kms")]
+use silius_bundler::AwsKmsSigner;
+use silius_bundler::{BundlerSigner, LocalSigner};
 use silius_grpc::{
     bundler_client::BundlerClient, bundler_service_run, uo_pool_client::UoPoolClient,
-    uopool_service_run,
+    uopool_service_run, Shutdown,
 };
-use silius_primitives::{chain::SUPPORTED_CHAINS, Chain, Wallet};
+use silius_primitives::{chain::SUPPORTED_CHAINS, Chain, ChainSpec, Wallet};
 use silius_rpc::{
     debug_api::{DebugApiServer, DebugApiServerImpl},
     eth_api::{EthApiServer, EthApiServerImpl},
+    rate_limit::EntryPointStake,
+    utils::supported_entry_points,
     web3_api::{Web3ApiServer, Web3ApiServerImpl},
     JsonRpcServer,
 };
-use std::{collections::HashSet, future::pending, panic, sync::Arc};
-use tracing::info;
+use std::{
+    collections::HashSet, future::pending, net::SocketAddr, panic, sync::Arc, time::Duration,
+};
+use tracing::{info, warn};
 
 #[derive(Parser)]
 #[clap(name = "silius", about = "Bundler for ERC-4337 Account Abstraction")]
 pub struct Opt {
+    #[clap(long, required_unless_present = "aws_kms_key_id")]
+    pub mnemonic_file: Option<ExpandedPathBuf>,
+
+    // AWS KMS key of the bundler's EOA used instead of the mnemonic file (requires the aws-kms feature)
     #[clap(long)]
-    pub mnemonic_file: ExpandedPathBuf,
+    pub aws_kms_key_id: Option<String>,
 
     #[clap(long, value_delimiter=',', value_parser=parse_address)]
     pub entry_points: Vec<Address>,
@@ -50,12 +63,23 @@ pub struct Opt {
     #[clap(long, default_value=None, value_parser = SUPPORTED_CHAINS)]
     pub chain: Option<String>,
 
+    // chain spec (pre-verification gas overhead and fees) of a custom network, selected by the chain id otherwise
+    #[clap(long)]
+    pub chain_spec: Option<ExpandedPathBuf>,
+
     // execution client rpc endpoint
     #[clap(long, default_value = "http://127.0.0.1:8545")]
     pub eth_client_address: String,
 
     #[clap(flatten)]
     pub bundler_opts: BundlerServiceOpts,
+
+    #[clap(long)]
+    pub metrics_listen_address: Option<SocketAddr>,
+
+    // time given to the services to finish their work on shutdown (in seconds)
+    #[clap(long, default_value = "30")]
+    pub shutdown_timeout: u64,
 }
 
 fn main() -> Result<()> {
@@ -71,6 +95,13 @@ fn main() -> Result<()> {
                 .thread_stack_size(128 * 1024 * 1024)
                 .build()?;
 
+            // the bundler is shut down first, then the uopool releases the user operations of unsent bundles
+            let bundler_shutdown = Shutdown::default();
+            let uopool_shutdown = Shutdown::default();
+            let bundler_signal = bundler_shutdown.signal();
+            let uopool_signal = uopool_shutdown.signal();
+            let shutdown_timeout = Duration::from_secs(opt.shutdown_timeout);
+
             let task = async move {
                 info!("Starting ERC-4337 AA Bundler");
 
@@ -95,23 +126,61 @@ fn main() -> Result<()> {
                     }
                 }
 
-                let wallet = Wallet::from_file(opt.mnemonic_file.clone(), &chain_id)
-                    .map_err(|error| format_err!("Could not load mnemonic file: {}", error))?;
-                info!("{:?}", wallet.signer);
+                let chain_spec = match opt.chain_spec {
+                    Some(path) => ChainSpec::from_file(path)
+                        .map_err(|error| format_err!("Could not load chain spec: {}", error))?,
+                    None => ChainSpec::for_chain(&chain),
+                };
+
+                let rate_limiter = opt.rpc_opts.rate_limiter(Arc::new(EntryPointStake {
+                    eth_client: eth_client.clone(),
+                    min_stake: opt.uopool_opts.min_stake,
+                    min_unstake_delay: opt.uopool_opts.min_unstake_delay,
+                }));
+
+                let signer: Arc<dyn BundlerSigner> =
+                    match (opt.aws_kms_key_id.as_deref(), opt.mnemonic_file.clone()) {
+                        #[cfg(feature = "aws-kms")]
+                        (Some(key_id), _) => Arc::new(
+                            AwsKmsSigner::new(key_id, chain_id.as_u64())
+                                .await
+                                .map_err(|error| format_err!("Could not load AWS KMS key: {}", error))?,
+                        ),
+                        #[cfg(not(feature = "aws-kms"))]
+                        (Some(_), _) => {
+                            return Err(format_err!(
+                                "AWS KMS signer requires building with the aws-kms feature"
+                            ))
+                        }
+                        (None, Some(mnemonic_file)) => {
+                            let wallet = Wallet::from_file(mnemonic_file, &chain_id).map_err(
+                                |error| format_err!("Could not load mnemonic file: {}", error),
+                            )?;
+                            Arc::new(LocalSigner::new(wallet.signer))
+                        }
+                        (None, None) => {
+                            return Err(format_err!(
+                                "Either mnemonic file or AWS KMS key id is required"
+                            ))
+                        }
+                    };
+                info!("Bundler address {:?}", signer.address());
+
+                if let Some(metrics_listen_address) = opt.metrics_listen_address {
+                    metrics_server_run(metrics_listen_address);
+                }
 
                 if !opt.no_uopool {
                     info!("Starting uopool gRPC service...");
                     uopool_service_run(
-                        opt.uopool_opts.uopool_grpc_listen_address,
-                        opt.entry_points.clone(),
+                        opt.uopool_opts.service_config(
+                            opt.entry_points.clone(),
+                            chain,
+                            chain_spec.clone(),
+                            opt.max_verification_gas,
+                        ),
                         eth_client,
-                        chain,
-                        opt.max_verification_gas,
-                        opt.uopool_opts.min_stake,
-                        opt.uopool_opts.min_unstake_delay,
-                        opt.uopool_opts.min_priority_fee_per_gas,
-                        opt.uopool_opts.whitelist,
-                        opt.uopool_opts.uo_pool_mode,
+                        uopool_signal,
                     )
                     .await?;
                     info!(
@@ -128,19 +197,42 @@ fn main() -> Result<()> {
                 .await?;
                 info!("Connected to uopool gRPC service");
 
+                let flashbots_searcher = opt
+                    .bundler_opts
+                    .flashbots_searcher_key
+                    .as_deref()
+                    .map(str::parse::<LocalWallet>)
+                    .transpose()?;
+
                 info!("Starting bundler gRPC service...");
                 bundler_service_run(
                     opt.bundler_opts.bundler_grpc_listen_address,
-                    wallet,
+                    signer,
                     opt.entry_points,
                     opt.eth_client_address.clone(),
                     chain,
+                    chain_spec,
                     opt.bundler_opts.beneficiary,
                     opt.bundler_opts.gas_factor,
                     opt.bundler_opts.min_balance,
                     opt.bundler_opts.bundle_interval,
+                    opt.bundler_opts.deadline_margin,
+                    opt.bundler_opts.deadline_policy,
+                    opt.bundler_opts.conditional_rpc,
+                    opt.bundler_opts.flashbots_relay.clone(),
+                    flashbots_searcher,
+                    opt.bundler_opts.flashbots_max_blocks,
+                    opt.bundler_opts.priority_fee_percentile,
+                    opt.bundler_opts.base_fee_buffer_perc,
+                    opt.bundler_opts.fixed_gas_price,
+                    opt.bundler_opts.submission_mode,
+                    opt.bundler_opts.block_watcher,
+                    opt.bundler_opts.block_poll_interval,
+                    opt.bundler_opts.bundle_gas_limit_buffer_perc,
                     uopool_grpc_client.clone(),
-                );
+                    bundler_signal,
+                )
+                .await?;
                 info!(
                     "Started bundler gRPC service at {:}",
                     opt.bundler_opts.bundler_grpc_listen_address
@@ -152,9 +244,16 @@ fn main() -> Result<()> {
                         async move {
                             let api: HashSet<String> =
                                 HashSet::from_iter(opt.rpc_opts.rpc_api.iter().cloned());
+                            let dummy_signatures = opt.rpc_opts.dummy_signatures();
+                            let request_limits = opt.rpc_opts.request_limits();
+                            let entry_points = supported_entry_points(&uopool_grpc_client).await?;
 
                             let mut server = JsonRpcServer::new(opt.rpc_opts.rpc_listen_address.clone()).with_proxy(opt.eth_client_address)
-                            .with_cors(opt.rpc_opts.cors_domain);
+                            .with_cors(opt.rpc_opts.cors_domain).with_request_limits(request_limits);
+
+                            if let Some(rate_limiter) = rate_limiter {
+                                server = server.with_rate_limit(rate_limiter);
+                            }
 
                             if api.contains("web3") {
                                 server.add_method(Web3ApiServerImpl{}.into_rpc())?;
@@ -164,6 +263,8 @@ fn main() -> Result<()> {
                                 server.add_method(
                                     EthApiServerImpl {
                                         uopool_grpc_client: uopool_grpc_client.clone(),
+                                        dummy_signatures,
+                                        entry_points: entry_points.clone(),
                                     }
                                     .into_rpc(),
                                 )?;
@@ -179,6 +280,7 @@ fn main() -> Result<()> {
                                     DebugApiServerImpl {
                                         uopool_grpc_client,
                                         bundler_grpc_client,
+                                        entry_points,
                                     }
                                     .into_rpc(),
                                 )?;
@@ -197,7 +299,18 @@ fn main() -> Result<()> {
 
                 pending().await
             };
-            rt.block_on(run_until_ctrl_c(task))?;
+            rt.block_on(async move {
+                run_until_ctrl_c(task).await?;
+
+                info!("Shutting down");
+                if !bundler_shutdown.shutdown(shutdown_timeout).await {
+                    warn!("Bundler service didn't shut down in {shutdown_timeout:?}");
+                }
+                if !uopool_shutdown.shutdown(shutdown_timeout).await {
+                    warn!("UoPool service didn't shut down in {shutdown_timeout:?}");
+                }
+                Ok::<(), anyhow::Error>(())
+            })?;
             Ok(())
 
         })?
@@ -1,6 +1,12 @@
-use ethers::types::{Address, U256};
+use ethers::types::{Address, Bytes, H256, U256};
 use pin_utils::pin_mut;
-use silius_primitives::UoPoolMode;
+use silius_primitives::{
+    bundler::{BlockWatcherMode, DeadlinePolicy, SubmissionMode},
+    sanity::SignatureShape,
+    simulation::{EnabledEntities, TracerMode},
+    uopool::{OrderingPolicy, P2pOpsPolicy},
+    UoPoolMode,
+};
 use std::{future::Future, str::FromStr};
 use tracing::info;
 
@@ -19,6 +25,75 @@ pub fn parse_uopool_mode(s: &str) -> Result<UoPoolMode, String> {
     UoPoolMode::from_str(s).map_err(|_| format!("String {s} is not a valid UoPoolMode"))
 }
 
+/// Parses DeadlinePolicy from string
+pub fn parse_deadline_policy(s: &str) -> Result<DeadlinePolicy, String> {
+    DeadlinePolicy::from_str(s).map_err(|_| format!("String {s} is not a valid DeadlinePolicy"))
+}
+
+/// Parses SubmissionMode from string
+pub fn parse_submission_mode(s: &str) -> Result<SubmissionMode, String> {
+    SubmissionMode::from_str(s).map_err(|_| format!("String {s} is not a valid SubmissionMode"))
+}
+
+/// Parses TracerMode from string
+pub fn parse_tracer_mode(s: &str) -> Result<TracerMode, String> {
+    TracerMode::from_str(s).map_err(|_| format!("String {s} is not a valid TracerMode"))
+}
+
+/// Parses BlockWatcherMode from string
+pub fn parse_block_watcher_mode(s: &str) -> Result<BlockWatcherMode, String> {
+    BlockWatcherMode::from_str(s).map_err(|_| format!("String {s} is not a valid BlockWatcherMode"))
+}
+
+/// Parses P2pOpsPolicy from string
+pub fn parse_p2p_ops_policy(s: &str) -> Result<P2pOpsPolicy, String> {
+    P2pOpsPolicy::from_str(s).map_err(|_| format!("String {s} is not a valid P2pOpsPolicy"))
+}
+
+/// Parses OrderingPolicy from string
+pub fn parse_ordering_policy(s: &str) -> Result<OrderingPolicy, String> {
+    OrderingPolicy::from_str(s).map_err(|_| format!("String {s} is not a valid OrderingPolicy"))
+}
+
+/// Parses entity types (`factory`, `account`, `paymaster`) the simulation rules are skipped for from comma
+/// separated string
+pub fn parse_skipped_entities(s: &str) -> Result<EnabledEntities, String> {
+    let entities: Vec<String> = s
+        .split(',')
+        .map(|entity| entity.trim().to_lowercase())
+        .filter(|entity| !entity.is_empty())
+        .collect();
+    EnabledEntities::except(&entities)
+}
+
+/// Parses dummy signature of the entry point or factory from string (`<address>=<signature>`)
+pub fn parse_dummy_signature(s: &str) -> Result<(Address, Bytes), String> {
+    let (address, signature) = s
+        .split_once('=')
+        .ok_or_else(|| format!("String {s} is not a valid <address>=<signature>"))?;
+    let signature =
+        Bytes::from_str(signature).map_err(|_| format!("String {signature} is not valid bytes"))?;
+    Ok((parse_address(address)?, signature))
+}
+
+/// Parses expected signature shape of the factory's accounts from string (`<address>=<shape>`)
+pub fn parse_factory_signature_shape(s: &str) -> Result<(Address, SignatureShape), String> {
+    let (address, shape) = s
+        .split_once('=')
+        .ok_or_else(|| format!("String {s} is not a valid <address>=<shape>"))?;
+    Ok((parse_address(address)?, SignatureShape::from_str(shape)?))
+}
+
+/// Parses expected signature shape of the accounts with the code hash from string (`<code hash>=<shape>`)
+pub fn parse_code_hash_signature_shape(s: &str) -> Result<(H256, SignatureShape), String> {
+    let (code_hash, shape) = s
+        .split_once('=')
+        .ok_or_else(|| format!("String {s} is not a valid <code hash>=<shape>"))?;
+    let code_hash =
+        H256::from_str(code_hash).map_err(|_| format!("String {code_hash} is not a valid hash"))?;
+    Ok((code_hash, SignatureShape::from_str(shape)?))
+}
+
 /// Runs the future to completion or until:
 /// - `ctrl-c` is received.
 /// - `SIGTERM` is received (unix only).
@@ -1,81 +1,994 @@
+use crate::{
+    conditional::send_raw_transaction_conditional, flashbots::FlashbotsClient,
+    gas_oracle::GasOracle, signer::BundlerSigner,
+};
 use ethers::{
-    prelude::SignerMiddleware,
-    providers::{Http, Middleware, Provider},
-    signers::Signer,
-    types::{transaction::eip2718::TypedTransaction, Address, H256},
+    abi::RawLog,
+    contract::EthEvent,
+    providers::{Http, Middleware, PendingTransaction, Provider},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockNumber, Bytes, TransactionReceipt,
+        H256, U256,
+    },
+};
+use silius_contracts::{
+    entry_point::{EntryPointAPI, EntryPointErr, UserOperationEventFilter},
+    Aggregator,
+};
+use silius_primitives::{
+    bundler::{BundleDump, DeadlinePolicy, DroppedUserOperation, KnownAccounts, SubmissionMode},
+    metrics::METRICS,
+    Chain, UserOperation, UserOperationHash, UserOperationsPerAggregator,
 };
-use silius_contracts::entry_point::EntryPointAPI;
-use silius_primitives::{Chain, UserOperation, Wallet};
-use std::{sync::Arc, time::Duration};
-use tracing::{info, trace};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+use tracing::{info, trace, warn};
+
+/// Base gas overhead of the bundle transaction
+const BUNDLE_TX_GAS_OVERHEAD: u64 = 21_000;
+/// Gas overhead of the entry point for each user operation in the bundle
+const BUNDLE_UO_GAS_OVERHEAD: u64 = 18_300;
+/// Default buffer (in percent) added to the estimated gas limit of the bundle transaction
+pub const DEFAULT_BUNDLE_GAS_LIMIT_BUFFER_PERC: u64 = 10;
+const SUBMISSIONS_CHANNEL_CAPACITY: usize = 128;
+
+/// Notification sent to subscribers when the bundle transaction is sent (before it's mined)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundleSubmission {
+    pub tx_hash: H256,
+    pub uo_hashes: Vec<UserOperationHash>,
+}
+
+/// Bundle transaction (filled, but not signed) with the user operations it includes
+#[derive(Clone, Debug)]
+pub struct PreparedBundle {
+    pub tx: TypedTransaction,
+    pub uos: Vec<UserOperation>,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    /// User operations selected by the mempool, but excluded by the bundler (with the reasons)
+    pub excluded: Vec<(UserOperation, String)>,
+}
 
 #[derive(Clone)]
 pub struct Bundler {
-    pub wallet: Wallet,
+    pub signer: Arc<dyn BundlerSigner>,
     pub eth_client_address: String,
-    pub beneficiary: Address,
+    pub beneficiary: Option<Address>,
     pub entry_point: Address,
     pub chain: Chain,
+    pub deadline_margin: u64,
+    pub deadline_policy: DeadlinePolicy,
+    pub conditional_rpc: bool,
+    pub flashbots: Option<FlashbotsClient>,
+    pub gas_oracle: GasOracle,
+    pub submission_mode: SubmissionMode,
+    pub gas_limit_buffer_perc: u64,
+    submissions: broadcast::Sender<BundleSubmission>,
 }
 
 impl Bundler {
     pub fn new(
-        wallet: Wallet,
+        signer: Arc<dyn BundlerSigner>,
         eth_client_address: String,
-        beneficiary: Address,
         entry_point: Address,
         chain: Chain,
     ) -> Self {
         Self {
-            wallet,
+            signer,
             eth_client_address,
-            beneficiary,
+            beneficiary: None,
             entry_point,
             chain,
+            deadline_margin: 0,
+            deadline_policy: DeadlinePolicy::default(),
+            conditional_rpc: false,
+            flashbots: None,
+            gas_oracle: GasOracle::default(),
+            submission_mode: SubmissionMode::default(),
+            gas_limit_buffer_perc: DEFAULT_BUNDLE_GAS_LIMIT_BUFFER_PERC,
+            submissions: broadcast::channel(SUBMISSIONS_CHANNEL_CAPACITY).0,
         }
     }
 
-    pub async fn send_next_bundle(&self, uos: &Vec<UserOperation>) -> anyhow::Result<H256> {
-        if uos.is_empty() {
+    /// Sets the address receiving the refunded gas of the bundles (the signer's address by default)
+    pub fn with_beneficiary(mut self, beneficiary: Address) -> Self {
+        self.beneficiary = Some(beneficiary);
+        self
+    }
+
+    /// Sets the confirmation time margin (in seconds) before `validUntil` of user operations and what to do
+    /// with user operations that are within the margin
+    pub fn with_deadline(mut self, deadline_margin: u64, deadline_policy: DeadlinePolicy) -> Self {
+        self.deadline_margin = deadline_margin;
+        self.deadline_policy = deadline_policy;
+        self
+    }
+
+    /// Sends bundles via `eth_sendRawTransactionConditional` with the storage preconditions observed during
+    /// the simulation (used on L2s supporting the conditional RPC method)
+    pub fn with_conditional_rpc(mut self, conditional_rpc: bool) -> Self {
+        self.conditional_rpc = conditional_rpc;
+        self
+    }
+
+    /// Sends bundles privately to the relay of the Flashbots client instead of the public mempool
+    pub fn with_flashbots(mut self, flashbots: FlashbotsClient) -> Self {
+        self.flashbots = Some(flashbots);
+        self
+    }
+
+    /// Sets the gas oracle estimating the fees of the bundle transaction
+    pub fn with_gas_oracle(mut self, gas_oracle: GasOracle) -> Self {
+        self.gas_oracle = gas_oracle;
+        self
+    }
+
+    /// Sets what to do with bundles failing on a user operation in the simulation (drop the user operation or
+    /// don't submit the bundle at all)
+    pub fn with_submission_mode(mut self, submission_mode: SubmissionMode) -> Self {
+        self.submission_mode = submission_mode;
+        self
+    }
+
+    /// Sets the buffer (in percent) added to the gas limit of the bundle transaction estimated by `eth_estimateGas`
+    pub fn with_gas_limit_buffer(mut self, gas_limit_buffer_perc: u64) -> Self {
+        self.gas_limit_buffer_perc = gas_limit_buffer_perc;
+        self
+    }
+
+    /// Subscribes to notifications about the sent bundle transactions
+    pub fn subscribe_submissions(&self) -> broadcast::Receiver<BundleSubmission> {
+        self.submissions.subscribe()
+    }
+
+    /// Applies the deadline policy to the user operations selected for the bundle
+    ///
+    /// `valid_until` holds `validUntil` of each user operation (in the same order as user operations).
+    /// Returns the user operations to bundle and whether the bundle transaction should be prioritized,
+    /// since the minimum `validUntil` is within the confirmation time margin.
+    pub fn apply_deadline(
+        &self,
+        uos: Vec<UserOperation>,
+        valid_until: &[u64],
+        now: u64,
+    ) -> (Vec<UserOperation>, bool) {
+        if self.deadline_margin == 0 {
+            return (uos, false);
+        }
+
+        let deadline = now.saturating_add(self.deadline_margin);
+
+        match self.deadline_policy {
+            DeadlinePolicy::Prioritize => {
+                let valid_until_min = (0..uos.len())
+                    .map(|i| valid_until.get(i).copied().unwrap_or(u64::MAX))
+                    .min()
+                    .unwrap_or(u64::MAX);
+                (uos, valid_until_min <= deadline)
+            }
+            DeadlinePolicy::Exclude => (
+                uos.into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| valid_until.get(*i).copied().unwrap_or(u64::MAX) > deadline)
+                    .map(|(_, uo)| uo)
+                    .collect(),
+                false,
+            ),
+        }
+    }
+
+    /// Groups the user operations by their signature aggregator (zero address for user operations without
+    /// an aggregator)
+    ///
+    /// Groups are ordered by the first appearance of the aggregator and the order of user operations within
+    /// each group is preserved. Signatures of the groups are left empty.
+    pub fn group_by_aggregator(
+        uos: Vec<UserOperation>,
+        aggregators: &HashMap<Address, Address>,
+    ) -> Vec<UserOperationsPerAggregator> {
+        let mut groups: Vec<UserOperationsPerAggregator> = vec![];
+
+        for uo in uos {
+            let aggregator = aggregators.get(&uo.sender).copied().unwrap_or_default();
+            match groups.iter_mut().find(|g| g.aggregator == aggregator) {
+                Some(group) => group.user_operations.push(uo),
+                None => groups.push(UserOperationsPerAggregator {
+                    user_operations: vec![uo],
+                    aggregator,
+                    signature: Default::default(),
+                }),
+            }
+        }
+
+        groups
+    }
+
+    /// Keeps only the user operations whose `max_fee_per_gas` covers the max fee of the bundle transaction
+    ///
+    /// Applied by the mempool before selecting the user operations (see [estimate_fees](Self::estimate_fees)), so the
+    /// user operations filtered out stay pending and can still be replaced.
+    pub fn filter_by_max_fee(uos: Vec<UserOperation>, max_fee_per_gas: U256) -> Vec<UserOperation> {
+        uos.into_iter()
+            .filter(|uo| {
+                let covered = uo.max_fee_per_gas >= max_fee_per_gas;
+                if !covered {
+                    trace!(
+                        "Excluding user operation of {:?} from the bundle, max fee per gas {} is lower than {max_fee_per_gas}",
+                        uo.sender,
+                        uo.max_fee_per_gas
+                    );
+                }
+                covered
+            })
+            .collect()
+    }
+
+    /// Estimates (`max_fee_per_gas`, `max_priority_fee_per_gas`) of the next bundle transaction
+    ///
+    /// The fees are estimated before the mempool selects the user operations, the max fee is passed to the selection.
+    pub async fn estimate_fees(&self) -> anyhow::Result<(U256, U256)> {
+        let eth_client = Provider::<Http>::try_from(self.eth_client_address.clone())?;
+        self.gas_oracle.estimate(&eth_client).await
+    }
+
+    /// Computes the gas limit of the bundle transaction
+    ///
+    /// The gas limit is the sum of all user operations' gas limits plus the entry point overhead for each
+    /// user operation and the base transaction overhead.
+    pub fn estimate_bundle_gas(uos: &[UserOperation]) -> U256 {
+        uos.iter()
+            .fold(U256::from(BUNDLE_TX_GAS_OVERHEAD), |gas, uo| {
+                gas.saturating_add(uo.pre_verification_gas)
+                    .saturating_add(uo.verification_gas_limit)
+                    .saturating_add(uo.call_gas_limit)
+                    .saturating_add(BUNDLE_UO_GAS_OVERHEAD.into())
+            })
+    }
+
+    /// Estimates the gas limit of the bundle transaction
+    ///
+    /// The whole `handleOps` call is estimated by `eth_estimateGas` and increased by the buffer. If the estimation
+    /// fails (e.g., the call reverts), falls back to the sum of the user operations' gas limits and overheads plus
+    /// the gas of the aggregators' signature validation.
+    pub async fn estimate_bundle_tx_gas<M: Middleware>(
+        &self,
+        eth_client: &M,
+        tx: &TypedTransaction,
+        uos: &[UserOperation],
+        aggregation_gas: U256,
+    ) -> U256 {
+        match eth_client.estimate_gas(tx, None).await {
+            Ok(gas) => {
+                gas.saturating_mul(U256::from(100 + self.gas_limit_buffer_perc)) / U256::from(100)
+            }
+            Err(err) => {
+                let gas = Self::estimate_bundle_gas(uos).saturating_add(aggregation_gas);
+                warn!("Estimating gas of the bundle transaction failed, using the sum of the user operations' gas limits {gas}: {err:?}");
+                gas
+            }
+        }
+    }
+
+    /// Address receiving the refunded gas of the bundle (the signer's address if not configured)
+    pub fn beneficiary(&self) -> anyhow::Result<Address> {
+        match self.beneficiary {
+            Some(beneficiary) if beneficiary.is_zero() => {
+                Err(anyhow::anyhow!("Beneficiary can't be the zero address"))
+            }
+            Some(beneficiary) => Ok(beneficiary),
+            None => Ok(self.signer.address()),
+        }
+    }
+
+    /// Creates the `handleOps` transaction of the bundle (`handleAggregatedOps` if any of the user operations
+    /// uses a signature aggregator) and returns it with the gas of the aggregators' signature validation
+    ///
+    /// The signature of each aggregated user operation is replaced by the one returned by the aggregator's
+    /// `validateUserOpSignature`, the signatures of each group are then aggregated by `aggregateSignatures`.
+    pub async fn create_bundle_tx<M: Middleware + 'static>(
+        &self,
+        eth_client: Arc<M>,
+        uos: &[UserOperation],
+        aggregators: &HashMap<Address, Address>,
+    ) -> anyhow::Result<(TypedTransaction, U256)> {
+        let beneficiary = self.beneficiary()?;
+        let ep = EntryPointAPI::new(self.entry_point, eth_client.clone());
+
+        if aggregators.is_empty() {
+            return Ok((
+                ep.handle_ops(uos.iter().cloned().map(Into::into).collect(), beneficiary)
+                    .tx,
+                U256::zero(),
+            ));
+        }
+
+        let mut aggregation_gas = U256::zero();
+        let mut groups = Self::group_by_aggregator(uos.to_vec(), aggregators);
+        for group in groups.iter_mut().filter(|g| !g.aggregator.is_zero()) {
+            let aggregator = Aggregator::new(eth_client.clone(), group.aggregator);
+            let err = |err: EntryPointErr| {
+                anyhow::anyhow!(
+                    "Aggregating signatures by {:?} failed: {err:?}",
+                    group.aggregator
+                )
+            };
+
+            for uo in group.user_operations.iter_mut() {
+                uo.signature = aggregator
+                    .validate_user_op_signature(uo.clone())
+                    .await
+                    .map_err(err)?;
+            }
+            group.signature = aggregator
+                .aggregate_signatures(group.user_operations.clone())
+                .await
+                .map_err(err)?;
+            aggregation_gas = aggregation_gas.saturating_add(
+                aggregator
+                    .estimate_validate_signatures_gas(
+                        self.entry_point,
+                        group.user_operations.clone(),
+                        group.signature.clone(),
+                    )
+                    .await
+                    .map_err(err)?,
+            );
+        }
+
+        Ok((
+            ep.handle_aggregated_ops(groups.into_iter().map(Into::into).collect(), beneficiary)
+                .tx,
+            aggregation_gas,
+        ))
+    }
+
+    /// Signs the bundle transaction and returns the signed RLP-encoded transaction
+    pub async fn sign_transaction(&self, tx: &TypedTransaction) -> anyhow::Result<Bytes> {
+        let signature = self.signer.sign_transaction(tx).await?;
+        Ok(tx.rlp_signed(&signature))
+    }
+
+    /// Creates the bundle transaction (filled, but not signed) from the user operations selected by the mempool
+    ///
+    /// `fees` are the (`max_fee_per_gas`, `max_priority_fee_per_gas`) the user operations were selected with.
+    /// Returns `None` if no user operations are left after applying the deadline policy.
+    pub async fn prepare_bundle(
+        &self,
+        eth_client: &Provider<Http>,
+        uos: &[UserOperation],
+        valid_until: &[u64],
+        aggregators: &[Address],
+        (max_fee_per_gas, max_priority_fee_per_gas): (U256, U256),
+    ) -> anyhow::Result<Option<PreparedBundle>> {
+        let aggregators: HashMap<Address, Address> = uos
+            .iter()
+            .zip(aggregators.iter())
+            .filter(|(_, aggregator)| !aggregator.is_zero())
+            .map(|(uo, aggregator)| (uo.sender, *aggregator))
+            .collect();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let (uos_selected, prioritize) = self.apply_deadline(uos.to_vec(), valid_until, now);
+        let excluded: Vec<(UserOperation, String)> = uos
+            .iter()
+            .filter(|uo| !uos_selected.contains(uo))
+            .map(|uo| {
+                (
+                    uo.clone(),
+                    "validUntil is within the deadline margin".into(),
+                )
+            })
+            .collect();
+
+        if uos_selected.is_empty() {
             info!("Skipping creating a new bundle, no user operations");
-            return Ok(H256::default());
+            return Ok(None);
         };
+        let uos = uos_selected;
 
         info!("Creating a new bundle with {} user operations", uos.len());
         trace!("Bundle content: {uos:?}");
 
+        let nonce = eth_client
+            .get_transaction_count(self.signer.address(), None)
+            .await?;
+        let (mut tx, aggregation_gas) = self
+            .create_bundle_tx(Arc::new(eth_client.clone()), &uos, &aggregators)
+            .await?;
+        tx.set_from(self.signer.address())
+            .set_nonce(nonce)
+            .set_chain_id(self.chain.id());
+        let gas = self
+            .estimate_bundle_tx_gas(eth_client, &tx, &uos, aggregation_gas)
+            .await;
+        tx.set_gas(gas);
+
+        let max_priority_fee_per_gas = if prioritize {
+            info!("User operations in the bundle are close to validUntil, prioritizing the bundle");
+            max_fee_per_gas
+        } else {
+            max_priority_fee_per_gas
+        };
+        match &mut tx {
+            TypedTransaction::Eip1559(tx) => {
+                tx.max_fee_per_gas = Some(max_fee_per_gas);
+                tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+            }
+            tx => {
+                tx.set_gas_price(max_fee_per_gas);
+            }
+        }
+
+        eth_client.fill_transaction(&mut tx, None).await?;
+
+        Ok(Some(PreparedBundle {
+            tx,
+            uos,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            excluded,
+        }))
+    }
+
+    /// Creates the next bundle without sending it (dry run)
+    ///
+    /// `dropped` are the user operations dropped from the mempool while the mempool selected the user operations,
+    /// the user operations excluded by the bundler are appended to them.
+    pub async fn dump_bundle(
+        &self,
+        uos: &[UserOperation],
+        valid_until: &[u64],
+        aggregators: &[Address],
+        dropped: Vec<DroppedUserOperation>,
+        fees: (U256, U256),
+    ) -> anyhow::Result<BundleDump> {
         let eth_client = Provider::<Http>::try_from(self.eth_client_address.clone())?;
-        let client = Arc::new(SignerMiddleware::new(
-            eth_client.clone(),
-            self.wallet.signer.clone(),
-        ));
-        let ep = EntryPointAPI::new(self.entry_point, client.clone());
+        let chain_id = U256::from(self.chain.id());
+        let mut dump = BundleDump {
+            entry_point: self.entry_point,
+            dropped,
+            ..Default::default()
+        };
 
-        let nonce = client
-            .clone()
-            .get_transaction_count(self.wallet.signer.address(), None)
-            .await?;
-        let mut tx: TypedTransaction = ep
-            .handle_ops(
-                uos.clone().into_iter().map(Into::into).collect(),
-                self.beneficiary,
+        let bundle = match self
+            .prepare_bundle(&eth_client, uos, valid_until, aggregators, fees)
+            .await?
+        {
+            Some(bundle) => bundle,
+            None => {
+                dump.dropped
+                    .extend(uos.iter().map(|uo| DroppedUserOperation {
+                        user_operation_hash: uo.hash(&self.entry_point, &chain_id),
+                        reason: "Excluded by the bundler (deadline margin)".into(),
+                    }));
+                return Ok(dump);
+            }
+        };
+
+        let base_fee_per_gas = eth_client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .and_then(|block| block.base_fee_per_gas);
+        let gas_limit = bundle.tx.gas().copied().unwrap_or_default();
+
+        dump.expected_revenue = Self::expected_revenue(
+            &bundle.uos,
+            gas_limit,
+            bundle.max_fee_per_gas,
+            bundle.max_priority_fee_per_gas,
+            base_fee_per_gas,
+        );
+        dump.dropped.extend(
+            bundle
+                .excluded
+                .iter()
+                .map(|(uo, reason)| DroppedUserOperation {
+                    user_operation_hash: uo.hash(&self.entry_point, &chain_id),
+                    reason: reason.clone(),
+                }),
+        );
+        dump.user_operation_hashes = bundle
+            .uos
+            .iter()
+            .map(|uo| uo.hash(&self.entry_point, &chain_id))
+            .collect();
+        dump.user_operations = bundle.uos;
+        dump.gas_limit = gas_limit;
+        dump.max_fee_per_gas = bundle.max_fee_per_gas;
+        dump.max_priority_fee_per_gas = bundle.max_priority_fee_per_gas;
+
+        Ok(dump)
+    }
+
+    /// Estimates the revenue of the beneficiary from the bundle
+    ///
+    /// The user operations pay `min(max_fee_per_gas, base_fee + max_priority_fee_per_gas)` per gas (`max_fee_per_gas`
+    /// if the base fee is unknown) and the bundle transaction is priced the same way. Full gas limits are assumed on
+    /// both sides. Returns zero if the bundle isn't profitable.
+    pub fn expected_revenue(
+        uos: &[UserOperation],
+        gas_limit: U256,
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+        base_fee_per_gas: Option<U256>,
+    ) -> U256 {
+        let gas_price = |max_fee: U256, max_priority_fee: U256| match base_fee_per_gas {
+            Some(base_fee) => max_fee.min(base_fee.saturating_add(max_priority_fee)),
+            None => max_fee,
+        };
+
+        let fees = uos.iter().fold(U256::zero(), |fees, uo| {
+            let gas = uo
+                .pre_verification_gas
+                .saturating_add(uo.verification_gas_limit)
+                .saturating_add(uo.call_gas_limit);
+            fees.saturating_add(
+                gas.saturating_mul(gas_price(uo.max_fee_per_gas, uo.max_priority_fee_per_gas)),
             )
-            .tx
-            .clone();
-        tx.set_nonce(nonce).set_chain_id(self.chain.id());
+        });
+        let cost = gas_limit.saturating_mul(gas_price(max_fee_per_gas, max_priority_fee_per_gas));
 
-        trace!("Sending transaction to the execution client: {tx:?}");
+        fees.saturating_sub(cost)
+    }
+
+    /// Gas cost of the mined bundle transaction and the refunds credited to the beneficiary
+    ///
+    /// The gas cost is the effective gas price times the gas used (L1 data fees aren't included), the refunds are
+    /// the sum of `actualGasCost` of the `UserOperationEvent`s emitted by the entry point.
+    pub fn bundle_gas_cost_and_refund(
+        tx_receipt: &TransactionReceipt,
+        entry_point: &Address,
+    ) -> (U256, U256) {
+        let gas_cost = tx_receipt
+            .effective_gas_price
+            .unwrap_or_default()
+            .saturating_mul(tx_receipt.gas_used.unwrap_or_default());
+        let refund = tx_receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == *entry_point)
+            .filter_map(|log| UserOperationEventFilter::decode_log(&RawLog::from(log.clone())).ok())
+            .fold(U256::zero(), |refund, event| {
+                refund.saturating_add(event.actual_gas_cost)
+            });
 
-        let tx = client
-            .send_transaction(tx, None)
+        (gas_cost, refund)
+    }
+
+    pub async fn send_next_bundle(
+        &self,
+        uos: &[UserOperation],
+        valid_until: &[u64],
+        aggregators: &[Address],
+        known_accounts: &KnownAccounts,
+        fees: (U256, U256),
+    ) -> anyhow::Result<H256> {
+        if self.flashbots.as_ref().map_or(false, |f| f.is_pending()) {
+            info!(
+                "Skipping creating a new bundle, the previous bundle is still pending at the relay"
+            );
+            return Ok(H256::default());
+        }
+
+        let eth_client = Provider::<Http>::try_from(self.eth_client_address.clone())?;
+
+        let PreparedBundle { tx, uos, .. } = match self
+            .prepare_bundle(&eth_client, uos, valid_until, aggregators, fees)
             .await?
-            .interval(Duration::from_millis(75));
-        let tx_hash = tx.tx_hash();
+        {
+            Some(bundle) => bundle,
+            None => return Ok(H256::default()),
+        };
+
+        trace!("Sending transaction to the execution client: {tx:?}");
+
+        let sent_at = Instant::now();
+
+        let signed_tx = self.sign_transaction(&tx).await?;
+
+        let tx_hash = if let Some(flashbots) = &self.flashbots {
+            flashbots
+                .send_bundle(Arc::new(eth_client.clone()), signed_tx)
+                .await?
+        } else if self.conditional_rpc {
+            send_raw_transaction_conditional(&eth_client, signed_tx, known_accounts).await?
+        } else {
+            eth_client.send_raw_transaction(signed_tx).await?.tx_hash()
+        };
 
-        let tx_receipt = tx.await?;
+        let chain_id = U256::from(self.chain.id());
+        // no subscribers is not an error
+        let _ = self.submissions.send(BundleSubmission {
+            tx_hash,
+            uo_hashes: uos
+                .iter()
+                .map(|uo| uo.hash(&self.entry_point, &chain_id))
+                .collect(),
+        });
+
+        let tx_receipt = PendingTransaction::new(tx_hash, &eth_client)
+            .interval(Duration::from_millis(75))
+            .await?;
 
         trace!("Transaction receipt: {tx_receipt:?}");
 
+        METRICS.bundles.inc();
+        METRICS.bundle_inclusion.observe(sent_at.elapsed());
+
+        if let Some(tx_receipt) = tx_receipt.as_ref() {
+            let (gas_cost, refund) =
+                Self::bundle_gas_cost_and_refund(tx_receipt, &self.entry_point);
+            if refund < gas_cost {
+                warn!(
+                    "Bundle {tx_hash:?} wasn't profitable (gas cost {gas_cost}, refunds {refund})"
+                );
+            }
+            METRICS.observe_bundle_profit(gas_cost, refund);
+        }
+
         Ok(tx_hash)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::tests::MockSigner;
+    use ethers::{
+        abi::{encode, AbiEncode, Token},
+        types::{Log, TransactionRequest},
+    };
+    use silius_contracts::utils::parse_per_aggregator_from_input_data;
+
+    #[test]
+    fn bundle_gas_estimation() {
+        let uos = vec![
+            UserOperation::default()
+                .pre_verification_gas(50_000.into())
+                .verification_gas_limit(100_000.into())
+                .call_gas_limit(200_000.into()),
+            UserOperation::default()
+                .pre_verification_gas(45_000.into())
+                .verification_gas_limit(150_000.into())
+                .call_gas_limit(30_000.into()),
+        ];
+
+        assert_eq!(
+            Bundler::estimate_bundle_gas(&uos),
+            U256::from(50_000 + 100_000 + 200_000 + 45_000 + 150_000 + 30_000)
+                + U256::from(2 * BUNDLE_UO_GAS_OVERHEAD + BUNDLE_TX_GAS_OVERHEAD)
+        );
+    }
+
+    #[test]
+    fn bundle_max_fee_filter() {
+        let uos = vec![
+            UserOperation::default().max_fee_per_gas(100.into()),
+            UserOperation::default().max_fee_per_gas(99.into()),
+            UserOperation::default().max_fee_per_gas(150.into()),
+        ];
+
+        assert_eq!(
+            Bundler::filter_by_max_fee(uos.clone(), 100.into()),
+            vec![uos[0].clone(), uos[2].clone()]
+        );
+        assert!(Bundler::filter_by_max_fee(uos, 200.into()).is_empty());
+    }
+
+    #[test]
+    fn bundle_expected_revenue() {
+        let uos = vec![
+            UserOperation::default()
+                .pre_verification_gas(50_000.into())
+                .verification_gas_limit(100_000.into())
+                .call_gas_limit(50_000.into())
+                .max_fee_per_gas(30.into())
+                .max_priority_fee_per_gas(5.into()),
+            UserOperation::default()
+                .pre_verification_gas(50_000.into())
+                .verification_gas_limit(50_000.into())
+                .call_gas_limit(0.into())
+                .max_fee_per_gas(12.into())
+                .max_priority_fee_per_gas(5.into()),
+        ];
+
+        // user operations pay 15 and 12 per gas, the bundle transaction 11 per gas
+        assert_eq!(
+            Bundler::expected_revenue(&uos, 300_000.into(), 20.into(), 1.into(), Some(10.into())),
+            U256::from(200_000 * 15 + 100_000 * 12 - 300_000 * 11)
+        );
+
+        // without the base fee the max fees are paid
+        assert_eq!(
+            Bundler::expected_revenue(&uos, 300_000.into(), 20.into(), 1.into(), None),
+            U256::from(200_000 * 30 + 100_000 * 12 - 300_000 * 20)
+        );
+
+        // unprofitable bundle
+        assert_eq!(
+            Bundler::expected_revenue(&uos, 1_000_000.into(), 20.into(), 1.into(), None),
+            U256::zero()
+        );
+    }
+
+    #[test]
+    fn bundle_gas_cost_and_refund() {
+        let ep = Address::random();
+        let event = |address: Address, actual_gas_cost: u64| Log {
+            address,
+            topics: vec![
+                UserOperationEventFilter::signature(),
+                H256::random(),
+                H256::random(),
+                H256::zero(),
+            ],
+            data: encode(&[
+                Token::Uint(0.into()),
+                Token::Bool(true),
+                Token::Uint(actual_gas_cost.into()),
+                Token::Uint(100.into()),
+            ])
+            .into(),
+            ..Default::default()
+        };
+        let tx_receipt = TransactionReceipt {
+            gas_used: Some(100_000.into()),
+            effective_gas_price: Some(12.into()),
+            logs: vec![
+                event(ep, 700_000),
+                // other logs of the entry point and other contracts aren't refunds
+                Log {
+                    address: ep,
+                    topics: vec![H256::random()],
+                    ..Default::default()
+                },
+                event(Address::random(), 1_000_000),
+                event(ep, 600_000),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Bundler::bundle_gas_cost_and_refund(&tx_receipt, &ep),
+            (U256::from(1_200_000), U256::from(1_300_000))
+        );
+    }
+
+    #[test]
+    fn bundle_deadline() {
+        let bundler = Bundler::new(
+            Arc::new(MockSigner::default()),
+            "http://127.0.0.1:8545".into(),
+            Address::random(),
+            Chain::from(1337_u64),
+        );
+        let uos = vec![
+            UserOperation::default().sender(Address::random()),
+            UserOperation::default().sender(Address::random()),
+        ];
+        let now = 1_000;
+
+        // no margin configured
+        assert_eq!(
+            bundler.apply_deadline(uos.clone(), &[1_010, u64::MAX], now),
+            (uos.clone(), false)
+        );
+
+        let bundler = bundler.with_deadline(30, DeadlinePolicy::Prioritize);
+        assert_eq!(
+            bundler.apply_deadline(uos.clone(), &[1_010, u64::MAX], now),
+            (uos.clone(), true)
+        );
+        assert_eq!(
+            bundler.apply_deadline(uos.clone(), &[1_031, u64::MAX], now),
+            (uos.clone(), false)
+        );
+
+        let bundler = bundler.with_deadline(30, DeadlinePolicy::Exclude);
+        assert_eq!(
+            bundler.apply_deadline(uos.clone(), &[1_010, u64::MAX], now),
+            (vec![uos[1].clone()], false)
+        );
+        assert_eq!(bundler.apply_deadline(uos.clone(), &[], now), (uos, false));
+    }
+
+    #[tokio::test]
+    async fn bundle_signer() {
+        let signer = Arc::new(MockSigner::default());
+        let bundler = Bundler::new(
+            signer.clone(),
+            "http://127.0.0.1:8545".into(),
+            Address::random(),
+            Chain::from(1337_u64),
+        );
+        let tx: TypedTransaction = TransactionRequest::new()
+            .from(signer.address())
+            .to(bundler.entry_point)
+            .nonce(1)
+            .chain_id(1337)
+            .into();
+
+        let signed_tx = bundler.sign_transaction(&tx).await.unwrap();
+        assert_eq!(*signer.signed.lock().unwrap(), vec![tx.clone()]);
+        let signature = signer.signer.sign_transaction(&tx).await.unwrap();
+        assert_eq!(signed_tx, tx.rlp_signed(&signature));
+    }
+
+    #[tokio::test]
+    async fn bundle_beneficiary() {
+        let signer = Arc::new(MockSigner::default());
+        let (eth_client, _) = Provider::mocked();
+        let eth_client = Arc::new(eth_client);
+        let bundler = Bundler::new(
+            signer.clone(),
+            "http://127.0.0.1:8545".into(),
+            Address::random(),
+            Chain::from(1337_u64),
+        );
+        let uos = vec![UserOperation::default().sender(Address::random())];
+
+        // beneficiary is the second argument of `handleOps(ops, beneficiary)`
+        let encoded_beneficiary = |tx: TypedTransaction| {
+            let data = tx.data().cloned().unwrap_or_default();
+            Address::from_slice(&data[4 + 32 + 12..4 + 64])
+        };
+
+        // defaults to the signer
+        let tx = bundler
+            .create_bundle_tx(eth_client.clone(), &uos, &HashMap::new())
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(encoded_beneficiary(tx), signer.address());
+
+        let beneficiary = Address::random();
+        let tx = bundler
+            .clone()
+            .with_beneficiary(beneficiary)
+            .create_bundle_tx(eth_client.clone(), &uos, &HashMap::new())
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(tx.to_addr(), Some(&bundler.entry_point));
+        assert_eq!(encoded_beneficiary(tx), beneficiary);
+
+        assert!(bundler
+            .with_beneficiary(Address::zero())
+            .create_bundle_tx(eth_client, &uos, &HashMap::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn bundle_tx_gas_estimation() {
+        let (eth_client, mock) = Provider::mocked();
+        let bundler = Bundler::new(
+            Arc::new(MockSigner::default()),
+            "http://127.0.0.1:8545".into(),
+            Address::random(),
+            Chain::from(1337_u64),
+        )
+        .with_gas_limit_buffer(20);
+        let uos = vec![UserOperation::default()
+            .pre_verification_gas(50_000.into())
+            .verification_gas_limit(100_000.into())
+            .call_gas_limit(200_000.into())];
+        let tx = TypedTransaction::default();
+
+        // estimated handleOps call with the buffer
+        mock.push(U256::from(300_000)).unwrap();
+        assert_eq!(
+            bundler
+                .estimate_bundle_tx_gas(&eth_client, &tx, &uos, 40_000.into())
+                .await,
+            U256::from(360_000)
+        );
+
+        // failed estimation falls back to the sum of the user operations' gas limits and the aggregation gas
+        assert_eq!(
+            bundler
+                .estimate_bundle_tx_gas(&eth_client, &tx, &uos, 40_000.into())
+                .await,
+            Bundler::estimate_bundle_gas(&uos) + U256::from(40_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn bundle_aggregated_tx() {
+        let (eth_client, mock) = Provider::mocked();
+        let bundler = Bundler::new(
+            Arc::new(MockSigner::default()),
+            "http://127.0.0.1:8545".into(),
+            Address::random(),
+            Chain::from(1337_u64),
+        );
+        let aggregator = Address::random();
+        let uos: Vec<UserOperation> = (0..3)
+            .map(|_| {
+                UserOperation::default()
+                    .sender(Address::random())
+                    .signature(vec![0x01; 65].into())
+            })
+            .collect();
+        let aggregators = HashMap::from([(uos[0].sender, aggregator), (uos[2].sender, aggregator)]);
+        let signature_0 = Bytes::from(vec![0xaa; 32]);
+        let signature = Bytes::from(vec![0xbb; 48]);
+
+        // responses are popped in reverse: validateUserOpSignature of both user operations,
+        // aggregateSignatures and the gas estimation of validateSignatures
+        mock.push(U256::from(40_000)).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(signature.clone().encode()))
+            .unwrap();
+        mock.push::<Bytes, _>(Bytes::from(Bytes::default().encode()))
+            .unwrap();
+        mock.push::<Bytes, _>(Bytes::from(signature_0.clone().encode()))
+            .unwrap();
+
+        let (tx, aggregation_gas) = bundler
+            .create_bundle_tx(Arc::new(eth_client), &uos, &aggregators)
+            .await
+            .unwrap();
+        assert_eq!(aggregation_gas, U256::from(40_000));
+
+        let groups =
+            parse_per_aggregator_from_input_data(tx.data().cloned().unwrap_or_default()).unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                UserOperationsPerAggregator {
+                    user_operations: vec![
+                        uos[0].clone().signature(signature_0),
+                        uos[2].clone().signature(Bytes::default()),
+                    ],
+                    aggregator,
+                    signature,
+                },
+                UserOperationsPerAggregator {
+                    user_operations: vec![uos[1].clone()],
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bundle_aggregator_groups() {
+        let (agg_1, agg_2) = (Address::random(), Address::random());
+        let uos: Vec<UserOperation> = (0..5)
+            .map(|_| UserOperation::default().sender(Address::random()))
+            .collect();
+        let aggregators = HashMap::from([
+            (uos[0].sender, agg_1),
+            (uos[2].sender, agg_2),
+            (uos[3].sender, agg_1),
+        ]);
+
+        let groups = Bundler::group_by_aggregator(uos.clone(), &aggregators);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].aggregator, agg_1);
+        assert_eq!(
+            groups[0].user_operations,
+            vec![uos[0].clone(), uos[3].clone()]
+        );
+        assert_eq!(groups[1].aggregator, Address::zero());
+        assert_eq!(
+            groups[1].user_operations,
+            vec![uos[1].clone(), uos[4].clone()]
+        );
+        assert_eq!(groups[2].aggregator, agg_2);
+        assert_eq!(groups[2].user_operations, vec![uos[2].clone()]);
+
+        assert_eq!(
+            Bundler::group_by_aggregator(uos.clone(), &HashMap::new())[0].user_operations,
+            uos
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use ethers::{
+    providers::{Middleware, ProviderError, RpcError},
+    types::{Bytes, H256},
+};
+use silius_primitives::bundler::{ConditionalOptions, KnownAccounts};
+use tracing::trace;
+
+/// JSON-RPC error code returned by the nodes that don't support the method
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Sends the signed bundle transaction with the storage preconditions via `eth_sendRawTransactionConditional`
+///
+/// Falls back to `eth_sendRawTransaction` if the node doesn't support the conditional method.
+pub async fn send_raw_transaction_conditional<M: Middleware>(
+    eth_client: &M,
+    raw_tx: Bytes,
+    known_accounts: &KnownAccounts,
+) -> anyhow::Result<H256> {
+    let options = ConditionalOptions {
+        known_accounts: known_accounts.clone(),
+    };
+
+    let res: Result<H256, ProviderError> = eth_client
+        .provider()
+        .request(
+            "eth_sendRawTransactionConditional",
+            (raw_tx.clone(), options),
+        )
+        .await;
+
+    match res {
+        Ok(tx_hash) => Ok(tx_hash),
+        Err(err)
+            if err
+                .as_error_response()
+                .map(|err| err.code == METHOD_NOT_FOUND)
+                .unwrap_or(false) =>
+        {
+            trace!("Conditional transactions are not supported, falling back to eth_sendRawTransaction");
+            let tx = eth_client
+                .send_raw_transaction(raw_tx)
+                .await
+                .map_err(|err| anyhow::anyhow!("Sending raw transaction failed: {err:?}"))?;
+            Ok(tx.tx_hash())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::{JsonRpcError, MockResponse, Provider};
+
+    fn method_not_found() -> MockResponse {
+        MockResponse::Error(JsonRpcError {
+            code: METHOD_NOT_FOUND,
+            message: "the method eth_sendRawTransactionConditional does not exist".into(),
+            data: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn conditional_fallback() {
+        let (eth_client, mock) = Provider::mocked();
+        let tx_hash = H256::random();
+
+        mock.push(tx_hash).unwrap();
+        assert_eq!(
+            send_raw_transaction_conditional(&eth_client, Bytes::default(), &Default::default())
+                .await
+                .unwrap(),
+            tx_hash
+        );
+
+        // falls back to eth_sendRawTransaction (responses are popped from the back)
+        mock.push(tx_hash).unwrap();
+        mock.push_response(method_not_found());
+        assert_eq!(
+            send_raw_transaction_conditional(&eth_client, Bytes::default(), &Default::default())
+                .await
+                .unwrap(),
+            tx_hash
+        );
+
+        // other errors (e.g., failed preconditions) are returned
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32003,
+            message: "storage slot value condition not met".into(),
+            data: None,
+        }));
+        assert!(send_raw_transaction_conditional(
+            &eth_client,
+            Bytes::default(),
+            &Default::default()
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,381 @@
+use ethers::{
+    providers::Middleware,
+    signers::{LocalWallet, Signer},
+    types::{Bytes, H256, U64},
+    utils::keccak256,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{info, trace, warn};
+
+/// Default number of blocks to wait for the inclusion of the bundle before falling back to the public mempool
+pub const DEFAULT_FLASHBOTS_MAX_BLOCKS: u64 = 5;
+
+/// Header carrying the signature of the request body by the searcher identity
+const FLASHBOTS_SIGNATURE_HEADER: &str = "X-Flashbots-Signature";
+
+/// Transport for the JSON-RPC requests to the private relay
+#[async_trait::async_trait]
+pub trait Relay: Send + Sync {
+    async fn request(&self, method: &str, params: Value) -> anyhow::Result<Value>;
+}
+
+/// Relay reachable over HTTP which authenticates requests with the searcher identity key
+///
+/// The searcher key only identifies the bundler to the relay (reputation) and doesn't hold any funds.
+pub struct HttpRelay {
+    url: String,
+    searcher: LocalWallet,
+    client: reqwest::Client,
+}
+
+impl HttpRelay {
+    pub fn new(url: String, searcher: LocalWallet) -> Self {
+        Self {
+            url,
+            searcher,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Value of the `X-Flashbots-Signature` header for the request body
+    async fn signature(&self, body: &str) -> anyhow::Result<String> {
+        let signature = self
+            .searcher
+            .sign_message(format!("{:?}", H256::from(keccak256(body.as_bytes()))))
+            .await?;
+        Ok(format!("{:?}:0x{signature}", self.searcher.address()))
+    }
+}
+
+#[async_trait::async_trait]
+impl Relay for HttpRelay {
+    async fn request(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+
+        let res: Value = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header(FLASHBOTS_SIGNATURE_HEADER, self.signature(&body).await?)
+            .body(body)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(err) = res.get("error") {
+            return Err(anyhow::anyhow!("Relay request {method} failed: {err}"));
+        }
+
+        res.get("result")
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Relay response to {method} has no result"))
+    }
+}
+
+/// Submits bundle transactions privately to a Flashbots-compatible relay
+///
+/// The bundle is submitted for each of the next `max_blocks` blocks at once and its inclusion is tracked in the
+/// background. If it isn't included by then (or the relay rejects it), the transaction is sent to the public
+/// mempool.
+#[derive(Clone)]
+pub struct FlashbotsClient {
+    relay: Arc<dyn Relay>,
+    max_blocks: u64,
+    poll_interval: Duration,
+    pending: Arc<AtomicBool>,
+}
+
+impl FlashbotsClient {
+    pub fn new(relay: Arc<dyn Relay>, max_blocks: u64) -> Self {
+        Self {
+            relay,
+            max_blocks,
+            poll_interval: Duration::from_secs(1),
+            pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Interval of polling the execution client for new blocks
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Whether the inclusion of the last bundle is still tracked (the next bundle would reuse its nonce)
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Sends the signed bundle transaction via `eth_sendBundle` for the next `max_blocks` blocks
+    ///
+    /// Returns without waiting for the inclusion, which is tracked in the background (see
+    /// [is_pending](Self::is_pending)). Falls back to `eth_sendRawTransaction` if the relay fails or the bundle
+    /// isn't included in time.
+    pub async fn send_bundle<M: Middleware + 'static>(
+        &self,
+        eth_client: Arc<M>,
+        raw_tx: Bytes,
+    ) -> anyhow::Result<H256> {
+        let tx_hash = H256::from(keccak256(&raw_tx));
+
+        let bundles = match self.submit_bundle(eth_client.as_ref(), &raw_tx).await {
+            Ok(bundles) => bundles,
+            Err(err) => {
+                warn!("Submitting the bundle to the relay failed, sending the transaction to the public mempool: {err:?}");
+                return Self::send_public(eth_client.as_ref(), raw_tx).await;
+            }
+        };
+
+        self.pending.store(true, Ordering::SeqCst);
+        let client = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = client
+                .track_bundle(eth_client.as_ref(), raw_tx, tx_hash, bundles)
+                .await
+            {
+                warn!("Tracking the inclusion of the bundle {tx_hash:?} failed: {err:?}");
+            }
+            client.pending.store(false, Ordering::SeqCst);
+        });
+
+        Ok(tx_hash)
+    }
+
+    /// Submits the bundle for each of the next `max_blocks` blocks, returns the bundle hashes with the target
+    /// blocks
+    async fn submit_bundle<M: Middleware + 'static>(
+        &self,
+        eth_client: &M,
+        raw_tx: &Bytes,
+    ) -> anyhow::Result<Vec<(Value, U64)>> {
+        let block_number = eth_client.get_block_number().await?;
+        let mut bundles = vec![];
+
+        for target in 1..=self.max_blocks {
+            let target = block_number + U64::from(target);
+
+            let res = self
+                .relay
+                .request(
+                    "eth_sendBundle",
+                    json!([{ "txs": [raw_tx], "blockNumber": target }]),
+                )
+                .await?;
+            let bundle_hash = res.get("bundleHash").cloned().unwrap_or_default();
+            trace!("Bundle {bundle_hash} submitted to the relay for block {target}");
+            bundles.push((bundle_hash, target));
+        }
+
+        Ok(bundles)
+    }
+
+    /// Waits for the target blocks of the submitted bundle, sends the transaction to the public mempool if the
+    /// bundle isn't included in any of them
+    async fn track_bundle<M: Middleware + 'static>(
+        &self,
+        eth_client: &M,
+        raw_tx: Bytes,
+        tx_hash: H256,
+        bundles: Vec<(Value, U64)>,
+    ) -> anyhow::Result<()> {
+        for (bundle_hash, target) in bundles {
+            while eth_client.get_block_number().await? < target {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+
+            match self
+                .relay
+                .request(
+                    "flashbots_getBundleStats",
+                    json!([{ "bundleHash": bundle_hash, "blockNumber": target }]),
+                )
+                .await
+            {
+                Ok(stats) => trace!("Bundle {bundle_hash} stats: {stats}"),
+                Err(err) => trace!("Getting stats of the bundle {bundle_hash} failed: {err:?}"),
+            }
+
+            if eth_client.get_transaction_receipt(tx_hash).await?.is_some() {
+                info!("Bundle {bundle_hash} included in block {target}");
+                return Ok(());
+            }
+        }
+
+        warn!(
+            "Bundle not included after {} blocks, sending the transaction to the public mempool",
+            self.max_blocks
+        );
+        Self::send_public(eth_client, raw_tx).await?;
+        Ok(())
+    }
+
+    async fn send_public<M: Middleware + 'static>(
+        eth_client: &M,
+        raw_tx: Bytes,
+    ) -> anyhow::Result<H256> {
+        let tx = eth_client
+            .send_raw_transaction(raw_tx)
+            .await
+            .map_err(|err| anyhow::anyhow!("Sending raw transaction failed: {err:?}"))?;
+        Ok(tx.tx_hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{providers::Provider, types::TransactionReceipt};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockRelay {
+        requests: Mutex<Vec<(String, Value)>>,
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Relay for MockRelay {
+        async fn request(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((method.to_string(), params));
+            if self.fail {
+                return Err(anyhow::anyhow!("Relay request {method} failed"));
+            }
+            match method {
+                "eth_sendBundle" => Ok(json!({ "bundleHash": format!("{:?}", H256::zero()) })),
+                _ => Ok(json!({ "isSimulated": true })),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn flashbots_bundle_inclusion() {
+        let relay = Arc::new(MockRelay::default());
+        let client =
+            FlashbotsClient::new(relay.clone(), 2).with_poll_interval(Duration::from_millis(1));
+        let (eth_client, mock) = Provider::mocked();
+        let raw_tx = Bytes::from(vec![1, 2, 3]);
+        let tx_hash = H256::from(keccak256(&raw_tx));
+
+        // submitted for both target blocks at once
+        mock.push(U64::from(100)).unwrap();
+        let bundles = client.submit_bundle(&eth_client, &raw_tx).await.unwrap();
+        assert_eq!(
+            bundles
+                .iter()
+                .map(|(_, target)| *target)
+                .collect::<Vec<_>>(),
+            vec![U64::from(101), U64::from(102)]
+        );
+        {
+            let requests = relay.requests.lock().unwrap();
+            assert_eq!(requests.len(), 2);
+            assert_eq!(requests[0].0, "eth_sendBundle");
+            assert_eq!(requests[0].1[0]["blockNumber"], json!("0x65"));
+            assert_eq!(requests[1].1[0]["blockNumber"], json!("0x66"));
+        }
+
+        // included in the first target block (responses are popped from the back)
+        relay.requests.lock().unwrap().clear();
+        mock.push(Some(TransactionReceipt::default())).unwrap();
+        mock.push(U64::from(101)).unwrap();
+        client
+            .track_bundle(&eth_client, raw_tx.clone(), tx_hash, bundles.clone())
+            .await
+            .unwrap();
+        {
+            let requests = relay.requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            assert_eq!(requests[0].0, "flashbots_getBundleStats");
+        }
+
+        // not included, falls back to the public mempool
+        relay.requests.lock().unwrap().clear();
+        mock.push(H256::random()).unwrap();
+        mock.push::<Option<TransactionReceipt>, _>(None).unwrap();
+        mock.push(U64::from(102)).unwrap();
+        mock.push::<Option<TransactionReceipt>, _>(None).unwrap();
+        mock.push(U64::from(101)).unwrap();
+        client
+            .track_bundle(&eth_client, raw_tx, tx_hash, bundles)
+            .await
+            .unwrap();
+        assert_eq!(relay.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn flashbots_send_bundle() {
+        let raw_tx = Bytes::from(vec![1, 2, 3]);
+
+        // returns without waiting for the inclusion
+        let (eth_client, mock) = Provider::mocked();
+        let client = FlashbotsClient::new(Arc::new(MockRelay::default()), 2);
+        mock.push(U64::from(100)).unwrap();
+        assert_eq!(
+            client
+                .send_bundle(Arc::new(eth_client), raw_tx.clone())
+                .await
+                .unwrap(),
+            H256::from(keccak256(&raw_tx))
+        );
+        assert!(client.is_pending());
+
+        // relay failure falls back to the public mempool right away
+        let (eth_client, mock) = Provider::mocked();
+        let relay = Arc::new(MockRelay {
+            fail: true,
+            ..Default::default()
+        });
+        let client = FlashbotsClient::new(relay.clone(), 2);
+        let tx_hash = H256::random();
+        mock.push(tx_hash).unwrap();
+        mock.push(U64::from(100)).unwrap();
+        assert_eq!(
+            client
+                .send_bundle(Arc::new(eth_client), raw_tx)
+                .await
+                .unwrap(),
+            tx_hash
+        );
+        assert!(!client.is_pending());
+        assert_eq!(relay.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn flashbots_signature_header() {
+        let searcher: LocalWallet =
+            "0000000000000000000000000000000000000000000000000000000000000001"
+                .parse()
+                .unwrap();
+        let relay = HttpRelay::new("http://127.0.0.1:0".into(), searcher.clone());
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"eth_sendBundle","params":[]}"#;
+
+        let header = relay.signature(body).await.unwrap();
+        let (address, signature) = header.split_once(':').unwrap();
+        assert_eq!(address, format!("{:?}", searcher.address()));
+
+        let signature: ethers::types::Signature = signature.parse().unwrap();
+        signature
+            .verify(
+                format!("{:?}", H256::from(keccak256(body.as_bytes()))),
+                searcher.address(),
+            )
+            .unwrap();
+    }
+}
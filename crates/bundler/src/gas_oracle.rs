@@ -0,0 +1,172 @@
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, U256},
+};
+
+/// Default number of latest blocks the priority fee is estimated from
+pub const DEFAULT_FEE_HISTORY_BLOCKS: u64 = 10;
+/// Default percentile of priority fees paid in each block
+pub const DEFAULT_PRIORITY_FEE_PERCENTILE: f64 = 50.0;
+/// Default buffer (in percents) added to the base fee, which covers two blocks of the maximum base fee increase
+pub const DEFAULT_BASE_FEE_BUFFER_PERC: u64 = 25;
+
+/// Estimates `max_fee_per_gas` and `max_priority_fee_per_gas` of the bundle transaction
+///
+/// The priority fee is the median of the configured percentile of priority fees paid in the latest blocks
+/// (`eth_feeHistory`), and the max fee is the base fee of the next block increased by the buffer plus the
+/// priority fee (at least the min priority fee of the chain). On chains with a fixed gas price, the fixed gas price
+/// is used for both.
+#[derive(Clone, Debug)]
+pub struct GasOracle {
+    pub blocks: u64,
+    pub priority_fee_percentile: f64,
+    pub base_fee_buffer_perc: u64,
+    pub fixed_gas_price: Option<U256>,
+    pub min_priority_fee_per_gas: U256,
+}
+
+impl Default for GasOracle {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_PRIORITY_FEE_PERCENTILE,
+            DEFAULT_BASE_FEE_BUFFER_PERC,
+        )
+    }
+}
+
+impl GasOracle {
+    pub fn new(priority_fee_percentile: f64, base_fee_buffer_perc: u64) -> Self {
+        Self {
+            blocks: DEFAULT_FEE_HISTORY_BLOCKS,
+            priority_fee_percentile,
+            base_fee_buffer_perc,
+            fixed_gas_price: None,
+            min_priority_fee_per_gas: U256::zero(),
+        }
+    }
+
+    /// Number of latest blocks the priority fee is estimated from
+    pub fn with_blocks(mut self, blocks: u64) -> Self {
+        self.blocks = blocks;
+        self
+    }
+
+    /// Uses the fixed gas price instead of the fee history (chains without EIP-1559 or with a constant gas price)
+    pub fn with_fixed_gas_price(mut self, fixed_gas_price: U256) -> Self {
+        self.fixed_gas_price = Some(fixed_gas_price);
+        self
+    }
+
+    /// Min priority fee accepted by the chain (e.g., 30 gwei on Polygon)
+    pub fn with_min_priority_fee_per_gas(mut self, min_priority_fee_per_gas: U256) -> Self {
+        self.min_priority_fee_per_gas = min_priority_fee_per_gas;
+        self
+    }
+
+    /// Returns the current estimate of (`max_fee_per_gas`, `max_priority_fee_per_gas`)
+    pub async fn estimate<M: Middleware + 'static>(
+        &self,
+        eth_client: &M,
+    ) -> anyhow::Result<(U256, U256)> {
+        if let Some(gas_price) = self.fixed_gas_price {
+            return Ok((gas_price, gas_price));
+        }
+
+        let fee_history = eth_client
+            .fee_history(
+                self.blocks,
+                BlockNumber::Latest,
+                &[self.priority_fee_percentile],
+            )
+            .await
+            .map_err(|err| anyhow::anyhow!("Getting fee history failed: {err:?}"))?;
+
+        // the last base fee is the base fee of the next block
+        let base_fee = fee_history
+            .base_fee_per_gas
+            .last()
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Fee history has no base fee"))?;
+
+        let mut rewards: Vec<U256> = fee_history
+            .reward
+            .iter()
+            .filter_map(|reward| reward.first().copied())
+            .collect();
+        rewards.sort();
+        let priority_fee = rewards
+            .get(rewards.len() / 2)
+            .copied()
+            .unwrap_or_default()
+            .max(self.min_priority_fee_per_gas);
+
+        let max_fee = base_fee
+            .saturating_mul(U256::from(100 + self.base_fee_buffer_perc))
+            .checked_div(U256::from(100))
+            .unwrap_or_default()
+            .saturating_add(priority_fee);
+
+        Ok((max_fee, priority_fee))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{providers::Provider, types::FeeHistory};
+
+    #[tokio::test]
+    async fn gas_oracle_estimate() {
+        let (eth_client, mock) = Provider::mocked();
+
+        mock.push(FeeHistory {
+            base_fee_per_gas: vec![90.into(), 95.into(), 100.into(), 100.into()],
+            gas_used_ratio: vec![0.5, 0.6, 0.5],
+            oldest_block: 100.into(),
+            reward: vec![vec![3.into()], vec![1.into()], vec![2.into()]],
+        })
+        .unwrap();
+        assert_eq!(
+            GasOracle::default().estimate(&eth_client).await.unwrap(),
+            (U256::from(127), U256::from(2))
+        );
+
+        mock.push(FeeHistory {
+            base_fee_per_gas: vec![100.into()],
+            gas_used_ratio: vec![],
+            oldest_block: 100.into(),
+            reward: vec![],
+        })
+        .unwrap();
+        assert_eq!(
+            GasOracle::new(50.0, 0).estimate(&eth_client).await.unwrap(),
+            (U256::from(100), U256::zero())
+        );
+
+        // priority fee raised to the min priority fee of the chain
+        mock.push(FeeHistory {
+            base_fee_per_gas: vec![100.into()],
+            gas_used_ratio: vec![],
+            oldest_block: 100.into(),
+            reward: vec![vec![1.into()]],
+        })
+        .unwrap();
+        assert_eq!(
+            GasOracle::new(50.0, 0)
+                .with_min_priority_fee_per_gas(30.into())
+                .estimate(&eth_client)
+                .await
+                .unwrap(),
+            (U256::from(130), U256::from(30))
+        );
+
+        assert_eq!(
+            GasOracle::default()
+                .with_fixed_gas_price(1_000.into())
+                .estimate(&eth_client)
+                .await
+                .unwrap(),
+            (U256::from(1_000), U256::from(1_000))
+        );
+    }
+}
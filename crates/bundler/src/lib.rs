@@ -1,5 +1,23 @@
 #![allow(dead_code)]
 
 mod bundler;
+mod conditional;
+mod flashbots;
+mod gas_oracle;
+mod signer;
+mod watcher;
 
-pub use bundler::Bundler;
+pub use bundler::{
+    BundleSubmission, Bundler, PreparedBundle, DEFAULT_BUNDLE_GAS_LIMIT_BUFFER_PERC,
+};
+pub use flashbots::{FlashbotsClient, HttpRelay, Relay, DEFAULT_FLASHBOTS_MAX_BLOCKS};
+pub use gas_oracle::{
+    GasOracle, DEFAULT_BASE_FEE_BUFFER_PERC, DEFAULT_FEE_HISTORY_BLOCKS,
+    DEFAULT_PRIORITY_FEE_PERCENTILE,
+};
+#[cfg(feature = "aws-kms")]
+pub use signer::AwsKmsSigner;
+pub use signer::{BundlerSigner, LocalSigner};
+pub use watcher::{
+    block_watcher, BlockWatcher, PollingWatcher, PubSubWatcher, DEFAULT_BLOCK_POLL_INTERVAL,
+};
@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::{transaction::eip2718::TypedTransaction, Address, Signature},
+};
+
+/// Signer of the bundle transactions (the bundler's EOA)
+#[async_trait]
+pub trait BundlerSigner: Send + Sync {
+    /// Address of the EOA sending the bundle transactions
+    fn address(&self) -> Address;
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> anyhow::Result<Signature>;
+}
+
+/// Signer with the private key held in memory
+#[derive(Clone, Debug)]
+pub struct LocalSigner {
+    wallet: LocalWallet,
+}
+
+impl LocalSigner {
+    pub fn new(wallet: LocalWallet) -> Self {
+        Self { wallet }
+    }
+}
+
+#[async_trait]
+impl BundlerSigner for LocalSigner {
+    fn address(&self) -> Address {
+        self.wallet.address()
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> anyhow::Result<Signature> {
+        Ok(self.wallet.sign_transaction(tx).await?)
+    }
+}
+
+/// Signer with the private key held in AWS KMS (the key never leaves KMS)
+#[cfg(feature = "aws-kms")]
+#[derive(Debug)]
+pub struct AwsKmsSigner {
+    signer: ethers::signers::AwsSigner,
+}
+
+#[cfg(feature = "aws-kms")]
+impl AwsKmsSigner {
+    /// Creates the signer of the KMS key, the region and credentials are taken from the environment
+    pub async fn new(key_id: &str, chain_id: u64) -> anyhow::Result<Self> {
+        Self::from_client(
+            rusoto_kms::KmsClient::new(rusoto_core::Region::default()),
+            key_id,
+            chain_id,
+        )
+        .await
+    }
+
+    /// Creates the signer of the KMS key with the given KMS client (fetches the public key of the key)
+    pub async fn from_client(
+        kms: rusoto_kms::KmsClient,
+        key_id: &str,
+        chain_id: u64,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            signer: ethers::signers::AwsSigner::new(kms, key_id, chain_id).await?,
+        })
+    }
+}
+
+#[cfg(feature = "aws-kms")]
+#[async_trait]
+impl BundlerSigner for AwsKmsSigner {
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> anyhow::Result<Signature> {
+        Ok(self.signer.sign_transaction(tx).await?)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ethers::types::{TransactionRequest, U256};
+    use std::sync::Mutex;
+
+    /// Signer recording the transactions it was asked to sign
+    pub struct MockSigner {
+        pub signer: LocalSigner,
+        pub signed: Mutex<Vec<TypedTransaction>>,
+    }
+
+    impl Default for MockSigner {
+        fn default() -> Self {
+            Self {
+                signer: LocalSigner::new(LocalWallet::new(&mut ethers::core::rand::thread_rng())),
+                signed: Mutex::new(vec![]),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl BundlerSigner for MockSigner {
+        fn address(&self) -> Address {
+            self.signer.address()
+        }
+
+        async fn sign_transaction(&self, tx: &TypedTransaction) -> anyhow::Result<Signature> {
+            self.signed.lock().unwrap().push(tx.clone());
+            self.signer.sign_transaction(tx).await
+        }
+    }
+
+    #[tokio::test]
+    async fn local_signer() {
+        let wallet = LocalWallet::new(&mut ethers::core::rand::thread_rng());
+        let signer = LocalSigner::new(wallet.clone());
+        assert_eq!(signer.address(), wallet.address());
+
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(Address::random())
+            .value(U256::from(1))
+            .nonce(0)
+            .chain_id(1337)
+            .into();
+        let signature = signer.sign_transaction(&tx).await.unwrap();
+        assert_eq!(signature.recover(tx.sighash()).unwrap(), wallet.address());
+    }
+}
@@ -0,0 +1,165 @@
+use ethers::{
+    providers::{Http, Middleware, Provider, StreamExt, Ws},
+    types::U64,
+};
+use silius_primitives::bundler::BlockWatcherMode;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{broadcast, oneshot};
+use tracing::{info, warn};
+
+/// Default interval of polling the execution client for new blocks (in milliseconds)
+pub const DEFAULT_BLOCK_POLL_INTERVAL: u64 = 1000;
+
+/// Capacity of the channel of new blocks (subscribers lagging behind skip blocks)
+const BLOCKS_CHANNEL_CAPACITY: usize = 16;
+
+/// Source of new blocks (block numbers of the new heads of the chain)
+pub trait BlockWatcher: Send + Sync {
+    fn subscribe(&self) -> broadcast::Receiver<U64>;
+}
+
+/// Watches new blocks through `eth_subscribe` to `newHeads` (requires WebSocket endpoint)
+pub struct PubSubWatcher {
+    blocks: broadcast::Sender<U64>,
+}
+
+impl PubSubWatcher {
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let eth_client = Provider::<Ws>::connect(url).await?;
+        let (blocks, _) = broadcast::channel(BLOCKS_CHANNEL_CAPACITY);
+        // the result of the subscription is reported back, so the node not supporting it is detected here
+        let (subscribed, res) = oneshot::channel();
+
+        let tx = blocks.clone();
+        tokio::spawn(async move {
+            let mut stream = match eth_client.subscribe_blocks().await {
+                Ok(stream) => {
+                    let _ = subscribed.send(Ok(()));
+                    stream
+                }
+                Err(err) => {
+                    let _ = subscribed.send(Err(err));
+                    return;
+                }
+            };
+
+            while let Some(block) = stream.next().await {
+                if let Some(number) = block.number {
+                    let _ = tx.send(number);
+                }
+            }
+            warn!("Subscription to new blocks ended");
+        });
+
+        res.await??;
+        Ok(Self { blocks })
+    }
+}
+
+impl BlockWatcher for PubSubWatcher {
+    fn subscribe(&self) -> broadcast::Receiver<U64> {
+        self.blocks.subscribe()
+    }
+}
+
+/// Watches new blocks by polling `eth_blockNumber` at the interval
+pub struct PollingWatcher {
+    blocks: broadcast::Sender<U64>,
+}
+
+impl PollingWatcher {
+    pub fn new<M: Middleware + 'static>(eth_client: Arc<M>, interval: Duration) -> Self {
+        let (blocks, _) = broadcast::channel(BLOCKS_CHANNEL_CAPACITY);
+
+        let tx = blocks.clone();
+        tokio::spawn(async move {
+            let mut last: Option<U64> = None;
+            loop {
+                match eth_client.get_block_number().await {
+                    Ok(number) if last.map_or(true, |last| number > last) => {
+                        last = Some(number);
+                        let _ = tx.send(number);
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!("Polling the block number failed: {err:?}"),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+
+        Self { blocks }
+    }
+}
+
+impl BlockWatcher for PollingWatcher {
+    fn subscribe(&self) -> broadcast::Receiver<U64> {
+        self.blocks.subscribe()
+    }
+}
+
+fn is_ws(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// Creates the block watcher of the execution client
+///
+/// In the auto mode the subscription is used when the endpoint supports it (WebSocket), falling back to polling.
+pub async fn block_watcher(
+    mode: BlockWatcherMode,
+    eth_client_address: &str,
+    poll_interval: Duration,
+) -> anyhow::Result<Arc<dyn BlockWatcher>> {
+    let subscribe = match mode {
+        BlockWatcherMode::Auto => is_ws(eth_client_address),
+        BlockWatcherMode::Subscription => true,
+        BlockWatcherMode::Polling => false,
+    };
+
+    if subscribe {
+        match PubSubWatcher::connect(eth_client_address).await {
+            Ok(watcher) => {
+                info!("Watching new blocks through subscription");
+                return Ok(Arc::new(watcher));
+            }
+            Err(err) if mode == BlockWatcherMode::Auto => {
+                warn!("Subscription to new blocks failed, falling back to polling: {err:?}")
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    info!("Watching new blocks by polling every {poll_interval:?}");
+    Ok(if is_ws(eth_client_address) {
+        Arc::new(PollingWatcher::new(
+            Arc::new(Provider::<Ws>::connect(eth_client_address).await?),
+            poll_interval,
+        ))
+    } else {
+        Arc::new(PollingWatcher::new(
+            Arc::new(Provider::<Http>::try_from(eth_client_address)?),
+            poll_interval,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn polling_watcher() {
+        let (eth_client, mock) = Provider::mocked();
+        // responses are popped from the back
+        mock.push(U64::from(2)).unwrap();
+        mock.push(U64::from(1)).unwrap();
+        mock.push(U64::from(1)).unwrap();
+
+        let watcher = PollingWatcher::new(Arc::new(eth_client), Duration::from_millis(1));
+        let mut blocks = watcher.subscribe();
+
+        // the same block polled twice is sent once
+        assert_eq!(blocks.recv().await.unwrap(), U64::from(1));
+        assert_eq!(blocks.recv().await.unwrap(), U64::from(2));
+        assert!(blocks.try_recv().is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::entry_point::EntryPointErr;
+use ethers::{
+    abi::{self, AbiDecode, Tokenizable},
+    providers::Middleware,
+    types::{Address, Bytes, TransactionRequest, U256},
+    utils::id,
+};
+use silius_primitives::UserOperation;
+use std::sync::Arc;
+
+/// ABI type of the user operation (entry point v0.6)
+const USER_OPERATION_TYPE: &str =
+    "(address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)";
+
+/// Signature aggregator (`IAggregator`) used by accounts with aggregated signatures (e.g., BLS)
+#[derive(Clone)]
+pub struct Aggregator<M: Middleware + 'static> {
+    eth_client: Arc<M>,
+    address: Address,
+}
+
+impl<M: Middleware + 'static> Aggregator<M> {
+    pub fn new(eth_client: Arc<M>, address: Address) -> Self {
+        Self {
+            eth_client,
+            address,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn call(&self, data: Vec<u8>) -> Result<Bytes, EntryPointErr> {
+        let res = self
+            .eth_client
+            .call(
+                &TransactionRequest::new().to(self.address).data(data).into(),
+                None,
+            )
+            .await
+            .map_err(|e| EntryPointErr::from_middleware_err::<M>(e))?;
+
+        Bytes::decode(res).map_err(|e| {
+            EntryPointErr::DecodeErr(format!("Aggregator returned invalid bytes: {e:?}"))
+        })
+    }
+
+    /// Validates the signature of a single user operation
+    ///
+    /// Returns the value to put into the signature field of the user operation in the bundle.
+    pub async fn validate_user_op_signature(
+        &self,
+        uo: UserOperation,
+    ) -> Result<Bytes, EntryPointErr> {
+        let data = [
+            id(format!("validateUserOpSignature({USER_OPERATION_TYPE})")).to_vec(),
+            abi::encode(&[uo.into_token()]),
+        ]
+        .concat();
+        self.call(data).await
+    }
+
+    /// Aggregates signatures of the user operations into the signature of the whole group
+    pub async fn aggregate_signatures(
+        &self,
+        uos: Vec<UserOperation>,
+    ) -> Result<Bytes, EntryPointErr> {
+        let data = [
+            id(format!("aggregateSignatures({USER_OPERATION_TYPE}[])")).to_vec(),
+            abi::encode(&[uos.into_token()]),
+        ]
+        .concat();
+        self.call(data).await
+    }
+
+    /// Estimates the gas of `validateSignatures` the entry point calls for the group of user operations
+    /// aggregated into the signature
+    pub async fn estimate_validate_signatures_gas(
+        &self,
+        entry_point: Address,
+        uos: Vec<UserOperation>,
+        signature: Bytes,
+    ) -> Result<U256, EntryPointErr> {
+        let data = [
+            id(format!("validateSignatures({USER_OPERATION_TYPE}[],bytes)")).to_vec(),
+            abi::encode(&[uos.into_token(), signature.into_token()]),
+        ]
+        .concat();
+        self.eth_client
+            .estimate_gas(
+                &TransactionRequest::new()
+                    .from(entry_point)
+                    .to(self.address)
+                    .data(data)
+                    .into(),
+                None,
+            )
+            .await
+            .map_err(|e| EntryPointErr::from_middleware_err::<M>(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{abi::AbiEncode, providers::Provider};
+
+    #[tokio::test]
+    async fn aggregator_calls() {
+        let (eth_client, mock) = Provider::mocked();
+        let aggregator = Aggregator::new(Arc::new(eth_client), Address::random());
+        let signature = Bytes::from(vec![0xaa; 48]);
+
+        mock.push::<Bytes, _>(Bytes::from(signature.clone().encode()))
+            .unwrap();
+        assert_eq!(
+            aggregator
+                .aggregate_signatures(vec![UserOperation::default(), UserOperation::default()])
+                .await
+                .unwrap(),
+            signature
+        );
+
+        mock.push::<Bytes, _>(Bytes::from(Bytes::default().encode()))
+            .unwrap();
+        assert_eq!(
+            aggregator
+                .validate_user_op_signature(UserOperation::default())
+                .await
+                .unwrap(),
+            Bytes::default()
+        );
+
+        mock.push(U256::from(40_000)).unwrap();
+        assert_eq!(
+            aggregator
+                .estimate_validate_signatures_gas(
+                    Address::random(),
+                    vec![UserOperation::default()],
+                    signature
+                )
+                .await
+                .unwrap(),
+            U256::from(40_000)
+        );
+    }
+}
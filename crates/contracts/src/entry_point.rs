@@ -4,35 +4,90 @@ use super::gen::entry_point_api::{
 };
 use super::gen::stake_manager_api::DepositInfo;
 pub use super::gen::{
-    EntryPointAPI, EntryPointAPIEvents, StakeManagerAPI, UserOperationEventFilter,
-    ValidatePaymasterUserOpReturn, CONTRACTS_FUNCTIONS,
+    BeforeExecutionFilter, EntryPointAPI, EntryPointAPIEvents, StakeManagerAPI,
+    UserOperationEventFilter, UserOperationRevertReasonFilter, ValidatePaymasterUserOpReturn,
+    CONTRACTS_FUNCTIONS,
 };
 use super::tracer::JS_TRACER;
 use crate::gen::ExecutionResult;
-use ethers::abi::AbiDecode;
-use ethers::prelude::{ContractError, Event};
-use ethers::providers::{Middleware, ProviderError};
+use ethers::abi::{AbiDecode, Detokenize};
+use ethers::prelude::{ContractCall, ContractError, Event};
+use ethers::providers::{call_raw::RawCall, Middleware, ProviderError};
 use ethers::types::{
-    Address, Bytes, GethDebugTracerType, GethDebugTracingCallOptions, GethDebugTracingOptions,
-    GethTrace, TransactionRequest, U256,
+    spoof, transaction::eip2718::TypedTransaction, Address, BlockId, Bytes,
+    GethDebugBuiltInTracerType, GethDebugTracerType, GethDebugTracingCallOptions,
+    GethDebugTracingOptions, GethTrace, TransactionRequest, U256,
 };
 use ethers_providers::{JsonRpcError, MiddlewareError};
+use silius_primitives::simulation::{
+    AggregatorStakeInfo, EntityStakeInfo, ReturnInfo, ValidationResultInfo,
+};
 use std::fmt::Display;
 use std::sync::Arc;
 use thiserror::Error;
 
+/// Lower bound of the call gas binary search (intrinsic gas of the transaction)
+const CALL_GAS_ESTIMATION_MIN: u64 = 21_000;
+/// The binary search stops once the bounds are closer than this
+const CALL_GAS_ESTIMATION_TOLERANCE: u64 = 1_000;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SimulateValidationResult {
     ValidationResult(ValidationResult),
     ValidationResultWithAggregation(ValidationResultWithAggregation),
 }
 
+impl From<&SimulateValidationResult> for ValidationResultInfo {
+    fn from(res: &SimulateValidationResult) -> Self {
+        let (return_info, sender_info, factory_info, paymaster_info, aggregator_info) = match res {
+            SimulateValidationResult::ValidationResult(res) => (
+                res.return_info.clone(),
+                res.sender_info,
+                res.factory_info,
+                res.paymaster_info,
+                None,
+            ),
+            SimulateValidationResult::ValidationResultWithAggregation(res) => (
+                res.return_info.clone(),
+                res.sender_info,
+                res.factory_info,
+                res.paymaster_info,
+                Some(res.aggregator_info),
+            ),
+        };
+        let stake_info = |(stake, unstake_delay)| EntityStakeInfo {
+            stake,
+            unstake_delay,
+        };
+
+        Self {
+            return_info: ReturnInfo {
+                pre_op_gas: return_info.0,
+                prefund: return_info.1,
+                sig_failed: return_info.2,
+                valid_after: return_info.3,
+                valid_until: return_info.4,
+                paymaster_context: return_info.5,
+            },
+            sender_info: stake_info(sender_info),
+            factory_info: stake_info(factory_info),
+            paymaster_info: stake_info(paymaster_info),
+            aggregator_info: aggregator_info.map(|(aggregator, info)| AggregatorStakeInfo {
+                aggregator,
+                stake_info: stake_info(info),
+            }),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct EntryPoint<M: Middleware + 'static> {
     eth_client: Arc<M>,
     address: Address,
     entry_point_api: EntryPointAPI<M>,
     stake_manager_api: StakeManagerAPI<M>,
+    block: Option<BlockId>,
+    state_override: Option<spoof::State>,
 }
 
 impl<M: Middleware + 'static> EntryPoint<M> {
@@ -44,9 +99,24 @@ impl<M: Middleware + 'static> EntryPoint<M> {
             address,
             entry_point_api,
             stake_manager_api,
+            block: None,
+            state_override: None,
         }
     }
 
+    /// Block (or block tag, e.g. `pending`) the simulations (`eth_call` and `debug_traceCall`) run against,
+    /// the node's default block if not set
+    pub fn with_block(mut self, block: BlockId) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// State overrides applied to the simulations (`eth_call` and `debug_traceCall`)
+    pub fn with_state_override(mut self, state_override: spoof::State) -> Self {
+        self.state_override = Some(state_override);
+        self
+    }
+
     pub fn entry_point_api(&self) -> &EntryPointAPI<M> {
         &self.entry_point_api
     }
@@ -63,6 +133,37 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         self.address
     }
 
+    /// Calls the contract against the simulation block with the state overrides
+    async fn call<D: Detokenize>(&self, call: ContractCall<M, D>) -> Result<D, ContractError<M>> {
+        let call = match self.block {
+            Some(block) => call.block(block),
+            None => call,
+        };
+        match &self.state_override {
+            Some(state) => call.call_raw().state(state).await,
+            None => call.call().await,
+        }
+    }
+
+    /// `eth_call` of the transaction against the simulation block with the state overrides
+    async fn call_tx(&self, tx: &TypedTransaction) -> Result<Bytes, EntryPointErr> {
+        match &self.state_override {
+            Some(state) => {
+                let call = self.eth_client.provider().call_raw(tx).state(state);
+                match self.block {
+                    Some(block) => call.block(block).await,
+                    None => call.await,
+                }
+                .map_err(EntryPointErr::from)
+            }
+            None => self
+                .eth_client
+                .call(tx, self.block)
+                .await
+                .map_err(|e| EntryPointErr::from_middleware_err::<M>(e)),
+        }
+    }
+
     fn deserialize_error_msg(
         err_msg: ContractError<M>,
     ) -> Result<EntryPointAPIErrors, EntryPointErr> {
@@ -90,7 +191,9 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         &self,
         uo: U,
     ) -> Result<SimulateValidationResult, EntryPointErr> {
-        let res = self.entry_point_api.simulate_validation(uo.into()).await;
+        let res = self
+            .call(self.entry_point_api.simulate_validation(uo.into()))
+            .await;
 
         match res {
             Ok(_) => Err(EntryPointErr::UnknownErr(
@@ -111,6 +214,30 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         }
     }
 
+    /// Calls `simulateValidation` and returns the raw revert data
+    pub async fn simulate_validation_revert_data<U: Into<UserOperation>>(
+        &self,
+        uo: U,
+    ) -> Result<Bytes, EntryPointErr> {
+        let res = self
+            .call(self.entry_point_api.simulate_validation(uo.into()))
+            .await;
+
+        match res {
+            Ok(_) => Err(EntryPointErr::UnknownErr(
+                "Simulate validation should expect revert".to_string(),
+            )),
+            Err(ContractError::Revert(data)) => Ok(data),
+            Err(e) => {
+                Err(Self::deserialize_error_msg(e)
+                    .err()
+                    .unwrap_or(EntryPointErr::UnknownErr(
+                        "Simulate validation with unexpected error".to_string(),
+                    )))
+            }
+        }
+    }
+
     pub async fn simulate_validation_trace<U: Into<UserOperation>>(
         &self,
         uo: U,
@@ -119,28 +246,99 @@ impl<M: Middleware + 'static> EntryPoint<M> {
 
         let res = self
             .eth_client
-            .debug_trace_call(
-                call.tx,
-                None,
-                GethDebugTracingCallOptions {
-                    tracing_options: GethDebugTracingOptions {
-                        disable_storage: None,
-                        disable_stack: None,
-                        enable_memory: None,
-                        enable_return_data: None,
-                        tracer: Some(GethDebugTracerType::JsTracer(JS_TRACER.to_string())),
-                        tracer_config: None,
-                        timeout: None,
-                    },
-                    state_overrides: None,
-                },
-            )
+            .debug_trace_call(call.tx, self.block, self.js_tracer_options())
             .await
             .map_err(|e| EntryPointErr::from_middleware_err::<M>(e))?;
 
         Ok(res)
     }
 
+    /// Traces `simulateValidation` with the native `callTracer` and `prestateTracer` (requested concurrently)
+    ///
+    /// Returns the call trace and the prestate trace.
+    pub async fn simulate_validation_native_trace<U: Into<UserOperation>>(
+        &self,
+        uo: U,
+    ) -> Result<(GethTrace, GethTrace), EntryPointErr> {
+        let call = self.entry_point_api.simulate_validation(uo.into());
+
+        tokio::try_join!(
+            self.eth_client.debug_trace_call(
+                call.tx.clone(),
+                self.block,
+                self.native_tracer_options(GethDebugBuiltInTracerType::CallTracer),
+            ),
+            self.eth_client.debug_trace_call(
+                call.tx,
+                self.block,
+                self.native_tracer_options(GethDebugBuiltInTracerType::PreStateTracer),
+            ),
+        )
+        .map_err(|e| EntryPointErr::from_middleware_err::<M>(e))
+    }
+
+    /// Whether the node supports `debug_traceCall` with the JavaScript tracer (needed by the validation trace)
+    ///
+    /// Probes the node by tracing an empty call into the entry point, which reverts without side effects.
+    pub async fn supports_validation_trace(&self) -> bool {
+        self.eth_client
+            .debug_trace_call(
+                TransactionRequest::new().to(self.address),
+                self.block,
+                self.js_tracer_options(),
+            )
+            .await
+            .is_ok()
+    }
+
+    /// Whether the node supports `debug_traceCall` with the native `callTracer` and `prestateTracer`
+    pub async fn supports_native_trace(&self) -> bool {
+        for tracer in [
+            GethDebugBuiltInTracerType::CallTracer,
+            GethDebugBuiltInTracerType::PreStateTracer,
+        ] {
+            if self
+                .eth_client
+                .debug_trace_call(
+                    TransactionRequest::new().to(self.address),
+                    self.block,
+                    self.native_tracer_options(tracer),
+                )
+                .await
+                .is_err()
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn js_tracer_options(&self) -> GethDebugTracingCallOptions {
+        self.tracer_options(GethDebugTracerType::JsTracer(JS_TRACER.to_string()))
+    }
+
+    fn native_tracer_options(
+        &self,
+        tracer: GethDebugBuiltInTracerType,
+    ) -> GethDebugTracingCallOptions {
+        self.tracer_options(GethDebugTracerType::BuiltInTracer(tracer))
+    }
+
+    fn tracer_options(&self, tracer: GethDebugTracerType) -> GethDebugTracingCallOptions {
+        GethDebugTracingCallOptions {
+            tracing_options: GethDebugTracingOptions {
+                disable_storage: None,
+                disable_stack: None,
+                enable_memory: None,
+                enable_return_data: None,
+                tracer: Some(tracer),
+                tracer_config: None,
+                timeout: None,
+            },
+            state_overrides: self.state_override.clone(),
+        }
+    }
+
     pub async fn handle_ops<U: Into<UserOperation>>(
         &self,
         uos: Vec<U>,
@@ -182,6 +380,18 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         }
     }
 
+    /// Returns the next nonce of the sender for the nonce key
+    pub async fn get_nonce(&self, addr: &Address, key: U256) -> Result<U256, EntryPointErr> {
+        let res = self.entry_point_api.get_nonce(*addr, key).call().await;
+
+        match res {
+            Ok(nonce) => Ok(nonce),
+            _ => Err(EntryPointErr::UnknownErr(
+                "Error calling get nonce".to_string(),
+            )),
+        }
+    }
+
     pub async fn get_sender_address(
         &self,
         init_code: Bytes,
@@ -212,22 +422,15 @@ impl<M: Middleware + 'static> EntryPoint<M> {
     ) -> Result<(), EntryPointErr> {
         let uo: UserOperation = uo.into();
 
-        let res = self
-            .eth_client
-            .call(
-                &TransactionRequest::new()
-                    .from(self.address)
-                    .to(uo.sender)
-                    .data(uo.call_data.clone())
-                    .into(),
-                None,
-            )
-            .await;
-
-        match res {
-            Ok(_) => Ok(()),
-            Err(e) => Err(EntryPointErr::from_middleware_err::<M>(e)),
-        }
+        self.call_tx(
+            &TransactionRequest::new()
+                .from(self.address)
+                .to(uo.sender)
+                .data(uo.call_data.clone())
+                .into(),
+        )
+        .await
+        .map(|_| ())
     }
 
     pub async fn simulate_handle_op<U: Into<UserOperation>>(
@@ -235,14 +438,21 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         uo: U,
     ) -> Result<ExecutionResult, EntryPointErr> {
         let res = self
-            .entry_point_api
-            .simulate_handle_op(uo.into(), Address::zero(), Bytes::default())
+            .call(self.entry_point_api.simulate_handle_op(
+                uo.into(),
+                Address::zero(),
+                Bytes::default(),
+            ))
             .await;
 
         match res {
-            Ok(_) => Err(EntryPointErr::UnknownErr(
-                "Simulate handle op should expect revert".to_string(),
+            // the entry point doesn't have simulateHandleOp (the call hits the fallback or reverts without data)
+            Ok(_) => Err(EntryPointErr::NotSupported(
+                "Simulate handle op didn't revert".to_string(),
             )),
+            Err(ContractError::Revert(data)) if data.is_empty() => Err(
+                EntryPointErr::NotSupported("Simulate handle op reverted without data".to_string()),
+            ),
             Err(e) => Self::deserialize_error_msg(e).and_then(|op| match op {
                 EntryPointAPIErrors::FailedOp(err) => Err(EntryPointErr::FailedOp(err)),
                 EntryPointAPIErrors::ExecutionResult(res) => Ok(res),
@@ -253,6 +463,42 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         }
     }
 
+    /// Estimates the call gas limit by binary searching the lowest gas (between the intrinsic gas and
+    /// `max_gas`, usually the block gas limit) with which the call from the entry point to the sender succeeds
+    ///
+    /// The estimation includes the intrinsic gas of the call, so it is an upper bound of the call gas limit.
+    /// Returns [EntryPointErr::JsonRpcError](EntryPointErr::JsonRpcError) if the call fails even with `max_gas`.
+    pub async fn estimate_call_gas<U: Into<UserOperation>>(
+        &self,
+        uo: U,
+        max_gas: U256,
+    ) -> Result<U256, EntryPointErr> {
+        let uo: UserOperation = uo.into();
+
+        let tx = TransactionRequest::new()
+            .from(self.address)
+            .to(uo.sender)
+            .data(uo.call_data.clone());
+
+        let mut lo = U256::from(CALL_GAS_ESTIMATION_MIN);
+        let mut hi = max_gas.max(lo);
+
+        // the call has to succeed with the max gas, otherwise it fails regardless of the gas
+        self.call_tx(&tx.clone().gas(hi).into()).await?;
+
+        while hi - lo > CALL_GAS_ESTIMATION_TOLERANCE.into() {
+            let mid = (lo + hi) / 2;
+            match self.call_tx(&tx.clone().gas(mid).into()).await {
+                Ok(_) => hi = mid,
+                // reverted or ran out of gas
+                Err(EntryPointErr::JsonRpcError(_)) => lo = mid,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(hi)
+    }
+
     pub async fn handle_aggregated_ops<U: Into<UserOperation>>(
         &self,
         _uos_per_aggregator: Vec<U>,
@@ -268,6 +514,8 @@ pub enum EntryPointErr {
     JsonRpcError(JsonRpcError),
     NetworkErr(String),
     DecodeErr(String),
+    NotSupported(String),
+    Timeout(String),
     UnknownErr(String), // describe impossible error. We should fix the codes here(or contract codes) if this occurs.
 }
 
@@ -284,6 +532,27 @@ impl From<ProviderError> for EntryPointErr {
 }
 
 impl EntryPointErr {
+    /// Whether the error is transient (network error, rate limit or timeout of the node) and the call may
+    /// succeed when retried, reverts and failed operations are never transient
+    pub fn is_transient(&self) -> bool {
+        match self {
+            EntryPointErr::NetworkErr(_) | EntryPointErr::Timeout(_) => true,
+            EntryPointErr::JsonRpcError(err) => {
+                let message = err.message.to_lowercase();
+                err.data.is_none()
+                    && (matches!(err.code, 429 | -32005)
+                        || message.contains("rate limit")
+                        || message.contains("too many requests")
+                        || message.contains("timeout"))
+            }
+            EntryPointErr::UnknownErr(message) => {
+                let message = message.to_lowercase();
+                message.contains("429") || message.contains("too many requests")
+            }
+            _ => false,
+        }
+    }
+
     fn from_provider_err(err: &ProviderError) -> Self {
         match err {
             ProviderError::JsonRpcClientError(err) => err
@@ -299,7 +568,7 @@ impl EntryPointErr {
         }
     }
 
-    fn from_middleware_err<M: Middleware>(err: M::Error) -> Self {
+    pub(crate) fn from_middleware_err<M: Middleware>(err: M::Error) -> Self {
         if let Some(err) = err.as_error_response() {
             return EntryPointErr::JsonRpcError(err.clone());
         }
@@ -316,8 +585,8 @@ impl EntryPointErr {
 mod tests {
     use super::*;
     use ethers::{
-        providers::{Http, Middleware, Provider},
-        types::{Bytes, GethTrace, U256},
+        providers::{Http, Middleware, MockResponse, Provider},
+        types::{BlockNumber, Bytes, GethTrace, U256},
     };
     use silius_primitives::UserOperation;
     use std::sync::Arc;
@@ -361,4 +630,124 @@ mod tests {
 
         assert!(matches!(trace, GethTrace::Unknown { .. },));
     }
+
+    #[test]
+    fn validation_result_info() {
+        let aggregator = Address::random();
+        let res = SimulateValidationResult::ValidationResultWithAggregation(
+            ValidationResultWithAggregation {
+                return_info: (
+                    50_000.into(),
+                    1_000_000.into(),
+                    false,
+                    1_000,
+                    2_000,
+                    Bytes::from(vec![0x01]),
+                ),
+                sender_info: (1.into(), 2.into()),
+                factory_info: (3.into(), 4.into()),
+                paymaster_info: (5.into(), 6.into()),
+                aggregator_info: (aggregator, (7.into(), 8.into())),
+            },
+        );
+
+        let info = ValidationResultInfo::from(&res);
+        assert_eq!(info.return_info.pre_op_gas, U256::from(50_000));
+        assert_eq!(info.return_info.prefund, U256::from(1_000_000));
+        assert!(!info.return_info.sig_failed);
+        assert_eq!(info.return_info.valid_after, 1_000);
+        assert_eq!(info.return_info.valid_until, 2_000);
+        assert_eq!(info.return_info.paymaster_context, Bytes::from(vec![0x01]));
+        assert_eq!(info.sender_info.stake, U256::from(1));
+        assert_eq!(info.factory_info.unstake_delay, U256::from(4));
+        assert_eq!(info.paymaster_info.stake, U256::from(5));
+        assert_eq!(
+            info.aggregator_info,
+            Some(AggregatorStakeInfo {
+                aggregator,
+                stake_info: EntityStakeInfo {
+                    stake: 7.into(),
+                    unstake_delay: 8.into(),
+                },
+            })
+        );
+
+        let res = SimulateValidationResult::ValidationResult(Default::default());
+        assert_eq!(ValidationResultInfo::from(&res).aggregator_info, None);
+    }
+
+    #[tokio::test]
+    async fn supports_validation_trace() {
+        let (eth_client, mock) = Provider::mocked();
+        let ep = EntryPoint::new(Arc::new(eth_client), Address::random());
+
+        mock.push(serde_json::json!({
+            "numberLevels": [],
+            "keccak": [],
+            "logs": [],
+            "calls": [],
+            "debug": [],
+        }))
+        .unwrap();
+        assert!(ep.supports_validation_trace().await);
+
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32601,
+            message: "the method debug_traceCall does not exist/is not available".into(),
+            data: None,
+        }));
+        assert!(!ep.supports_validation_trace().await);
+    }
+
+    #[tokio::test]
+    async fn supports_native_trace() {
+        let (eth_client, mock) = Provider::mocked();
+        let ep = EntryPoint::new(Arc::new(eth_client), Address::random());
+
+        // prestate and call traces (popped from the back)
+        mock.push(serde_json::json!({})).unwrap();
+        mock.push(serde_json::json!({
+            "type": "CALL",
+            "from": Address::zero(),
+            "to": ep.address(),
+            "input": "0x",
+            "gas": "0x0",
+            "gasUsed": "0x0",
+        }))
+        .unwrap();
+        assert!(ep.supports_native_trace().await);
+
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: "tracer not found".into(),
+            data: None,
+        }));
+        assert!(!ep.supports_native_trace().await);
+    }
+
+    #[tokio::test]
+    async fn simulation_block_and_state_override() {
+        let (eth_client, mock) = Provider::mocked();
+        let sender = Address::random();
+        let state = spoof::balance(sender, U256::from(1_000_000));
+        let ep = EntryPoint::new(Arc::new(eth_client), Address::random())
+            .with_block(BlockNumber::Pending.into())
+            .with_state_override(state.clone());
+
+        let uo = UserOperation {
+            sender,
+            call_data: Bytes::from(vec![0x01]),
+            ..Default::default()
+        };
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+        ep.simulate_execution(uo.clone()).await.unwrap();
+
+        let tx: TypedTransaction = TransactionRequest::new()
+            .from(ep.address())
+            .to(sender)
+            .data(uo.call_data)
+            .into();
+        mock.assert_request("eth_call", (tx, BlockId::from(BlockNumber::Pending), state))
+            .unwrap();
+    }
 }
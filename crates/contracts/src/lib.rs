@@ -1,8 +1,10 @@
 #![allow(dead_code)]
 
+pub mod aggregator;
 pub mod entry_point;
 mod gen;
 pub mod tracer;
 pub mod utils;
 
+pub use aggregator::Aggregator;
 pub use entry_point::EntryPoint;
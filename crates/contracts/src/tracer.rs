@@ -1,8 +1,12 @@
 use anyhow::format_err;
-use ethers::types::{Address, Bytes, GethTrace, U256};
-use serde::Deserialize;
+use ethers::types::{Address, Bytes, GethTrace, H256, U256};
+use serde::{de::DeserializeOwned, Deserialize};
+use silius_primitives::simulation::{NUMBER_LEVELS, RETURN_OPCODE, REVERT_OPCODE};
 use std::collections::HashMap;
 
+/// Opcodes counted by the native tracers (they're call frames of the `callTracer`)
+const NATIVE_OPCODES: [&str; 3] = ["CREATE", "CREATE2", "SELFDESTRUCT"];
+
 /// Object (frame) return the JavaScript tracer when simulating validation of user operation
 #[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
 pub struct JsTracerFrame {
@@ -14,6 +18,102 @@ pub struct JsTracerFrame {
     pub debug: Vec<String>,
 }
 
+impl JsTracerFrame {
+    /// Builds the frame from the traces of the native `callTracer` and `prestateTracer`
+    ///
+    /// The levels are split by the entry point's calls into the sender and the paymaster instead of the `NUMBER`
+    /// opcode. Storage slots of the prestate are attributed (as reads) to every level calling into the account,
+    /// code sizes are known for the called contracts and only the opcodes of [NATIVE_OPCODES] are counted.
+    /// Keccak preimages aren't traced.
+    pub fn from_native(
+        call: &NativeCallFrame,
+        prestate: &HashMap<Address, PrestateAccount>,
+        sender: Address,
+        paymaster: Option<Address>,
+    ) -> Self {
+        let mut frame = Self {
+            number_levels: vec![Level::default(); NUMBER_LEVELS],
+            ..Default::default()
+        };
+
+        let mut level = 0;
+        for c in call.calls.iter() {
+            if c.to == Some(sender) {
+                level = level.max(1);
+            } else if c.to.is_some() && c.to == paymaster {
+                level = level.max(2);
+            }
+            frame.number_levels[level].add_native(c, prestate);
+            frame.add_native_calls(c);
+        }
+
+        // exit of the top level call (the JavaScript tracer reconstructs it from the opcode)
+        frame.calls.push(Call {
+            typ: native_exit_type(call),
+            gas_used: Some(0),
+            data: call.output.clone(),
+            ..Default::default()
+        });
+
+        frame
+    }
+
+    /// Parses the traces of the native `callTracer` and `prestateTracer` and builds the frame
+    pub fn try_from_native(
+        call: GethTrace,
+        prestate: GethTrace,
+        sender: Address,
+        paymaster: Option<Address>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::from_native(
+            &parse_native_trace(call)?,
+            &parse_native_trace(prestate)?,
+            sender,
+            paymaster,
+        ))
+    }
+
+    fn add_native_calls(&mut self, call: &NativeCallFrame) {
+        self.calls.push(Call {
+            typ: call.typ.clone(),
+            from: Some(call.from),
+            to: call.to,
+            method: Some(Bytes::from(
+                call.input.get(..4).unwrap_or(&call.input).to_vec(),
+            )),
+            gas: call.gas.map(|gas| gas.low_u64()),
+            value: call.value,
+            ..Default::default()
+        });
+        for c in call.calls.iter() {
+            self.add_native_calls(c);
+        }
+        self.calls.push(Call {
+            typ: native_exit_type(call),
+            gas_used: call.gas_used.map(|gas| gas.low_u64()),
+            data: call.output.clone(),
+            ..Default::default()
+        });
+    }
+}
+
+fn native_exit_type(call: &NativeCallFrame) -> String {
+    if call.error.is_some() {
+        REVERT_OPCODE.clone()
+    } else {
+        RETURN_OPCODE.clone()
+    }
+}
+
+fn parse_native_trace<T: DeserializeOwned>(trace: GethTrace) -> anyhow::Result<T> {
+    let value = match trace {
+        GethTrace::Unknown(value) => value,
+        trace => serde_json::to_value(trace)?,
+    };
+    serde_json::from_value(value)
+        .map_err(|error| format_err!("Failed to parse native trace: {error}"))
+}
+
 impl TryFrom<GethTrace> for JsTracerFrame {
     type Error = anyhow::Error;
     fn try_from(val: GethTrace) -> Result<Self, Self::Error> {
@@ -34,6 +134,44 @@ pub struct Level {
     pub oog: Option<bool>,
 }
 
+impl Level {
+    fn add_native(&mut self, call: &NativeCallFrame, prestate: &HashMap<Address, PrestateAccount>) {
+        if NATIVE_OPCODES.contains(&call.typ.as_str()) {
+            *self.opcodes.entry(call.typ.clone()).or_insert(0) += 1;
+        } else if let Some(to) = call.to {
+            let size = prestate
+                .get(&to)
+                .and_then(|account| account.code.as_ref())
+                .map_or(0, |code| code.len() as u64);
+            self.contract_size.insert(to, size);
+        }
+
+        if call
+            .error
+            .as_ref()
+            .map_or(false, |error| error.contains("out of gas"))
+        {
+            self.oog = Some(true);
+        }
+
+        if let Some(addr) = call.storage_address() {
+            if let Some(account) = prestate.get(&addr).filter(|a| !a.storage.is_empty()) {
+                let access = self.access.entry(addr).or_default();
+                for slot in account.storage.keys() {
+                    // same format as the JavaScript tracer (hex without leading zeros)
+                    access
+                        .reads
+                        .insert(format!("{:x}", U256::from(slot.as_bytes())), 1);
+                }
+            }
+        }
+
+        for c in call.calls.iter() {
+            self.add_native(c, prestate);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
 pub struct ReadsAndWrites {
     pub reads: HashMap<String, u64>,
@@ -71,6 +209,43 @@ pub struct CallEntry {
     pub value: Option<U256>,
 }
 
+/// Call frame returned by the native `callTracer`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct NativeCallFrame {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub from: Address,
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub input: Bytes,
+    pub output: Option<Bytes>,
+    pub error: Option<String>,
+    pub gas: Option<U256>,
+    #[serde(rename = "gasUsed")]
+    pub gas_used: Option<U256>,
+    pub value: Option<U256>,
+    #[serde(default)]
+    pub calls: Vec<NativeCallFrame>,
+}
+
+impl NativeCallFrame {
+    /// Account whose storage the call accesses (the caller's storage for `DELEGATECALL` and `CALLCODE`)
+    fn storage_address(&self) -> Option<Address> {
+        match self.typ.as_str() {
+            "DELEGATECALL" | "CALLCODE" => Some(self.from),
+            _ => self.to,
+        }
+    }
+}
+
+/// Account touched by the call as returned by the native `prestateTracer`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct PrestateAccount {
+    pub code: Option<Bytes>,
+    #[serde(default)]
+    pub storage: HashMap<H256, H256>,
+}
+
 // https://github.com/eth-infinitism/bundler/blob/main/packages/bundler/src/BundlerCollectorTracer.ts
 pub const JS_TRACER: &str = r#"
 {
@@ -213,3 +388,95 @@ pub const JS_TRACER: &str = r#"
     }
 }
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_native() {
+        let (ep, factory, sender, paymaster, implementation) = (
+            Address::random(),
+            Address::random(),
+            Address::random(),
+            Address::random(),
+            Address::random(),
+        );
+        let call: NativeCallFrame = serde_json::from_value(serde_json::json!({
+            "type": "CALL",
+            "from": Address::zero(),
+            "to": ep,
+            "input": "0xee219423",
+            "output": "0xe0cff05f",
+            "error": "execution reverted",
+            "calls": [
+                {
+                    "type": "CALL",
+                    "from": ep,
+                    "to": factory,
+                    "input": "0x570e1a36",
+                    "gasUsed": "0x100",
+                    "calls": [{ "type": "CREATE2", "from": factory, "to": sender, "input": "0x" }]
+                },
+                {
+                    "type": "CALL",
+                    "from": ep,
+                    "to": sender,
+                    "input": "0x3a871cdd",
+                    "calls": [{ "type": "DELEGATECALL", "from": sender, "to": implementation, "input": "0x12345678" }]
+                },
+                { "type": "CALL", "from": ep, "to": paymaster, "input": "0xf465c77e", "error": "out of gas" }
+            ]
+        }))
+        .unwrap();
+        let prestate: HashMap<Address, PrestateAccount> = serde_json::from_value(serde_json::json!({
+            format!("{sender:?}"): { "storage": { format!("{:?}", H256::from_low_u64_be(1)): H256::zero() } },
+            format!("{implementation:?}"): { "code": "0x60016002" },
+        }))
+        .unwrap();
+
+        let frame = JsTracerFrame::from_native(&call, &prestate, sender, Some(paymaster));
+
+        assert_eq!(frame.number_levels.len(), NUMBER_LEVELS);
+        assert_eq!(frame.number_levels[0].opcodes.get("CREATE2"), Some(&1));
+        assert_eq!(frame.number_levels[0].contract_size.get(&factory), Some(&0));
+        // storage accessed through the delegate call belongs to the sender
+        assert_eq!(
+            frame.number_levels[1].access.get(&sender).unwrap().reads,
+            HashMap::from([("1".to_string(), 1)])
+        );
+        assert_eq!(
+            frame.number_levels[1].contract_size.get(&implementation),
+            Some(&4)
+        );
+        assert_eq!(frame.number_levels[2].oog, Some(true));
+
+        // enter and exit of every call, the exit of the top level call is last
+        let types: Vec<&str> = frame.calls.iter().map(|c| c.typ.as_str()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "CALL",
+                "CREATE2",
+                "RETURN",
+                "RETURN",
+                "CALL",
+                "DELEGATECALL",
+                "RETURN",
+                "RETURN",
+                "CALL",
+                "REVERT",
+                "REVERT"
+            ]
+        );
+        assert_eq!(frame.calls[3].gas_used, Some(0x100));
+        assert_eq!(
+            frame.calls[0].method,
+            Some(Bytes::from(vec![0x57, 0x0e, 0x1a, 0x36]))
+        );
+        assert_eq!(
+            frame.calls.last().unwrap().data,
+            Some(Bytes::from(vec![0xe0, 0xcf, 0xf0, 0x5f]))
+        );
+    }
+}
@@ -1,6 +1,9 @@
-use crate::gen::entry_point_api::{self, EntryPointAPICalls};
+use crate::gen::entry_point_api::{self, EntryPointAPICalls, EntryPointAPIErrors};
 use ethers::{abi::AbiDecode, types::Bytes};
-use silius_primitives::UserOperation;
+use silius_primitives::{
+    simulation::{FailedOpInfo, SimulateValidationRevert},
+    UserOperation, UserOperationsPerAggregator,
+};
 
 impl From<UserOperation> for entry_point_api::UserOperation {
     fn from(uo: UserOperation) -> Self {
@@ -38,21 +41,76 @@ impl From<entry_point_api::UserOperation> for UserOperation {
     }
 }
 
+impl From<UserOperationsPerAggregator> for entry_point_api::UserOpsPerAggregator {
+    fn from(uos: UserOperationsPerAggregator) -> Self {
+        Self {
+            user_ops: uos.user_operations.into_iter().map(Into::into).collect(),
+            aggregator: uos.aggregator,
+            signature: uos.signature,
+        }
+    }
+}
+
+/// Decodes user operations from the input data of `handleOps` or `handleAggregatedOps`
+///
+/// User operations of `handleAggregatedOps` are flattened in the order of aggregators.
 pub fn parse_from_input_data(data: Bytes) -> Option<Vec<UserOperation>> {
+    parse_per_aggregator_from_input_data(data).map(|uos_per_aggregator| {
+        uos_per_aggregator
+            .into_iter()
+            .flat_map(|uos| uos.user_operations)
+            .collect()
+    })
+}
+
+/// Decodes user operations from the input data of `handleOps` or `handleAggregatedOps` keeping them grouped
+/// by the aggregator
+///
+/// User operations of `handleOps` are returned as a single group with zero aggregator address.
+pub fn parse_per_aggregator_from_input_data(
+    data: Bytes,
+) -> Option<Vec<UserOperationsPerAggregator>> {
     EntryPointAPICalls::decode(data)
         .ok()
         .and_then(|call| match call {
-            EntryPointAPICalls::HandleOps(ops) => {
-                Some(ops.ops.into_iter().map(|op| op.into()).collect())
-            }
+            EntryPointAPICalls::HandleOps(ops) => Some(vec![UserOperationsPerAggregator {
+                user_operations: ops.ops.into_iter().map(|op| op.into()).collect(),
+                ..Default::default()
+            }]),
+            EntryPointAPICalls::HandleAggregatedOps(ops) => Some(
+                ops.ops_per_aggregator
+                    .into_iter()
+                    .map(|ops| UserOperationsPerAggregator {
+                        user_operations: ops.user_ops.into_iter().map(|op| op.into()).collect(),
+                        aggregator: ops.aggregator,
+                        signature: ops.signature,
+                    })
+                    .collect(),
+            ),
             _ => None,
         })
 }
 
+/// Decodes the `FailedOp` revert of the entry point (if present) and keeps the raw revert data
+pub fn parse_simulate_validation_revert(data: Bytes) -> SimulateValidationRevert {
+    let failed_op = match EntryPointAPIErrors::decode(&data) {
+        Ok(EntryPointAPIErrors::FailedOp(err)) => Some(FailedOpInfo {
+            op_index: err.op_index,
+            reason: err.reason,
+        }),
+        _ => None,
+    };
+
+    SimulateValidationRevert {
+        failed_op,
+        revert_data: data,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ethers::types::Bytes;
+    use ethers::{abi::AbiEncode, types::Bytes};
     use std::str::FromStr;
 
     #[test]
@@ -62,4 +120,73 @@ mod tests {
         let res = parse_from_input_data(data);
         assert!(matches!(res, Some(..)), "No user operation found")
     }
+
+    #[test]
+    fn parse_aggregated_input_data() {
+        // not captured from a mined transaction: ABI-encoded from sample user operations (two aggregators) with the
+        // v0.6 `handleAggregatedOps` signature, to be replaced by the calldata of a real aggregated bundle
+        let data = Bytes::from_str("0x4b1d7cf50000000000000000000000000000000000000000000000000000000000000040000000000000000000000000690b9a9e9aa1c9db991c7721a92d351db4fac9900000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000005600000000000000000000000000000000000000000000000000000000000000060000000000000000000000000690b9a9e9aa1c9db991c7721a92d351db4fac99000000000000000000000000000000000000000000000000000000000000004c00000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000004000000000000000000000000000000000000000000000000000000000000002400000000000000000000000001ec271771e84999634e5e0330970feeb1c75f352000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001600000000000000000000000000000000000000000000000000000000000000180000000000000000000000000000000000000000000000000000000000000c35000000000000000000000000000000000000000000000000000000000000186a0000000000000000000000000000000000000000000000000000000000000bb800000000000000000000000000000000000000000000000000000000077359400000000000000000000000000000000000000000000000000000000003b9aca0000000000000000000000000000000000000000000000000000000000000001c000000000000000000000000000000000000000000000000000000000000001e000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000004b61d27f600000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000009c5754de1443984659e1b3a8d1931d83475ba29c000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000001600000000000000000000000000000000000000000000000000000000000000180000000000000000000000000000000000000000000000000000000000000c35000000000000000000000000000000000000000000000000000000000000186a0000000000000000000000000000000000000000000000000000000000000bb800000000000000000000000000000000000000000000000000000000077359400000000000000000000000000000000000000000000000000000000003b9aca0000000000000000000000000000000000000000000000000000000000000001c000000000000000000000000000000000000000000000000000000000000001e000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000004b61d27f600000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000030aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000600000000000000000000000000000000071727de22e5e9d8baf0edac6f37da03200000000000000000000000000000000000000000000000000000000000002a000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000020000000000000000000000000ab7e2cbfcfb6a5f33a75ad745c3e5fb48d689b54000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000001600000000000000000000000000000000000000000000000000000000000000180000000000000000000000000000000000000000000000000000000000000c35000000000000000000000000000000000000000000000000000000000000186a0000000000000000000000000000000000000000000000000000000000000bb800000000000000000000000000000000000000000000000000000000077359400000000000000000000000000000000000000000000000000000000003b9aca0000000000000000000000000000000000000000000000000000000000000001c000000000000000000000000000000000000000000000000000000000000001e000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000004b61d27f600000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000030bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb00000000000000000000000000000000")
+            .unwrap();
+
+        let uos_per_aggregator = parse_per_aggregator_from_input_data(data.clone()).unwrap();
+        assert_eq!(uos_per_aggregator.len(), 2);
+        assert_eq!(uos_per_aggregator[0].user_operations.len(), 2);
+        assert_eq!(
+            uos_per_aggregator[0].aggregator,
+            "0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(uos_per_aggregator[0].signature, Bytes::from(vec![0xaa; 48]));
+        assert_eq!(uos_per_aggregator[1].user_operations.len(), 1);
+        assert_eq!(uos_per_aggregator[1].user_operations[0].nonce, 2.into());
+
+        // the fixture is the canonical encoding of the decoded call
+        assert_eq!(
+            Bytes::from(
+                EntryPointAPICalls::HandleAggregatedOps(entry_point_api::HandleAggregatedOpsCall {
+                    ops_per_aggregator: uos_per_aggregator.into_iter().map(Into::into).collect(),
+                    beneficiary: "0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990"
+                        .parse()
+                        .unwrap(),
+                })
+                .encode()
+            ),
+            data
+        );
+
+        let uos = parse_from_input_data(data).unwrap();
+        assert_eq!(
+            uos.iter().map(|uo| uo.nonce).collect::<Vec<_>>(),
+            vec![0.into(), 1.into(), 2.into()]
+        );
+    }
+
+    #[test]
+    fn parse_simulate_validation_revert_data() {
+        let data: Bytes = EntryPointAPIErrors::FailedOp(entry_point_api::FailedOp {
+            op_index: 0.into(),
+            reason: "AA23 reverted (or OOG)".to_string(),
+        })
+        .encode()
+        .into();
+        let res = parse_simulate_validation_revert(data.clone());
+        assert_eq!(
+            res.failed_op,
+            Some(FailedOpInfo {
+                op_index: 0.into(),
+                reason: "AA23 reverted (or OOG)".to_string(),
+            })
+        );
+        assert_eq!(res.revert_data, data);
+
+        // custom account error unknown to the entry point ABI
+        let data = Bytes::from_str(
+            "0xdeadbeef000000000000000000000000000000000000000000000000000000000000002a",
+        )
+        .unwrap();
+        let res = parse_simulate_validation_revert(data.clone());
+        assert_eq!(res.failed_op, None);
+        assert_eq!(res.revert_data, data);
+    }
 }
@@ -1,19 +1,46 @@
 use crate::proto::bundler::*;
-use crate::proto::uopool::{GetSortedRequest, HandlePastEventRequest};
-use crate::uo_pool_client::UoPoolClient;
+use crate::proto::uopool::{
+    GetSortedRequest, HandlePastEventRequest, SetSubmittedRequest, SubmissionMode,
+};
+use crate::{shutdown::ShutdownSignal, uo_pool_client::UoPoolClient};
 use async_trait::async_trait;
-use ethers::types::{Address, H256, U256};
+use ethers::{
+    prelude::rand,
+    signers::LocalWallet,
+    types::{Address, H256, U256},
+};
 use parking_lot::Mutex;
-use silius_bundler::Bundler;
-use silius_primitives::{Chain, UserOperation, Wallet};
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use silius_bundler::{BlockWatcher, Bundler, BundlerSigner, FlashbotsClient, GasOracle, HttpRelay};
+use silius_primitives::{
+    bundler::{
+        BlockWatcherMode, BundleDump, DeadlinePolicy, DroppedUserOperation, KnownAccounts,
+        SubmissionMode as BundleSubmissionMode,
+    },
+    Chain, ChainSpec, UserOperation,
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, RwLock};
 use tonic::{Request, Response, Status};
 use tracing::{error, info, warn};
 
 pub struct BundlerService {
     pub bundlers: Vec<Bundler>,
     pub running: Arc<Mutex<bool>>,
+    // incremented whenever auto bundling is stopped, so the tasks of the previous run don't keep bundling
+    // after the mode is switched back to auto
+    epoch: Arc<AtomicU64>,
     pub uopool_grpc_client: UoPoolClient<tonic::transport::Channel>,
+    // new blocks trigger auto bundling
+    block_watcher: Arc<dyn BlockWatcher>,
+    // held (read) while a bundle is being sent, so the shutdown can wait for the in-flight bundles
+    in_flight: Arc<RwLock<()>>,
 }
 
 fn is_running(running: Arc<Mutex<bool>>) -> bool {
@@ -21,41 +48,79 @@ fn is_running(running: Arc<Mutex<bool>>) -> bool {
     *r
 }
 
+fn is_current(running: Arc<Mutex<bool>>, epoch: &AtomicU64, task_epoch: u64) -> bool {
+    is_running(running) && epoch.load(Ordering::SeqCst) == task_epoch
+}
+
 impl BundlerService {
     pub fn new(
         bundlers: Vec<Bundler>,
         uopool_grpc_client: UoPoolClient<tonic::transport::Channel>,
+        block_watcher: Arc<dyn BlockWatcher>,
     ) -> Self {
         Self {
             bundlers,
             running: Arc::new(Mutex::new(false)),
+            epoch: Arc::new(AtomicU64::new(0)),
             uopool_grpc_client,
+            block_watcher,
+            in_flight: Arc::new(RwLock::new(())),
         }
     }
 
     async fn get_user_operations(
         uopool_grpc_client: &UoPoolClient<tonic::transport::Channel>,
-        ep: &Address,
-    ) -> anyhow::Result<Vec<UserOperation>> {
+        bundler: &Bundler,
+        max_fee_per_gas: U256,
+    ) -> anyhow::Result<(
+        Vec<UserOperation>,
+        Vec<u64>,
+        Vec<Address>,
+        Vec<DroppedUserOperation>,
+        KnownAccounts,
+    )> {
         let req = Request::new(GetSortedRequest {
-            ep: Some((*ep).into()),
+            ep: Some(bundler.entry_point.into()),
+            submission_mode: SubmissionMode::from(bundler.submission_mode).into(),
+            // the storage preconditions are used only by the conditional submission
+            known_accounts: bundler.conditional_rpc,
+            max_fee_per_gas: Some(max_fee_per_gas.into()),
         });
         let res = uopool_grpc_client
             .clone()
             .get_sorted_user_operations(req)
             .await?;
 
-        let uos: Vec<UserOperation> = res.into_inner().uos.into_iter().map(|u| u.into()).collect();
-        Ok(uos)
+        let res = res.into_inner();
+        let uos: Vec<UserOperation> = res.uos.into_iter().map(|u| u.into()).collect();
+        let aggregators = res.aggregators.into_iter().map(Into::into).collect();
+        let dropped = res
+            .dropped
+            .into_iter()
+            .map(|d| DroppedUserOperation {
+                user_operation_hash: d.hash.unwrap_or_default().into(),
+                reason: d.reason,
+            })
+            .collect();
+        let known_accounts = if res.known_accounts.is_empty() {
+            KnownAccounts::default()
+        } else {
+            serde_json::from_str(&res.known_accounts)?
+        };
+        Ok((uos, res.valid_until, aggregators, dropped, known_accounts))
     }
 
     pub async fn send_bundles(&self) -> anyhow::Result<H256> {
+        let _in_flight = self.in_flight.read().await;
         let mut tx_hashes: Vec<H256> = vec![];
 
         for bundler in self.bundlers.iter() {
-            let uos =
-                Self::get_user_operations(&self.uopool_grpc_client, &bundler.entry_point).await?;
-            let tx_hash = bundler.send_next_bundle(&uos).await?;
+            let fees = bundler.estimate_fees().await?;
+            let (uos, valid_until, aggregators, _, known_accounts) =
+                Self::get_user_operations(&self.uopool_grpc_client, bundler, fees.0).await?;
+            let tx_hash = bundler
+                .send_next_bundle(&uos, &valid_until, &aggregators, &known_accounts, fees)
+                .await?;
 
             Self::handle_past_events(&self.uopool_grpc_client, &bundler.entry_point).await?;
 
@@ -70,16 +135,45 @@ impl BundlerService {
             .expect("Must have at least one tx hash"))
     }
 
+    /// Creates the next bundle of every entry point without sending it
+    ///
+    /// The mempool selects and re-validates the user operations as for the real bundle, so user operations
+    /// failing the re-validation are dropped from the mempool.
+    pub async fn dump_bundles(&self) -> anyhow::Result<Vec<BundleDump>> {
+        let _in_flight = self.in_flight.read().await;
+        let mut dumps = vec![];
+
+        for bundler in self.bundlers.iter() {
+            let fees = bundler.estimate_fees().await?;
+            let (uos, valid_until, aggregators, dropped, _) =
+                Self::get_user_operations(&self.uopool_grpc_client, bundler, fees.0).await?;
+            dumps.push(
+                bundler
+                    .dump_bundle(&uos, &valid_until, &aggregators, dropped, fees)
+                    .await?,
+            );
+        }
+
+        Ok(dumps)
+    }
+
     pub fn stop_bundling(&self) {
         info!("Stopping auto bundling");
         let mut r = self.running.lock();
         *r = false;
+        self.epoch.fetch_add(1, Ordering::SeqCst);
     }
 
     pub fn is_running(&self) -> bool {
         is_running(self.running.clone())
     }
 
+    /// Stops auto bundling and waits for the bundles being sent to resolve
+    pub async fn shutdown(&self) {
+        self.stop_bundling();
+        let _ = self.in_flight.write().await;
+    }
+
     async fn handle_past_events(
         uopool_grpc_client: &UoPoolClient<tonic::transport::Channel>,
         ep: &Address,
@@ -100,7 +194,44 @@ impl BundlerService {
         Ok(())
     }
 
+    /// Marks the user operations of the sent bundle transactions as submitted in the uopool (until they're mined)
+    pub fn forward_submissions(&self) {
+        for bundler in self.bundlers.iter() {
+            let mut submissions = bundler.subscribe_submissions();
+            let uopool_grpc_client = self.uopool_grpc_client.clone();
+            let ep = bundler.entry_point;
+
+            tokio::spawn(async move {
+                loop {
+                    let submission = match submissions.recv().await {
+                        Ok(submission) => submission,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+
+                    let req = Request::new(SetSubmittedRequest {
+                        hashes: submission.uo_hashes.into_iter().map(Into::into).collect(),
+                        ep: Some(ep.into()),
+                        tx_hash: Some(submission.tx_hash.into()),
+                    });
+                    if let Err(err) = uopool_grpc_client.clone().set_submitted(req).await {
+                        warn!("Failed to mark user operations as submitted: {err:?}");
+                    }
+                }
+            });
+        }
+    }
+
+    /// Starts auto bundling with the interval (in seconds)
+    ///
+    /// Bundles are sent on new blocks of the block watcher, at most once per interval. Zero interval keeps
+    /// the bundler in the manual mode, bundles are then sent only on demand (`debug_bundler_sendBundleNow`).
     pub fn start_bundling(&self, int: u64) {
+        if int == 0 {
+            info!("Auto bundling disabled, bundles are sent on demand");
+            return;
+        }
+
         if !self.is_running() {
             info!("Starting auto bundling");
 
@@ -110,24 +241,54 @@ impl BundlerService {
             for bundler in self.bundlers.iter() {
                 let bundler_own = bundler.clone();
                 let running_lock = self.running.clone();
+                let epoch = self.epoch.clone();
+                let task_epoch = epoch.load(Ordering::SeqCst);
                 let uopool_grpc_client = self.uopool_grpc_client.clone();
+                let mut blocks = self.block_watcher.subscribe();
+                let in_flight = self.in_flight.clone();
 
                 tokio::spawn(async move {
-                    let mut interval = tokio::time::interval(Duration::from_secs(int));
+                    let interval = Duration::from_secs(int);
+                    let mut last_bundle: Option<Instant> = None;
                     loop {
-                        if !is_running(running_lock.clone()) {
+                        match blocks.recv().await {
+                            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                            Err(broadcast::error::RecvError::Closed) => {
+                                warn!("Block watcher stopped, auto bundling stopped");
+                                break;
+                            }
+                        }
+                        // the mode might have been switched to manual (or shut down) while waiting
+                        let _in_flight = in_flight.read().await;
+                        if !is_current(running_lock.clone(), &epoch, task_epoch) {
                             break;
                         }
-                        interval.tick().await;
+                        if last_bundle.map_or(false, |last| last.elapsed() < interval) {
+                            continue;
+                        }
+                        last_bundle = Some(Instant::now());
 
-                        match Self::get_user_operations(
-                            &uopool_grpc_client,
-                            &bundler_own.entry_point,
-                        )
-                        .await
+                        let fees = match bundler_own.estimate_fees().await {
+                            Ok(fees) => fees,
+                            Err(e) => {
+                                error!("Error while estimating the bundle fees: {e:?}");
+                                continue;
+                            }
+                        };
+                        match Self::get_user_operations(&uopool_grpc_client, &bundler_own, fees.0)
+                            .await
                         {
-                            Ok(bundle) => {
-                                if let Err(e) = bundler_own.send_next_bundle(&bundle).await {
+                            Ok((bundle, valid_until, aggregators, _, known_accounts)) => {
+                                if let Err(e) = bundler_own
+                                    .send_next_bundle(
+                                        &bundle,
+                                        &valid_until,
+                                        &aggregators,
+                                        &known_accounts,
+                                        fees,
+                                    )
+                                    .await
+                                {
                                     error!("Error while sending bundle: {e:?}");
                                 }
                                 if let Err(e) = Self::handle_past_events(
@@ -187,43 +348,115 @@ impl bundler_server::Bundler for BundlerService {
             res: Some(res.into()),
         }))
     }
+
+    async fn dump_bundle(&self, _req: Request<()>) -> Result<Response<DumpBundleResponse>, Status> {
+        let dumps = self
+            .dump_bundles()
+            .await
+            .map_err(|e| tonic::Status::internal(format!("Dump bundle with error: {e:?}")))?;
+        Ok(Response::new(DumpBundleResponse {
+            data: serde_json::to_string(&dumps)
+                .map_err(|e| tonic::Status::internal(format!("Dump bundle with error: {e:?}")))?,
+        }))
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
-pub fn bundler_service_run(
+pub async fn bundler_service_run(
     grpc_listen_address: SocketAddr,
-    wallet: Wallet,
+    signer: Arc<dyn BundlerSigner>,
     eps: Vec<Address>,
     eth_client_address: String,
     chain: Chain,
-    beneficiary: Address,
+    chain_spec: ChainSpec,
+    beneficiary: Option<Address>,
     _gas_factor: U256,
     _min_balance: U256,
     bundle_interval: u64,
+    deadline_margin: u64,
+    deadline_policy: DeadlinePolicy,
+    conditional_rpc: bool,
+    flashbots_relay: Option<String>,
+    flashbots_searcher: Option<LocalWallet>,
+    flashbots_max_blocks: u64,
+    priority_fee_percentile: f64,
+    base_fee_buffer_perc: u64,
+    fixed_gas_price: Option<U256>,
+    submission_mode: BundleSubmissionMode,
+    block_watcher_mode: BlockWatcherMode,
+    block_poll_interval: u64,
+    bundle_gas_limit_buffer_perc: u64,
     uopool_grpc_client: UoPoolClient<tonic::transport::Channel>,
-) {
+    mut shutdown: ShutdownSignal,
+) -> anyhow::Result<()> {
+    let block_watcher = silius_bundler::block_watcher(
+        block_watcher_mode,
+        &eth_client_address,
+        Duration::from_millis(block_poll_interval),
+    )
+    .await?;
+
+    let flashbots = flashbots_relay.map(|relay| {
+        let searcher =
+            flashbots_searcher.unwrap_or_else(|| LocalWallet::new(&mut rand::thread_rng()));
+        FlashbotsClient::new(
+            Arc::new(HttpRelay::new(relay, searcher)),
+            flashbots_max_blocks,
+        )
+    });
+
+    let gas_oracle = GasOracle::new(priority_fee_percentile, base_fee_buffer_perc)
+        .with_min_priority_fee_per_gas(chain_spec.min_priority_fee_per_gas);
+    let gas_oracle = match fixed_gas_price {
+        Some(fixed_gas_price) => gas_oracle.with_fixed_gas_price(fixed_gas_price),
+        None => gas_oracle,
+    };
+
     let bundlers: Vec<Bundler> = eps
         .iter()
         .map(|ep| {
-            Bundler::new(
-                wallet.clone(),
-                eth_client_address.clone(),
-                beneficiary,
-                *ep,
-                chain,
-            )
+            let bundler = Bundler::new(signer.clone(), eth_client_address.clone(), *ep, chain)
+                .with_deadline(deadline_margin, deadline_policy)
+                .with_submission_mode(submission_mode)
+                .with_conditional_rpc(conditional_rpc)
+                .with_gas_oracle(gas_oracle.clone())
+                .with_gas_limit_buffer(bundle_gas_limit_buffer_perc);
+            let bundler = match beneficiary {
+                Some(beneficiary) => bundler.with_beneficiary(beneficiary),
+                None => bundler,
+            };
+            match flashbots.clone() {
+                Some(flashbots) => bundler.with_flashbots(flashbots),
+                None => bundler,
+            }
         })
         .collect();
 
-    let bundler_service = BundlerService::new(bundlers, uopool_grpc_client);
+    let bundler_service = Arc::new(BundlerService::new(
+        bundlers,
+        uopool_grpc_client,
+        block_watcher,
+    ));
 
     info!("Bundler gRPC server starting on {}", grpc_listen_address);
 
+    bundler_service.forward_submissions();
     bundler_service.start_bundling(bundle_interval);
 
+    tokio::spawn({
+        let bundler_service = bundler_service.clone();
+        async move {
+            shutdown.recv().await;
+            bundler_service.shutdown().await;
+            info!("Bundler service shut down");
+        }
+    });
+
     tokio::spawn(async move {
         let mut builder = tonic::transport::Server::builder();
-        let svc = bundler_server::BundlerServer::new(bundler_service);
+        let svc = bundler_server::BundlerServer::from_arc(bundler_service);
         builder.add_service(svc).serve(grpc_listen_address).await
     });
+
+    Ok(())
 }
@@ -2,6 +2,7 @@
 
 mod bundler;
 mod proto;
+mod shutdown;
 mod uopool;
 mod utils;
 
@@ -9,4 +10,5 @@ pub use bundler::{bundler_service_run, BundlerService};
 pub use proto::bundler::*;
 pub use proto::types::*;
 pub use proto::uopool::*;
-pub use uopool::{uopool_service_run, UoPoolService};
+pub use shutdown::{Shutdown, ShutdownSignal};
+pub use uopool::{uopool_service_run, UoPoolService, UoPoolServiceConfig};
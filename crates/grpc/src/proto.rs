@@ -322,7 +322,41 @@ pub mod types {
 }
 
 pub mod uopool {
+    use silius_primitives::{
+        bundler::SubmissionMode as GrpcSubmissionMode, uopool::InclusionEvent,
+    };
+
     tonic::include_proto!("uopool");
+
+    impl From<SubmissionMode> for GrpcSubmissionMode {
+        fn from(value: SubmissionMode) -> Self {
+            match value {
+                SubmissionMode::DropInvalid => Self::DropInvalid,
+                SubmissionMode::AllOrNothing => Self::AllOrNothing,
+            }
+        }
+    }
+
+    impl From<GrpcSubmissionMode> for SubmissionMode {
+        fn from(value: GrpcSubmissionMode) -> Self {
+            match value {
+                GrpcSubmissionMode::DropInvalid => Self::DropInvalid,
+                GrpcSubmissionMode::AllOrNothing => Self::AllOrNothing,
+            }
+        }
+    }
+
+    impl From<InclusionEvent> for UserOperationEventResponse {
+        fn from(value: InclusionEvent) -> Self {
+            Self {
+                user_operation_hash: Some(value.user_op_hash.into()),
+                sender: Some(value.sender.into()),
+                success: value.success,
+                actual_gas_cost: Some(value.actual_gas_cost.into()),
+                transaction_hash: Some(value.tx_hash.into()),
+            }
+        }
+    }
 }
 
 pub mod bundler {
@@ -0,0 +1,106 @@
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+/// Graceful shutdown of the services
+///
+/// Services get a [ShutdownSignal] when they're started. On shutdown every signal is notified and the shutdown
+/// waits (up to the timeout) until all the signals are dropped, i.e., the services finished their work.
+pub struct Shutdown {
+    signal: watch::Sender<bool>,
+    complete_tx: mpsc::Sender<()>,
+    complete_rx: mpsc::Receiver<()>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (signal, _) = watch::channel(false);
+        let (complete_tx, complete_rx) = mpsc::channel(1);
+        Self {
+            signal,
+            complete_tx,
+            complete_rx,
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal {
+            signal: self.signal.subscribe(),
+            _complete: self.complete_tx.clone(),
+        }
+    }
+
+    /// Notifies the services and waits until they're done, returns false if the timeout elapsed first
+    pub async fn shutdown(self, timeout: Duration) -> bool {
+        let Self {
+            signal,
+            complete_tx,
+            mut complete_rx,
+        } = self;
+
+        let _ = signal.send(true);
+        drop(complete_tx);
+
+        // the channel is closed once all the signals are dropped
+        tokio::time::timeout(timeout, complete_rx.recv())
+            .await
+            .is_ok()
+    }
+}
+
+/// Notification of the shutdown held by a service until it finished its work
+pub struct ShutdownSignal {
+    signal: watch::Receiver<bool>,
+    _complete: mpsc::Sender<()>,
+}
+
+impl ShutdownSignal {
+    pub fn is_shutdown(&self) -> bool {
+        *self.signal.borrow()
+    }
+
+    /// Waits for the shutdown
+    pub async fn recv(&mut self) {
+        while !*self.signal.borrow() {
+            if self.signal.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    #[tokio::test]
+    async fn shutdown() {
+        let shutdown = Shutdown::default();
+        let mut signal = shutdown.signal();
+        let done = Arc::new(AtomicBool::new(false));
+
+        tokio::spawn({
+            let done = done.clone();
+            async move {
+                signal.recv().await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                done.store(true, Ordering::SeqCst);
+            }
+        });
+
+        // waits until the service dropped the signal
+        assert!(shutdown.shutdown(Duration::from_secs(5)).await);
+        assert!(done.load(Ordering::SeqCst));
+
+        // the service not finishing in time
+        let shutdown = Shutdown::default();
+        let signal = shutdown.signal();
+        assert!(!shutdown.shutdown(Duration::from_millis(10)).await);
+        assert!(signal.is_shutdown());
+    }
+}
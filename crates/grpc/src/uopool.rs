@@ -1,9 +1,9 @@
 use crate::{
     proto::types::{GetChainIdResponse, GetSupportedEntryPointsResponse},
-    utils::{parse_addr, parse_hash, parse_uo, parse_uo_pool_mut},
+    utils::{parse_addr, parse_hash, parse_state_override, parse_uo, parse_uo_pool_mut},
 };
-use crate::{proto::uopool::*, utils::parse_uo_pool};
-use anyhow::Result;
+use crate::{proto::uopool::*, shutdown::ShutdownSignal, utils::parse_uo_pool};
+use anyhow::{format_err, Result};
 use async_trait::async_trait;
 use dashmap::{
     mapref::one::{Ref, RefMut},
@@ -11,46 +11,110 @@ use dashmap::{
 };
 use ethers::{
     providers::{Http, Middleware, Provider},
-    types::{Address, H256, U256},
+    types::{Address, BlockNumber, H256, U256},
 };
+use silius_bundler::Bundler;
 use silius_contracts::{entry_point::EntryPointErr, EntryPoint};
 use silius_primitives::{
-    reputation::{BAN_SLACK, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK},
-    uopool::AddError,
-    Chain, UoPoolMode,
+    metrics::METRICS,
+    sanity::SignatureShape,
+    simulation::{EnabledEntities, TracerMode},
+    uopool::{AddError, OrderingPolicy, P2pOpsPolicy, UserOperationState},
+    Chain, ChainSpec, UoPoolMode, UserOperationHash,
 };
 use silius_uopool::{
-    mempool_id,
+    bundle::UnstakedEntityCaps,
+    mempool_id, pre_verification_gas_calculator,
     validate::{
+        retry::RetryPolicy,
         sanity::{
-            call_gas::CallGas, max_fee::MaxFee, paymaster::Paymaster, sender::SenderOrInitCode,
-            sender_uos::SenderUos, verification_gas::VerificationGas,
+            access_list::AccessListCheck,
+            call_data::CallDataTargets,
+            call_gas::CallGas,
+            factory::{Factory, MAX_UOS_PER_UNSTAKED_FACTORY},
+            fee_bounds::FeeBounds,
+            inner_calls::InnerCalls,
+            max_fee::MaxFee,
+            nonce::NonceCheck,
+            paymaster::Paymaster,
+            paymaster_gas::PaymasterGasLimits,
+            paymaster_signature::PaymasterSignature,
+            sender::SenderOrInitCode,
+            sender_code_size::SenderCodeSize,
+            sender_uos::SenderUos,
+            signature_shape::SignatureShapeCheck,
+            size::SizeCheck,
+            verification_gas::VerificationGas,
+        },
+        simulation::{
+            aggregator::Aggregator, delegation::Delegation, prefund::Prefund, signature::Signature,
+            time_range::TimeRange, timestamp::Timestamp, verification_gas::VerificationGasUsage,
         },
-        simulation::{signature::Signature, timestamp::Timestamp},
         simulation_trace::{
-            call_stack::CallStack, code_hashes::CodeHashes, gas::Gas, opcodes::Opcodes,
+            call_stack::CallStack, code_hashes::CodeHashes, entry_point_calls::EntryPointCalls,
+            gas::Gas, opcodes::Opcodes, sender_deployment::SenderDeployment,
             storage_access::StorageAccess,
         },
         validator::StandardUserOperationValidator,
         UserOperationValidator,
     },
-    MemoryMempool, MemoryReputation, MempoolId, Reputation, UoPool as UserOperationPool,
+    DatabaseMempool, DatabaseReputation, Lifecycle, MemoryMempool, MemoryReputation, MempoolBox,
+    MempoolId, Overhead, Reputation, SenderPenalty, SystemClock, UoPool as UserOperationPool,
+    VecCh, VecUo, WriteMap,
+};
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
-use tracing::info;
+use tracing::{info, trace, warn};
 
-const MAX_UOS_PER_UNSTAKED_SENDER: usize = 4;
-const GAS_INCREASE_PERC: u64 = 10;
+const EXPIRATION_CHECK_INTERVAL: u64 = 10;
+const USER_OPERATION_EVENTS_CHANNEL_CAPACITY: usize = 128;
 
 pub struct UoPoolService<M: Middleware + 'static, V: UserOperationValidator> {
     pub uo_pools: Arc<DashMap<MempoolId, UserOperationPool<M, V>>>,
+    // supported entry points (in the configured order)
+    pub eps: Vec<Address>,
     pub chain: Chain,
+    // new user operations are rejected while shutting down
+    shutting_down: AtomicBool,
 }
 
 impl<M: Middleware + 'static, V: UserOperationValidator> UoPoolService<M, V> {
-    pub fn new(uo_pools: Arc<DashMap<MempoolId, UserOperationPool<M, V>>>, chain: Chain) -> Self {
-        Self { uo_pools, chain }
+    pub fn new(
+        uo_pools: Arc<DashMap<MempoolId, UserOperationPool<M, V>>>,
+        eps: Vec<Address>,
+        chain: Chain,
+    ) -> Self {
+        Self {
+            uo_pools,
+            eps,
+            chain,
+            shutting_down: AtomicBool::new(false),
+        }
+    }
+
+    /// Stops accepting new user operations and prepares the pools for the shutdown (user operations of unsent
+    /// bundles are released and the reputation is flushed to the database)
+    pub fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        self.uo_pools.iter_mut().for_each(|mut m| {
+            let released = m.value_mut().shutdown();
+            if released > 0 {
+                info!(
+                    "Released {released} user operations of the unsent bundle of mempool {:?}",
+                    m.key()
+                );
+            }
+        });
     }
 
     fn get_uo_pool(&self, ep: &Address) -> Option<Ref<H256, UserOperationPool<M, V>>> {
@@ -71,22 +135,32 @@ where
     EntryPointErr: From<<M as Middleware>::Error>,
 {
     async fn add(&self, req: Request<AddRequest>) -> Result<Response<AddResponse>, Status> {
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(Status::unavailable("Bundler is shutting down"));
+        }
+
         let req = req.into_inner();
 
         let uo = parse_uo(req.uo)?;
         let ep = parse_addr(req.ep)?;
+        let uo_hash = uo.hash(&ep, &self.chain.id().into());
+
+        parse_uo_pool_mut(self.get_uo_pool_mut(&ep))?.receive_user_operation(&uo_hash);
 
         let res = {
             let uo_pool = parse_uo_pool(self.get_uo_pool(&ep))?;
             match uo_pool.validate_user_operation(&uo).await {
                 Ok(res) => res,
                 Err(err) => {
+                    drop(uo_pool);
+                    parse_uo_pool_mut(self.get_uo_pool_mut(&ep))?
+                        .reject_user_operation(&uo_hash, &err);
                     return Ok(Response::new(AddResponse {
                         res: AddResult::NotAdded as i32,
                         data: serde_json::to_string(&err).map_err(|err| {
                             Status::internal(format!("Failed to serialize error: {err}"))
                         })?,
-                    }))
+                    }));
                 }
             }
         };
@@ -138,11 +212,7 @@ where
         _req: Request<()>,
     ) -> Result<Response<GetSupportedEntryPointsResponse>, Status> {
         Ok(Response::new(GetSupportedEntryPointsResponse {
-            eps: self
-                .uo_pools
-                .iter()
-                .map(|mempool| mempool.entry_point_address().into())
-                .collect(),
+            eps: self.eps.iter().map(|ep| (*ep).into()).collect(),
         }))
     }
 
@@ -154,11 +224,15 @@ where
 
         let uo = parse_uo(req.uo)?;
         let ep = parse_addr(req.ep)?;
+        let state_override = parse_state_override(&req.state_override)?;
 
         let uo_pool = parse_uo_pool(self.get_uo_pool(&ep))?;
 
         Ok(Response::new(
-            match uo_pool.estimate_user_operation_gas(&uo).await {
+            match uo_pool
+                .estimate_user_operation_gas(&uo, state_override.as_ref())
+                .await
+            {
                 Ok(gas) => EstimateUserOperationGasResponse {
                     res: EstimateUserOperationGasResult::Estimated as i32,
                     data: serde_json::to_string(&gas).map_err(|err| {
@@ -181,25 +255,81 @@ where
     ) -> Result<Response<GetSortedResponse>, Status> {
         let req = req.into_inner();
 
+        let submission_mode = req.submission_mode().into();
+        let with_known_accounts = req.known_accounts;
+        let max_fee_per_gas = req.max_fee_per_gas.map(U256::from);
         let ep = parse_addr(req.ep)?;
 
         let uos = {
             let uo_pool = parse_uo_pool(self.get_uo_pool(&ep))?;
-            uo_pool.get_sorted_user_operations().map_err(|e| {
+            uo_pool.get_sorted_user_operations().await.map_err(|e| {
                 tonic::Status::internal(format!("Get sorted uos internal error: {e}"))
             })?
         };
+        // filtered before the selection, so the user operations stay pending (and replaceable) for the later bundles
+        let uos = match max_fee_per_gas {
+            Some(max_fee_per_gas) => Bundler::filter_by_max_fee(uos, max_fee_per_gas),
+            None => uos,
+        };
 
-        let uos_valid = {
+        let (uos_valid, valid_until, aggregators, dropped, known_accounts) = {
             let mut uo_pool = parse_uo_pool_mut(self.get_uo_pool_mut(&ep))?;
-            uo_pool
-                .bundle_user_operations(uos)
+            let uos_valid = uo_pool
+                .bundle_user_operations(uos, submission_mode)
                 .await
-                .map_err(|e| tonic::Status::internal(format!("Bundle uos internal error: {e}")))?
+                .map_err(|e| tonic::Status::internal(format!("Bundle uos internal error: {e}")))?;
+            let valid_until = uos_valid
+                .iter()
+                .map(|uo| {
+                    uo_pool
+                        .get_bundled_valid_until(
+                            &uo.hash(&uo_pool.entry_point_address(), &uo_pool.chain.id().into()),
+                        )
+                        .map(|v| v.min(u64::MAX.into()).as_u64())
+                        .unwrap_or(u64::MAX)
+                })
+                .collect();
+            let aggregators = uos_valid
+                .iter()
+                .map(|uo| {
+                    uo_pool
+                        .get_bundled_aggregator(
+                            &uo.hash(&uo_pool.entry_point_address(), &uo_pool.chain.id().into()),
+                        )
+                        .unwrap_or_default()
+                        .into()
+                })
+                .collect();
+            let dropped = uo_pool
+                .get_bundle_dropped()
+                .iter()
+                .map(|(uo_hash, reason)| DroppedUserOperation {
+                    hash: Some((*uo_hash).into()),
+                    reason: reason.clone(),
+                })
+                .collect();
+            let known_accounts = if with_known_accounts {
+                let known_accounts = uo_pool
+                    .get_bundled_known_accounts(&uos_valid)
+                    .await
+                    .map_err(|e| {
+                        tonic::Status::internal(format!("Known accounts internal error: {e}"))
+                    })?;
+                serde_json::to_string(&known_accounts).map_err(|err| {
+                    Status::internal(format!("Failed to serialize known accounts: {err}"))
+                })?
+            } else {
+                String::new()
+            };
+            (uos_valid, valid_until, aggregators, dropped, known_accounts)
         };
 
         Ok(Response::new(GetSortedResponse {
             uos: uos_valid.into_iter().map(Into::into).collect(),
+            valid_until,
+            aggregators,
+            dropped,
+            known_accounts,
         }))
     }
 
@@ -229,7 +359,8 @@ where
         let uo_hash = parse_hash(req.hash)?;
 
         for uo_pool in self.uo_pools.iter() {
-            if let Ok(uo_by_hash) = uo_pool.get_user_operation_by_hash(&uo_hash.into()).await {
+            if let Ok(Some(uo_by_hash)) = uo_pool.get_user_operation_by_hash(&uo_hash.into()).await
+            {
                 return Ok(Response::new(GetUserOperationByHashResponse {
                     user_operation: Some(uo_by_hash.user_operation.into()),
                     entry_point: Some(uo_by_hash.entry_point.into()),
@@ -271,6 +402,96 @@ where
         Err(tonic::Status::not_found("User operation receipt not found"))
     }
 
+    async fn get_user_operation_status(
+        &self,
+        req: Request<UserOperationHashRequest>,
+    ) -> Result<Response<GetUserOperationStatusResponse>, Status> {
+        let req = req.into_inner();
+
+        let uo_hash = parse_hash(req.hash)?;
+        let mut state = UserOperationState::NotFound;
+
+        for uo_pool in self.uo_pools.iter() {
+            match uo_pool.get_user_operation_state(&uo_hash.into()).await {
+                Ok(UserOperationState::NotFound) => continue,
+                Ok(uo_state) => {
+                    state = uo_state;
+                    break;
+                }
+                Err(err) => {
+                    return Err(Status::internal(format!(
+                        "Getting user operation status failed: {err:?}"
+                    )))
+                }
+            }
+        }
+
+        Ok(Response::new(GetUserOperationStatusResponse {
+            data: serde_json::to_string(&state)
+                .map_err(|err| Status::internal(format!("Failed to serialize status: {err}")))?,
+        }))
+    }
+
+    async fn set_submitted(
+        &self,
+        req: Request<SetSubmittedRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = req.into_inner();
+
+        let ep = parse_addr(req.ep)?;
+        let tx_hash = parse_hash(req.tx_hash)?;
+        let uo_hashes: Vec<UserOperationHash> = req.hashes.into_iter().map(Into::into).collect();
+
+        parse_uo_pool_mut(self.get_uo_pool_mut(&ep))?.submit_user_operations(&uo_hashes, tx_hash);
+
+        Ok(Response::new(()))
+    }
+
+    type SubscribeUserOperationEventsStream =
+        ReceiverStream<Result<UserOperationEventResponse, Status>>;
+
+    async fn subscribe_user_operation_events(
+        &self,
+        req: Request<SubscribeUserOperationEventsRequest>,
+    ) -> Result<Response<Self::SubscribeUserOperationEventsStream>, Status> {
+        let req = req.into_inner();
+
+        let sender: Option<Address> = req.sender.map(Into::into);
+        let (tx, rx) = mpsc::channel(USER_OPERATION_EVENTS_CHANNEL_CAPACITY);
+
+        for uo_pool in self.uo_pools.iter() {
+            let mut inclusions = uo_pool.subscribe_inclusions();
+            let tx = tx.clone();
+
+            tokio::spawn(async move {
+                loop {
+                    let event = tokio::select! {
+                        // the subscriber is gone
+                        _ = tx.closed() => break,
+                        event = inclusions.recv() => event,
+                    };
+
+                    match event {
+                        Ok(event) => {
+                            if sender.map_or(false, |sender| sender != event.sender) {
+                                continue;
+                            }
+                            if tx.send(Ok(event.into())).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("User operation events subscriber lagged behind, skipped {skipped} events")
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
     async fn get_all(
         &self,
         req: Request<GetAllRequest>,
@@ -319,107 +540,478 @@ where
         let ep = parse_addr(req.ep)?;
         let mut uo_pool = parse_uo_pool_mut(self.get_uo_pool_mut(&ep))?;
 
-        uo_pool.set_reputation(req.rep.iter().map(|re| re.clone().into()).collect());
+        uo_pool
+            .set_reputation(req.rep.iter().map(|re| re.clone().into()).collect())
+            .map_err(|err| Status::internal(format!("Failed to set reputation: {err:?}")))?;
 
         Ok(Response::new(SetReputationResponse {
             res: SetReputationResult::SetReputation as i32,
         }))
     }
+
+    async fn simulate_user_operation(
+        &self,
+        req: Request<SimulateUserOperationRequest>,
+    ) -> Result<Response<SimulateUserOperationResponse>, Status> {
+        let req = req.into_inner();
+
+        let uo = parse_uo(req.uo)?;
+        let ep = parse_addr(req.ep)?;
+
+        let uo_pool = parse_uo_pool(self.get_uo_pool(&ep))?;
+
+        let res = uo_pool.simulate_user_operation(&uo).await.map_err(|err| {
+            Status::internal(format!("Failed to simulate user operation: {err:?}"))
+        })?;
+
+        Ok(Response::new(SimulateUserOperationResponse {
+            data: serde_json::to_string(&res)
+                .map_err(|err| Status::internal(format!("Failed to serialize result: {err}")))?,
+        }))
+    }
+}
+
+/// Settings of the uopool service: the gRPC listen address, the entry points with a uopool each and the
+/// validation, mempool and bundle settings of the uopools
+#[derive(Debug, Clone)]
+pub struct UoPoolServiceConfig {
+    pub grpc_listen_address: SocketAddr,
+    pub entry_points: Vec<Address>,
+    pub chain: Chain,
+    pub chain_spec: ChainSpec,
+    pub max_verification_gas: U256,
+    pub min_stake: U256,
+    pub min_unstake_delay: U256,
+    pub min_priority_fee_per_gas: U256,
+    pub whitelist: Vec<Address>,
+    pub uo_pool_mode: UoPoolMode,
+    pub uo_ttl: u64,
+    pub sender_penalty_window: u64,
+    pub sender_penalty_max_failures: usize,
+    pub min_sender_code_size: usize,
+    pub gas_increase_perc: U256,
+    pub operator_paymaster: Option<Address>,
+    pub operator_paymaster_signer: Option<Address>,
+    pub min_inclusion_denominator: u64,
+    pub throttling_slack: u64,
+    pub ban_slack: u64,
+    pub hourly_decay_denominator: u64,
+    pub max_inner_calls: Option<usize>,
+    pub time_range_buffer: Option<u64>,
+    pub prefund_safety_factor_perc: U256,
+    pub p2p_ops_policy: P2pOpsPolicy,
+    pub call_gas_safety_margin_perc: U256,
+    pub mempool_db: Option<PathBuf>,
+    pub max_user_operations_per_sender: usize,
+    pub max_mempool_size: Option<usize>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub max_revalidations: Option<usize>,
+    pub max_init_code_size: usize,
+    pub max_call_data_size: usize,
+    pub max_paymaster_and_data_size: usize,
+    pub max_user_operation_size: usize,
+    pub revert_penalty: Option<u64>,
+    pub max_bundle_gas: U256,
+    pub max_ops_per_bundle: Option<usize>,
+    pub allowlist: Vec<Address>,
+    pub denylist: Vec<Address>,
+    pub simulation_timeout: u64,
+    pub simulation_max_retries: u32,
+    pub max_verification_gas_overdeclare_perc: Option<u64>,
+    pub opcode_rules_entities: EnabledEntities,
+    pub storage_rules_entities: EnabledEntities,
+    pub tracer: TracerMode,
+    pub simulation_block: Option<BlockNumber>,
+    pub confirmation_blocks: u64,
+    pub factory_signature_shapes: Vec<(Address, SignatureShape)>,
+    pub code_hash_signature_shapes: Vec<(H256, SignatureShape)>,
+    pub bundle_ordering: OrderingPolicy,
+    pub enforce_unstaked_entity_caps: bool,
+    pub unstaked_factory_bundle_cap: usize,
+    pub unstaked_paymaster_bundle_cap: usize,
 }
 
-#[allow(clippy::too_many_arguments)]
 pub async fn uopool_service_run(
-    grpc_listen_address: SocketAddr,
-    eps: Vec<Address>,
+    config: UoPoolServiceConfig,
     eth_client: Arc<Provider<Http>>,
-    chain: Chain,
-    max_verification_gas: U256,
-    min_stake: U256,
-    min_unstake_delay: U256,
-    min_priority_fee_per_gas: U256,
-    whitelist: Vec<Address>,
-    uo_pool_mode: UoPoolMode,
+    mut shutdown: ShutdownSignal,
 ) -> Result<()> {
-    tokio::spawn(async move {
-        let mut builder = tonic::transport::Server::builder();
-
-        let m_map = Arc::new(DashMap::<
-            MempoolId,
-            UserOperationPool<Provider<Http>, StandardUserOperationValidator<Provider<Http>>>,
-        >::new());
-
-        for ep in eps {
-            let id = mempool_id(&ep, &U256::from(chain.id()));
-
-            let mut reputation = Box::<MemoryReputation>::default();
-            reputation.init(
-                MIN_INCLUSION_RATE_DENOMINATOR,
-                THROTTLING_SLACK,
-                BAN_SLACK,
-                min_stake,
-                min_unstake_delay,
-            );
-            for addr in whitelist.iter() {
-                reputation.add_whitelist(addr);
-            }
+    let UoPoolServiceConfig {
+        grpc_listen_address,
+        entry_points: eps,
+        chain,
+        chain_spec,
+        max_verification_gas,
+        min_stake,
+        min_unstake_delay,
+        min_priority_fee_per_gas,
+        whitelist,
+        uo_pool_mode,
+        uo_ttl,
+        sender_penalty_window,
+        sender_penalty_max_failures,
+        min_sender_code_size,
+        gas_increase_perc,
+        operator_paymaster,
+        operator_paymaster_signer,
+        min_inclusion_denominator,
+        throttling_slack,
+        ban_slack,
+        hourly_decay_denominator,
+        max_inner_calls,
+        time_range_buffer,
+        prefund_safety_factor_perc,
+        p2p_ops_policy,
+        call_gas_safety_margin_perc,
+        mempool_db,
+        max_user_operations_per_sender,
+        max_mempool_size,
+        max_priority_fee_per_gas,
+        max_revalidations,
+        max_init_code_size,
+        max_call_data_size,
+        max_paymaster_and_data_size,
+        max_user_operation_size,
+        revert_penalty,
+        max_bundle_gas,
+        max_ops_per_bundle,
+        allowlist,
+        denylist,
+        simulation_timeout,
+        simulation_max_retries,
+        max_verification_gas_overdeclare_perc,
+        opcode_rules_entities,
+        storage_rules_entities,
+        tracer,
+        simulation_block,
+        confirmation_blocks,
+        factory_signature_shapes,
+        code_hash_signature_shapes,
+        bundle_ordering,
+        enforce_unstaked_entity_caps,
+        unstaked_factory_bundle_cap,
+        unstaked_paymaster_bundle_cap,
+    } = config;
+
+    let m_map = Arc::new(DashMap::<
+        MempoolId,
+        UserOperationPool<Provider<Http>, StandardUserOperationValidator<Provider<Http>>>,
+    >::new());
+
+    for ep in eps.iter().copied() {
+        let id = mempool_id(&ep, &U256::from(chain.id()));
+
+        let mut reputation = Box::<MemoryReputation>::default();
+        reputation.init(
+            min_inclusion_denominator,
+            throttling_slack,
+            ban_slack,
+            hourly_decay_denominator,
+            min_stake,
+            min_unstake_delay,
+        );
+        for addr in whitelist.iter() {
+            reputation.add_whitelist(addr);
+        }
 
-            let entry_point = EntryPoint::<Provider<Http>>::new(eth_client.clone(), ep);
-
-            let mut validator =
-                StandardUserOperationValidator::new(eth_client.clone(), entry_point.clone(), chain)
-                    .with_sanity_check(SenderOrInitCode)
-                    .with_sanity_check(VerificationGas {
-                        max_verification_gas,
-                    })
-                    .with_sanity_check(Paymaster)
-                    .with_sanity_check(CallGas)
-                    .with_sanity_check(MaxFee {
-                        min_priority_fee_per_gas,
-                    })
-                    .with_sanity_check(SenderUos {
-                        max_uos_per_unstaked_sender: MAX_UOS_PER_UNSTAKED_SENDER,
-                        gas_increase_perc: GAS_INCREASE_PERC.into(),
-                    })
-                    .with_simulation_check(Signature)
-                    .with_simulation_check(Timestamp);
-
-            if uo_pool_mode != UoPoolMode::Unsafe {
-                validator = validator
-                    .with_simulation_trace_check(Gas)
-                    .with_simulation_trace_check(Opcodes)
-                    .with_simulation_trace_check(StorageAccess)
-                    .with_simulation_trace_check(CallStack)
-                    .with_simulation_trace_check(CodeHashes);
+        let mut entry_point = EntryPoint::<Provider<Http>>::new(eth_client.clone(), ep);
+        if let Some(block) = simulation_block {
+            entry_point = entry_point.with_block(block.into());
+        }
+
+        // the JavaScript tracer is preferred in the auto mode, since the native tracers don't trace opcodes
+        let (uo_pool_mode, native_tracer) = match (uo_pool_mode, tracer) {
+            (UoPoolMode::Standard, TracerMode::Auto | TracerMode::Js)
+                if entry_point.supports_validation_trace().await =>
+            {
+                (UoPoolMode::Standard, false)
+            }
+            (UoPoolMode::Standard, TracerMode::Auto | TracerMode::Native)
+                if entry_point.supports_native_trace().await =>
+            {
+                warn!("Tracing the validation with the native tracers for entry point {ep:?}: forbidden opcode rules (except CREATE, CREATE2 and SELFDESTRUCT) and the GAS rule are disabled");
+                (UoPoolMode::Standard, true)
             }
+            (UoPoolMode::Standard, _) => {
+                warn!("Node doesn't support debug_traceCall with the {tracer:?} tracer, falling back to partial validation");
+                (UoPoolMode::Partial, false)
+            }
+            (mode, _) => (mode, false),
+        };
+        if uo_pool_mode == UoPoolMode::Partial {
+            warn!("Partial validation (simulateValidation through eth_call) for entry point {ep:?}: opcode, storage access and other trace checks are skipped, full spec compliance is disabled");
+        }
 
-            m_map.insert(
-                id,
-                UserOperationPool::<Provider<Http>, StandardUserOperationValidator<Provider<Http>>>::new(
-                    entry_point,
-                    validator,
-                    Box::<MemoryMempool>::default(),
-                    reputation,
-                    eth_client.clone(),
+        // denied (or not allowed) entities are rejected before any other check
+        let access_list = AccessListCheck::new(
+            allowlist.iter().copied().collect(),
+            denylist.iter().copied().collect(),
+        );
+
+        let retry_policy = RetryPolicy::new(
+            Duration::from_millis(simulation_timeout),
+            simulation_max_retries,
+        );
+
+        let mut validator =
+            StandardUserOperationValidator::new(eth_client.clone(), entry_point.clone(), chain)
+                .with_simulation_cache()
+                .with_retry_policy(retry_policy)
+                .with_sanity_check(access_list.clone())
+                .with_sanity_check(SenderOrInitCode)
+                .with_sanity_check(SizeCheck {
+                    max_init_code_size,
+                    max_call_data_size,
+                    max_paymaster_and_data_size,
+                    max_user_operation_size,
+                })
+                .with_sanity_check(NonceCheck)
+                .with_sanity_check(VerificationGas {
+                    max_verification_gas,
+                    overhead: Overhead::from(&chain_spec).with_calculator(
+                        pre_verification_gas_calculator(&chain_spec, eth_client.clone()),
+                    ),
+                })
+                .with_sanity_check(Paymaster {
+                    min_stake,
+                    min_unstake_delay,
+                })
+                .with_sanity_check(PaymasterGasLimits {
                     max_verification_gas,
-                    chain,
-                ),
-            );
+                    max_post_op_gas: max_verification_gas,
+                })
+                .with_sanity_check(CallGas {
+                    safety_margin_perc: call_gas_safety_margin_perc,
+                })
+                .with_sanity_check(MaxFee)
+                .with_sanity_check(FeeBounds {
+                    min_priority_fee_per_gas: min_priority_fee_per_gas
+                        .max(chain_spec.min_priority_fee_per_gas),
+                    max_priority_fee_per_gas,
+                })
+                .with_sanity_check(SenderUos {
+                    max_uos_per_unstaked_sender: max_user_operations_per_sender,
+                    gas_increase_perc,
+                })
+                .with_sanity_check(Factory {
+                    max_uos_per_unstaked_factory: MAX_UOS_PER_UNSTAKED_FACTORY,
+                })
+                .with_sanity_check(CallDataTargets)
+                .with_simulation_check(access_list)
+                .with_simulation_check(Signature)
+                .with_simulation_check(Timestamp)
+                .with_simulation_check(Aggregator)
+                .with_simulation_check(Delegation);
+
+        if min_sender_code_size > 0 {
+            validator = validator.with_sanity_check(SenderCodeSize {
+                min_code_size: min_sender_code_size,
+            });
         }
 
-        let svc = uo_pool_server::UoPoolServer::new(UoPoolService::new(m_map.clone(), chain));
+        if let (Some(paymaster), Some(signer)) = (operator_paymaster, operator_paymaster_signer) {
+            validator = validator.with_sanity_check(PaymasterSignature { paymaster, signer });
+        }
+
+        if let Some(buffer) = time_range_buffer {
+            validator = validator.with_simulation_check(TimeRange { buffer });
+        }
+
+        if prefund_safety_factor_perc > U256::from(100) {
+            validator = validator.with_simulation_check(Prefund {
+                safety_factor_perc: prefund_safety_factor_perc,
+            });
+        }
+
+        // off by default, some wallets legitimately pad the verification gas limit
+        if let Some(perc) = max_verification_gas_overdeclare_perc {
+            validator = validator.with_simulation_check(VerificationGasUsage {
+                max_overdeclare_perc: perc.into(),
+            });
+        }
 
-        tokio::spawn(async move {
+        // opt-in per account type, exotic accounts aren't known to have any signature shape
+        let signature_shape = SignatureShapeCheck {
+            factories: factory_signature_shapes.iter().copied().collect(),
+            code_hashes: code_hash_signature_shapes.iter().copied().collect(),
+        };
+        if !signature_shape.is_empty() {
+            validator = validator.with_sanity_check(signature_shape);
+        }
+
+        if let Some(max_inner_calls) = max_inner_calls {
+            validator = validator.with_sanity_check(InnerCalls { max_inner_calls });
+        }
+
+        if native_tracer {
+            validator = validator.with_native_tracer();
+        }
+
+        if uo_pool_mode == UoPoolMode::Standard {
+            validator = validator
+                .with_simulation_trace_check(Gas)
+                .with_simulation_trace_check(Opcodes {
+                    entities: opcode_rules_entities,
+                })
+                .with_simulation_trace_check(StorageAccess {
+                    entities: storage_rules_entities,
+                })
+                .with_simulation_trace_check(CallStack)
+                .with_simulation_trace_check(EntryPointCalls)
+                .with_simulation_trace_check(SenderDeployment)
+                .with_simulation_trace_check(CodeHashes);
+        }
+
+        let mempool: MempoolBox<VecUo, VecCh> = match mempool_db.as_ref() {
+            Some(path) => {
+                let path = path.join(format!("{id:?}"));
+                std::fs::create_dir_all(&path).map_err(|err| {
+                    format_err!("Creating mempool database directory failed: {err:?}")
+                })?;
+                let mempool = DatabaseMempool::<WriteMap>::new(path)
+                    .map_err(|err| format_err!("Opening mempool database failed: {err:?}"))?;
+                mempool.create_tables().map_err(|err| {
+                    format_err!("Creating mempool database tables failed: {err:?}")
+                })?;
+                Box::new(mempool)
+            }
+            None => Box::<MemoryMempool>::default(),
+        };
+
+        let mut uo_pool = UserOperationPool::<
+            Provider<Http>,
+            StandardUserOperationValidator<Provider<Http>>,
+        >::new(
+            entry_point,
+            validator,
+            mempool,
+            reputation,
+            eth_client.clone(),
+            max_verification_gas,
+            chain,
+        )
+        .with_lifecycle(
+            Lifecycle::new(Arc::new(SystemClock), uo_ttl)
+                .with_expiry_buffer(time_range_buffer.unwrap_or_default()),
+        )
+        .with_chain_spec(&chain_spec)
+        .with_gas_increase_perc(gas_increase_perc)
+        .with_max_bundle_gas(max_bundle_gas)
+        .with_p2p_ops_policy(p2p_ops_policy)
+        .with_retry_policy(retry_policy)
+        .with_confirmation_blocks(confirmation_blocks)
+        .with_bundle_ordering(bundle_ordering);
+
+        if enforce_unstaked_entity_caps {
+            uo_pool = uo_pool.with_unstaked_entity_caps(UnstakedEntityCaps {
+                factory: unstaked_factory_bundle_cap,
+                paymaster: unstaked_paymaster_bundle_cap,
+            });
+        }
+
+        if sender_penalty_window > 0 {
+            uo_pool = uo_pool.with_sender_penalty(SenderPenalty::new(
+                Arc::new(SystemClock),
+                sender_penalty_window,
+                sender_penalty_max_failures,
+            ));
+        }
+
+        if let Some(max_mempool_size) = max_mempool_size {
+            uo_pool = uo_pool.with_max_mempool_size(max_mempool_size);
+        }
+
+        if let Some(max_revalidations) = max_revalidations {
+            uo_pool = uo_pool.with_max_revalidations(max_revalidations);
+        }
+
+        if let Some(revert_penalty) = revert_penalty {
+            uo_pool = uo_pool.with_revert_penalty(revert_penalty);
+        }
+
+        if let Some(max_ops_per_bundle) = max_ops_per_bundle {
+            uo_pool = uo_pool.with_max_ops_per_bundle(max_ops_per_bundle);
+        }
+
+        if let Some(path) = mempool_db.as_ref() {
+            let path = path.join("reputation").join(format!("{id:?}"));
+            std::fs::create_dir_all(&path).map_err(|err| {
+                format_err!("Creating reputation database directory failed: {err:?}")
+            })?;
+            let reputation_db = DatabaseReputation::<WriteMap>::new(path)
+                .map_err(|err| format_err!("Opening reputation database failed: {err:?}"))?;
+            reputation_db.create_tables().map_err(|err| {
+                format_err!("Creating reputation database tables failed: {err:?}")
+            })?;
+            uo_pool = uo_pool.with_reputation_db(Box::new(reputation_db));
+
+            match uo_pool.load_reputation_from_db() {
+                Ok(restored) => {
+                    info!("Restored {restored} reputation entries from the reputation database")
+                }
+                Err(err) => {
+                    warn!("Restoring the reputation from the reputation database failed: {err:?}")
+                }
+            }
+
+            match uo_pool.load_from_db().await {
+                Ok(restored) => {
+                    info!("Restored {restored} user operations from the mempool database")
+                }
+                Err(err) => {
+                    warn!("Restoring user operations from the mempool database failed: {err:?}")
+                }
+            }
+        }
+
+        m_map.insert(id, uo_pool);
+    }
+
+    let uopool_service = Arc::new(UoPoolService::new(m_map.clone(), eps, chain));
+    let svc = uo_pool_server::UoPoolServer::from_arc(uopool_service.clone());
+
+    tokio::spawn(async move {
+        shutdown.recv().await;
+        uopool_service.shutdown();
+        info!("UoPool service shut down");
+    });
+
+    tokio::spawn({
+        let m_map = m_map.clone();
+        async move {
             loop {
-                m_map
-                    .iter_mut()
-                    .for_each(|mut m| m.value_mut().reputation.update_hourly());
-                tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+                m_map.iter_mut().for_each(|mut m| {
+                    let uo_hashes = m.value_mut().remove_expired_user_operations();
+                    if !uo_hashes.is_empty() {
+                        trace!("Removed expired user operations {uo_hashes:?}");
+                    }
+                    METRICS
+                        .mempool_size
+                        .set(&format!("{:?}", m.key()), m.value().get_all().len() as u64);
+                });
+                tokio::time::sleep(Duration::from_secs(EXPIRATION_CHECK_INTERVAL)).await;
             }
-        });
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            m_map.iter_mut().for_each(|mut m| {
+                if let Err(err) = m.value_mut().reputation.update_hourly() {
+                    warn!("Hourly reputation update of {:?} failed: {err:?}", m.key());
+                }
+            });
+            tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+        }
+    });
 
-        info!("UoPool gRPC server starting on {}", grpc_listen_address);
+    info!("UoPool gRPC server starting on {}", grpc_listen_address);
 
-        builder.add_service(svc).serve(grpc_listen_address).await
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(svc)
+            .serve(grpc_listen_address)
+            .await
     });
 
     tokio::time::sleep(Duration::from_secs(1)).await;
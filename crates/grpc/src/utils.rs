@@ -1,7 +1,7 @@
 use dashmap::mapref::one::{Ref, RefMut};
 use ethers::{
     providers::Middleware,
-    types::{Address, H256},
+    types::{spoof, Address, H256},
 };
 use silius_primitives::UserOperation;
 use silius_uopool::{validate::UserOperationValidator, UoPool as UserOperationPool};
@@ -31,6 +31,21 @@ pub fn parse_uo(uo: Option<crate::UserOperation>) -> Result<UserOperation, Statu
     }
 }
 
+/// Parses the state overrides from JSON, none if empty
+pub fn parse_state_override(state_override: &str) -> Result<Option<spoof::State>, Status> {
+    if state_override.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(state_override)
+        .map(Some)
+        .map_err(|err| {
+            Status::new(
+                Code::InvalidArgument,
+                format!("State override is not valid: {err}"),
+            )
+        })
+}
+
 pub fn parse_uo_pool<M: Middleware, V: UserOperationValidator>(
     uo_pool: Option<Ref<H256, UserOperationPool<M, V>>>,
 ) -> Result<Ref<H256, UserOperationPool<M, V>>, Status> {
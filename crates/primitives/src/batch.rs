@@ -0,0 +1,201 @@
+use ethers::{
+    abi::{decode, ParamType, Token},
+    types::{Address, Bytes, U256},
+    utils::id,
+};
+
+/// Signature of the single call execution of the `SimpleAccount`
+pub const EXECUTE: &str = "execute(address,uint256,bytes)";
+/// Signature of the batch execution of the `SimpleAccount` (without values)
+pub const EXECUTE_BATCH: &str = "executeBatch(address[],bytes[])";
+/// Signature of the batch execution of the `SimpleAccount` (with values)
+pub const EXECUTE_BATCH_WITH_VALUES: &str = "executeBatch(address[],uint256[],bytes[])";
+
+/// Inner call of a batch user operation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InnerCall {
+    pub to: Address,
+    pub value: U256,
+    pub data: Bytes,
+}
+
+/// Decodes the inner call from the call data of a single call execution
+///
+/// Returns `None` if the call data isn't a (well-formed) single call execution.
+pub fn decode_execute(call_data: &[u8]) -> Option<InnerCall> {
+    if call_data.len() < 4 || call_data[..4] != id(EXECUTE) {
+        return None;
+    }
+
+    let mut tokens = decode(
+        &[ParamType::Address, ParamType::Uint(256), ParamType::Bytes],
+        &call_data[4..],
+    )
+    .ok()?
+    .into_iter();
+
+    Some(InnerCall {
+        to: tokens.next()?.into_address()?,
+        value: tokens.next()?.into_uint()?,
+        data: tokens.next()?.into_bytes()?.into(),
+    })
+}
+
+/// Decodes inner calls from the call data of a single call or batch execution
+pub fn decode_inner_calls(call_data: &[u8]) -> Option<Vec<InnerCall>> {
+    decode_execute(call_data)
+        .map(|call| vec![call])
+        .or_else(|| decode_batch(call_data))
+}
+
+/// Decodes inner calls from the call data of a batch user operation
+///
+/// Returns `None` if the call data isn't a (well-formed) batch execution.
+pub fn decode_batch(call_data: &[u8]) -> Option<Vec<InnerCall>> {
+    if call_data.len() < 4 {
+        return None;
+    }
+    let (selector, data) = call_data.split_at(4);
+
+    let address_array = ParamType::Array(Box::new(ParamType::Address));
+    let bytes_array = ParamType::Array(Box::new(ParamType::Bytes));
+
+    let (to, values, data) = if selector == id(EXECUTE_BATCH) {
+        let mut tokens = decode(&[address_array, bytes_array], data)
+            .ok()?
+            .into_iter();
+        (tokens.next()?, vec![], tokens.next()?)
+    } else if selector == id(EXECUTE_BATCH_WITH_VALUES) {
+        let uint_array = ParamType::Array(Box::new(ParamType::Uint(256)));
+        let mut tokens = decode(&[address_array, uint_array, bytes_array], data)
+            .ok()?
+            .into_iter();
+        let to = tokens.next()?;
+        let values = tokens
+            .next()?
+            .into_array()?
+            .into_iter()
+            .map(Token::into_uint)
+            .collect::<Option<Vec<_>>>()?;
+        (to, values, tokens.next()?)
+    } else {
+        return None;
+    };
+
+    let to = to
+        .into_array()?
+        .into_iter()
+        .map(Token::into_address)
+        .collect::<Option<Vec<_>>>()?;
+    let data = data
+        .into_array()?
+        .into_iter()
+        .map(Token::into_bytes)
+        .collect::<Option<Vec<_>>>()?;
+
+    // empty values mean that no value is transferred
+    if to.len() != data.len() || (!values.is_empty() && values.len() != to.len()) {
+        return None;
+    }
+
+    Some(
+        to.into_iter()
+            .zip(data)
+            .enumerate()
+            .map(|(i, (to, data))| InnerCall {
+                to,
+                value: values.get(i).copied().unwrap_or_default(),
+                data: data.into(),
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::encode;
+
+    #[test]
+    fn decode_batch_call_data() {
+        let to = [Address::random(), Address::random()];
+        let data = [vec![0x01, 0x02], vec![]];
+
+        let call_data = [
+            id(EXECUTE_BATCH).to_vec(),
+            encode(&[
+                Token::Array(to.iter().map(|a| Token::Address(*a)).collect()),
+                Token::Array(data.iter().map(|d| Token::Bytes(d.clone())).collect()),
+            ]),
+        ]
+        .concat();
+        assert_eq!(
+            decode_batch(&call_data),
+            Some(vec![
+                InnerCall {
+                    to: to[0],
+                    value: U256::zero(),
+                    data: vec![0x01, 0x02].into(),
+                },
+                InnerCall {
+                    to: to[1],
+                    value: U256::zero(),
+                    data: Bytes::default(),
+                },
+            ])
+        );
+
+        let call_data = [
+            id(EXECUTE_BATCH_WITH_VALUES).to_vec(),
+            encode(&[
+                Token::Array(to.iter().map(|a| Token::Address(*a)).collect()),
+                Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+                Token::Array(data.iter().map(|d| Token::Bytes(d.clone())).collect()),
+            ]),
+        ]
+        .concat();
+        let calls = decode_batch(&call_data).unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[1].value, 2.into());
+
+        // mismatched lengths
+        let call_data = [
+            id(EXECUTE_BATCH).to_vec(),
+            encode(&[
+                Token::Array(to.iter().map(|a| Token::Address(*a)).collect()),
+                Token::Array(vec![]),
+            ]),
+        ]
+        .concat();
+        assert_eq!(decode_batch(&call_data), None);
+
+        // not a batch
+        assert_eq!(decode_batch(&id("execute(address,uint256,bytes)")), None);
+        assert_eq!(decode_batch(&[]), None);
+    }
+
+    #[test]
+    fn decode_execute_call_data() {
+        let to = Address::random();
+        let call_data = [
+            id(EXECUTE).to_vec(),
+            encode(&[
+                Token::Address(to),
+                Token::Uint(1.into()),
+                Token::Bytes(vec![0x01, 0x02]),
+            ]),
+        ]
+        .concat();
+        let call = InnerCall {
+            to,
+            value: 1.into(),
+            data: vec![0x01, 0x02].into(),
+        };
+        assert_eq!(decode_execute(&call_data), Some(call.clone()));
+        assert_eq!(decode_inner_calls(&call_data), Some(vec![call]));
+
+        // truncated arguments
+        assert_eq!(decode_execute(&call_data[..40]), None);
+        assert_eq!(decode_inner_calls(&id("transfer(address,uint256)")), None);
+    }
+}
@@ -1,4 +1,8 @@
-use serde::Deserialize;
+use crate::{UserOperation, UserOperationHash};
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::{btree_map::Entry, BTreeMap};
+use strum_macros::{EnumString, EnumVariantNames};
 
 /// Default time interval for auto bundling mode (in seconds)
 pub const DEFAULT_BUNDLE_INTERVAL: u64 = 10;
@@ -11,3 +15,177 @@ pub enum Mode {
     #[serde(rename = "manual")]
     Manual,
 }
+
+/// What to do with user operations whose `validUntil` is within the confirmation time margin
+#[derive(Clone, Copy, Debug, Default, EnumString, EnumVariantNames, PartialEq, Eq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum DeadlinePolicy {
+    /// Submit the bundle transaction with high priority fee
+    #[default]
+    Prioritize,
+    /// Defer the user operations out of the bundle
+    Exclude,
+}
+
+/// What to do with the bundle when the simulation of `handleOps` fails on a user operation (`FailedOp`)
+#[derive(Clone, Copy, Debug, Default, EnumString, EnumVariantNames, PartialEq, Eq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum SubmissionMode {
+    /// Drop the failing user operation and simulate the rest of the bundle again
+    #[default]
+    DropInvalid,
+    /// Don't submit the bundle and leave the user operations in the mempool unchanged
+    AllOrNothing,
+}
+
+/// Source of the new blocks that trigger auto bundling
+#[derive(Clone, Copy, Debug, Default, EnumString, EnumVariantNames, PartialEq, Eq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum BlockWatcherMode {
+    /// Subscription if the execution client supports it (WebSocket endpoint), polling otherwise
+    #[default]
+    Auto,
+    /// `eth_subscribe` to `newHeads`
+    Subscription,
+    /// `eth_blockNumber` at an interval
+    Polling,
+}
+
+/// Expected storage of an account (used as a precondition of the conditional bundle transaction)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AccountStorage {
+    /// Expected storage root hash
+    RootHash(H256),
+    /// Expected values of storage slots
+    SlotValues(BTreeMap<H256, H256>),
+}
+
+/// Storage preconditions (`knownAccounts`) of `eth_sendRawTransactionConditional`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownAccounts(pub BTreeMap<Address, AccountStorage>);
+
+impl KnownAccounts {
+    /// Adds the expected value of the storage slot (the first observed value is kept)
+    pub fn insert_slot(&mut self, address: Address, slot: H256, value: H256) {
+        match self
+            .0
+            .entry(address)
+            .or_insert_with(|| AccountStorage::SlotValues(BTreeMap::new()))
+        {
+            AccountStorage::RootHash(_) => {}
+            AccountStorage::SlotValues(slots) => {
+                slots.entry(slot).or_insert(value);
+            }
+        }
+    }
+
+    /// Merges preconditions of another user operation in the bundle
+    ///
+    /// Slot expectations of the same account are combined. The storage root hash takes precedence over
+    /// slot expectations, since it covers the whole storage of the account.
+    pub fn merge(&mut self, other: KnownAccounts) {
+        for (address, storage) in other.0 {
+            match self.0.entry(address) {
+                Entry::Vacant(entry) => {
+                    entry.insert(storage);
+                }
+                Entry::Occupied(mut entry) => match (entry.get_mut(), storage) {
+                    (AccountStorage::RootHash(_), _) => {}
+                    (current, AccountStorage::RootHash(root)) => {
+                        *current = AccountStorage::RootHash(root);
+                    }
+                    (AccountStorage::SlotValues(slots), AccountStorage::SlotValues(other)) => {
+                        for (slot, value) in other {
+                            slots.entry(slot).or_insert(value);
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Options of `eth_sendRawTransactionConditional`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalOptions {
+    pub known_accounts: KnownAccounts,
+}
+
+/// User operation dropped from the mempool or excluded from the bundle while creating the bundle
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DroppedUserOperation {
+    pub user_operation_hash: UserOperationHash,
+    pub reason: String,
+}
+
+/// Bundle created by the dry run (`debug_bundler_dumpBundle`), the bundle transaction isn't sent
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleDump {
+    pub entry_point: Address,
+    /// User operations in the order of the bundle
+    pub user_operations: Vec<UserOperation>,
+    pub user_operation_hashes: Vec<UserOperationHash>,
+    pub gas_limit: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    /// Gas fees paid by the user operations to the beneficiary less the cost of the bundle transaction
+    /// (zero if the bundle isn't profitable), assuming the full gas limits are used
+    pub expected_revenue: U256,
+    pub dropped: Vec<DroppedUserOperation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_accounts_merge() {
+        let (a, b, c) = (Address::random(), Address::random(), Address::random());
+        let (s1, s2, root) = (
+            H256::from_low_u64_be(1),
+            H256::from_low_u64_be(2),
+            H256::random(),
+        );
+
+        let mut known_accounts = KnownAccounts::default();
+        known_accounts.insert_slot(a, s1, H256::from_low_u64_be(10));
+        known_accounts.insert_slot(b, s1, H256::from_low_u64_be(10));
+
+        let mut other = KnownAccounts::default();
+        other.insert_slot(a, s1, H256::from_low_u64_be(11));
+        other.insert_slot(a, s2, H256::from_low_u64_be(20));
+        other.0.insert(b, AccountStorage::RootHash(root));
+        other.insert_slot(c, s2, H256::from_low_u64_be(30));
+
+        known_accounts.merge(other);
+        assert_eq!(
+            known_accounts.0.get(&a),
+            Some(&AccountStorage::SlotValues(BTreeMap::from([
+                (s1, H256::from_low_u64_be(10)),
+                (s2, H256::from_low_u64_be(20))
+            ])))
+        );
+        assert_eq!(
+            known_accounts.0.get(&b),
+            Some(&AccountStorage::RootHash(root))
+        );
+        assert_eq!(known_accounts.0.len(), 3);
+
+        let options = serde_json::to_value(ConditionalOptions {
+            known_accounts: KnownAccounts(BTreeMap::from([(b, AccountStorage::RootHash(root))])),
+        })
+        .unwrap();
+        assert_eq!(
+            options,
+            serde_json::json!({ "knownAccounts": { format!("{b:?}"): format!("{root:?}") } })
+        );
+    }
+}
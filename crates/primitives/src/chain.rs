@@ -1,4 +1,7 @@
 use ethers::types::U256;
+use expanded_pathbuf::ExpandedPathBuf;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 /// Currently supported chains
 pub const SUPPORTED_CHAINS: [&str; 3] = [
@@ -43,3 +46,147 @@ impl From<U256> for Chain {
         id.as_u64().into()
     }
 }
+
+const MAINNET_LIKE_CHAIN_IDS: [u64; 6] = [1, 5, 17000, 11155111, 1337, 31337];
+const POLYGON_CHAIN_IDS: [u64; 3] = [137, 80001, 80002];
+const ARBITRUM_CHAIN_IDS: [u64; 4] = [42161, 42170, 421613, 421614];
+const OPTIMISM_CHAIN_IDS: [u64; 6] = [10, 420, 11155420, 8453, 84531, 84532];
+
+/// Min priority fee of the Polygon PoS chains (30 gwei)
+pub const POLYGON_MIN_PRIORITY_FEE_PER_GAS: u64 = 30_000_000_000;
+
+/// Source of the L1 data fee added to the pre-verification gas on L2s
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum L1GasOracle {
+    /// No L1 data fee (L1s and sidechains)
+    #[default]
+    None,
+    /// `gasEstimateL1Component` of the Arbitrum node interface
+    Arbitrum,
+    /// `getL1Fee` of the OP stack gas price oracle
+    Optimism,
+}
+
+/// Chain-specific parameters of the pre-verification gas and fees
+///
+/// Known chains are selected by the chain id with [ChainSpec::for_chain], custom networks can load the spec
+/// from a JSON file (missing fields take the mainnet defaults).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChainSpec {
+    /// Fixed gas of the bundle transaction, shared by the user operations of the bundle
+    pub fixed_overhead: U256,
+    /// Gas of the entry point per user operation
+    pub per_user_op_overhead: U256,
+    /// Gas per word of the packed user operation
+    pub per_user_op_word_overhead: U256,
+    /// Calldata gas of a zero byte
+    pub zero_byte_gas: U256,
+    /// Calldata gas of a non-zero byte
+    pub non_zero_byte_gas: U256,
+    pub l1_gas_oracle: L1GasOracle,
+    /// Min priority fee accepted by the chain
+    pub min_priority_fee_per_gas: U256,
+}
+
+impl Default for ChainSpec {
+    fn default() -> Self {
+        Self {
+            fixed_overhead: U256::from(21000),
+            per_user_op_overhead: U256::from(18300),
+            per_user_op_word_overhead: U256::from(4),
+            zero_byte_gas: U256::from(4),
+            non_zero_byte_gas: U256::from(16),
+            l1_gas_oracle: L1GasOracle::None,
+            min_priority_fee_per_gas: U256::zero(),
+        }
+    }
+}
+
+impl ChainSpec {
+    /// Returns the spec of the known chain
+    pub fn known(chain_id: u64) -> Option<Self> {
+        if MAINNET_LIKE_CHAIN_IDS.contains(&chain_id) {
+            Some(Self::default())
+        } else if POLYGON_CHAIN_IDS.contains(&chain_id) {
+            Some(Self {
+                min_priority_fee_per_gas: U256::from(POLYGON_MIN_PRIORITY_FEE_PER_GAS),
+                ..Default::default()
+            })
+        } else if ARBITRUM_CHAIN_IDS.contains(&chain_id) {
+            Some(Self {
+                l1_gas_oracle: L1GasOracle::Arbitrum,
+                ..Default::default()
+            })
+        } else if OPTIMISM_CHAIN_IDS.contains(&chain_id) {
+            Some(Self {
+                l1_gas_oracle: L1GasOracle::Optimism,
+                ..Default::default()
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the spec of the chain, unknown chains fall back to the mainnet defaults
+    pub fn for_chain(chain: &Chain) -> Self {
+        Self::known(chain.id()).unwrap_or_else(|| {
+            warn!(
+                "No chain spec for chain id {}, using the mainnet defaults",
+                chain.id()
+            );
+            Self::default()
+        })
+    }
+
+    /// Loads the spec of a custom network from the JSON file
+    pub fn from_file(path: ExpandedPathBuf) -> anyhow::Result<Self> {
+        let spec = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&spec)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_spec_registry() {
+        assert_eq!(ChainSpec::known(1), Some(ChainSpec::default()));
+        assert_eq!(
+            ChainSpec::known(137).unwrap().min_priority_fee_per_gas,
+            U256::from(POLYGON_MIN_PRIORITY_FEE_PER_GAS)
+        );
+        assert_eq!(
+            ChainSpec::known(42161).unwrap().l1_gas_oracle,
+            L1GasOracle::Arbitrum
+        );
+        assert_eq!(
+            ChainSpec::known(8453).unwrap().l1_gas_oracle,
+            L1GasOracle::Optimism
+        );
+
+        // unknown chain falls back to the mainnet defaults
+        assert_eq!(ChainSpec::known(123_456), None);
+        assert_eq!(
+            ChainSpec::for_chain(&Chain::from(123_456_u64)),
+            ChainSpec::default()
+        );
+    }
+
+    #[test]
+    fn chain_spec_override() {
+        let spec: ChainSpec =
+            serde_json::from_str(r#"{"perUserOpOverhead": "0x4e20", "l1GasOracle": "optimism"}"#)
+                .unwrap();
+        assert_eq!(
+            spec,
+            ChainSpec {
+                per_user_op_overhead: U256::from(20_000),
+                l1_gas_oracle: L1GasOracle::Optimism,
+                ..Default::default()
+            }
+        );
+    }
+}
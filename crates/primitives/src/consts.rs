@@ -17,6 +17,10 @@ pub mod rpc_error_codes {
     pub const EXECUTION: i32 = -32521;
     pub const USER_OPERATION_HASH: i32 = -32601;
     pub const SANITY_CHECK: i32 = -32602;
+    pub const RATE_LIMITED: i32 = -32005;
+    pub const TIMEOUT: i32 = -32006;
+    pub const OVERSIZED_REQUEST: i32 = -32007;
+    pub const BATCH_TOO_LARGE: i32 = -32010;
 }
 
 /// Entities
@@ -24,4 +28,5 @@ pub mod entities {
     pub const FACTORY: &str = "factory";
     pub const ACCOUNT: &str = "account";
     pub const PAYMASTER: &str = "paymaster";
+    pub const AGGREGATOR: &str = "aggregator";
 }
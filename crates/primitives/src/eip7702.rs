@@ -0,0 +1,50 @@
+//! EIP-7702 delegated senders (EOAs with the code delegated to a smart account implementation)
+
+use ethers::types::Address;
+
+/// Prefix of the delegation designator (the code of the delegated EOA is the prefix followed by the delegate)
+pub const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+/// Length of the delegation designator
+pub const DELEGATION_LENGTH: usize = 23;
+/// Marker of the init code of the delegated sender (zero padded to the length of the factory address)
+pub const INIT_CODE_MARKER: [u8; 2] = [0x77, 0x02];
+
+/// Returns the delegate if the code is the delegation designator
+pub fn delegate_address(code: &[u8]) -> Option<Address> {
+    if code.len() == DELEGATION_LENGTH && code.starts_with(&DELEGATION_PREFIX) {
+        Some(Address::from_slice(&code[DELEGATION_PREFIX.len()..]))
+    } else {
+        None
+    }
+}
+
+/// Whether the init code is the marker of the delegated sender (instead of the factory and its data)
+pub fn is_delegation_init_code(init_code: &[u8]) -> bool {
+    init_code.starts_with(&INIT_CODE_MARKER)
+        && init_code[INIT_CODE_MARKER.len()..init_code.len().min(20)]
+            .iter()
+            .all(|b| *b == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delegation() {
+        let delegate = Address::random();
+        let code = [&DELEGATION_PREFIX[..], delegate.as_bytes()].concat();
+        assert_eq!(delegate_address(&code), Some(delegate));
+        assert_eq!(delegate_address(&code[..22]), None);
+        assert_eq!(delegate_address(&[0xfe; 23]), None);
+
+        assert!(is_delegation_init_code(&[0x77, 0x02]));
+        assert!(is_delegation_init_code(
+            &[&[0x77, 0x02][..], &[0; 18], &[0xaa; 4]].concat()
+        ));
+        assert!(!is_delegation_init_code(&[]));
+        assert!(!is_delegation_init_code(
+            &[&[0x77, 0x02][..], &[0xaa; 18]].concat()
+        ));
+    }
+}
@@ -1,8 +1,12 @@
 #![allow(dead_code)]
 
+pub mod batch;
 pub mod bundler;
 pub mod chain;
 pub mod consts;
+pub mod eip7702;
+pub mod metrics;
+mod packed_user_operation;
 pub mod reputation;
 pub mod sanity;
 pub mod simulation;
@@ -12,11 +16,15 @@ mod utils;
 mod wallet;
 
 pub use bundler::Mode as BundlerMode;
-pub use chain::Chain;
+pub use chain::{Chain, ChainSpec};
+pub use packed_user_operation::{
+    PackedUserOperation, PackedUserOperationError, PaymasterAndData, UserOperationVariant,
+};
 pub use uopool::Mode as UoPoolMode;
 pub use user_operation::{
-    UserOperation, UserOperationByHash, UserOperationGasEstimation, UserOperationHash,
-    UserOperationPartial, UserOperationReceipt,
+    CallGasEstimationMethod, DummySignatures, UserOperation, UserOperationByHash,
+    UserOperationGasEstimation, UserOperationHash, UserOperationPartial, UserOperationReceipt,
+    UserOperationsPerAggregator,
 };
 pub use utils::get_address;
 pub use wallet::Wallet;
@@ -0,0 +1,413 @@
+//! Prometheus metrics of the mempool and bundling
+//!
+//! Metrics are collected in the global [METRICS] registry and rendered in the Prometheus text exposition
+//! format.
+
+use ethers::types::U256;
+use lazy_static::lazy_static;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Buckets (in seconds) of the simulation duration histogram
+pub const SIMULATION_DURATION_BUCKETS: [f64; 10] =
+    [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+/// Buckets (in seconds) of the bundle inclusion latency histogram
+pub const BUNDLE_INCLUSION_BUCKETS: [f64; 10] =
+    [1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+/// Monotonically increasing counter
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        let _ = writeln!(out, "{} {}", self.name, self.get());
+    }
+}
+
+/// Counter with a single label
+pub struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl CounterVec {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, label_value: &str) {
+        let mut values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        *values.entry(label_value.to_string()).or_default() += 1;
+    }
+
+    pub fn get(&self, label_value: &str) -> u64 {
+        let values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        values.get(label_value).copied().unwrap_or_default()
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        let values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        for (label_value, value) in values.iter() {
+            let _ = writeln!(
+                out,
+                "{}{{{}=\"{label_value}\"}} {value}",
+                self.name, self.label
+            );
+        }
+    }
+}
+
+/// Gauge of a (possibly negative) amount, e.g., of wei
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: Mutex<f64>,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: Mutex::new(0.0),
+        }
+    }
+
+    pub fn set(&self, value: f64) {
+        *self.value.lock().unwrap_or_else(|err| err.into_inner()) = value;
+    }
+
+    pub fn add(&self, value: f64) {
+        *self.value.lock().unwrap_or_else(|err| err.into_inner()) += value;
+    }
+
+    pub fn get(&self) -> f64 {
+        *self.value.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "gauge");
+        let _ = writeln!(out, "{} {}", self.name, self.get());
+    }
+}
+
+/// Gauge with a single label
+pub struct GaugeVec {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl GaugeVec {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn set(&self, label_value: &str, value: u64) {
+        let mut values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        values.insert(label_value.to_string(), value);
+    }
+
+    pub fn get(&self, label_value: &str) -> u64 {
+        let values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        values.get(label_value).copied().unwrap_or_default()
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "gauge");
+        let values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        for (label_value, value) in values.iter() {
+            let _ = writeln!(
+                out,
+                "{}{{{}=\"{label_value}\"}} {value}",
+                self.name, self.label
+            );
+        }
+    }
+}
+
+/// Histogram of durations (in seconds)
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    buckets: &'static [f64],
+    // observations per bucket (not cumulative) and count and sum of all observations
+    values: Mutex<(Vec<u64>, u64, f64)>,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, help: &'static str, buckets: &'static [f64]) -> Self {
+        Self {
+            name,
+            help,
+            buckets,
+            values: Mutex::new((vec![0; buckets.len()], 0, 0.0)),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let mut values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(i) = self.buckets.iter().position(|le| secs <= *le) {
+            values.0[i] += 1;
+        }
+        values.1 += 1;
+        values.2 += secs;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.values.lock().unwrap_or_else(|err| err.into_inner()).1
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "histogram");
+        let values = self.values.lock().unwrap_or_else(|err| err.into_inner());
+        let mut cumulative = 0;
+        for (le, count) in self.buckets.iter().zip(values.0.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{le}\"}} {cumulative}", self.name);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", self.name, values.1);
+        let _ = writeln!(out, "{}_sum {}", self.name, values.2);
+        let _ = writeln!(out, "{}_count {}", self.name, values.1);
+    }
+}
+
+/// Converts the amount of wei to float (Prometheus values are floats), saturating above `u128`
+fn wei_to_f64(value: U256) -> f64 {
+    if value > U256::from(u128::MAX) {
+        u128::MAX as f64
+    } else {
+        value.as_u128() as f64
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Metrics of the mempool and bundling
+pub struct Metrics {
+    /// Number of user operations in the mempool (labeled by the mempool id)
+    pub mempool_size: GaugeVec,
+    pub uos_accepted: Counter,
+    /// Rejected user operations (labeled by the failed sanity or simulation check)
+    pub uos_rejected: CounterVec,
+    /// User operations that left the mempool without being included (labeled by the drop reason)
+    pub uos_dropped: CounterVec,
+    pub bundles: Counter,
+    /// Time from sending the bundle transaction until its inclusion
+    pub bundle_inclusion: Histogram,
+    /// Gas cost of the mined bundle transactions (in wei)
+    pub bundle_gas_cost: Gauge,
+    /// Refunds of the user operations credited to the beneficiary (`actualGasCost` of the `UserOperationEvent`s)
+    pub bundle_refund: Gauge,
+    /// Refunds minus the gas cost of all the mined bundles
+    pub bundle_profit: Gauge,
+    /// Refunds minus the gas cost of the latest mined bundle
+    pub last_bundle_profit: Gauge,
+    /// Mined bundles whose gas cost exceeded the refunds
+    pub unprofitable_bundles: Counter,
+    /// Duration of the validation simulation (`simulateValidation`)
+    pub simulation: Histogram,
+    /// Lookups of the simulation cache (labeled by `hit` or `miss`)
+    pub simulation_cache: CounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            mempool_size: GaugeVec::new(
+                "silius_mempool_size",
+                "Number of user operations in the mempool",
+                "mempool",
+            ),
+            uos_accepted: Counter::new(
+                "silius_user_operations_accepted_total",
+                "Number of user operations accepted into the mempool",
+            ),
+            uos_rejected: CounterVec::new(
+                "silius_user_operations_rejected_total",
+                "Number of rejected user operations by the reason",
+                "reason",
+            ),
+            uos_dropped: CounterVec::new(
+                "silius_user_operations_dropped_total",
+                "Number of user operations dropped from the mempool by the reason",
+                "reason",
+            ),
+            bundles: Counter::new("silius_bundles_total", "Number of sent bundles"),
+            bundle_inclusion: Histogram::new(
+                "silius_bundle_inclusion_seconds",
+                "Time from sending the bundle transaction until its inclusion",
+                &BUNDLE_INCLUSION_BUCKETS,
+            ),
+            bundle_gas_cost: Gauge::new(
+                "silius_bundle_gas_cost_wei",
+                "Gas cost of the mined bundle transactions",
+            ),
+            bundle_refund: Gauge::new(
+                "silius_bundle_refund_wei",
+                "Refunds of the user operations credited to the beneficiary",
+            ),
+            bundle_profit: Gauge::new(
+                "silius_bundle_profit_wei",
+                "Refunds minus the gas cost of all the mined bundles",
+            ),
+            last_bundle_profit: Gauge::new(
+                "silius_last_bundle_profit_wei",
+                "Refunds minus the gas cost of the latest mined bundle",
+            ),
+            unprofitable_bundles: Counter::new(
+                "silius_unprofitable_bundles_total",
+                "Number of mined bundles whose gas cost exceeded the refunds",
+            ),
+            simulation: Histogram::new(
+                "silius_simulation_seconds",
+                "Duration of the validation simulation",
+                &SIMULATION_DURATION_BUCKETS,
+            ),
+            simulation_cache: CounterVec::new(
+                "silius_simulation_cache_total",
+                "Number of simulation cache lookups by the result",
+                "result",
+            ),
+        }
+    }
+}
+
+impl Metrics {
+    /// Accounts the gas cost and the refunds of the mined bundle
+    pub fn observe_bundle_profit(&self, gas_cost: U256, refund: U256) {
+        let (gas_cost, refund) = (wei_to_f64(gas_cost), wei_to_f64(refund));
+        let profit = refund - gas_cost;
+        self.bundle_gas_cost.add(gas_cost);
+        self.bundle_refund.add(refund);
+        self.bundle_profit.add(profit);
+        self.last_bundle_profit.set(profit);
+        if profit < 0.0 {
+            self.unprofitable_bundles.inc();
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.mempool_size.render(&mut out);
+        self.uos_accepted.render(&mut out);
+        self.uos_rejected.render(&mut out);
+        self.uos_dropped.render(&mut out);
+        self.bundles.render(&mut out);
+        self.bundle_inclusion.render(&mut out);
+        self.bundle_gas_cost.render(&mut out);
+        self.bundle_refund.render(&mut out);
+        self.bundle_profit.render(&mut out);
+        self.last_bundle_profit.render(&mut out);
+        self.unprofitable_bundles.render(&mut out);
+        self.simulation.render(&mut out);
+        self.simulation_cache.render(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_render() {
+        let metrics = Metrics::default();
+        metrics.mempool_size.set("0x01", 3);
+        metrics.uos_accepted.inc();
+        metrics.uos_rejected.inc("SenderOverLimit");
+        metrics.uos_rejected.inc("SenderOverLimit");
+        metrics.uos_rejected.inc("Signature");
+        metrics.simulation.observe(Duration::from_millis(20));
+        metrics.simulation.observe(Duration::from_secs(10));
+        metrics.simulation_cache.inc("hit");
+        metrics.uos_dropped.inc("expired");
+
+        let out = metrics.render();
+        assert!(out.contains("# TYPE silius_mempool_size gauge\n"));
+        assert!(out.contains("silius_mempool_size{mempool=\"0x01\"} 3\n"));
+        assert!(out.contains("silius_user_operations_accepted_total 1\n"));
+        assert!(
+            out.contains("silius_user_operations_rejected_total{reason=\"SenderOverLimit\"} 2\n")
+        );
+        assert!(out.contains("silius_user_operations_rejected_total{reason=\"Signature\"} 1\n"));
+        assert!(out.contains("silius_bundles_total 0\n"));
+        assert!(out.contains("silius_simulation_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(out.contains("silius_simulation_seconds_bucket{le=\"0.025\"} 1\n"));
+        assert!(out.contains("silius_simulation_seconds_bucket{le=\"5\"} 1\n"));
+        assert!(out.contains("silius_simulation_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(out.contains("silius_simulation_seconds_count 2\n"));
+        assert!(out.contains("silius_bundle_inclusion_seconds_count 0\n"));
+        assert!(out.contains("silius_simulation_cache_total{result=\"hit\"} 1\n"));
+        assert!(out.contains("silius_user_operations_dropped_total{reason=\"expired\"} 1\n"));
+    }
+
+    #[test]
+    fn metrics_bundle_profit() {
+        let metrics = Metrics::default();
+        metrics.observe_bundle_profit(1_000.into(), 1_500.into());
+        metrics.observe_bundle_profit(2_000.into(), 1_200.into());
+
+        assert_eq!(metrics.bundle_gas_cost.get(), 3_000.0);
+        assert_eq!(metrics.bundle_refund.get(), 2_700.0);
+        assert_eq!(metrics.bundle_profit.get(), -300.0);
+        assert_eq!(metrics.last_bundle_profit.get(), -800.0);
+        assert_eq!(metrics.unprofitable_bundles.get(), 1);
+
+        let out = metrics.render();
+        assert!(out.contains("# TYPE silius_bundle_profit_wei gauge\n"));
+        assert!(out.contains("silius_bundle_profit_wei -300\n"));
+        assert!(out.contains("silius_unprofitable_bundles_total 1\n"));
+    }
+}
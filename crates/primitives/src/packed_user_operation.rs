@@ -0,0 +1,475 @@
+use super::utils::as_checksum;
+use crate::{UserOperation, UserOperationHash};
+use ethers::{
+    abi::AbiEncode,
+    prelude::{EthAbiCodec, EthAbiType},
+    types::{Address, Bytes, H256, U256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+
+/// Length of the paymaster address and gas limits at the start of the v0.7 paymaster and data
+const PAYMASTER_DATA_OFFSET: usize = 20 + 16 + 16;
+
+/// Packed user operation used by the entry point v0.7
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    EthAbiCodec,
+    EthAbiType,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PackedUserOperation {
+    /// Sender of the user operation
+    #[serde(serialize_with = "as_checksum")]
+    pub sender: Address,
+
+    /// Nonce (anti replay protection)
+    pub nonce: U256,
+
+    /// Init code for the account (needed if account not yet deployed and needs to be created)
+    pub init_code: Bytes,
+
+    /// The data that is passed to the sender during the main call
+    pub call_data: Bytes,
+
+    /// Verification gas limit (upper 128 bits) and call gas limit (lower 128 bits)
+    pub account_gas_limits: H256,
+
+    /// Gas that compensates the bundler
+    pub pre_verification_gas: U256,
+
+    /// Max priority fee per gas (upper 128 bits) and max fee per gas (lower 128 bits)
+    pub gas_fees: H256,
+
+    /// Paymaster address, paymaster verification gas limit, paymaster post-op gas limit and paymaster data
+    pub paymaster_and_data: Bytes,
+
+    /// Signature of the user operation
+    pub signature: Bytes,
+}
+
+impl PackedUserOperation {
+    /// Packs the user operation into bytes
+    pub fn pack(&self) -> Bytes {
+        self.clone().encode().into()
+    }
+
+    /// Packs the user operation without signature to bytes (used for calculating the hash)
+    pub fn pack_without_signature(&self) -> Bytes {
+        PackedUserOperationUnsigned::from(self.clone())
+            .encode()
+            .into()
+    }
+
+    /// Calculates the hash of the user operation
+    pub fn hash(&self, entry_point: &Address, chain_id: &U256) -> UserOperationHash {
+        H256::from_slice(
+            keccak256(
+                [
+                    keccak256(self.pack_without_signature().deref()).to_vec(),
+                    entry_point.encode(),
+                    chain_id.encode(),
+                ]
+                .concat(),
+            )
+            .as_slice(),
+        )
+        .into()
+    }
+
+    pub fn verification_gas_limit(&self) -> U256 {
+        unpack_u128_pair(&self.account_gas_limits).0
+    }
+
+    pub fn call_gas_limit(&self) -> U256 {
+        unpack_u128_pair(&self.account_gas_limits).1
+    }
+
+    pub fn max_priority_fee_per_gas(&self) -> U256 {
+        unpack_u128_pair(&self.gas_fees).0
+    }
+
+    pub fn max_fee_per_gas(&self) -> U256 {
+        unpack_u128_pair(&self.gas_fees).1
+    }
+
+    /// Returns the paymaster verification gas limit (packed after the paymaster address, zero without paymaster)
+    pub fn paymaster_verification_gas_limit(&self) -> U256 {
+        self.paymaster_and_data
+            .get(20..36)
+            .map_or(U256::zero(), U256::from_big_endian)
+    }
+
+    /// Returns the paymaster post-op gas limit (packed after the paymaster verification gas limit)
+    pub fn paymaster_post_op_gas_limit(&self) -> U256 {
+        self.paymaster_and_data
+            .get(36..PAYMASTER_DATA_OFFSET)
+            .map_or(U256::zero(), U256::from_big_endian)
+    }
+
+    /// Returns the prefund the payer (paymaster or sender) has to deposit on the entry point (all the gas limits,
+    /// including the paymaster ones, at `max_fee_per_gas`)
+    pub fn required_prefund(&self) -> U256 {
+        self.verification_gas_limit()
+            .saturating_add(self.call_gas_limit())
+            .saturating_add(self.paymaster_verification_gas_limit())
+            .saturating_add(self.paymaster_post_op_gas_limit())
+            .saturating_add(self.pre_verification_gas)
+            .saturating_mul(self.max_fee_per_gas())
+    }
+}
+
+/// Structured paymaster and data of the packed user operation (entry point v0.7)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PaymasterAndData {
+    pub paymaster: Address,
+    pub paymaster_verification_gas_limit: U256,
+    pub paymaster_post_op_gas_limit: U256,
+    pub paymaster_data: Bytes,
+}
+
+impl PaymasterAndData {
+    /// Parses the paymaster address, the 16-byte gas limits and the paymaster-specific data, none if there's no
+    /// paymaster
+    pub fn parse(paymaster_and_data: &Bytes) -> Result<Option<Self>, PackedUserOperationError> {
+        if paymaster_and_data.is_empty() {
+            return Ok(None);
+        }
+        if paymaster_and_data.len() < PAYMASTER_DATA_OFFSET {
+            return Err(PackedUserOperationError::MalformedPaymasterAndData {
+                paymaster_and_data: paymaster_and_data.clone(),
+            });
+        }
+
+        Ok(Some(Self {
+            paymaster: Address::from_slice(&paymaster_and_data[..20]),
+            paymaster_verification_gas_limit: U256::from_big_endian(&paymaster_and_data[20..36]),
+            paymaster_post_op_gas_limit: U256::from_big_endian(
+                &paymaster_and_data[36..PAYMASTER_DATA_OFFSET],
+            ),
+            paymaster_data: paymaster_and_data[PAYMASTER_DATA_OFFSET..].to_vec().into(),
+        }))
+    }
+}
+
+/// Packed user operation without signature (entry point v0.7)
+#[derive(EthAbiCodec, EthAbiType)]
+pub struct PackedUserOperationUnsigned {
+    pub sender: Address,
+    pub nonce: U256,
+    pub init_code: H256,
+    pub call_data: H256,
+    pub account_gas_limits: H256,
+    pub pre_verification_gas: U256,
+    pub gas_fees: H256,
+    pub paymaster_and_data: H256,
+}
+
+impl From<PackedUserOperation> for PackedUserOperationUnsigned {
+    fn from(value: PackedUserOperation) -> Self {
+        Self {
+            sender: value.sender,
+            nonce: value.nonce,
+            init_code: keccak256(value.init_code.deref()).into(),
+            call_data: keccak256(value.call_data.deref()).into(),
+            account_gas_limits: value.account_gas_limits,
+            pre_verification_gas: value.pre_verification_gas,
+            gas_fees: value.gas_fees,
+            paymaster_and_data: keccak256(value.paymaster_and_data.deref()).into(),
+        }
+    }
+}
+
+/// Error when converting the packed user operation (entry point v0.7) into the user operation (entry point v0.6)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PackedUserOperationError {
+    MalformedPaymasterAndData { paymaster_and_data: Bytes },
+}
+
+/// Converts the user operation (entry point v0.6) into the packed user operation (entry point v0.7)
+///
+/// Entry point v0.6 uses the verification gas limit also for the paymaster validation and post-op,
+/// so it is used for both paymaster gas limits. Gas values larger than 128 bits are saturated.
+impl From<UserOperation> for PackedUserOperation {
+    fn from(uo: UserOperation) -> Self {
+        let paymaster_and_data = if uo.paymaster_and_data.len() >= 20 {
+            let mut paymaster_and_data = uo.paymaster_and_data[..20].to_vec();
+            paymaster_and_data.extend_from_slice(&to_u128(uo.verification_gas_limit).to_be_bytes());
+            paymaster_and_data.extend_from_slice(&to_u128(uo.verification_gas_limit).to_be_bytes());
+            paymaster_and_data.extend_from_slice(&uo.paymaster_and_data[20..]);
+            paymaster_and_data.into()
+        } else {
+            uo.paymaster_and_data
+        };
+
+        Self {
+            sender: uo.sender,
+            nonce: uo.nonce,
+            init_code: uo.init_code,
+            call_data: uo.call_data,
+            account_gas_limits: pack_u128_pair(uo.verification_gas_limit, uo.call_gas_limit),
+            pre_verification_gas: uo.pre_verification_gas,
+            gas_fees: pack_u128_pair(uo.max_priority_fee_per_gas, uo.max_fee_per_gas),
+            paymaster_and_data,
+            signature: uo.signature,
+        }
+    }
+}
+
+/// Converts the packed user operation (entry point v0.7) into the user operation (entry point v0.6)
+///
+/// Paymaster gas limits are dropped from the paymaster and data, since entry point v0.6 doesn't support them.
+impl TryFrom<PackedUserOperation> for UserOperation {
+    type Error = PackedUserOperationError;
+
+    fn try_from(uo: PackedUserOperation) -> Result<Self, Self::Error> {
+        let paymaster_and_data = match PaymasterAndData::parse(&uo.paymaster_and_data)? {
+            Some(p) => [p.paymaster.as_bytes(), &p.paymaster_data].concat().into(),
+            None => Bytes::default(),
+        };
+
+        Ok(Self {
+            sender: uo.sender,
+            nonce: uo.nonce,
+            init_code: uo.init_code.clone(),
+            call_data: uo.call_data.clone(),
+            call_gas_limit: uo.call_gas_limit(),
+            verification_gas_limit: uo.verification_gas_limit(),
+            pre_verification_gas: uo.pre_verification_gas,
+            max_fee_per_gas: uo.max_fee_per_gas(),
+            max_priority_fee_per_gas: uo.max_priority_fee_per_gas(),
+            paymaster_and_data,
+            signature: uo.signature,
+        })
+    }
+}
+
+/// User operation for any of the supported entry point versions
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UserOperationVariant {
+    V0_6(UserOperation),
+    V0_7(PackedUserOperation),
+}
+
+impl UserOperationVariant {
+    pub fn sender(&self) -> Address {
+        match self {
+            Self::V0_6(uo) => uo.sender,
+            Self::V0_7(uo) => uo.sender,
+        }
+    }
+
+    pub fn nonce(&self) -> U256 {
+        match self {
+            Self::V0_6(uo) => uo.nonce,
+            Self::V0_7(uo) => uo.nonce,
+        }
+    }
+
+    /// Packs the user operation into bytes (layout depends on the entry point version)
+    pub fn pack(&self) -> Bytes {
+        match self {
+            Self::V0_6(uo) => uo.pack(),
+            Self::V0_7(uo) => uo.pack(),
+        }
+    }
+
+    /// Returns the prefund the payer has to deposit on the entry point (formula depends on the entry point version)
+    pub fn required_prefund(&self) -> U256 {
+        match self {
+            Self::V0_6(uo) => uo.required_prefund(),
+            Self::V0_7(uo) => uo.required_prefund(),
+        }
+    }
+
+    /// Calculates the hash of the user operation (preimage depends on the entry point version)
+    pub fn hash(&self, entry_point: &Address, chain_id: &U256) -> UserOperationHash {
+        match self {
+            Self::V0_6(uo) => uo.hash(entry_point, chain_id),
+            Self::V0_7(uo) => uo.hash(entry_point, chain_id),
+        }
+    }
+}
+
+impl From<UserOperation> for UserOperationVariant {
+    fn from(uo: UserOperation) -> Self {
+        Self::V0_6(uo)
+    }
+}
+
+impl From<PackedUserOperation> for UserOperationVariant {
+    fn from(uo: PackedUserOperation) -> Self {
+        Self::V0_7(uo)
+    }
+}
+
+fn to_u128(value: U256) -> u128 {
+    if value > U256::from(u128::MAX) {
+        u128::MAX
+    } else {
+        value.as_u128()
+    }
+}
+
+fn pack_u128_pair(high: U256, low: U256) -> H256 {
+    let mut packed = [0u8; 32];
+    packed[..16].copy_from_slice(&to_u128(high).to_be_bytes());
+    packed[16..].copy_from_slice(&to_u128(low).to_be_bytes());
+    packed.into()
+}
+
+fn unpack_u128_pair(packed: &H256) -> (U256, U256) {
+    (
+        U256::from_big_endian(&packed[..16]),
+        U256::from_big_endian(&packed[16..]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_required_prefund() {
+        let uo = UserOperation::default()
+            .call_gas_limit(200_000.into())
+            .verification_gas_limit(100_000.into())
+            .pre_verification_gas(21_000.into())
+            .max_fee_per_gas(10.into());
+
+        let uo_packed = PackedUserOperation::from(uo.clone());
+        assert_eq!(uo_packed.paymaster_verification_gas_limit(), U256::zero());
+        assert_eq!(uo_packed.required_prefund(), U256::from(3_210_000));
+
+        // paymaster gas limits are added (the conversion uses the verification gas limit for both, matching
+        // the v0.6 formula)
+        let uo = uo.paymaster_and_data(Address::random().as_bytes().to_vec().into());
+        let mut uo_packed = PackedUserOperation::from(uo.clone());
+        assert_eq!(
+            uo_packed.paymaster_verification_gas_limit(),
+            U256::from(100_000)
+        );
+        assert_eq!(uo_packed.paymaster_post_op_gas_limit(), U256::from(100_000));
+        assert_eq!(uo_packed.required_prefund(), U256::from(5_210_000));
+        assert_eq!(
+            UserOperationVariant::from(uo.clone()).required_prefund(),
+            UserOperationVariant::from(uo_packed.clone()).required_prefund()
+        );
+
+        let mut paymaster_and_data = uo_packed.paymaster_and_data.to_vec();
+        paymaster_and_data[36..52].copy_from_slice(&50_000_u128.to_be_bytes());
+        uo_packed.paymaster_and_data = paymaster_and_data.into();
+        assert_eq!(uo_packed.required_prefund(), U256::from(4_710_000));
+    }
+
+    #[test]
+    fn paymaster_and_data() {
+        let paymaster = Address::random();
+        let paymaster_and_data: Bytes = [
+            paymaster.as_bytes(),
+            &100_000_u128.to_be_bytes(),
+            &50_000_u128.to_be_bytes(),
+            &[0xab; 3],
+        ]
+        .concat()
+        .into();
+        assert_eq!(
+            PaymasterAndData::parse(&paymaster_and_data).unwrap(),
+            Some(PaymasterAndData {
+                paymaster,
+                paymaster_verification_gas_limit: 100_000.into(),
+                paymaster_post_op_gas_limit: 50_000.into(),
+                paymaster_data: vec![0xab; 3].into(),
+            })
+        );
+
+        assert_eq!(PaymasterAndData::parse(&Bytes::default()).unwrap(), None);
+        // missing post-op gas limit
+        assert!(matches!(
+            PaymasterAndData::parse(&paymaster_and_data[..40].to_vec().into()),
+            Err(PackedUserOperationError::MalformedPaymasterAndData { .. })
+        ));
+    }
+
+    #[test]
+    fn packed_user_operation_conversion() {
+        let uo = UserOperation::default()
+            .sender(
+                "0x9c5754De1443984659E1b3a8d1931D83475ba29C"
+                    .parse()
+                    .unwrap(),
+            )
+            .call_gas_limit(200_000.into())
+            .verification_gas_limit(100_000.into())
+            .pre_verification_gas(21_000.into())
+            .max_fee_per_gas(3_000_000_000_u64.into())
+            .max_priority_fee_per_gas(1_000_000_000.into())
+            .paymaster_and_data(
+                "0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990aabb"
+                    .parse()
+                    .unwrap(),
+            );
+
+        let uo_packed = PackedUserOperation::from(uo.clone());
+        assert_eq!(
+            uo_packed.account_gas_limits,
+            "0x000000000000000000000000000186a000000000000000000000000000030d40"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            uo_packed.gas_fees,
+            "0x0000000000000000000000003b9aca00000000000000000000000000b2d05e00"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(uo_packed.paymaster_and_data, "0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990000000000000000000000000000186a0000000000000000000000000000186a0aabb".parse::<Bytes>().unwrap());
+        assert_eq!(UserOperation::try_from(uo_packed.clone()).unwrap(), uo);
+
+        let entry_point = "0x0000000071727De22E5E9d8BAf0edAc6f37da032"
+            .parse()
+            .unwrap();
+        assert_ne!(
+            uo_packed.hash(&entry_point, &1.into()),
+            uo.hash(&entry_point, &1.into())
+        );
+        assert_eq!(
+            UserOperationVariant::from(uo_packed.clone()).hash(&entry_point, &1.into()),
+            uo_packed.hash(&entry_point, &1.into())
+        );
+
+        let uo_packed = PackedUserOperation {
+            paymaster_and_data: "0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990"
+                .parse()
+                .unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            UserOperation::try_from(uo_packed),
+            Err(PackedUserOperationError::MalformedPaymasterAndData { .. })
+        ));
+    }
+
+    #[test]
+    fn user_operation_variant_deserialize() {
+        let uo: UserOperationVariant =
+            serde_json::from_value(serde_json::to_value(UserOperation::default()).unwrap())
+                .unwrap();
+        assert!(matches!(uo, UserOperationVariant::V0_6(..)));
+
+        let uo: UserOperationVariant =
+            serde_json::from_value(serde_json::to_value(PackedUserOperation::default()).unwrap())
+                .unwrap();
+        assert!(matches!(uo, UserOperationVariant::V0_7(..)));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::utils::deserialize_u64;
 use educe::Educe;
 use ethers::types::{Address, U256};
 use serde::{Deserialize, Serialize};
@@ -5,26 +6,36 @@ use serde::{Deserialize, Serialize};
 pub const MIN_INCLUSION_RATE_DENOMINATOR: u64 = 10;
 pub const THROTTLING_SLACK: u64 = 10;
 pub const BAN_SLACK: u64 = 50;
+// Every hour, the number of seen and included user operations is multiplied by (1 - 1 / denominator)
+pub const HOURLY_DECAY_DENOMINATOR: u64 = 24;
 
 // If the paymaster is throttle, maximum amount in one bundle is 1.
 pub const THROTTLED_MAX_INCLUDE: u64 = 1;
 
-/// All possible reputation statuses
-#[derive(Clone, Copy, Educe, PartialEq, Eq, Serialize, Deserialize)]
+/// All possible reputation statuses (serialized as `"ok"`, `"throttled"` and `"banned"`)
+#[derive(Clone, Copy, Default, Educe, PartialEq, Eq, Serialize, Deserialize)]
 #[educe(Debug)]
+#[serde(rename_all = "lowercase")]
 pub enum ReputationStatus {
+    #[default]
     OK,
     THROTTLED,
     BANNED,
 }
 
 /// Reputation entry for entities
+///
+/// Serialized in the format of `debug_bundler_dumpReputation` (`{address, opsSeen, opsIncluded, status}`), the
+/// counters are accepted as numbers or hex strings and the status is optional when deserializing.
 #[derive(Clone, Copy, Educe, Eq, PartialEq, Serialize, Deserialize)]
 #[educe(Debug)]
 pub struct ReputationEntry {
     pub address: Address,
+    #[serde(rename = "opsSeen", deserialize_with = "deserialize_u64")]
     pub uo_seen: u64,
+    #[serde(rename = "opsIncluded", deserialize_with = "deserialize_u64")]
     pub uo_included: u64,
+    #[serde(default)]
     pub status: ReputationStatus,
 }
 
@@ -59,3 +70,46 @@ pub enum ReputationError {
         message: String,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reputation_entry_serde() {
+        let address = Address::random();
+        let entry = ReputationEntry {
+            address,
+            uo_seen: 20,
+            uo_included: 1,
+            status: ReputationStatus::THROTTLED,
+        };
+
+        assert_eq!(
+            serde_json::to_value(entry).unwrap(),
+            json!({
+                "address": address,
+                "opsSeen": 20,
+                "opsIncluded": 1,
+                "status": "throttled",
+            })
+        );
+
+        // counters as hex strings, status omitted
+        let entry: ReputationEntry = serde_json::from_value(json!({
+            "address": address,
+            "opsSeen": "0x14",
+            "opsIncluded": 1,
+        }))
+        .unwrap();
+        assert_eq!(entry.uo_seen, 20);
+        assert_eq!(entry.uo_included, 1);
+        assert_eq!(entry.status, ReputationStatus::OK);
+
+        assert_eq!(
+            serde_json::from_value::<ReputationStatus>(json!("banned")).unwrap(),
+            ReputationStatus::BANNED
+        );
+    }
+}
@@ -3,9 +3,14 @@ use ethers::{
     types::{Address, Bytes, U256},
 };
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use strum_macros::IntoStaticStr;
+
+/// Length of the ECDSA signature (`r`, `s` and `v`)
+pub const ECDSA_SIGNATURE_LENGTH: usize = 65;
 
 /// Error object for sanity check
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
 pub enum SanityCheckError {
     SenderOrInitCode {
         sender: Address,
@@ -14,6 +19,13 @@ pub enum SanityCheckError {
     FactoryVerification {
         init_code: Bytes,
     },
+    FactoryNotStaked {
+        factory: Address,
+        uos_in_mempool: usize,
+    },
+    FactoryThrottled {
+        factory: Address,
+    },
     HighVerificationGasLimit {
         verification_gas_limit: U256,
         max_verification_gas: U256,
@@ -25,6 +37,32 @@ pub enum SanityCheckError {
     PaymasterVerification {
         paymaster_and_data: Bytes,
     },
+    MalformedPaymasterData {
+        paymaster_and_data: Bytes,
+        message: String,
+    },
+    InvalidPaymasterGasLimit {
+        field: String,
+        gas_limit: U256,
+        max_gas_limit: U256,
+    },
+    StakeTooLow {
+        entity: String,
+        address: Address,
+        stake: U256,
+        min_stake: U256,
+    },
+    UnstakeDelayTooShort {
+        entity: String,
+        address: Address,
+        unstake_delay: U256,
+        min_unstake_delay: U256,
+    },
+    PaymasterDepositTooLow {
+        paymaster: Address,
+        deposit: U256,
+        required_deposit: U256,
+    },
     LowCallGasLimit {
         call_gas_limit: U256,
         call_gas_limit_expected: U256,
@@ -37,17 +75,83 @@ pub enum SanityCheckError {
         max_priority_fee_per_gas: U256,
         max_fee_per_gas: U256,
     },
-    LowMaxPriorityFeePerGas {
+    PriorityFeeTooLow {
         max_priority_fee_per_gas: U256,
         min_priority_fee_per_gas: U256,
     },
+    PriorityFeeTooHigh {
+        max_priority_fee_per_gas: U256,
+        max_allowed_priority_fee_per_gas: U256,
+    },
     SenderVerification {
         sender: Address,
         message: String,
     },
+    SenderOverLimit {
+        sender: Address,
+        uos_in_mempool: usize,
+        max_uos: usize,
+    },
+    MempoolFull {
+        effective_gas_price: U256,
+        min_effective_gas_price: U256,
+    },
+    SenderPenalized {
+        sender: Address,
+        retry_after: u64,
+    },
+    InvalidPaymasterSignature {
+        paymaster: Address,
+        signer: Address,
+    },
+    TooManyInnerCalls {
+        sender: Address,
+        inner_calls: usize,
+        max_inner_calls: usize,
+    },
+    SuspiciousCallData {
+        sender: Address,
+        target: Address,
+        message: String,
+    },
+    InvalidSignatureFormat {
+        sender: Address,
+        message: String,
+    },
+    SenderNotDelegated {
+        sender: Address,
+    },
+    SenderCodeTooSmall {
+        sender: Address,
+        code_size: usize,
+        min_code_size: usize,
+    },
+    InvalidNonce {
+        sender: Address,
+        key: U256,
+        sequence: u64,
+        expected_sequence: u64,
+    },
+    OpSizeTooLarge {
+        field: String,
+        size: usize,
+        max: usize,
+    },
+    InsufficientPrefund {
+        payer: Address,
+        balance: U256,
+        required_prefund: U256,
+    },
+    EntityBanned {
+        entity: String,
+        address: Address,
+    },
     Validation {
         message: String,
     },
+    Timeout {
+        message: String,
+    },
     MiddlewareError {
         message: String,
     },
@@ -63,3 +167,111 @@ impl<M: MiddlewareError> From<M> for SanityCheckError {
         }
     }
 }
+
+/// Expected shape of the signature of an account type
+///
+/// Only the length and the structure are checked (the signature isn't recovered), accounts without a configured
+/// shape aren't checked at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureShape {
+    /// ECDSA signature (65 bytes with `v` of 0, 1, 27 or 28) after the prefix of the given length, e.g., the
+    /// validation mode of the account
+    Ecdsa { prefix: usize },
+    /// Signature of the exact length
+    Length(usize),
+}
+
+impl SignatureShape {
+    /// Checks the signature has the shape, returns the reason otherwise
+    pub fn check(&self, signature: &[u8]) -> Result<(), String> {
+        match *self {
+            Self::Ecdsa { prefix } => {
+                if signature.len() != prefix + ECDSA_SIGNATURE_LENGTH {
+                    return Err(format!(
+                        "signature length {} isn't {} (ECDSA signature after {prefix} bytes)",
+                        signature.len(),
+                        prefix + ECDSA_SIGNATURE_LENGTH
+                    ));
+                }
+                let v = signature[signature.len() - 1];
+                if !matches!(v, 0 | 1 | 27 | 28) {
+                    return Err(format!("ECDSA signature v {v} isn't 0, 1, 27 or 28"));
+                }
+                Ok(())
+            }
+            Self::Length(len) if signature.len() != len => {
+                Err(format!("signature length {} isn't {len}", signature.len()))
+            }
+            Self::Length(_) => Ok(()),
+        }
+    }
+}
+
+/// Parses the shape from `ecdsa`, `ecdsa:<prefix length>` or `length:<length>`
+impl FromStr for SignatureShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, len) = match s.split_once(':') {
+            Some((kind, len)) => (
+                kind,
+                Some(
+                    len.parse::<usize>()
+                        .map_err(|_| format!("String {len} is not a valid length"))?,
+                ),
+            ),
+            None => (s, None),
+        };
+
+        match (kind.to_lowercase().as_str(), len) {
+            ("ecdsa", prefix) => Ok(Self::Ecdsa {
+                prefix: prefix.unwrap_or_default(),
+            }),
+            ("length", Some(len)) => Ok(Self::Length(len)),
+            _ => Err(format!("String {s} is not a valid signature shape")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_shape() {
+        let ecdsa = |prefix: usize, v: u8| {
+            let mut signature = vec![1; prefix + ECDSA_SIGNATURE_LENGTH];
+            signature[prefix + ECDSA_SIGNATURE_LENGTH - 1] = v;
+            signature
+        };
+
+        assert_eq!(
+            "ecdsa".parse::<SignatureShape>().unwrap(),
+            SignatureShape::Ecdsa { prefix: 0 }
+        );
+        assert_eq!(
+            "ecdsa:4".parse::<SignatureShape>().unwrap(),
+            SignatureShape::Ecdsa { prefix: 4 }
+        );
+        assert_eq!(
+            "length:96".parse::<SignatureShape>().unwrap(),
+            SignatureShape::Length(96)
+        );
+        assert!("length".parse::<SignatureShape>().is_err());
+        assert!("ecdsa:x".parse::<SignatureShape>().is_err());
+        assert!("webauthn".parse::<SignatureShape>().is_err());
+
+        let shape = SignatureShape::Ecdsa { prefix: 0 };
+        assert!(shape.check(&ecdsa(0, 27)).is_ok());
+        assert!(shape.check(&ecdsa(0, 1)).is_ok());
+        assert!(shape.check(&ecdsa(0, 29)).is_err());
+        assert!(shape.check(&ecdsa(4, 27)).is_err());
+        assert!(shape.check(&[]).is_err());
+        assert!(SignatureShape::Ecdsa { prefix: 4 }
+            .check(&ecdsa(4, 28))
+            .is_ok());
+
+        assert!(SignatureShape::Length(96).check(&[0; 96]).is_ok());
+        assert!(SignatureShape::Length(96).check(&[0; 65]).is_err());
+    }
+}
@@ -1,12 +1,14 @@
 use crate::consts::entities::{ACCOUNT, FACTORY, PAYMASTER};
 use ethers::{
+    abi::AbiDecode,
     prelude::{EthAbiCodec, EthAbiType},
     providers::MiddlewareError,
-    types::{Address, H256, U256},
+    types::{Address, Bytes, H256, U256},
 };
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use strum_macros::{EnumString, EnumVariantNames, IntoStaticStr};
 
 /// Markers used in entry point smart contracts to differentiate between parts of user operation
 // https://github.com/eth-infinitism/account-abstraction/blob/develop/contracts/core/EntryPoint.sol#L514
@@ -18,6 +20,18 @@ pub const LEVEL_TO_ENTITY: [&str; NUMBER_LEVELS] = [FACTORY, ACCOUNT, PAYMASTER]
 /// Time ineterval before user operation expires (in seconds)
 pub const EXPIRATION_TIMESTAMP_DIFF: u64 = 30;
 
+/// Precompiles which can be called during validation (0x01-0x09)
+pub const MAX_ALLOWED_PRECOMPILE: u64 = 9;
+/// Addresses below are reserved for precompiles
+pub const PRECOMPILE_RANGE_END: u64 = 0x10000;
+
+/// Whether the address is a precompile which can't be called during validation
+pub fn is_forbidden_precompile(address: &Address) -> bool {
+    let address = U256::from_big_endian(address.as_bytes());
+    address < U256::from(PRECOMPILE_RANGE_END)
+        && !(U256::one()..=U256::from(MAX_ALLOWED_PRECOMPILE)).contains(&address)
+}
+
 lazy_static! {
     pub static ref FORBIDDEN_OPCODES: HashSet<String> = {
         let mut set = HashSet::new();
@@ -40,14 +54,75 @@ lazy_static! {
         set
     };
     pub static ref CREATE2_OPCODE: String = "CREATE2".to_string();
+    pub static ref GAS_OPCODE: String = "GAS".to_string();
     pub static ref RETURN_OPCODE: String = "RETURN".to_string();
     pub static ref REVERT_OPCODE: String = "REVERT".to_string();
     pub static ref CREATE_OPCODE: String = "CREATE".to_string();
     pub static ref PAYMASTER_VALIDATION_FUNCTION: String = "validatePaymasterUserOp".to_string();
 }
 
+/// Entity types a simulation rule is enforced for
+///
+/// Enabled for all the entities by default (full spec compliance). Disabling an entity relaxes the rule for the
+/// validation of that entity only, e.g., the storage rules for a trusted paymaster of a private bundler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnabledEntities {
+    pub factory: bool,
+    pub account: bool,
+    pub paymaster: bool,
+}
+
+impl Default for EnabledEntities {
+    fn default() -> Self {
+        Self {
+            factory: true,
+            account: true,
+            paymaster: true,
+        }
+    }
+}
+
+impl EnabledEntities {
+    /// Enabled for all the entities except the listed ones
+    pub fn except(entities: &[String]) -> Result<Self, String> {
+        let mut enabled = Self::default();
+        for entity in entities {
+            match entity.as_str() {
+                FACTORY => enabled.factory = false,
+                ACCOUNT => enabled.account = false,
+                PAYMASTER => enabled.paymaster = false,
+                _ => return Err(format!("Unknown entity {entity}")),
+            }
+        }
+        Ok(enabled)
+    }
+
+    pub fn is_enabled(&self, entity: &str) -> bool {
+        match entity {
+            FACTORY => self.factory,
+            ACCOUNT => self.account,
+            PAYMASTER => self.paymaster,
+            _ => true,
+        }
+    }
+}
+
+/// Tracer of the validation of user operations (`debug_traceCall`)
+#[derive(Clone, Copy, Debug, Default, EnumString, EnumVariantNames, PartialEq, Eq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum TracerMode {
+    /// JavaScript tracer if the node supports it, native tracers otherwise
+    #[default]
+    Auto,
+    /// Custom JavaScript tracer (all the trace rules)
+    Js,
+    /// Native `callTracer` and `prestateTracer`, faster, but opcodes other than `CREATE*` and `SELFDESTRUCT` and
+    /// keccak preimages aren't traced
+    Native,
+}
+
 /// Error object for simulation
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
 pub enum SimulationCheckError {
     Signature {},
     Expiration {
@@ -55,6 +130,23 @@ pub enum SimulationCheckError {
         valid_until: U256,
         paymaster: Option<Address>,
     },
+    Expired {
+        valid_until: U256,
+        timestamp: U256,
+    },
+    NotYetValid {
+        valid_after: U256,
+        timestamp: U256,
+    },
+    InsufficientPrefund {
+        payer: Address,
+        funds: U256,
+        required_prefund: U256,
+    },
+    VerificationGasOverDeclared {
+        verification_gas_limit: U256,
+        verification_gas_used: U256,
+    },
     Validation {
         message: String,
     },
@@ -62,11 +154,32 @@ pub enum SimulationCheckError {
         entity: String,
         opcode: String,
     },
+    InvalidGasOpcode {
+        entity: String,
+    },
+    ForbiddenPrecompile {
+        entity: String,
+        address: Address,
+    },
     Execution {
         message: String,
+        reason: Option<String>,
+        revert_data: Bytes,
+    },
+    RevertedAtMaxGas {
+        max_gas: U256,
+        message: String,
+        reason: Option<String>,
+        revert_data: Bytes,
     },
-    StorageAccess {
+    StorageAccessViolation {
         slot: String,
+        address: Address,
+    },
+    PaymasterStorageViolation {
+        paymaster: Address,
+        slot: String,
+        address: Address,
     },
     Unstaked {
         entity: String,
@@ -75,10 +188,33 @@ pub enum SimulationCheckError {
     CallStack {
         message: String,
     },
-    CodeHashes {
-        message: String,
+    ForbiddenEntryPointCall {
+        from: Address,
+        selector: Bytes,
+    },
+    CodeHashChanged {
+        address: Address,
+    },
+    InvalidDelegation {
+        sender: Address,
+        delegate: Address,
+    },
+    SenderNotDeployed {
+        sender: Address,
+        factory: Address,
     },
     OutOfGas {},
+    Aggregator {
+        aggregator: Address,
+        message: String,
+    },
+    EntityBanned {
+        entity: String,
+        address: Address,
+    },
+    Timeout {
+        message: String,
+    },
     MiddlewareError {
         message: String,
     },
@@ -112,3 +248,402 @@ pub struct CodeHash {
     pub address: Address,
     pub hash: H256,
 }
+
+/// Decoded `FailedOp` revert of the entry point
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedOpInfo {
+    pub op_index: U256,
+    pub reason: String,
+}
+
+/// Revert of `simulateValidation` together with the raw revert data (used for debugging)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateValidationRevert {
+    pub failed_op: Option<FailedOpInfo>,
+    pub revert_data: Bytes,
+}
+
+/// Selector of `Error(string)` reverts
+pub const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)` reverts
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+/// Selector of `FailedOp(uint256,string)` reverts of the entry point
+pub const FAILED_OP_SELECTOR: [u8; 4] = [0x22, 0x02, 0x66, 0xb6];
+
+/// Selector of `ValidationResult` reverts of `simulateValidation`
+pub const VALIDATION_RESULT_SELECTOR: [u8; 4] = [0xe0, 0xcf, 0xf0, 0x5f];
+/// Selector of `ValidationResultWithAggregation` reverts of `simulateValidation`
+pub const VALIDATION_RESULT_WITH_AGGREGATION_SELECTOR: [u8; 4] = [0xfa, 0xec, 0xb4, 0xe4];
+
+/// `returnInfo` of the `simulateValidation` result
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, EthAbiCodec, EthAbiType)]
+#[serde(rename_all = "camelCase")]
+pub struct ReturnInfo {
+    pub pre_op_gas: U256,
+    pub prefund: U256,
+    pub sig_failed: bool,
+    pub valid_after: u64,
+    pub valid_until: u64,
+    pub paymaster_context: Bytes,
+}
+
+/// Stake of an entity in the `simulateValidation` result
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, EthAbiCodec, EthAbiType,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityStakeInfo {
+    pub stake: U256,
+    pub unstake_delay: U256,
+}
+
+/// Aggregator and its stake in the `simulateValidation` result
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, EthAbiCodec, EthAbiType,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatorStakeInfo {
+    pub aggregator: Address,
+    pub stake_info: EntityStakeInfo,
+}
+
+/// Decoded `ValidationResult`/`ValidationResultWithAggregation` revert of `simulateValidation`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationResultInfo {
+    pub return_info: ReturnInfo,
+    pub sender_info: EntityStakeInfo,
+    pub factory_info: EntityStakeInfo,
+    pub paymaster_info: EntityStakeInfo,
+    pub aggregator_info: Option<AggregatorStakeInfo>,
+}
+
+impl ValidationResultInfo {
+    /// Decodes the revert data of `simulateValidation`, returns `None` for other reverts (e.g., `FailedOp`)
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 4 {
+            return None;
+        }
+
+        let (selector, args) = data.split_at(4);
+        if selector == VALIDATION_RESULT_SELECTOR {
+            <(
+                ReturnInfo,
+                EntityStakeInfo,
+                EntityStakeInfo,
+                EntityStakeInfo,
+            )>::decode(args)
+            .ok()
+            .map(
+                |(return_info, sender_info, factory_info, paymaster_info)| Self {
+                    return_info,
+                    sender_info,
+                    factory_info,
+                    paymaster_info,
+                    aggregator_info: None,
+                },
+            )
+        } else if selector == VALIDATION_RESULT_WITH_AGGREGATION_SELECTOR {
+            <(
+                ReturnInfo,
+                EntityStakeInfo,
+                EntityStakeInfo,
+                EntityStakeInfo,
+                AggregatorStakeInfo,
+            )>::decode(args)
+            .ok()
+            .map(
+                |(return_info, sender_info, factory_info, paymaster_info, aggregator_info)| Self {
+                    return_info,
+                    sender_info,
+                    factory_info,
+                    paymaster_info,
+                    aggregator_info: Some(aggregator_info),
+                },
+            )
+        } else {
+            None
+        }
+    }
+}
+
+/// Validation data returned packed by `validateUserOp` and `validatePaymasterUserOp`
+///
+/// The low 160 bits are the aggregator (or 1 if the signature failed), followed by 48 bits of `validUntil`
+/// (0 means no expiration) and 48 bits of `validAfter`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValidationData {
+    pub aggregator: Option<Address>,
+    pub sig_failed: bool,
+    pub valid_after: u64,
+    pub valid_until: u64,
+}
+
+impl ValidationData {
+    /// Max value of the 48-bit timestamps
+    pub const MAX_TIMESTAMP: u64 = (1 << 48) - 1;
+
+    pub fn unpack(data: U256) -> Self {
+        let mut bytes = [0u8; 32];
+        data.to_big_endian(&mut bytes);
+        let aggregator = Address::from_slice(&bytes[12..]);
+        let timestamp = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte))
+        };
+        let valid_until = timestamp(&bytes[6..12]);
+
+        Self {
+            aggregator: (aggregator > Address::from_low_u64_be(1)).then_some(aggregator),
+            sig_failed: aggregator == Address::from_low_u64_be(1),
+            valid_after: timestamp(&bytes[..6]),
+            valid_until: if valid_until == 0 {
+                Self::MAX_TIMESTAMP
+            } else {
+                valid_until
+            },
+        }
+    }
+}
+
+/// Decodes the human-readable reason of the revert data (`Error(string)`, `Panic(uint256)` or `FailedOp`)
+pub fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    if data.len() < 4 {
+        return None;
+    }
+
+    let (selector, args) = data.split_at(4);
+    if selector == ERROR_SELECTOR {
+        String::decode(args).ok()
+    } else if selector == PANIC_SELECTOR {
+        U256::decode(args)
+            .ok()
+            .map(|code| format!("panic: {} (0x{code:x})", panic_reason(code)))
+    } else if selector == FAILED_OP_SELECTOR {
+        <(U256, String)>::decode(args)
+            .ok()
+            .map(|(_, reason)| reason)
+    } else {
+        None
+    }
+}
+
+// https://docs.soliditylang.org/en/latest/control-structures.html#panic-via-assert-and-error-via-require
+fn panic_reason(code: U256) -> &'static str {
+    match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to zero-initialized function",
+        _ => "unknown panic",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{abi::AbiEncode, utils::id};
+
+    #[test]
+    fn revert_reason_decoding() {
+        assert_eq!(id("Error(string)"), ERROR_SELECTOR);
+        assert_eq!(id("Panic(uint256)"), PANIC_SELECTOR);
+        assert_eq!(id("FailedOp(uint256,string)"), FAILED_OP_SELECTOR);
+
+        let data = [
+            ERROR_SELECTOR.to_vec(),
+            "insufficient funds".to_string().encode(),
+        ]
+        .concat();
+        assert_eq!(
+            decode_revert_reason(&data),
+            Some("insufficient funds".to_string())
+        );
+
+        let data = [PANIC_SELECTOR.to_vec(), U256::from(0x11).encode()].concat();
+        assert_eq!(
+            decode_revert_reason(&data),
+            Some("panic: arithmetic overflow or underflow (0x11)".to_string())
+        );
+
+        let data = [
+            FAILED_OP_SELECTOR.to_vec(),
+            (
+                U256::zero(),
+                "AA23 reverted: insufficient funds".to_string(),
+            )
+                .encode(),
+        ]
+        .concat();
+        assert_eq!(
+            decode_revert_reason(&data),
+            Some("AA23 reverted: insufficient funds".to_string())
+        );
+
+        assert_eq!(decode_revert_reason(&[0xde, 0xad]), None);
+        assert_eq!(decode_revert_reason(&[0xde, 0xad, 0xbe, 0xef]), None);
+    }
+
+    #[test]
+    fn validation_result_decoding() {
+        assert_eq!(
+            id("ValidationResult((uint256,uint256,bool,uint48,uint48,bytes),(uint256,uint256),(uint256,uint256),(uint256,uint256))"),
+            VALIDATION_RESULT_SELECTOR
+        );
+        assert_eq!(
+            id("ValidationResultWithAggregation((uint256,uint256,bool,uint48,uint48,bytes),(uint256,uint256),(uint256,uint256),(uint256,uint256),(address,(uint256,uint256)))"),
+            VALIDATION_RESULT_WITH_AGGREGATION_SELECTOR
+        );
+
+        let return_info = ReturnInfo {
+            pre_op_gas: 50_000.into(),
+            prefund: 1_000_000.into(),
+            sig_failed: true,
+            valid_after: 1_000,
+            valid_until: 2_000,
+            paymaster_context: vec![0x01, 0x02].into(),
+        };
+        let sender_info = EntityStakeInfo::default();
+        let factory_info = EntityStakeInfo {
+            stake: 1.into(),
+            unstake_delay: 2.into(),
+        };
+        let paymaster_info = EntityStakeInfo {
+            stake: 3.into(),
+            unstake_delay: 4.into(),
+        };
+        let aggregator_info = AggregatorStakeInfo {
+            aggregator: Address::random(),
+            stake_info: EntityStakeInfo {
+                stake: 5.into(),
+                unstake_delay: 6.into(),
+            },
+        };
+
+        let data = [
+            VALIDATION_RESULT_SELECTOR.to_vec(),
+            (
+                return_info.clone(),
+                sender_info,
+                factory_info,
+                paymaster_info,
+            )
+                .encode(),
+        ]
+        .concat();
+        assert_eq!(
+            ValidationResultInfo::decode(&data),
+            Some(ValidationResultInfo {
+                return_info: return_info.clone(),
+                sender_info,
+                factory_info,
+                paymaster_info,
+                aggregator_info: None,
+            })
+        );
+
+        let data = [
+            VALIDATION_RESULT_WITH_AGGREGATION_SELECTOR.to_vec(),
+            (
+                return_info.clone(),
+                sender_info,
+                factory_info,
+                paymaster_info,
+                aggregator_info,
+            )
+                .encode(),
+        ]
+        .concat();
+        assert_eq!(
+            ValidationResultInfo::decode(&data),
+            Some(ValidationResultInfo {
+                return_info,
+                sender_info,
+                factory_info,
+                paymaster_info,
+                aggregator_info: Some(aggregator_info),
+            })
+        );
+
+        let data = [
+            FAILED_OP_SELECTOR.to_vec(),
+            (U256::zero(), "AA23 reverted".to_string()).encode(),
+        ]
+        .concat();
+        assert_eq!(ValidationResultInfo::decode(&data), None);
+        assert_eq!(ValidationResultInfo::decode(&[0xe0, 0xcf]), None);
+    }
+
+    #[test]
+    fn validation_data_unpacking() {
+        let aggregator = Address::random();
+        let pack = |aggregator: Address, valid_until: u64, valid_after: u64| {
+            U256::from_big_endian(aggregator.as_bytes())
+                | (U256::from(valid_until) << 160)
+                | (U256::from(valid_after) << 208)
+        };
+
+        assert_eq!(
+            ValidationData::unpack(pack(aggregator, 2_000, 1_000)),
+            ValidationData {
+                aggregator: Some(aggregator),
+                sig_failed: false,
+                valid_after: 1_000,
+                valid_until: 2_000,
+            }
+        );
+        assert_eq!(
+            ValidationData::unpack(pack(Address::from_low_u64_be(1), 0, 0)),
+            ValidationData {
+                aggregator: None,
+                sig_failed: true,
+                valid_after: 0,
+                valid_until: ValidationData::MAX_TIMESTAMP,
+            }
+        );
+        assert_eq!(
+            ValidationData::unpack(pack(Address::zero(), ValidationData::MAX_TIMESTAMP, 5)),
+            ValidationData {
+                aggregator: None,
+                sig_failed: false,
+                valid_after: 5,
+                valid_until: ValidationData::MAX_TIMESTAMP,
+            }
+        );
+    }
+
+    #[test]
+    fn forbidden_precompiles() {
+        for address in [1_u64, 5, 9] {
+            assert!(!is_forbidden_precompile(&Address::from_low_u64_be(address)));
+        }
+        for address in [0_u64, 0x0a, 0x100, 0xffff] {
+            assert!(is_forbidden_precompile(&Address::from_low_u64_be(address)));
+        }
+        assert!(!is_forbidden_precompile(&Address::from_low_u64_be(
+            PRECOMPILE_RANGE_END
+        )));
+        assert!(!is_forbidden_precompile(&Address::random()));
+    }
+
+    #[test]
+    fn enabled_entities() {
+        let enabled = EnabledEntities::except(&[PAYMASTER.into()]).unwrap();
+        assert!(enabled.is_enabled(ACCOUNT));
+        assert!(enabled.is_enabled(FACTORY));
+        assert!(!enabled.is_enabled(PAYMASTER));
+        assert_eq!(
+            EnabledEntities::except(&[]).unwrap(),
+            EnabledEntities::default()
+        );
+        assert!(EnabledEntities::except(&["bundler".into()]).is_err());
+    }
+}
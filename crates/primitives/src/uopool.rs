@@ -1,6 +1,7 @@
-use crate::{sanity::SanityCheckError, simulation::SimulationCheckError};
+use crate::{sanity::SanityCheckError, simulation::SimulationCheckError, UserOperationHash};
+use ethers::types::{Address, H256, U256, U64};
 use serde::{Deserialize, Serialize};
-use strum_macros::{EnumString, EnumVariantNames};
+use strum_macros::{EnumString, EnumVariantNames, IntoStaticStr};
 
 /// Verification modes for user operation mempool
 #[derive(Clone, Copy, Debug, EnumString, EnumVariantNames, PartialEq, Eq)]
@@ -8,6 +9,171 @@ use strum_macros::{EnumString, EnumVariantNames};
 pub enum Mode {
     Standard,
     Unsafe,
+    /// `simulateValidation` through `eth_call` only, for nodes without `debug_traceCall`
+    /// (the trace-dependent checks are skipped, so the validation isn't fully spec compliant)
+    Partial,
+}
+
+/// What to do with user operations received over P2P
+#[derive(Clone, Copy, Debug, Default, EnumString, EnumVariantNames, PartialEq, Eq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum P2pOpsPolicy {
+    /// Add into the mempool and include in local bundles
+    #[default]
+    Bundle,
+    /// Add into the mempool (for relaying), but don't include in local bundles
+    RelayOnly,
+    /// Reject user operations received over P2P
+    Ignore,
+}
+
+/// Order in which the candidate user operations are selected into the bundle
+#[derive(Clone, Copy, Debug, Default, EnumString, EnumVariantNames, PartialEq, Eq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum OrderingPolicy {
+    /// Highest effective gas price first
+    #[default]
+    EffectiveGasPrice,
+    /// Earliest arrival first
+    Fifo,
+    /// Highest effective gas price weighted by the reputation of the paymaster and factory first
+    PriorityScore,
+}
+
+/// Position of the user operation nonce relative to the on-chain nonce of the sender
+///
+/// Nonces are compared within the same 192-bit nonce key, so each key (parallel nonce channel) is tracked
+/// independently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonceStatus {
+    /// Nonce is lower than the on-chain nonce (the user operation can't be executed anymore)
+    Stale,
+    /// Nonce equals the on-chain nonce (the user operation can be bundled)
+    Ready,
+    /// Nonce is higher than the on-chain nonce (the user operation waits for its predecessors to land)
+    Queued,
+}
+
+/// Default min percentage of gas increase for replacing the user operation with the same sender and nonce
+pub const GAS_INCREASE_PERC: u64 = 10;
+
+/// Default time (in seconds) a user operation can stay in the mempool
+pub const DEFAULT_UO_TTL: u64 = 30 * 60;
+
+/// Lifecycle status of a user operation tracked by the mempool
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UserOperationStatus {
+    Pending,
+    Expired,
+    Invalidated,
+    Dropped,
+}
+
+/// Status of the user operation returned by `eth_getUserOperationStatus`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum UserOperationState {
+    /// Received by the bundler, the validation is in progress
+    Received,
+    /// Waiting in the mempool
+    Pending,
+    /// Part of the bundle transaction that was sent, but isn't mined yet
+    #[serde(rename_all = "camelCase")]
+    Submitted {
+        transaction_hash: H256,
+    },
+    /// Mined on chain, but without enough confirmations to be final (the block can still be reorged)
+    #[serde(rename_all = "camelCase")]
+    Mined {
+        block_hash: H256,
+        block_number: U64,
+        transaction_hash: H256,
+        confirmations: u64,
+    },
+    /// Mined on chain (found by the `UserOperationEvent` of the entry point) with enough confirmations
+    #[serde(rename_all = "camelCase")]
+    Included {
+        block_hash: H256,
+        block_number: U64,
+        transaction_hash: H256,
+    },
+    /// Rejected by the validation or evicted from the mempool
+    Rejected {
+        error: String,
+    },
+    NotFound,
+}
+
+/// Reason why a user operation was evicted from the mempool
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EvictionReason {
+    Expired,
+    Invalidated,
+    /// Dropped to make room for a higher paying user operation in the full mempool
+    Dropped,
+}
+
+impl From<EvictionReason> for UserOperationStatus {
+    fn from(reason: EvictionReason) -> Self {
+        match reason {
+            EvictionReason::Expired => Self::Expired,
+            EvictionReason::Invalidated => Self::Invalidated,
+            EvictionReason::Dropped => Self::Dropped,
+        }
+    }
+}
+
+/// Reason why a user operation left the mempool (without being included on chain, except for `Bundled`)
+///
+/// Reported by the structured drop event of the mempool and used as the label of the dropped user operations
+/// metric.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, IntoStaticStr)]
+#[serde(rename_all = "camelCase")]
+#[strum(serialize_all = "snake_case")]
+pub enum DropReason {
+    /// Outlived the TTL or its `validUntil`
+    Expired,
+    /// Replaced by the user operation with the same sender and nonce paying more
+    Replaced,
+    /// Included on chain by the bundle
+    Bundled,
+    /// Dropped to make room in the full mempool or because of a banned entity
+    Evicted,
+    /// Failed the validation before or during bundling (stale nonce, missing prefund, failing in the bundle)
+    RevalidationFailed,
+    /// Part of the orphaned block, but invalid on the new chain
+    ReorgLost,
+}
+
+impl From<EvictionReason> for DropReason {
+    fn from(reason: EvictionReason) -> Self {
+        match reason {
+            EvictionReason::Expired => Self::Expired,
+            EvictionReason::Invalidated => Self::RevalidationFailed,
+            EvictionReason::Dropped => Self::Evicted,
+        }
+    }
+}
+
+/// Notification sent to subscribers when a user operation is evicted from the mempool
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvictionEvent {
+    pub hash: UserOperationHash,
+    pub reason: EvictionReason,
+}
+
+/// Notification sent to subscribers when a user operation of the mempool is included on chain (observed
+/// `UserOperationEvent` of the entry point)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionEvent {
+    pub user_op_hash: UserOperationHash,
+    pub sender: Address,
+    pub success: bool,
+    pub actual_gas_cost: U256,
+    pub tx_hash: H256,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,6 +182,16 @@ pub enum ValidationError {
     Simulation(SimulationCheckError),
 }
 
+impl ValidationError {
+    /// Name of the failed check (variant of the sanity or simulation error)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sanity(err) => err.into(),
+            Self::Simulation(err) => err.into(),
+        }
+    }
+}
+
 impl From<SanityCheckError> for ValidationError {
     fn from(err: SanityCheckError) -> Self {
         Self::Sanity(err)
@@ -1,6 +1,6 @@
-use super::utils::as_checksum;
+use super::utils::{as_checksum, deserialize_option_u256, deserialize_u256, get_address};
 use ethers::{
-    abi::AbiEncode,
+    abi::{self, AbiDecode, AbiEncode, AbiError, AbiType, ParamType},
     prelude::{EthAbiCodec, EthAbiType},
     types::{Address, Bytes, Log, TransactionReceipt, H256, U256, U64},
     utils::keccak256,
@@ -9,6 +9,8 @@ use rustc_hex::FromHexError;
 use serde::{Deserialize, Serialize};
 use ssz_rs::Sized;
 use std::{
+    collections::HashMap,
+    fmt,
     ops::{AddAssign, Deref},
     slice::Windows,
     str::FromStr,
@@ -35,6 +37,7 @@ pub struct UserOperation {
     pub sender: Address,
 
     /// Nonce (anti replay protection)
+    #[serde(deserialize_with = "deserialize_u256")]
     pub nonce: U256,
 
     /// Init code for the account (needed if account not yet deployed and needs to be created)
@@ -44,18 +47,23 @@ pub struct UserOperation {
     pub call_data: Bytes,
 
     /// The amount of gas to allocate for the main execution call
+    #[serde(deserialize_with = "deserialize_u256")]
     pub call_gas_limit: U256,
 
     /// The amount of gas to allocate for the verification step
+    #[serde(deserialize_with = "deserialize_u256")]
     pub verification_gas_limit: U256,
 
     /// The amount of gas to pay bundler to compensate for the pre-verification execution and calldata
+    #[serde(deserialize_with = "deserialize_u256")]
     pub pre_verification_gas: U256,
 
     /// Maximum fee per gas (similar to EIP-1559)
+    #[serde(deserialize_with = "deserialize_u256")]
     pub max_fee_per_gas: U256,
 
     /// Maximum priority fee per gas (similar to EIP-1559)
+    #[serde(deserialize_with = "deserialize_u256")]
     pub max_priority_fee_per_gas: U256,
 
     /// Address of paymaster sponsoring the user operation, followed by extra data to send to the paymaster (can be empty)
@@ -71,6 +79,26 @@ impl UserOperation {
         self.clone().encode().into()
     }
 
+    /// Unpacks the user operation from bytes (reverse of [pack](UserOperation::pack))
+    pub fn unpack(data: &Bytes) -> Result<Self, AbiError> {
+        let min_len: usize = match Self::param_type() {
+            ParamType::Tuple(params) => params.iter().map(abi::minimum_size).sum(),
+            _ => unreachable!(),
+        };
+
+        if data.len() < min_len || data.len() % 32 != 0 {
+            return Err(AbiError::DecodingError(abi::Error::Other(
+                format!(
+                    "Invalid length of packed user operation: expected at least {min_len} bytes (multiple of 32), got {}",
+                    data.len()
+                )
+                .into(),
+            )));
+        }
+
+        Self::decode(data)
+    }
+
     /// Packs the user operation without signature to bytes (used for calculating the hash)
     pub fn pack_without_signature(&self) -> Bytes {
         let user_operation_packed = UserOperationUnsigned::from(self.clone());
@@ -93,6 +121,43 @@ impl UserOperation {
         .into()
     }
 
+    /// Returns the gas price paid for the user operation with the given base fee
+    /// (`min(max_fee_per_gas, base_fee + max_priority_fee_per_gas)`)
+    pub fn effective_gas_price(&self, base_fee: U256) -> U256 {
+        self.max_fee_per_gas
+            .min(base_fee.saturating_add(self.max_priority_fee_per_gas))
+    }
+
+    /// Returns the max gas the entry point accounts for the user operation (`pre_verification_gas +
+    /// verification_gas_limit + call_gas_limit`, the verification gas limit is counted three times if the paymaster
+    /// is used, for the account and paymaster validation and the post-op)
+    pub fn max_gas(&self) -> U256 {
+        let mul = if self.paymaster_and_data.is_empty() {
+            1
+        } else {
+            3
+        };
+        self.call_gas_limit
+            .saturating_add(self.verification_gas_limit.saturating_mul(mul.into()))
+            .saturating_add(self.pre_verification_gas)
+    }
+
+    /// Returns the prefund the payer (paymaster or sender) has to deposit on the entry point
+    /// ([max_gas](UserOperation::max_gas) at `max_fee_per_gas`)
+    pub fn required_prefund(&self) -> U256 {
+        self.max_gas().saturating_mul(self.max_fee_per_gas)
+    }
+
+    /// Returns the nonce key (upper 192 bits of the nonce), each key is a separate nonce channel of the sender
+    pub fn nonce_key(&self) -> U256 {
+        self.nonce >> 64
+    }
+
+    /// Returns the nonce sequence (lower 64 bits of the nonce) within the nonce key
+    pub fn nonce_sequence(&self) -> u64 {
+        self.nonce.low_u64()
+    }
+
     // Builder pattern helpers
 
     /// Sets the sender of the user operation
@@ -203,6 +268,13 @@ impl FromStr for UserOperationHash {
     }
 }
 
+/// Full `0x`-prefixed hex (`Display` of `H256` abbreviates the middle bytes)
+impl fmt::Display for UserOperationHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
 impl UserOperationHash {
     #[inline]
     pub const fn as_fixed_bytes(&self) -> &[u8; 32] {
@@ -287,6 +359,15 @@ pub struct UserOperationReceipt {
     pub tx_receipt: TransactionReceipt,
 }
 
+/// User operations sharing the aggregator and the aggregated signature (entry point `handleAggregatedOps`)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationsPerAggregator {
+    pub user_operations: Vec<UserOperation>,
+    pub aggregator: Address,
+    pub signature: Bytes,
+}
+
 /// Struct that is returned from the RPC endpoint eth_getUserOperationByHash
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -300,17 +381,23 @@ pub struct UserOperationByHash {
 }
 
 /// User operation with all fields being optional
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UserOperationPartial {
     pub sender: Option<Address>,
+    #[serde(default, deserialize_with = "deserialize_option_u256")]
     pub nonce: Option<U256>,
     pub init_code: Option<Bytes>,
     pub call_data: Option<Bytes>,
+    #[serde(default, deserialize_with = "deserialize_option_u256")]
     pub call_gas_limit: Option<U256>,
+    #[serde(default, deserialize_with = "deserialize_option_u256")]
     pub verification_gas_limit: Option<U256>,
+    #[serde(default, deserialize_with = "deserialize_option_u256")]
     pub pre_verification_gas: Option<U256>,
+    #[serde(default, deserialize_with = "deserialize_option_u256")]
     pub max_fee_per_gas: Option<U256>,
+    #[serde(default, deserialize_with = "deserialize_option_u256")]
     pub max_priority_fee_per_gas: Option<U256>,
     pub paymaster_and_data: Option<Bytes>,
     pub signature: Option<Bytes>,
@@ -400,6 +487,67 @@ impl From<UserOperationPartial> for UserOperation {
     }
 }
 
+impl UserOperationPartial {
+    /// Converts into the user operation, a missing signature is replaced with the dummy signature configured for
+    /// the account type or entry point
+    pub fn into_user_operation(
+        self,
+        dummy_signatures: &DummySignatures,
+        entry_point: &Address,
+    ) -> UserOperation {
+        let dummy_signature = if self.signature.is_none() {
+            dummy_signatures.get(&self, entry_point)
+        } else {
+            None
+        };
+
+        let mut uo = UserOperation::from(self);
+        if let Some(signature) = dummy_signature {
+            uo.signature = signature;
+        }
+        uo
+    }
+}
+
+/// Dummy signatures of user operations estimated without a signature
+///
+/// Accounts expect signatures of different shapes and lengths, which affects the verification gas. The dummy
+/// signature is selected by the factory of the account (account type), then by the entry point, and falls back to
+/// the default. Without any dummy signature, the signature stays empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DummySignatures {
+    pub default: Option<Bytes>,
+    pub entry_points: HashMap<Address, Bytes>,
+    pub factories: HashMap<Address, Bytes>,
+}
+
+impl DummySignatures {
+    pub fn with_default(mut self, signature: Bytes) -> Self {
+        self.default = Some(signature);
+        self
+    }
+
+    pub fn with_entry_point(mut self, entry_point: Address, signature: Bytes) -> Self {
+        self.entry_points.insert(entry_point, signature);
+        self
+    }
+
+    pub fn with_factory(mut self, factory: Address, signature: Bytes) -> Self {
+        self.factories.insert(factory, signature);
+        self
+    }
+
+    pub fn get(&self, uo: &UserOperationPartial, entry_point: &Address) -> Option<Bytes> {
+        uo.init_code
+            .as_ref()
+            .and_then(|init_code| get_address(init_code))
+            .and_then(|factory| self.factories.get(&factory))
+            .or_else(|| self.entry_points.get(entry_point))
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
 /// Gas estimations for user operation (returned from the RPC endpoint eth_estimateUserOperationGas)
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -407,6 +555,18 @@ pub struct UserOperationGasEstimation {
     pub pre_verification_gas: U256,
     pub verification_gas_limit: U256,
     pub call_gas_limit: U256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_gas_estimation_method: Option<CallGasEstimationMethod>,
+}
+
+/// Method used to estimate the call gas limit of the user operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CallGasEstimationMethod {
+    /// `simulateHandleOp` of the entry point
+    SimulateHandleOp,
+    /// Binary search of the call gas with `eth_call` (entry point doesn't support `simulateHandleOp`)
+    EthCall,
 }
 
 fn ssz_pack_u256(
@@ -682,6 +842,26 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn required_prefund() {
+        let uo = UserOperation::default()
+            .call_gas_limit(100_000.into())
+            .verification_gas_limit(100_000.into())
+            .pre_verification_gas(50_000.into())
+            .max_fee_per_gas(10.into());
+
+        // (100_000 + 100_000 + 50_000) * 10
+        assert_eq!(uo.max_gas(), U256::from(250_000));
+        assert_eq!(uo.required_prefund(), U256::from(2_500_000));
+
+        // verification gas limit counted three times with the paymaster
+        let uo = uo.paymaster_and_data(Address::random().as_bytes().to_vec().into());
+        assert_eq!(uo.required_prefund(), U256::from(4_500_000));
+
+        let uo = uo.max_fee_per_gas(U256::MAX);
+        assert_eq!(uo.required_prefund(), U256::MAX);
+    }
+
     #[test]
     fn user_operation_pack() {
         let uos =  vec![
@@ -692,6 +872,115 @@ mod tests {
         assert_eq!(uos[1].pack(), "0x0000000000000000000000009c5754de1443984659e1b3a8d1931d83475ba29c0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000016000000000000000000000000000000000000000000000000000000000000001800000000000000000000000000000000000000000000000000000000000030d4000000000000000000000000000000000000000000000000000000000000186a0000000000000000000000000000000000000000000000000000000000000520800000000000000000000000000000000000000000000000000000000b2d05e00000000000000000000000000000000000000000000000000000000003b9aca0000000000000000000000000000000000000000000000000000000000000001a000000000000000000000000000000000000000000000000000000000000001c000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000417cb39607585dee8e297d0d7a669ad8c5e43975220b6773c10a138deadbc8ec864981de4b9b3c735288a217115fb33f8326a61ddabc60a534e3b5536515c70f931c00000000000000000000000000000000000000000000000000000000000000".parse::<Bytes>().unwrap());
     }
 
+    #[test]
+    fn dummy_signatures() {
+        let ep = Address::random();
+        let factory = Address::random();
+        let dummy_signatures = DummySignatures::default()
+            .with_entry_point(ep, vec![1; 65].into())
+            .with_factory(factory, vec![2; 97].into());
+
+        let uo = UserOperationPartial::default();
+        assert_eq!(
+            uo.clone()
+                .into_user_operation(&dummy_signatures, &ep)
+                .signature,
+            Bytes::from(vec![1; 65])
+        );
+        assert_eq!(
+            uo.clone()
+                .into_user_operation(&dummy_signatures, &Address::random())
+                .signature,
+            Bytes::default()
+        );
+        assert_eq!(
+            uo.clone()
+                .into_user_operation(
+                    &dummy_signatures.clone().with_default(vec![3; 65].into()),
+                    &Address::random()
+                )
+                .signature,
+            Bytes::from(vec![3; 65])
+        );
+
+        // account type of the factory
+        let uo_factory = UserOperationPartial {
+            init_code: Some([factory.as_bytes(), &[0x01][..]].concat().into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            uo_factory
+                .into_user_operation(&dummy_signatures, &ep)
+                .signature,
+            Bytes::from(vec![2; 97])
+        );
+
+        // signature is kept
+        let uo_signed = UserOperationPartial {
+            signature: Some(vec![4; 65].into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            uo_signed
+                .into_user_operation(&dummy_signatures, &ep)
+                .signature,
+            Bytes::from(vec![4; 65])
+        );
+    }
+
+    #[test]
+    fn user_operation_unpack() {
+        let uos = vec![
+            UserOperation::default(),
+            UserOperation::default()
+                .sender("0x9c5754De1443984659E1b3a8d1931D83475ba29C".parse().unwrap())
+                .nonce(1.into())
+                .init_code("0x9406Cc6185a346906296840746125a0E449764545fbfb9cf000000000000000000000000ce0fefa6f7979c4c9b5373e0f5105b7259092c6d0000000000000000000000000000000000000000000000000000000000000000".parse().unwrap())
+                .call_data("0xb61d27f6".parse().unwrap())
+                .call_gas_limit(200_000.into())
+                .verification_gas_limit(100_000.into())
+                .pre_verification_gas(21_000.into())
+                .max_fee_per_gas(3_000_000_000_u64.into())
+                .max_priority_fee_per_gas(1_000_000_000.into())
+                .paymaster_and_data("0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990".parse().unwrap())
+                .signature("0x7cb39607585dee8e297d0d7a669ad8c5e43975220b6773c10a138deadbc8ec864981de4b9b3c735288a217115fb33f8326a61ddabc60a534e3b5536515c70f931c".parse().unwrap()),
+        ];
+
+        for uo in uos {
+            assert_eq!(UserOperation::unpack(&uo.pack()).unwrap(), uo);
+        }
+
+        let packed = UserOperation::default().pack();
+        assert!(UserOperation::unpack(&Bytes::default()).is_err());
+        assert!(UserOperation::unpack(&packed.0.slice(..packed.len() - 32).into()).is_err());
+        assert!(UserOperation::unpack(&packed.0.slice(..packed.len() - 1).into()).is_err());
+    }
+
+    #[test]
+    fn user_operation_effective_gas_price() {
+        let uo = UserOperation::default()
+            .max_fee_per_gas(100.into())
+            .max_priority_fee_per_gas(10.into());
+
+        // capped by the max fee
+        assert_eq!(uo.effective_gas_price(95.into()), U256::from(100));
+        // capped by the base fee and priority fee
+        assert_eq!(uo.effective_gas_price(50.into()), U256::from(60));
+        // zero base fee
+        assert_eq!(uo.effective_gas_price(U256::zero()), U256::from(10));
+    }
+
+    #[test]
+    fn user_operation_nonce() {
+        let uo = UserOperation::default().nonce((U256::from(7) << 64) + U256::from(3));
+        assert_eq!(uo.nonce_key(), U256::from(7));
+        assert_eq!(uo.nonce_sequence(), 3);
+
+        let uo = UserOperation::default().nonce(U256::MAX);
+        assert_eq!(uo.nonce_key(), U256::MAX >> 64);
+        assert_eq!(uo.nonce_sequence(), u64::MAX);
+    }
+
     #[test]
     fn user_operation_pack_without_signature() {
         let uos =  vec![
@@ -758,6 +1047,102 @@ mod tests {
         );
     }
 
+    #[test]
+    fn user_operation_hash_format() {
+        let s = "0x95418c07086df02ff6bc9e8bdc150b380cb761beecc098630440bcec6e862702";
+        let uo_hash: UserOperationHash = s.parse().unwrap();
+
+        assert_eq!(format!("{uo_hash}"), s);
+        assert_eq!(
+            format!("{uo_hash}").parse::<UserOperationHash>().unwrap(),
+            uo_hash
+        );
+        assert_eq!(
+            "95418C07086DF02FF6BC9E8BDC150B380CB761BEECC098630440BCEC6E862702"
+                .parse::<UserOperationHash>()
+                .unwrap(),
+            uo_hash
+        );
+
+        assert_eq!(serde_json::to_string(&uo_hash).unwrap(), format!("\"{s}\""));
+        assert_eq!(
+            serde_json::from_str::<UserOperationHash>(&format!("\"{s}\"")).unwrap(),
+            uo_hash
+        );
+    }
+
+    #[test]
+    fn user_operation_deserialize_u256() {
+        let uo = |nonce: serde_json::Value, gas: serde_json::Value| {
+            serde_json::json!({
+                "sender": "0x9c5754De1443984659E1b3a8d1931D83475ba29C",
+                "nonce": nonce,
+                "initCode": "0x",
+                "callData": "0x",
+                "callGasLimit": gas,
+                "verificationGasLimit": gas,
+                "preVerificationGas": gas,
+                "maxFeePerGas": gas,
+                "maxPriorityFeePerGas": gas,
+                "paymasterAndData": "0x",
+                "signature": "0x",
+            })
+        };
+
+        let expected = UserOperation::default()
+            .sender(
+                "0x9c5754De1443984659E1b3a8d1931D83475ba29C"
+                    .parse()
+                    .unwrap(),
+            )
+            .nonce(1.into())
+            .call_gas_limit(100_000.into())
+            .verification_gas_limit(100_000.into())
+            .pre_verification_gas(100_000.into())
+            .max_fee_per_gas(100_000.into())
+            .max_priority_fee_per_gas(100_000.into());
+
+        for (nonce, gas) in [
+            (serde_json::json!("0x1"), serde_json::json!("0x186a0")),
+            (serde_json::json!("1"), serde_json::json!("100000")),
+            (serde_json::json!(1), serde_json::json!(100000)),
+        ] {
+            assert_eq!(
+                serde_json::from_value::<UserOperation>(uo(nonce, gas)).unwrap(),
+                expected
+            );
+        }
+
+        // malformed values are rejected with the field value in the error
+        let err = serde_json::from_value::<UserOperation>(uo(
+            serde_json::json!("0xzz"),
+            serde_json::json!("1"),
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("\"0xzz\" is not a valid U256"));
+        assert!(serde_json::from_value::<UserOperation>(uo(
+            serde_json::json!(-1),
+            serde_json::json!("1")
+        ))
+        .is_err());
+        assert!(serde_json::from_value::<UserOperation>(uo(
+            serde_json::json!("1.5"),
+            serde_json::json!("1")
+        ))
+        .is_err());
+
+        let uo: UserOperationPartial = serde_json::from_value(serde_json::json!({
+            "nonce": "7",
+            "callGasLimit": 5,
+            "maxFeePerGas": null,
+        }))
+        .unwrap();
+        assert_eq!(uo.nonce, Some(7.into()));
+        assert_eq!(uo.call_gas_limit, Some(5.into()));
+        assert_eq!(uo.max_fee_per_gas, None);
+        assert_eq!(uo.verification_gas_limit, None);
+    }
+
     #[test]
     fn user_operation_ssz() {
         let uo = UserOperation {
@@ -1,4 +1,12 @@
-use ethers::{types::Address, utils::to_checksum};
+use ethers::{
+    types::{Address, U256},
+    utils::to_checksum,
+};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer,
+};
+use std::fmt;
 
 /// Converts address to checksum address
 pub fn as_checksum<S>(val: &Address, s: S) -> Result<S::Ok, S::Error>
@@ -16,3 +24,85 @@ pub fn get_address(buf: &[u8]) -> Option<Address> {
         None
     }
 }
+
+/// U256 deserialized from a hex string (`"0x1"`), a decimal string (`"1"`) or a number (`1`)
+struct FlexibleU256(U256);
+
+impl<'de> Deserialize<'de> for FlexibleU256 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FlexibleU256Visitor;
+
+        impl<'de> Visitor<'de> for FlexibleU256Visitor {
+            type Value = FlexibleU256;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a hex string, a decimal string or a non-negative integer")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(FlexibleU256(v.into()))
+            }
+
+            fn visit_u128<E: de::Error>(self, v: u128) -> Result<Self::Value, E> {
+                Ok(FlexibleU256(v.into()))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map(|v| FlexibleU256(v.into()))
+                    .map_err(|_| E::custom(format!("negative value {v} is not a valid U256")))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                Err(E::custom(format!(
+                    "number {v} is not a valid U256 (too large integers must be passed as strings)"
+                )))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                let s = v.trim();
+                let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                    Some("") => Ok(U256::zero()),
+                    Some(hex) => U256::from_str_radix(hex, 16).map_err(|err| err.to_string()),
+                    None => U256::from_dec_str(s).map_err(|err| err.to_string()),
+                };
+                value
+                    .map(FlexibleU256)
+                    .map_err(|err| E::custom(format!("string {v:?} is not a valid U256: {err}")))
+            }
+        }
+
+        deserializer.deserialize_any(FlexibleU256Visitor)
+    }
+}
+
+/// Deserializes U256 from a hex string, a decimal string or a number (SDKs differ in the representation)
+pub fn deserialize_u256<'de, D>(deserializer: D) -> Result<U256, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    FlexibleU256::deserialize(deserializer).map(|v| v.0)
+}
+
+/// Deserializes u64 from a hex string, a decimal string or a number
+pub fn deserialize_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let v = FlexibleU256::deserialize(deserializer)?.0;
+    if v > U256::from(u64::MAX) {
+        return Err(de::Error::custom(format!("{v} overflows u64")));
+    }
+    Ok(v.as_u64())
+}
+
+/// Deserializes optional U256 from a hex string, a decimal string or a number
+pub fn deserialize_option_u256<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<FlexibleU256>::deserialize(deserializer).map(|v| v.map(|v| v.0))
+}
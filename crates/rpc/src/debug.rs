@@ -1,4 +1,4 @@
-use crate::{debug_api::DebugApiServer, error::JsonRpcError};
+use crate::{debug_api::DebugApiServer, error::JsonRpcError, utils::check_entry_point};
 use async_trait::async_trait;
 use ethers::types::{Address, H256};
 use jsonrpsee::{
@@ -8,15 +8,21 @@ use jsonrpsee::{
 use silius_grpc::{
     bundler_client::BundlerClient, uo_pool_client::UoPoolClient, GetAllReputationRequest,
     GetAllRequest, Mode as GrpcMode, SetModeRequest, SetReputationRequest, SetReputationResult,
+    SimulateUserOperationRequest,
 };
 use silius_primitives::{
-    bundler::DEFAULT_BUNDLE_INTERVAL, reputation::ReputationEntry, BundlerMode, UserOperation,
+    bundler::{BundleDump, DEFAULT_BUNDLE_INTERVAL},
+    reputation::ReputationEntry,
+    simulation::SimulateValidationRevert,
+    BundlerMode, UserOperation,
 };
 use tonic::Request;
 
 pub struct DebugApiServerImpl {
     pub uopool_grpc_client: UoPoolClient<tonic::transport::Channel>,
     pub bundler_grpc_client: BundlerClient<tonic::transport::Channel>,
+    /// Entry points of the uopool (see [supported_entry_points](crate::utils::supported_entry_points))
+    pub entry_points: Vec<Address>,
 }
 
 #[async_trait]
@@ -34,6 +40,8 @@ impl DebugApiServer for DebugApiServerImpl {
     }
 
     async fn dump_mempool(&self, ep: Address) -> RpcResult<Vec<UserOperation>> {
+        check_entry_point(&self.entry_points, &ep)?;
+
         let mut uopool_grpc_client = self.uopool_grpc_client.clone();
 
         let req = Request::new(GetAllRequest {
@@ -46,12 +54,12 @@ impl DebugApiServer for DebugApiServerImpl {
             .map_err(JsonRpcError::from)?
             .into_inner();
 
-        let mut uos: Vec<UserOperation> = res.uos.iter().map(|uo| uo.clone().into()).collect();
-        uos.sort_by(|a, b| a.nonce.cmp(&b.nonce));
-        Ok(uos)
+        Ok(res.uos.into_iter().map(Into::into).collect())
     }
 
     async fn set_reputation(&self, entries: Vec<ReputationEntry>, ep: Address) -> RpcResult<()> {
+        check_entry_point(&self.entry_points, &ep)?;
+
         let mut uopool_grpc_client = self.uopool_grpc_client.clone();
 
         let req = Request::new(SetReputationRequest {
@@ -77,6 +85,8 @@ impl DebugApiServer for DebugApiServerImpl {
     }
 
     async fn dump_reputation(&self, ep: Address) -> RpcResult<Vec<ReputationEntry>> {
+        check_entry_point(&self.entry_points, &ep)?;
+
         let mut uopool_grpc_client = self.uopool_grpc_client.clone();
 
         let request = Request::new(GetAllReputationRequest {
@@ -120,4 +130,46 @@ impl DebugApiServer for DebugApiServerImpl {
             Err(s) => Err(JsonRpcError::from(s).into()),
         }
     }
+
+    async fn dump_bundle(&self) -> RpcResult<Vec<BundleDump>> {
+        let mut bundler_grpc_client = self.bundler_grpc_client.clone();
+
+        let res = bundler_grpc_client
+            .dump_bundle(Request::new(()))
+            .await
+            .map_err(JsonRpcError::from)?
+            .into_inner();
+
+        serde_json::from_str(&res.data).map_err(|err| {
+            ErrorObjectOwned::owned(
+                INTERNAL_ERROR_CODE,
+                format!("Failed to parse the bundle: {err:?}"),
+                None::<bool>,
+            )
+        })
+    }
+
+    async fn simulate_user_operation(
+        &self,
+        uo: UserOperation,
+        ep: Address,
+    ) -> RpcResult<SimulateValidationRevert> {
+        check_entry_point(&self.entry_points, &ep)?;
+
+        let mut uopool_grpc_client = self.uopool_grpc_client.clone();
+
+        let req = Request::new(SimulateUserOperationRequest {
+            uo: Some(uo.into()),
+            ep: Some(ep.into()),
+        });
+
+        let res = uopool_grpc_client
+            .simulate_user_operation(req)
+            .await
+            .map_err(JsonRpcError::from)?
+            .into_inner();
+
+        Ok(serde_json::from_str::<SimulateValidationRevert>(&res.data)
+            .map_err(JsonRpcError::from)?)
+    }
 }
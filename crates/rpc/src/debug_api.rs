@@ -1,7 +1,10 @@
 pub use crate::debug::DebugApiServerImpl;
 use ethers::types::{Address, H256};
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
-use silius_primitives::{reputation::ReputationEntry, BundlerMode, UserOperation};
+use silius_primitives::{
+    bundler::BundleDump, reputation::ReputationEntry, simulation::SimulateValidationRevert,
+    BundlerMode, UserOperation,
+};
 
 #[rpc(server, namespace = "debug_bundler")]
 pub trait DebugApi {
@@ -26,4 +29,14 @@ pub trait DebugApi {
 
     #[method(name = "sendBundleNow")]
     async fn send_bundle_now(&self) -> RpcResult<H256>;
+
+    #[method(name = "dumpBundle")]
+    async fn dump_bundle(&self) -> RpcResult<Vec<BundleDump>>;
+
+    #[method(name = "simulateUserOperation")]
+    async fn simulate_user_operation(
+        &self,
+        user_operation: UserOperation,
+        entry_point: Address,
+    ) -> RpcResult<SimulateValidationRevert>;
 }
@@ -3,8 +3,8 @@ use jsonrpsee::types::{error::ErrorCode, ErrorObject, ErrorObjectOwned};
 use serde_json::json;
 use silius_primitives::{
     consts::rpc_error_codes::{
-        ENTITY_BANNED, EXECUTION, EXPIRATION, OPCODE, SANITY_CHECK, SIGNATURE, STAKE_TOO_LOW,
-        VALIDATION,
+        ENTITY_BANNED, EXECUTION, EXPIRATION, OPCODE, PAYMASTER, SANITY_CHECK, SIGNATURE,
+        SIGNATURE_AGGREGATOR, STAKE_TOO_LOW, TIMEOUT, VALIDATION,
     },
     reputation::ReputationError,
     sanity::SanityCheckError,
@@ -38,6 +38,21 @@ impl From<SanityCheckError> for JsonRpcError {
                 format!("Init code {init_code} is not valid (factory check)",),
                 None::<bool>,
             ),
+            SanityCheckError::FactoryNotStaked {
+                factory,
+                uos_in_mempool,
+            } => ErrorObject::owned(
+                STAKE_TOO_LOW,
+                format!(
+                    "Factory {factory:?} is not staked and already has {uos_in_mempool} user operations in the mempool",
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::FactoryThrottled { factory } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!("Factory {factory:?} is throttled"),
+                None::<bool>,
+            ),
             SanityCheckError::HighVerificationGasLimit {
                 verification_gas_limit,
                 max_verification_gas,
@@ -67,6 +82,64 @@ impl From<SanityCheckError> for JsonRpcError {
                     None::<bool>,
                 )
             },
+            SanityCheckError::MalformedPaymasterData {
+                paymaster_and_data,
+                message,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!("Paymaster and data {paymaster_and_data} is malformed: {message}"),
+                None::<bool>,
+            ),
+            SanityCheckError::InvalidPaymasterGasLimit {
+                field,
+                gas_limit,
+                max_gas_limit,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!(
+                    "Paymaster {field} {gas_limit} has to be non-zero and at most {max_gas_limit}"
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::StakeTooLow {
+                entity,
+                address,
+                stake,
+                min_stake,
+            } => ErrorObject::owned(
+                STAKE_TOO_LOW,
+                format!("{entity} {address:?} stake {stake} is lower than min stake {min_stake}",),
+                Some(json!({
+                    "entity": entity,
+                    "address": address,
+                    "minimumStake": min_stake,
+                })),
+            ),
+            SanityCheckError::UnstakeDelayTooShort {
+                entity,
+                address,
+                unstake_delay,
+                min_unstake_delay,
+            } => ErrorObject::owned(
+                STAKE_TOO_LOW,
+                format!("{entity} {address:?} unstake delay {unstake_delay} is lower than min unstake delay {min_unstake_delay}",),
+                Some(json!({
+                    "entity": entity,
+                    "address": address,
+                    "minimumUnstakeDelay": min_unstake_delay,
+                })),
+            ),
+            SanityCheckError::PaymasterDepositTooLow {
+                paymaster,
+                deposit,
+                required_deposit,
+            } => ErrorObject::owned(
+                PAYMASTER,
+                format!(
+                    "Paymaster {paymaster:?} deposit {deposit} is lower than required deposit {required_deposit}",
+                ),
+                None::<bool>,
+            ),
             SanityCheckError::LowCallGasLimit {
                 call_gas_limit,
                 call_gas_limit_expected,
@@ -97,7 +170,7 @@ impl From<SanityCheckError> for JsonRpcError {
                 ),
                 None::<bool>,
             ),
-            SanityCheckError::LowMaxPriorityFeePerGas {
+            SanityCheckError::PriorityFeeTooLow {
                 max_priority_fee_per_gas,
                 min_priority_fee_per_gas,
             } => ErrorObject::owned(
@@ -105,13 +178,153 @@ impl From<SanityCheckError> for JsonRpcError {
                 format!(
                     "Max priority fee per gas {max_priority_fee_per_gas} is lower than min priority fee per gas {min_priority_fee_per_gas}",
                 ),
-                None::<bool>,
+                Some(json!({
+                    "max_priority_fee_per_gas": max_priority_fee_per_gas,
+                    "min_priority_fee_per_gas": min_priority_fee_per_gas,
+                })),
+            ),
+            SanityCheckError::PriorityFeeTooHigh {
+                max_priority_fee_per_gas,
+                max_allowed_priority_fee_per_gas,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!(
+                    "Max priority fee per gas {max_priority_fee_per_gas} is higher than max allowed priority fee per gas {max_allowed_priority_fee_per_gas}",
+                ),
+                Some(json!({
+                    "max_priority_fee_per_gas": max_priority_fee_per_gas,
+                    "max_allowed_priority_fee_per_gas": max_allowed_priority_fee_per_gas,
+                })),
             ),
             SanityCheckError::SenderVerification { sender, message } => ErrorObject::owned(
                 SANITY_CHECK,
                 format!("Sender {sender} {message}",),
                 None::<bool>,
             ),
+            SanityCheckError::SenderOverLimit {
+                sender,
+                uos_in_mempool,
+                max_uos,
+            } => ErrorObject::owned(
+                STAKE_TOO_LOW,
+                format!(
+                    "Sender {sender:?} is not staked and already has {uos_in_mempool} user operations in the mempool (max {max_uos})",
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::MempoolFull {
+                effective_gas_price,
+                min_effective_gas_price,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!(
+                    "Mempool is full, effective gas price {effective_gas_price} has to be higher than {min_effective_gas_price}",
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::SenderPenalized { sender, retry_after } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!(
+                    "Sender {sender} is temporarily rejected because of recent failed validations, retry after {retry_after} seconds",
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::InvalidPaymasterSignature { paymaster, signer } => ErrorObject::owned(
+                SIGNATURE,
+                format!(
+                    "Paymaster {paymaster} sponsorship signature doesn't recover to signer {signer}",
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::TooManyInnerCalls {
+                sender,
+                inner_calls,
+                max_inner_calls,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!(
+                    "Sender {sender} batch has {inner_calls} inner calls, more than max inner calls {max_inner_calls}",
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::SuspiciousCallData {
+                sender,
+                target,
+                message,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!("Sender {sender} call data calls {target}: {message}"),
+                None::<bool>,
+            ),
+            SanityCheckError::InvalidSignatureFormat { sender, message } => ErrorObject::owned(
+                SIGNATURE,
+                format!("Sender {sender} signature is malformed: {message}"),
+                None::<bool>,
+            ),
+            SanityCheckError::SenderNotDelegated { sender } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!("Init code is the EIP-7702 marker, but the sender {sender} isn't delegated"),
+                None::<bool>,
+            ),
+            SanityCheckError::SenderCodeTooSmall {
+                sender,
+                code_size,
+                min_code_size,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!(
+                    "Sender {sender} code size {code_size} is lower than min code size {min_code_size}",
+                ),
+                None::<bool>,
+            ),
+            SanityCheckError::InvalidNonce {
+                sender,
+                key,
+                sequence,
+                expected_sequence,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!(
+                    "Sender {sender:?} nonce sequence {sequence} under key {key} is lower than the next sequence {expected_sequence}",
+                ),
+                Some(json!({
+                    "sender": sender,
+                    "key": key,
+                    "sequence": sequence,
+                    "expected_sequence": expected_sequence,
+                })),
+            ),
+            SanityCheckError::OpSizeTooLarge { field, size, max } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!("User operation {field} size {size} is larger than max size {max}"),
+                Some(json!({
+                    "field": field,
+                    "size": size,
+                    "max": max,
+                })),
+            ),
+            SanityCheckError::InsufficientPrefund {
+                payer,
+                balance,
+                required_prefund,
+            } => ErrorObject::owned(
+                VALIDATION,
+                format!(
+                    "Payer {payer:?} balance {balance} doesn't cover the required prefund {required_prefund}",
+                ),
+                Some(json!({
+                    "payer": payer,
+                    "balance": balance,
+                    "required_prefund": required_prefund,
+                })),
+            ),
+            SanityCheckError::EntityBanned { entity, address } => ErrorObject::owned(
+                ENTITY_BANNED,
+                format!("{entity} with address {address:?} is not allowed by the bundler"),
+                Some(json!({
+                    entity: address,
+                })),
+            ),
             SanityCheckError::Validation { message } => {
                 ErrorObject::owned(
                     VALIDATION,
@@ -119,6 +332,13 @@ impl From<SanityCheckError> for JsonRpcError {
                     None::<bool>,
                 )
             },
+            SanityCheckError::Timeout { message } => {
+                ErrorObject::owned(
+                    TIMEOUT,
+                    message,
+                    None::<bool>,
+                )
+            },
             SanityCheckError::MiddlewareError { message } => {
                 ErrorObject::owned(
                     ErrorCode::InternalError.code(),
@@ -165,6 +385,43 @@ impl From<SimulationCheckError> for JsonRpcError {
                     }
                 },
             ),
+            SimulationCheckError::Expired {
+                valid_until,
+                timestamp,
+            } => ErrorObject::owned(
+                EXPIRATION,
+                format!("User operation expired at {valid_until} (block timestamp with buffer {timestamp})"),
+                Some(json!({
+                    "valid_until": valid_until,
+                })),
+            ),
+            SimulationCheckError::NotYetValid {
+                valid_after,
+                timestamp,
+            } => ErrorObject::owned(
+                EXPIRATION,
+                format!("User operation is not valid until {valid_after} (block timestamp with buffer {timestamp})"),
+                Some(json!({
+                    "valid_after": valid_after,
+                })),
+            ),
+            SimulationCheckError::InsufficientPrefund {
+                payer,
+                funds,
+                required_prefund,
+            } => ErrorObject::owned(
+                VALIDATION,
+                format!("Payer {payer:?} funds {funds} don't cover required prefund (with safety factor) {required_prefund}"),
+                None::<bool>,
+            ),
+            SimulationCheckError::VerificationGasOverDeclared {
+                verification_gas_limit,
+                verification_gas_used,
+            } => ErrorObject::owned(
+                SANITY_CHECK,
+                format!("Verification gas limit {verification_gas_limit} is too high for the verification gas used {verification_gas_used}"),
+                None::<bool>,
+            ),
             SimulationCheckError::Validation { message } => {
                 ErrorObject::owned(VALIDATION, message, None::<bool>)
             }
@@ -173,26 +430,125 @@ impl From<SimulationCheckError> for JsonRpcError {
                 format!("{entity} uses banned opcode: {opcode}"),
                 None::<bool>,
             ),
-            SimulationCheckError::Execution { message } => {
-                ErrorObject::owned(EXECUTION, message, None::<bool>)
-            }
-            SimulationCheckError::StorageAccess { slot } => ErrorObject::owned(
+            SimulationCheckError::InvalidGasOpcode { entity } => ErrorObject::owned(
                 OPCODE,
-                format!("Storage access validation failed for slot: {slot}"),
+                format!("{entity} uses GAS opcode not followed by a call"),
+                Some(json!({
+                    "entity": entity,
+                })),
+            ),
+            SimulationCheckError::ForbiddenPrecompile { entity, address } => ErrorObject::owned(
+                OPCODE,
+                format!("{entity} calls forbidden precompile {address:?}"),
+                Some(json!({
+                    "entity": entity,
+                    "address": address,
+                })),
+            ),
+            SimulationCheckError::Execution {
+                message,
+                reason,
+                revert_data,
+            } => ErrorObject::owned(
+                EXECUTION,
+                message,
+                Some(json!({
+                    "reason": reason,
+                    "revert_data": revert_data,
+                })),
+            ),
+            SimulationCheckError::RevertedAtMaxGas {
+                max_gas,
+                message,
+                reason,
+                revert_data,
+            } => ErrorObject::owned(
+                EXECUTION,
+                format!("User operation execution reverts even with the maximum gas {max_gas}: {message}"),
+                Some(json!({
+                    "max_gas": max_gas,
+                    "reason": reason,
+                    "revert_data": revert_data,
+                })),
+            ),
+            SimulationCheckError::StorageAccessViolation { slot, address } => ErrorObject::owned(
+                OPCODE,
+                format!("Storage access validation failed for slot: {slot} of {address:?}"),
                 None::<bool>,
             ),
+            SimulationCheckError::PaymasterStorageViolation {
+                paymaster,
+                slot,
+                address,
+            } => ErrorObject::owned(
+                OPCODE,
+                format!(
+                    "Unstaked paymaster {paymaster:?} accessed slot: {slot} of {address:?} outside its own storage"
+                ),
+                Some(json!({
+                    "paymaster": paymaster,
+                })),
+            ),
             SimulationCheckError::Unstaked { entity, message } => {
                 ErrorObject::owned(OPCODE, format!("unstaked {entity} {message}"), None::<bool>)
             }
             SimulationCheckError::CallStack { message } => {
                 ErrorObject::owned(OPCODE, message, None::<bool>)
             }
-            SimulationCheckError::CodeHashes { message } => {
-                ErrorObject::owned(OPCODE, message, None::<bool>)
-            }
+            SimulationCheckError::ForbiddenEntryPointCall { from, selector } => ErrorObject::owned(
+                OPCODE,
+                format!("Forbidden call from {from:?} into the entry point (selector {selector}) during validation"),
+                Some(json!({
+                    "from": from,
+                    "selector": selector,
+                })),
+            ),
+            SimulationCheckError::CodeHashChanged { address } => ErrorObject::owned(
+                OPCODE,
+                format!("Code of {address:?} changed after the first validation"),
+                Some(json!({
+                    "address": address,
+                })),
+            ),
+            SimulationCheckError::InvalidDelegation { sender, delegate } => ErrorObject::owned(
+                VALIDATION,
+                format!("Sender {sender:?} is delegated to {delegate:?}, which isn't a smart account implementation"),
+                Some(json!({
+                    "sender": sender,
+                    "delegate": delegate,
+                })),
+            ),
+            SimulationCheckError::SenderNotDeployed { sender, factory } => ErrorObject::owned(
+                VALIDATION,
+                format!("Factory {factory:?} did not deploy the sender {sender:?}"),
+                Some(json!({
+                    "sender": sender,
+                    "factory": factory,
+                })),
+            ),
             SimulationCheckError::OutOfGas {} => {
                 ErrorObject::owned(OPCODE, "User operation out of gas", None::<bool>)
             }
+            SimulationCheckError::Aggregator {
+                aggregator,
+                message,
+            } => ErrorObject::owned(
+                SIGNATURE_AGGREGATOR,
+                format!("Aggregator {aggregator:?} {message}"),
+                Some(json!({
+                    "aggregator": aggregator,
+                })),
+            ),
+            SimulationCheckError::EntityBanned { entity, address } => ErrorObject::owned(
+                ENTITY_BANNED,
+                format!("{entity} with address {address:?} is not allowed by the bundler"),
+                Some(json!({
+                    entity: address,
+                })),
+            ),
+            SimulationCheckError::Timeout { message } => {
+                ErrorObject::owned(TIMEOUT, message, None::<bool>)
+            }
             SimulationCheckError::MiddlewareError { message } => {
                 ErrorObject::owned(ErrorCode::InternalError.code(), message, None::<bool>)
             }
@@ -1,17 +1,31 @@
-use crate::{error::JsonRpcError, eth_api::EthApiServer};
+use crate::{
+    error::JsonRpcError,
+    eth_api::{
+        EthApiServer, SendUserOperationResult, UserOperationEventFilter, MAX_SEND_USER_OPERATIONS,
+        USER_OPERATION_EVENT_SUBSCRIPTION,
+    },
+    utils::check_entry_point,
+};
 use async_trait::async_trait;
 use ethers::{
-    types::{Address, U64},
+    types::{spoof, Address, U64},
     utils::to_checksum,
 };
-use jsonrpsee::{core::RpcResult, types::ErrorObjectOwned};
+use jsonrpsee::{
+    core::{RpcResult, SubscriptionResult},
+    server::SubscriptionMessage,
+    types::{error::ErrorCode, ErrorObjectOwned},
+    PendingSubscriptionSink,
+};
 use silius_grpc::{
     uo_pool_client::UoPoolClient, AddRequest, AddResult, EstimateUserOperationGasRequest,
-    EstimateUserOperationGasResult, UserOperationHashRequest,
+    EstimateUserOperationGasResult, SubscribeUserOperationEventsRequest, UserOperationHashRequest,
 };
 use silius_primitives::{
-    consts::rpc_error_codes::USER_OPERATION_HASH, simulation::SimulationCheckError,
-    uopool::ValidationError, UserOperation, UserOperationByHash, UserOperationGasEstimation,
+    consts::rpc_error_codes::USER_OPERATION_HASH,
+    simulation::SimulationCheckError,
+    uopool::{InclusionEvent, UserOperationState, ValidationError},
+    DummySignatures, UserOperation, UserOperationByHash, UserOperationGasEstimation,
     UserOperationHash, UserOperationPartial, UserOperationReceipt,
 };
 use std::str::FromStr;
@@ -19,6 +33,9 @@ use tonic::Request;
 
 pub struct EthApiServerImpl {
     pub uopool_grpc_client: UoPoolClient<tonic::transport::Channel>,
+    pub dummy_signatures: DummySignatures,
+    /// Entry points of the uopool (see [supported_entry_points](crate::utils::supported_entry_points))
+    pub entry_points: Vec<Address>,
 }
 
 #[async_trait]
@@ -36,18 +53,10 @@ impl EthApiServer for EthApiServerImpl {
     }
 
     async fn supported_entry_points(&self) -> RpcResult<Vec<String>> {
-        let mut uopool_grpc_client = self.uopool_grpc_client.clone();
-
-        let res = uopool_grpc_client
-            .get_supported_entry_points(Request::new(()))
-            .await
-            .map_err(JsonRpcError::from)?
-            .into_inner();
-
-        return Ok(res
-            .eps
-            .into_iter()
-            .map(|ep| to_checksum(&ep.into(), None))
+        return Ok(self
+            .entry_points
+            .iter()
+            .map(|ep| to_checksum(ep, None))
             .collect());
     }
 
@@ -56,6 +65,8 @@ impl EthApiServer for EthApiServerImpl {
         uo: UserOperation,
         ep: Address,
     ) -> RpcResult<UserOperationHash> {
+        check_entry_point(&self.entry_points, &ep)?;
+
         let mut uopool_grpc_client = self.uopool_grpc_client.clone();
 
         let req = Request::new(AddRequest {
@@ -81,16 +92,54 @@ impl EthApiServer for EthApiServerImpl {
         .0)
     }
 
+    async fn send_user_operations(
+        &self,
+        uos: Vec<(UserOperationPartial, Address)>,
+    ) -> RpcResult<Vec<SendUserOperationResult>> {
+        if uos.len() > MAX_SEND_USER_OPERATIONS {
+            return Err(ErrorObjectOwned::owned(
+                ErrorCode::InvalidParams.code(),
+                format!(
+                    "Too many user operations ({}, max {MAX_SEND_USER_OPERATIONS})",
+                    uos.len()
+                ),
+                None::<bool>,
+            ));
+        }
+
+        let mut res = vec![];
+
+        // sequentially, so the user operations of the same sender are added in order
+        for (uo, ep) in uos {
+            res.push(
+                match self.send_user_operation(UserOperation::from(uo), ep).await {
+                    Ok(uo_hash) => SendUserOperationResult::Hash(uo_hash),
+                    Err(error) => SendUserOperationResult::Error { error },
+                },
+            );
+        }
+
+        Ok(res)
+    }
+
     async fn estimate_user_operation_gas(
         &self,
         uo: UserOperationPartial,
         ep: Address,
+        state_override: Option<spoof::State>,
     ) -> RpcResult<UserOperationGasEstimation> {
+        check_entry_point(&self.entry_points, &ep)?;
+
         let mut uopool_grpc_client = self.uopool_grpc_client.clone();
 
         let req = Request::new(EstimateUserOperationGasRequest {
-            uo: Some(UserOperation::from(uo).into()),
+            uo: Some(uo.into_user_operation(&self.dummy_signatures, &ep).into()),
             ep: Some(ep.into()),
+            state_override: state_override
+                .map(|state_override| serde_json::to_string(&state_override))
+                .transpose()
+                .map_err(JsonRpcError::from)?
+                .unwrap_or_default(),
         });
 
         let res = uopool_grpc_client
@@ -139,7 +188,7 @@ impl EthApiServer for EthApiServerImpl {
                                 actual_gas_cost: res.actual_gas_cost?.into(),
                                 actual_gas_used: res.actual_gas_used?.into(),
                                 success: res.success,
-                                reason: String::new(),
+                                reason: res.reason,
                                 logs: res.logs.into_iter().map(|l| l.into()).collect(),
                                 tx_receipt: res.tx_receipt?.into(),
                             })
@@ -214,4 +263,100 @@ impl EthApiServer for EthApiServerImpl {
             )),
         }
     }
+
+    async fn get_user_operation_status(&self, uo_hash: String) -> RpcResult<UserOperationState> {
+        let uo_hash = UserOperationHash::from_str(&uo_hash).map_err(|_| {
+            ErrorObjectOwned::owned(
+                USER_OPERATION_HASH,
+                "Missing/invalid userOpHash".to_string(),
+                None::<bool>,
+            )
+        })?;
+
+        let req = Request::new(UserOperationHashRequest {
+            hash: Some(uo_hash.into()),
+        });
+
+        let res = self
+            .uopool_grpc_client
+            .clone()
+            .get_user_operation_status(req)
+            .await
+            .map_err(JsonRpcError::from)?
+            .into_inner();
+
+        Ok(serde_json::from_str::<UserOperationState>(&res.data).map_err(JsonRpcError::from)?)
+    }
+
+    async fn subscribe(
+        &self,
+        pending: PendingSubscriptionSink,
+        kind: String,
+        filter: Option<UserOperationEventFilter>,
+    ) -> SubscriptionResult {
+        if kind != USER_OPERATION_EVENT_SUBSCRIPTION {
+            pending
+                .reject(ErrorObjectOwned::owned(
+                    ErrorCode::InvalidParams.code(),
+                    format!("Unsupported subscription {kind}"),
+                    None::<bool>,
+                ))
+                .await;
+            return Ok(());
+        }
+
+        let req = Request::new(SubscribeUserOperationEventsRequest {
+            sender: filter.and_then(|f| f.sender).map(Into::into),
+        });
+
+        let mut events = match self
+            .uopool_grpc_client
+            .clone()
+            .subscribe_user_operation_events(req)
+            .await
+        {
+            Ok(res) => res.into_inner(),
+            Err(s) => {
+                pending.reject(JsonRpcError::from(s)).await;
+                return Ok(());
+            }
+        };
+
+        let sink = pending.accept().await?;
+
+        loop {
+            let event = tokio::select! {
+                // unsubscribed or disconnected, dropping the gRPC stream cancels the subscription
+                _ = sink.closed() => break,
+                event = events.message() => event,
+            };
+
+            let event = match event {
+                Ok(Some(event)) => event,
+                _ => break,
+            };
+
+            let event = event.user_operation_hash.and_then(|uo_hash| {
+                Some(InclusionEvent {
+                    user_op_hash: uo_hash.into(),
+                    sender: event.sender?.into(),
+                    success: event.success,
+                    actual_gas_cost: event.actual_gas_cost?.into(),
+                    tx_hash: event.transaction_hash?.into(),
+                })
+            });
+
+            if let Some(event) = event {
+                if sink
+                    .send(SubscriptionMessage::from_json(&event)?)
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
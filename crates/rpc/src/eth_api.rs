@@ -1,11 +1,37 @@
 pub use crate::eth::EthApiServerImpl;
-use ethers::types::{Address, U64};
-use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use ethers::types::{spoof, Address, U64};
+use jsonrpsee::{
+    core::{RpcResult, SubscriptionResult},
+    proc_macros::rpc,
+    types::ErrorObjectOwned,
+};
+use serde::{Deserialize, Serialize};
 use silius_primitives::{
+    uopool::{InclusionEvent, UserOperationState},
     UserOperation, UserOperationByHash, UserOperationGasEstimation, UserOperationHash,
     UserOperationPartial, UserOperationReceipt,
 };
 
+/// Max number of user operations sent in one `eth_sendUserOperations` call
+pub const MAX_SEND_USER_OPERATIONS: usize = 32;
+
+/// Result of a single user operation of the batch (`eth_sendUserOperations`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum SendUserOperationResult {
+    Hash(UserOperationHash),
+    Error { error: ErrorObjectOwned },
+}
+
+/// Kind of the `eth_subscribe` subscription to `UserOperationEvent`s
+pub const USER_OPERATION_EVENT_SUBSCRIPTION: &str = "userOperationEvent";
+
+/// Filter of the `userOperationEvent` subscription
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserOperationEventFilter {
+    pub sender: Option<Address>,
+}
+
 #[rpc(server, namespace = "eth")]
 pub trait EthApi {
     #[method(name = "chainId")]
@@ -21,11 +47,20 @@ pub trait EthApi {
         entry_point: Address,
     ) -> RpcResult<UserOperationHash>;
 
+    /// Adds the user operations one by one, a failed user operation doesn't fail the whole batch
+    #[method(name = "sendUserOperations")]
+    async fn send_user_operations(
+        &self,
+        user_operations: Vec<(UserOperationPartial, Address)>,
+    ) -> RpcResult<Vec<SendUserOperationResult>>;
+
+    /// Estimates the gas of the user operation, the simulations run with the state overrides (if any)
     #[method(name = "estimateUserOperationGas")]
     async fn estimate_user_operation_gas(
         &self,
         user_operation: UserOperationPartial,
         entry_point: Address,
+        state_override: Option<spoof::State>,
     ) -> RpcResult<UserOperationGasEstimation>;
 
     #[method(name = "getUserOperationReceipt")]
@@ -39,4 +74,20 @@ pub trait EthApi {
         &self,
         user_operation_hash: String,
     ) -> RpcResult<Option<UserOperationByHash>>;
+
+    /// Status of the user operation (received, pending in the mempool, submitted in the bundle transaction,
+    /// included on chain or rejected)
+    #[method(name = "getUserOperationStatus")]
+    async fn get_user_operation_status(
+        &self,
+        user_operation_hash: String,
+    ) -> RpcResult<UserOperationState>;
+
+    /// Streams `UserOperationEvent`s of the user operations submitted to the bundler (WebSocket only)
+    #[subscription(name = "subscribe" => "subscription", unsubscribe = "unsubscribe", item = InclusionEvent)]
+    async fn subscribe(
+        &self,
+        kind: String,
+        filter: Option<UserOperationEventFilter>,
+    ) -> SubscriptionResult;
 }
@@ -5,8 +5,11 @@ pub mod debug_api;
 mod error;
 mod eth;
 pub mod eth_api;
+pub mod limits;
 pub mod middleware;
+pub mod rate_limit;
 mod rpc;
+pub mod utils;
 mod web3;
 pub mod web3_api;
 
@@ -0,0 +1,213 @@
+use hyper::{
+    body::{Bytes, HttpBody},
+    Body, Request, Response, StatusCode,
+};
+use serde::de::IgnoredAny;
+use serde_json::json;
+use silius_primitives::consts::rpc_error_codes::{BATCH_TOO_LARGE, OVERSIZED_REQUEST};
+use std::{
+    error::Error,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// Default max size of the request body (in bytes)
+pub const DEFAULT_MAX_REQUEST_BODY_SIZE: u32 = 10 * 1024 * 1024;
+/// Default max number of calls in the batch request
+pub const DEFAULT_MAX_BATCH_LEN: u32 = 100;
+
+/// Limits of the JSON-RPC requests checked before the calls are deserialized
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestLimits {
+    pub max_request_body_size: u32,
+    pub max_batch_len: u32,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+            max_batch_len: DEFAULT_MAX_BATCH_LEN,
+        }
+    }
+}
+
+/// Reads the body, none if it's bigger than the limit
+///
+/// The declared `Content-Length` is checked first, so the oversized bodies aren't read at all.
+async fn read_body(
+    content_length: Option<u64>,
+    mut body: Body,
+    limit: u32,
+) -> Result<Option<Bytes>, hyper::Error> {
+    let limit = limit as usize;
+    if content_length.map_or(false, |len| len > limit as u64) {
+        return Ok(None);
+    }
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+
+    Ok(Some(Bytes::from(buf)))
+}
+
+/// Number of calls of the batch request, none if it isn't a (valid) batch
+///
+/// The calls are skipped without being deserialized, invalid requests are left to the server to report.
+fn batch_len(body: &[u8]) -> Option<usize> {
+    if body.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'[') {
+        return None;
+    }
+    serde_json::from_slice::<Vec<IgnoredAny>>(body)
+        .ok()
+        .map(|calls| calls.len())
+}
+
+fn error_response(status: StatusCode, code: i32, message: String) -> Response<Body> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "error": {
+            "code": code,
+            "message": message,
+        },
+        "id": null,
+    });
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("Request limit response should be valid")
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RequestLimitLayer {
+    limits: RequestLimits,
+}
+
+impl RequestLimitLayer {
+    pub fn new(limits: RequestLimits) -> Self {
+        Self { limits }
+    }
+}
+
+impl<S> Layer<S> for RequestLimitLayer {
+    type Service = RequestLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLimitService {
+            inner,
+            limits: self.limits,
+        }
+    }
+}
+
+/// Rejects the requests with the body or the batch over the limits with the JSON-RPC error
+#[derive(Clone)]
+pub struct RequestLimitService<S> {
+    inner: S,
+    limits: RequestLimits,
+}
+
+impl<S> Service<Request<Body>> for RequestLimitService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Response: 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limits = self.limits;
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            let (req_h, req_b) = req.into_parts();
+            let content_length = req_h
+                .headers
+                .get(hyper::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok());
+
+            let req_bb =
+                match read_body(content_length, req_b, limits.max_request_body_size).await? {
+                    Some(req_bb) => req_bb,
+                    None => {
+                        return Ok(error_response(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            OVERSIZED_REQUEST,
+                            format!(
+                                "Request is too big (max {} bytes)",
+                                limits.max_request_body_size
+                            ),
+                        ))
+                    }
+                };
+
+            if let Some(len) = batch_len(&req_bb) {
+                if len > limits.max_batch_len as usize {
+                    return Ok(error_response(
+                        StatusCode::OK,
+                        BATCH_TOO_LARGE,
+                        format!(
+                            "Batch request is too big ({len} calls, max {})",
+                            limits.max_batch_len
+                        ),
+                    ));
+                }
+            }
+
+            inner
+                .call(Request::from_parts(req_h, Body::from(req_bb)))
+                .await
+                .map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn request_body_limit() {
+        let body = || Body::from(vec![b'a'; 100]);
+
+        assert_eq!(
+            read_body(None, body(), 100).await.unwrap().unwrap().len(),
+            100
+        );
+        assert!(read_body(None, body(), 99).await.unwrap().is_none());
+        // declared length is rejected before reading the body
+        assert!(read_body(Some(1000), body(), 100).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn request_batch_len() {
+        let call = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_chainId", "params": [] });
+
+        assert_eq!(
+            batch_len(json!([call, call, call]).to_string().as_bytes()),
+            Some(3)
+        );
+        assert_eq!(batch_len(b" \n[]"), Some(0));
+        assert_eq!(batch_len(call.to_string().as_bytes()), None);
+        assert_eq!(batch_len(b"[{\"jsonrpc\""), None);
+    }
+}
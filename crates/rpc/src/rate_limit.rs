@@ -0,0 +1,617 @@
+use async_trait::async_trait;
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+};
+use hyper::{Body, Request, Response};
+use serde_json::{json, Value};
+use silius_contracts::EntryPoint;
+use silius_primitives::consts::rpc_error_codes::RATE_LIMITED;
+use std::{
+    collections::HashMap,
+    error::Error,
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Instant,
+};
+use tower::{Layer, Service};
+
+/// Method of the JSON-RPC API the rate limit applies to
+pub const RATE_LIMITED_METHOD: &str = "eth_sendUserOperation";
+/// Batch method of the JSON-RPC API the rate limit applies to (each user operation of the batch is limited)
+pub const RATE_LIMITED_BATCH_METHOD: &str = "eth_sendUserOperations";
+/// Max number of buckets kept before the full ones are pruned
+const MAX_BUCKETS: usize = 10_000;
+
+/// Token bucket limit: up to `capacity` requests at once, refilled by `refill_per_sec` requests per second
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TokenBucket {
+    pub capacity: f64,
+    pub refill_per_sec: f64,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        Self {
+            capacity: f64::from(capacity.max(1)),
+            refill_per_sec,
+        }
+    }
+}
+
+/// Tells whether the sender is staked in the entry point (staked senders get the higher limit)
+#[async_trait]
+pub trait SenderStake: Send + Sync {
+    async fn is_staked(&self, entry_point: &Address, sender: &Address) -> bool;
+}
+
+/// Reads the stake of the sender from `getDepositInfo` of the entry point
+pub struct EntryPointStake<M: Middleware + 'static> {
+    pub eth_client: Arc<M>,
+    pub min_stake: U256,
+    pub min_unstake_delay: U256,
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> SenderStake for EntryPointStake<M> {
+    async fn is_staked(&self, entry_point: &Address, sender: &Address) -> bool {
+        match EntryPoint::new(self.eth_client.clone(), *entry_point)
+            .get_deposit_info(sender)
+            .await
+        {
+            Ok(info) => {
+                info.staked
+                    && U256::from(info.stake) >= self.min_stake
+                    && U256::from(info.unstake_delay_sec) >= self.min_unstake_delay
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum RateLimitKey {
+    Peer(IpAddr),
+    Sender(Address),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Rate limits `eth_sendUserOperation` and each user operation of `eth_sendUserOperations` per transport peer and
+/// per sender of the user operation
+///
+/// The peer is taken from the `X-Forwarded-For` (right-most address) or `X-Real-IP` headers set by the reverse
+/// proxy in front of the bundler (the server doesn't expose the socket address to the middleware), requests
+/// without them are limited per sender only. Limited calls of a batch get the error in the batch response.
+pub struct RateLimiter {
+    pub peer_limit: Option<TokenBucket>,
+    pub sender_limit: Option<TokenBucket>,
+    pub staked_sender_limit: Option<TokenBucket>,
+    stake: Option<Arc<dyn SenderStake>>,
+    buckets: Mutex<HashMap<RateLimitKey, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        peer_limit: Option<TokenBucket>,
+        sender_limit: Option<TokenBucket>,
+        staked_sender_limit: Option<TokenBucket>,
+    ) -> Self {
+        Self {
+            peer_limit,
+            sender_limit,
+            staked_sender_limit,
+            stake: None,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Looks up the stake of senders to apply the staked sender limit (unstaked limit applies otherwise)
+    pub fn with_sender_stake(mut self, stake: Arc<dyn SenderStake>) -> Self {
+        self.stake = Some(stake);
+        self
+    }
+
+    /// Takes `n` tokens from the bucket, none if there aren't enough
+    fn try_acquire(&self, key: RateLimitKey, limit: &TokenBucket, n: f64, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().expect("Rate limiter lock poisoned");
+
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * limit.refill_per_sec
+                    < limit.capacity
+            });
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: limit.capacity,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.updated).as_secs_f64() * limit.refill_per_sec)
+            .min(limit.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens < n {
+            return false;
+        }
+        bucket.tokens -= n;
+        true
+    }
+
+    /// Returns `n` tokens taken for a call that was rejected by another limit
+    fn release(&self, key: RateLimitKey, limit: &TokenBucket, n: f64) {
+        let mut buckets = self.buckets.lock().expect("Rate limiter lock poisoned");
+        if let Some(bucket) = buckets.get_mut(&key) {
+            bucket.tokens = (bucket.tokens + n).min(limit.capacity);
+        }
+    }
+
+    /// Whether the request of the peer is within the limit (consumes a token)
+    pub fn check_peer(&self, peer: IpAddr, now: Instant) -> bool {
+        self.check_peer_n(peer, 1, now)
+    }
+
+    /// Whether `n` user operations of the peer are within the limit (consumes `n` tokens)
+    pub fn check_peer_n(&self, peer: IpAddr, n: usize, now: Instant) -> bool {
+        self.peer_limit.as_ref().map_or(true, |limit| {
+            self.try_acquire(RateLimitKey::Peer(peer), limit, n as f64, now)
+        })
+    }
+
+    /// Whether the user operation of the sender is within the limit (consumes a token)
+    pub async fn check_sender(
+        &self,
+        entry_point: &Address,
+        sender: &Address,
+        now: Instant,
+    ) -> bool {
+        self.check_sender_n(entry_point, sender, 1, now).await
+    }
+
+    /// Whether `n` user operations of the sender are within the limit (consumes `n` tokens)
+    pub async fn check_sender_n(
+        &self,
+        entry_point: &Address,
+        sender: &Address,
+        n: usize,
+        now: Instant,
+    ) -> bool {
+        self.sender_limit(entry_point, sender)
+            .await
+            .map_or(true, |limit| {
+                self.try_acquire(RateLimitKey::Sender(*sender), limit, n as f64, now)
+            })
+    }
+
+    /// Limit of the sender, the staked sender limit if the sender is staked
+    async fn sender_limit(&self, entry_point: &Address, sender: &Address) -> Option<&TokenBucket> {
+        let staked = match (&self.stake, &self.staked_sender_limit) {
+            (Some(stake), Some(_)) => stake.is_staked(entry_point, sender).await,
+            _ => false,
+        };
+        if staked {
+            self.staked_sender_limit.as_ref()
+        } else {
+            self.sender_limit.as_ref()
+        }
+    }
+
+    /// Whether the user operations of the call are within the peer and sender limits
+    ///
+    /// The tokens are taken for all the user operations of the call or for none of them, so a rejected call
+    /// doesn't use up the limits.
+    pub async fn check_call(
+        &self,
+        peer: Option<IpAddr>,
+        uos: &[(Address, Address)],
+        now: Instant,
+    ) -> bool {
+        if uos.is_empty() {
+            return true;
+        }
+        if !peer.map_or(true, |peer| self.check_peer_n(peer, uos.len(), now)) {
+            return false;
+        }
+
+        let mut senders: Vec<((Address, Address), usize)> = vec![];
+        for uo in uos {
+            match senders.iter_mut().find(|(s, _)| s == uo) {
+                Some((_, n)) => *n += 1,
+                None => senders.push((*uo, 1)),
+            }
+        }
+
+        let mut acquired = vec![];
+        for ((entry_point, sender), n) in senders {
+            let limit = match self.sender_limit(&entry_point, &sender).await {
+                Some(limit) => limit,
+                None => continue,
+            };
+            if self.try_acquire(RateLimitKey::Sender(sender), limit, n as f64, now) {
+                acquired.push((sender, limit, n));
+                continue;
+            }
+
+            // give back the tokens taken for the call
+            if let (Some(peer), Some(limit)) = (peer, self.peer_limit.as_ref()) {
+                self.release(RateLimitKey::Peer(peer), limit, uos.len() as f64);
+            }
+            for (sender, limit, n) in acquired {
+                self.release(RateLimitKey::Sender(sender), limit, n as f64);
+            }
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Peer of the request forwarded by the reverse proxy
+///
+/// The right-most `X-Forwarded-For` address is the one appended by the proxy in front of the bundler, the addresses
+/// before it are sent by the client and can be spoofed.
+fn forwarded_peer(req: &Request<Body>) -> Option<IpAddr> {
+    let headers = req.headers();
+    headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .or_else(|| headers.get("x-real-ip").and_then(|v| v.to_str().ok()))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// (entry point, sender) of the user operations sent by the call, none if the call isn't rate limited
+fn call_user_operations(call: &Value) -> Option<Vec<(Address, Address)>> {
+    let uo = |uo: &Value, ep: &Value| -> Option<(Address, Address)> {
+        Some((
+            serde_json::from_value(ep.clone()).ok()?,
+            serde_json::from_value(uo["sender"].clone()).ok()?,
+        ))
+    };
+
+    if call["method"] == RATE_LIMITED_METHOD {
+        uo(&call["params"][0], &call["params"][1]).map(|uo| vec![uo])
+    } else if call["method"] == RATE_LIMITED_BATCH_METHOD {
+        Some(
+            call["params"][0]
+                .as_array()?
+                .iter()
+                .filter_map(|item| uo(&item[0], &item[1]))
+                .collect(),
+        )
+    } else {
+        None
+    }
+}
+
+/// Calls of the request (one for a single call) and whether it's a batch, none if it isn't valid JSON
+fn request_calls(body: &[u8]) -> Option<(Vec<Value>, bool)> {
+    match serde_json::from_slice::<Value>(body).ok()? {
+        Value::Array(calls) => Some((calls, true)),
+        call => Some((vec![call], false)),
+    }
+}
+
+fn rate_exceeded_error(id: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": {
+            "code": RATE_LIMITED,
+            "message": "request rate exceeded",
+        },
+        "id": id,
+    })
+}
+
+fn json_response(body: Value) -> Response<Body> {
+    Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("Rate exceeded response should be valid")
+}
+
+/// Adds the errors of the rate limited calls to the response of the rest of the batch
+async fn merge_batch_response(
+    res: Response<Body>,
+    errors: Vec<Value>,
+) -> Result<Response<Body>, hyper::Error> {
+    let (mut res_h, res_b) = res.into_parts();
+    let res_bb = hyper::body::to_bytes(res_b).await?;
+
+    let mut responses = match serde_json::from_slice::<Value>(&res_bb) {
+        Ok(Value::Array(responses)) => responses,
+        // notifications only
+        _ if res_bb.is_empty() => vec![],
+        _ => return Ok(Response::from_parts(res_h, Body::from(res_bb))),
+    };
+    responses.extend(errors);
+
+    res_h.headers.remove(hyper::header::CONTENT_LENGTH);
+    Ok(Response::from_parts(
+        res_h,
+        Body::from(Value::Array(responses).to_string()),
+    ))
+}
+
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: RateLimiter) -> Self {
+        Self {
+            limiter: Arc::new(limiter),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S> Service<Request<Body>> for RateLimitService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Response: 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limiter = self.limiter.clone();
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            let peer = forwarded_peer(&req);
+            let (req_h, req_b) = req.into_parts();
+            let req_bb = hyper::body::to_bytes(req_b).await?;
+
+            let (calls, batch) = match request_calls(&req_bb) {
+                Some(calls) => calls,
+                None => {
+                    return inner
+                        .call(Request::from_parts(req_h, Body::from(req_bb)))
+                        .await
+                        .map_err(Into::into)
+                }
+            };
+
+            let now = Instant::now();
+            let mut allowed = vec![];
+            let mut errors = vec![];
+            for call in calls {
+                match call_user_operations(&call) {
+                    Some(uos) if !limiter.check_call(peer, &uos, now).await => {
+                        errors.push(rate_exceeded_error(call["id"].clone()))
+                    }
+                    _ => allowed.push(call),
+                }
+            }
+
+            if errors.is_empty() {
+                return inner
+                    .call(Request::from_parts(req_h, Body::from(req_bb)))
+                    .await
+                    .map_err(Into::into);
+            }
+
+            // the limited calls get the error in place of their responses, the rest of the batch is served
+            if !batch {
+                return Ok(json_response(errors.remove(0)));
+            }
+            if allowed.is_empty() {
+                return Ok(json_response(Value::Array(errors)));
+            }
+
+            let mut req_h = req_h;
+            req_h.headers.remove(hyper::header::CONTENT_LENGTH);
+            let res = inner
+                .call(Request::from_parts(
+                    req_h,
+                    Body::from(Value::Array(allowed).to_string()),
+                ))
+                .await
+                .map_err(Into::into)?;
+            Ok(merge_batch_response(res, errors).await?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct MockStake(Address);
+
+    #[async_trait]
+    impl SenderStake for MockStake {
+        async fn is_staked(&self, _entry_point: &Address, sender: &Address) -> bool {
+            *sender == self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn token_buckets() {
+        let staked = Address::random();
+        let unstaked = Address::random();
+        let ep = Address::random();
+        let limiter = RateLimiter::new(
+            Some(TokenBucket::new(2, 1.0)),
+            Some(TokenBucket::new(1, 0.5)),
+            Some(TokenBucket::new(3, 1.0)),
+        )
+        .with_sender_stake(Arc::new(MockStake(staked)));
+        let now = Instant::now();
+
+        // unstaked sender gets one request, refilled after 2 seconds
+        assert!(limiter.check_sender(&ep, &unstaked, now).await);
+        assert!(!limiter.check_sender(&ep, &unstaked, now).await);
+        assert!(
+            !limiter
+                .check_sender(&ep, &unstaked, now + Duration::from_secs(1))
+                .await
+        );
+        assert!(
+            limiter
+                .check_sender(&ep, &unstaked, now + Duration::from_secs(3))
+                .await
+        );
+
+        // staked sender gets the higher limit
+        for _ in 0..3 {
+            assert!(limiter.check_sender(&ep, &staked, now).await);
+        }
+        assert!(!limiter.check_sender(&ep, &staked, now).await);
+
+        // peers are limited independently
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(limiter.check_peer(peer, now));
+        assert!(limiter.check_peer(peer, now));
+        assert!(!limiter.check_peer(peer, now));
+        assert!(limiter.check_peer("10.0.0.2".parse().unwrap(), now));
+    }
+
+    #[test]
+    fn rate_limited_request() {
+        let sender = Address::random();
+        let other = Address::random();
+        let ep = Address::random();
+        let call = |id: u64, method: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": [{ "sender": sender }, ep],
+            })
+        };
+
+        assert_eq!(
+            call_user_operations(&call(1, RATE_LIMITED_METHOD)),
+            Some(vec![(ep, sender)])
+        );
+        assert_eq!(call_user_operations(&call(1, "eth_chainId")), None);
+        // every user operation of the batch method is limited
+        assert_eq!(
+            call_user_operations(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": RATE_LIMITED_BATCH_METHOD,
+                "params": [[[{ "sender": sender }, ep], [{ "sender": other }, ep]]],
+            })),
+            Some(vec![(ep, sender), (ep, other)])
+        );
+
+        assert_eq!(
+            request_calls(
+                json!([call(1, "eth_chainId"), call(2, RATE_LIMITED_METHOD)])
+                    .to_string()
+                    .as_bytes()
+            )
+            .map(|(calls, batch)| (calls.len(), batch)),
+            Some((2, true))
+        );
+        assert_eq!(
+            request_calls(call(1, RATE_LIMITED_METHOD).to_string().as_bytes())
+                .map(|(calls, batch)| (calls.len(), batch)),
+            Some((1, false))
+        );
+        assert!(request_calls(b"not json").is_none());
+
+        // the address appended by the proxy, not the one sent by the client
+        let req = Request::post("/")
+            .header("x-forwarded-for", "10.0.0.1, 10.0.0.2")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(forwarded_peer(&req), Some("10.0.0.2".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_call() {
+        let sender = Address::random();
+        let other = Address::random();
+        let ep = Address::random();
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        let limiter = RateLimiter::new(
+            Some(TokenBucket::new(3, 0.0)),
+            Some(TokenBucket::new(2, 0.0)),
+            None,
+        );
+        let now = Instant::now();
+
+        // over the sender limit, no tokens are taken
+        assert!(
+            !limiter
+                .check_call(
+                    Some(peer),
+                    &[(ep, sender), (ep, other), (ep, sender), (ep, sender)],
+                    now
+                )
+                .await
+        );
+        assert!(
+            limiter
+                .check_call(Some(peer), &[(ep, sender), (ep, sender)], now)
+                .await
+        );
+        // over the peer limit
+        assert!(
+            !limiter
+                .check_call(Some(peer), &[(ep, other), (ep, other)], now)
+                .await
+        );
+        assert!(limiter.check_call(Some(peer), &[(ep, other)], now).await);
+        assert!(limiter.check_call(Some(peer), &[], now).await);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_batch_response() {
+        let res = Response::builder()
+            .header(hyper::header::CONTENT_LENGTH, "100")
+            .body(Body::from(
+                json!([{ "jsonrpc": "2.0", "id": 1, "result": "0x1" }]).to_string(),
+            ))
+            .unwrap();
+        let res = merge_batch_response(res, vec![rate_exceeded_error(json!(2))])
+            .await
+            .unwrap();
+        assert!(res.headers().get(hyper::header::CONTENT_LENGTH).is_none());
+
+        let body: Value =
+            serde_json::from_slice(&hyper::body::to_bytes(res.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body[0]["result"], "0x1");
+        assert_eq!(body[1]["id"], 2);
+        assert_eq!(body[1]["error"]["code"], RATE_LIMITED);
+    }
+}
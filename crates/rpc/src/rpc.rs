@@ -1,4 +1,8 @@
-use super::middleware::ProxyJsonRpcLayer;
+use super::{
+    limits::{RequestLimitLayer, RequestLimits},
+    middleware::ProxyJsonRpcLayer,
+    rate_limit::{RateLimitLayer, RateLimiter},
+};
 use anyhow::Error;
 use hyper::{http::HeaderValue, Method};
 use jsonrpsee::{
@@ -12,6 +16,8 @@ pub struct JsonRpcServer {
     listen_address: String,
     cors_layer: Option<CorsLayer>,
     proxy_layer: Option<ProxyJsonRpcLayer>,
+    rate_limit_layer: Option<RateLimitLayer>,
+    request_limits: RequestLimits,
     methods: Methods,
 }
 
@@ -21,6 +27,8 @@ impl JsonRpcServer {
             listen_address,
             cors_layer: None,
             proxy_layer: None,
+            rate_limit_layer: None,
+            request_limits: RequestLimits::default(),
             methods: Methods::new(),
         }
     }
@@ -55,6 +63,17 @@ impl JsonRpcServer {
         self
     }
 
+    pub fn with_rate_limit(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limit_layer = Some(RateLimitLayer::new(rate_limiter));
+        self
+    }
+
+    /// Limits of the request body size and the batch length (requests over them are rejected)
+    pub fn with_request_limits(mut self, request_limits: RequestLimits) -> Self {
+        self.request_limits = request_limits;
+        self
+    }
+
     pub fn add_method(&mut self, methods: impl Into<Methods>) -> Result<(), Error> {
         self.methods.merge(methods).map_err(|e| e.into())
     }
@@ -62,9 +81,12 @@ impl JsonRpcServer {
     pub async fn start(&self) -> anyhow::Result<ServerHandle> {
         let service = ServiceBuilder::new()
             .option_layer(self.cors_layer.clone())
+            .layer(RequestLimitLayer::new(self.request_limits))
+            .option_layer(self.rate_limit_layer.clone())
             .option_layer(self.proxy_layer.clone());
 
         let server = ServerBuilder::new()
+            .max_request_body_size(self.request_limits.max_request_body_size)
             .set_middleware(service)
             .build(&self.listen_address)
             .await?;
@@ -0,0 +1,53 @@
+use crate::error::JsonRpcError;
+use ethers::types::Address;
+use jsonrpsee::types::{error::ErrorCode, ErrorObject};
+use serde_json::json;
+use silius_grpc::uo_pool_client::UoPoolClient;
+use tonic::{Request, Status};
+
+/// Fetches the entry points the uopool is configured with (once, when the APIs are built)
+pub async fn supported_entry_points(
+    uopool_grpc_client: &UoPoolClient<tonic::transport::Channel>,
+) -> Result<Vec<Address>, Status> {
+    Ok(uopool_grpc_client
+        .clone()
+        .get_supported_entry_points(Request::new(()))
+        .await?
+        .into_inner()
+        .eps
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+/// Rejects the entry point the bundler isn't configured with (before any validation work is done)
+pub fn check_entry_point(eps: &[Address], ep: &Address) -> Result<(), JsonRpcError> {
+    if eps.contains(ep) {
+        return Ok(());
+    }
+
+    Err(JsonRpcError(ErrorObject::owned(
+        ErrorCode::InvalidParams.code(),
+        "unsupported entry point",
+        Some(json!({
+            "entry_point": ep,
+            "supported_entry_points": eps,
+        })),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_point_check() {
+        let ep = Address::random();
+
+        assert!(check_entry_point(&[ep], &ep).is_ok());
+
+        let err = check_entry_point(&[ep], &Address::random()).unwrap_err();
+        assert_eq!(err.0.code(), ErrorCode::InvalidParams.code());
+        assert_eq!(err.0.message(), "unsupported entry point");
+    }
+}
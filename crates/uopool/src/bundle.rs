@@ -0,0 +1,754 @@
+use ethers::types::{Address, U256};
+use silius_primitives::{
+    consts::entities::{ACCOUNT, FACTORY, PAYMASTER},
+    get_address,
+    reputation::ReputationStatus,
+    sanity::SanityCheckError,
+    simulation::SimulationCheckError,
+    uopool::{OrderingPolicy, ValidationError},
+    UserOperation,
+};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+/// Gas of the `handleOps` transaction not attributed to the user operations (intrinsic gas and the loop of
+/// the entry point)
+pub const HANDLE_OPS_GAS_OVERHEAD: u64 = 50_000;
+/// Default max gas of the bundle (half of the 30M gas limit of mainnet blocks)
+pub const DEFAULT_MAX_BUNDLE_GAS: u64 = 15_000_000;
+
+/// Default max number of user operations of an unstaked factory in the bundle
+pub const UNSTAKED_FACTORY_BUNDLE_CAP: usize = 1;
+/// Default max number of user operations of an unstaked paymaster in the bundle
+pub const UNSTAKED_PAYMASTER_BUNDLE_CAP: usize = 1;
+
+/// Percentage of the effective gas price counted in the priority score of user operations with a throttled entity
+pub const THROTTLED_PRIORITY_PERC: u64 = 50;
+
+/// Ordering of the candidates of [create_bundle]
+///
+/// User operations of the same priority are ordered by the nonce (so the user operations of a sender stay in
+/// order) and then by the hash, the entry point and chain id are needed to compute the hash.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BundleOrdering {
+    pub policy: OrderingPolicy,
+    pub entry_point: Address,
+    pub chain_id: U256,
+}
+
+/// Max number of user operations of the unstaked entities in the bundle (staked entities aren't capped)
+///
+/// Senders aren't configurable, the bundle includes at most one user operation per sender regardless of its
+/// stake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnstakedEntityCaps {
+    pub factory: usize,
+    pub paymaster: usize,
+}
+
+impl Default for UnstakedEntityCaps {
+    fn default() -> Self {
+        Self {
+            factory: UNSTAKED_FACTORY_BUNDLE_CAP,
+            paymaster: UNSTAKED_PAYMASTER_BUNDLE_CAP,
+        }
+    }
+}
+
+/// Limits of the bundle built by [create_bundle]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundleLimits {
+    /// Gas limit of the block the bundle is submitted to
+    pub block_gas_limit: U256,
+    /// Max percentage of the block gas limit the bundle can use
+    pub block_gas_fraction_perc: U256,
+    /// Max gas of the bundle (including [HANDLE_OPS_GAS_OVERHEAD])
+    pub max_bundle_gas: U256,
+    /// Max number of user operations in the bundle
+    pub max_uos: usize,
+    /// Caps of the unstaked entities (none to not enforce them)
+    pub unstaked_entity_caps: Option<UnstakedEntityCaps>,
+}
+
+impl Default for BundleLimits {
+    fn default() -> Self {
+        Self {
+            block_gas_limit: 30_000_000.into(),
+            block_gas_fraction_perc: 100.into(),
+            max_bundle_gas: DEFAULT_MAX_BUNDLE_GAS.into(),
+            max_uos: usize::MAX,
+            unstaked_entity_caps: Some(UnstakedEntityCaps::default()),
+        }
+    }
+}
+
+impl BundleLimits {
+    pub fn max_gas(&self) -> U256 {
+        (self
+            .block_gas_limit
+            .saturating_mul(self.block_gas_fraction_perc)
+            / U256::from(100))
+        .min(self.max_bundle_gas)
+    }
+}
+
+/// Payer of the user operation, the paymaster if present and the sender otherwise
+pub fn prefund_payer(uo: &UserOperation) -> Address {
+    get_address(&uo.paymaster_and_data).unwrap_or(uo.sender)
+}
+
+/// Whether the user operation conflicts with the user operations already in the bundle
+///
+/// The sender of one user operation can't be an entity (paymaster, factory or aggregator) of another user
+/// operation in the same bundle and vice versa, since their state changes would interfere.
+pub fn is_entity_conflict(
+    sender: &Address,
+    entities: &[Address],
+    bundle_senders: &HashSet<Address>,
+    bundle_entities: &HashSet<Address>,
+) -> bool {
+    bundle_entities.contains(sender) || entities.iter().any(|e| bundle_senders.contains(e))
+}
+
+/// Entity responsible for the user operation failing in the bundle, by the code of the `FailedOp` reason
+///
+/// `AA1x` codes are failures of the factory, `AA2x` of the account and `AA3x` of the paymaster.
+pub fn failed_op_entity(uo: &UserOperation, reason: &str) -> Option<Address> {
+    if reason.starts_with("AA1") {
+        get_address(&uo.init_code)
+    } else if reason.starts_with("AA2") {
+        Some(uo.sender)
+    } else if reason.starts_with("AA3") {
+        get_address(&uo.paymaster_and_data)
+    } else {
+        None
+    }
+}
+
+/// Entity responsible for the user operation failing the (second) validation
+///
+/// The entity is named by the failed trace check (factory, account or paymaster) or given by the code of the
+/// `FailedOp` reason (see [failed_op_entity]).
+pub fn validation_error_entity(uo: &UserOperation, err: &ValidationError) -> Option<Address> {
+    let entity = match err {
+        ValidationError::Simulation(SimulationCheckError::ForbiddenOpcode { entity, .. })
+        | ValidationError::Simulation(SimulationCheckError::InvalidGasOpcode { entity })
+        | ValidationError::Simulation(SimulationCheckError::ForbiddenPrecompile {
+            entity, ..
+        })
+        | ValidationError::Simulation(SimulationCheckError::Unstaked { entity, .. }) => entity,
+        ValidationError::Simulation(SimulationCheckError::Validation { message })
+        | ValidationError::Sanity(SanityCheckError::Validation { message }) => {
+            return failed_op_entity(uo, message)
+        }
+        _ => return None,
+    };
+
+    match entity.as_str() {
+        FACTORY => get_address(&uo.init_code),
+        ACCOUNT => Some(uo.sender),
+        PAYMASTER => get_address(&uo.paymaster_and_data),
+        _ => None,
+    }
+}
+
+/// Entity responsible for the user operation reverting on chain (`success` of the `UserOperationEvent` is false)
+///
+/// A reverted execution of the account emits `UserOperationRevertReason`. If the `postOp` of the paymaster
+/// reverts, the event is rolled back, so a failed user operation with a paymaster and without the revert reason
+/// is attributed to the paymaster.
+pub fn reverted_op_entity(
+    sender: Address,
+    paymaster: Address,
+    execution_reverted: bool,
+) -> Address {
+    if execution_reverted || paymaster.is_zero() {
+        sender
+    } else {
+        paymaster
+    }
+}
+
+/// Priority score of the user operation, the effective gas price weighted by the reputation of its entities
+///
+/// The price counts fully if the paymaster and factory are ok, [THROTTLED_PRIORITY_PERC] of it if any of them
+/// is throttled and nothing if any is banned.
+pub fn priority_score<R>(uo: &UserOperation, base_fee: U256, reputation: R) -> U256
+where
+    R: Fn(&Address) -> ReputationStatus,
+{
+    let perc = [
+        get_address(&uo.paymaster_and_data),
+        get_address(&uo.init_code),
+    ]
+    .iter()
+    .flatten()
+    .map(|entity| match reputation(entity) {
+        ReputationStatus::OK => 100,
+        ReputationStatus::THROTTLED => THROTTLED_PRIORITY_PERC,
+        ReputationStatus::BANNED => 0,
+    })
+    .min()
+    .unwrap_or(100);
+
+    uo.effective_gas_price(base_fee).saturating_mul(perc.into()) / U256::from(100)
+}
+
+/// Selects a profitable, non-conflicting subset of user operations for the bundle
+///
+/// User operations are given in the arrival order and ordered by the [OrderingPolicy] (ties are broken by the
+/// nonce and the hash, see [BundleOrdering]), `reputation` is used by the priority score. At most one user operation
+/// per sender is included, the one with the lowest nonce (the next ones can't execute before it), paymasters and factories are limited to `max_uos_per_entity` user operations
+/// (and to the [UnstakedEntityCaps] if `is_staked` tells they're unstaked), user operations whose sender is
+/// an entity of another user operation (or vice versa) are skipped and the selection stops once the
+/// cumulative gas (with [HANDLE_OPS_GAS_OVERHEAD]) would exceed the max gas of the bundle or the bundle has
+/// the max number of user operations.
+pub fn create_bundle<F, S, R>(
+    uos: Vec<UserOperation>,
+    base_fee: U256,
+    limits: &BundleLimits,
+    ordering: &BundleOrdering,
+    max_uos_per_entity: F,
+    is_staked: S,
+    reputation: R,
+) -> Vec<UserOperation>
+where
+    F: Fn(&Address) -> usize,
+    S: Fn(&Address) -> bool,
+    R: Fn(&Address) -> ReputationStatus,
+{
+    let mut lowest_nonces: HashMap<Address, U256> = HashMap::new();
+    for uo in uos.iter() {
+        lowest_nonces
+            .entry(uo.sender)
+            .and_modify(|nonce| *nonce = (*nonce).min(uo.nonce))
+            .or_insert(uo.nonce);
+    }
+    let mut uos: Vec<UserOperation> = uos
+        .into_iter()
+        .filter(|uo| lowest_nonces.get(&uo.sender) == Some(&uo.nonce))
+        .collect();
+
+    let tie_break =
+        |uo: &UserOperation| (uo.nonce, uo.hash(&ordering.entry_point, &ordering.chain_id));
+    match ordering.policy {
+        OrderingPolicy::EffectiveGasPrice => {
+            uos.sort_by_cached_key(|uo| (Reverse(uo.effective_gas_price(base_fee)), tie_break(uo)))
+        }
+        OrderingPolicy::Fifo => (),
+        OrderingPolicy::PriorityScore => uos.sort_by_cached_key(|uo| {
+            (
+                Reverse(priority_score(uo, base_fee, &reputation)),
+                tie_break(uo),
+            )
+        }),
+    }
+
+    let max_gas_total = limits.max_gas();
+    let mut gas_total = U256::from(HANDLE_OPS_GAS_OVERHEAD);
+    let mut senders = HashSet::new();
+    let mut entities_c: HashMap<Address, usize> = HashMap::new();
+    let mut paymasters_c: HashMap<Address, usize> = HashMap::new();
+    let mut factories_c: HashMap<Address, usize> = HashMap::new();
+    let mut bundle_entities = HashSet::new();
+    let mut bundle = vec![];
+
+    for uo in uos {
+        if bundle.len() >= limits.max_uos {
+            break;
+        }
+
+        if senders.contains(&uo.sender) {
+            continue;
+        }
+
+        let paymaster = get_address(&uo.paymaster_and_data);
+        let factory = get_address(&uo.init_code);
+        let entities = [paymaster, factory];
+        if entities.iter().flatten().any(|entity| {
+            entities_c.get(entity).copied().unwrap_or(0) >= max_uos_per_entity(entity)
+        }) {
+            continue;
+        }
+
+        if let Some(caps) = &limits.unstaked_entity_caps {
+            let over_cap = |entity: Option<Address>, c: &HashMap<Address, usize>, cap: usize| {
+                entity.map_or(false, |entity| {
+                    c.get(&entity).copied().unwrap_or(0) >= cap && !is_staked(&entity)
+                })
+            };
+            if over_cap(paymaster, &paymasters_c, caps.paymaster)
+                || over_cap(factory, &factories_c, caps.factory)
+            {
+                continue;
+            }
+        }
+
+        let entities: Vec<Address> = entities.into_iter().flatten().collect();
+        if is_entity_conflict(&uo.sender, &entities, &senders, &bundle_entities) {
+            continue;
+        }
+
+        let gas_total_new = gas_total.saturating_add(uo.max_gas());
+        if gas_total_new > max_gas_total {
+            break;
+        }
+
+        for entity in entities {
+            *entities_c.entry(entity).or_insert(0) += 1;
+            bundle_entities.insert(entity);
+        }
+        if let Some(paymaster) = paymaster {
+            *paymasters_c.entry(paymaster).or_insert(0) += 1;
+        }
+        if let Some(factory) = factory {
+            *factories_c.entry(factory).or_insert(0) += 1;
+        }
+        gas_total = gas_total_new;
+        senders.insert(uo.sender);
+        bundle.push(uo);
+    }
+
+    bundle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uo(max_fee_per_gas: u64, max_priority_fee_per_gas: u64) -> UserOperation {
+        UserOperation::default()
+            .sender(Address::random())
+            .call_gas_limit(100_000.into())
+            .verification_gas_limit(100_000.into())
+            .pre_verification_gas(50_000.into())
+            .max_fee_per_gas(max_fee_per_gas.into())
+            .max_priority_fee_per_gas(max_priority_fee_per_gas.into())
+    }
+
+    #[test]
+    fn create_bundle_ordering() {
+        let limits = BundleLimits {
+            block_gas_fraction_perc: 50.into(),
+            ..Default::default()
+        };
+        let base_fee = U256::from(100);
+
+        // effective gas prices: 110, 150, 120
+        let uos = vec![uo(200, 10), uo(150, 100), uo(1_000, 20)];
+        let bundle = create_bundle(
+            uos.clone(),
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |_| usize::MAX,
+            |_| true,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(bundle, vec![uos[1].clone(), uos[2].clone(), uos[0].clone()]);
+
+        // the same sender is included only once, with the lowest nonce even if the next one pays more
+        let uo_same_sender = uos[0]
+            .clone()
+            .nonce(1.into())
+            .max_priority_fee_per_gas(50.into());
+        let bundle = create_bundle(
+            vec![uos[0].clone(), uo_same_sender.clone()],
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |_| usize::MAX,
+            |_| true,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(bundle, vec![uos[0].clone()]);
+    }
+
+    #[test]
+    fn create_bundle_ordering_policies() {
+        let base_fee = U256::from(100);
+        let limits = BundleLimits::default();
+        let throttled = Address::random();
+        let reputation = |entity: &Address| {
+            if *entity == throttled {
+                ReputationStatus::THROTTLED
+            } else {
+                ReputationStatus::OK
+            }
+        };
+
+        // effective gas prices: 110, 150 (throttled paymaster), 120, 120 (in the arrival order)
+        let uos = vec![
+            uo(200, 10),
+            uo(150, 100).paymaster_and_data(throttled.as_bytes().to_vec().into()),
+            uo(1_000, 20),
+            uo(500, 20),
+        ];
+        let ordering = |policy| BundleOrdering {
+            policy,
+            entry_point: Address::random(),
+            chain_id: 1.into(),
+        };
+        let bundle = |ordering: &BundleOrdering| {
+            create_bundle(
+                uos.clone(),
+                base_fee,
+                &limits,
+                ordering,
+                |_| usize::MAX,
+                |_| true,
+                reputation,
+            )
+        };
+
+        // user operations with the same price are ordered by the hash
+        let ordering_price = ordering(OrderingPolicy::EffectiveGasPrice);
+        let (first, second) = if uos[2].hash(&ordering_price.entry_point, &ordering_price.chain_id)
+            < uos[3].hash(&ordering_price.entry_point, &ordering_price.chain_id)
+        {
+            (uos[2].clone(), uos[3].clone())
+        } else {
+            (uos[3].clone(), uos[2].clone())
+        };
+        assert_eq!(
+            bundle(&ordering_price),
+            vec![
+                uos[1].clone(),
+                first.clone(),
+                second.clone(),
+                uos[0].clone()
+            ]
+        );
+
+        assert_eq!(bundle(&ordering(OrderingPolicy::Fifo)), uos);
+
+        // price of the user operation with the throttled paymaster counts by half (75)
+        let ordering_score = BundleOrdering {
+            policy: OrderingPolicy::PriorityScore,
+            ..ordering_price
+        };
+        assert_eq!(
+            bundle(&ordering_score),
+            vec![first, second, uos[0].clone(), uos[1].clone()]
+        );
+        assert_eq!(priority_score(&uos[1], base_fee, reputation), 75.into());
+        assert_eq!(
+            priority_score(&uos[1], base_fee, |_| ReputationStatus::BANNED),
+            U256::zero()
+        );
+    }
+
+    #[test]
+    fn create_bundle_limits() {
+        let base_fee = U256::from(100);
+        let paymaster = Address::random();
+        let throttled = Address::random();
+        let with_paymaster =
+            |uo: UserOperation, p: Address| uo.paymaster_and_data(p.as_bytes().to_vec().into());
+
+        let uos = vec![
+            with_paymaster(uo(500, 500), paymaster),
+            with_paymaster(uo(400, 400), paymaster),
+            with_paymaster(uo(300, 300), paymaster),
+            with_paymaster(uo(200, 200), throttled),
+            with_paymaster(uo(150, 150), throttled),
+        ];
+        let limits = BundleLimits::default();
+        let bundle = create_bundle(
+            uos.clone(),
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |entity| {
+                if *entity == throttled {
+                    1
+                } else {
+                    2
+                }
+            },
+            |_| true,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(bundle, vec![uos[0].clone(), uos[1].clone(), uos[3].clone()]);
+
+        // each user operation uses 100_000 + 100_000 + 50_000 gas (on top of the handleOps overhead)
+        let uos = vec![uo(500, 500), uo(400, 400), uo(300, 300)];
+        let limits = BundleLimits {
+            block_gas_limit: 1_100_000.into(),
+            block_gas_fraction_perc: 50.into(),
+            ..Default::default()
+        };
+        let bundle = create_bundle(
+            uos.clone(),
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |_| usize::MAX,
+            |_| true,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(bundle, vec![uos[0].clone(), uos[1].clone()]);
+
+        let limits = BundleLimits {
+            max_bundle_gas: 549_999.into(),
+            ..Default::default()
+        };
+        let bundle = create_bundle(
+            uos.clone(),
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |_| usize::MAX,
+            |_| true,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(bundle, vec![uos[0].clone()]);
+
+        let limits = BundleLimits {
+            max_uos: 2,
+            ..Default::default()
+        };
+        let bundle = create_bundle(
+            uos.clone(),
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |_| usize::MAX,
+            |_| true,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(bundle, vec![uos[0].clone(), uos[1].clone()]);
+    }
+
+    #[test]
+    fn create_bundle_unstaked_entity_caps() {
+        let base_fee = U256::from(100);
+        let staked = Address::random();
+        let unstaked = Address::random();
+        let factory = Address::random();
+        let with_paymaster =
+            |uo: UserOperation, p: Address| uo.paymaster_and_data(p.as_bytes().to_vec().into());
+        let is_staked = |entity: &Address| *entity == staked;
+
+        let uos = vec![
+            with_paymaster(uo(500, 500), unstaked),
+            with_paymaster(uo(400, 400), staked),
+            with_paymaster(uo(300, 300), unstaked),
+            with_paymaster(uo(200, 200), staked),
+            uo(150, 150).init_code(factory.as_bytes().to_vec().into()),
+            uo(100, 100).init_code(factory.as_bytes().to_vec().into()),
+        ];
+
+        // unstaked paymaster and factory back one user operation each, the staked paymaster isn't capped
+        let limits = BundleLimits::default();
+        let bundle = create_bundle(
+            uos.clone(),
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |_| usize::MAX,
+            is_staked,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(
+            bundle,
+            vec![
+                uos[0].clone(),
+                uos[1].clone(),
+                uos[3].clone(),
+                uos[4].clone()
+            ]
+        );
+
+        let limits = BundleLimits {
+            unstaked_entity_caps: Some(UnstakedEntityCaps {
+                factory: 2,
+                paymaster: 1,
+            }),
+            ..Default::default()
+        };
+        let bundle = create_bundle(
+            uos.clone(),
+            base_fee,
+            &limits,
+            &BundleOrdering::default(),
+            |_| usize::MAX,
+            is_staked,
+            |_| ReputationStatus::OK,
+        );
+        assert_eq!(bundle.len(), 5);
+        assert!(!bundle.contains(&uos[2]));
+
+        // caps not enforced
+        let limits = BundleLimits {
+            unstaked_entity_caps: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            create_bundle(
+                uos.clone(),
+                base_fee,
+                &limits,
+                &BundleOrdering::default(),
+                |_| usize::MAX,
+                is_staked,
+                |_| ReputationStatus::OK
+            ),
+            uos
+        );
+    }
+
+    #[test]
+    fn create_bundle_entity_conflicts() {
+        let base_fee = U256::from(100);
+        let limits = BundleLimits::default();
+
+        // sender of the second user operation is the paymaster of the first one
+        let uo_paymaster = uo(500, 500);
+        let uo_sponsored =
+            uo(400, 400).paymaster_and_data(uo_paymaster.sender.as_bytes().to_vec().into());
+        let uos = vec![uo_sponsored.clone(), uo_paymaster.clone()];
+        assert_eq!(
+            create_bundle(
+                uos,
+                base_fee,
+                &limits,
+                &BundleOrdering::default(),
+                |_| usize::MAX,
+                |_| true,
+                |_| ReputationStatus::OK
+            ),
+            vec![uo_paymaster.clone()]
+        );
+
+        // factory of the second user operation is the sender of the first one
+        let uo_sender = uo(500, 500);
+        let uo_deployed = uo(400, 400).init_code(uo_sender.sender.as_bytes().to_vec().into());
+        let uo_other = uo(300, 300);
+        let uos = vec![uo_sender.clone(), uo_deployed, uo_other.clone()];
+        assert_eq!(
+            create_bundle(
+                uos,
+                base_fee,
+                &limits,
+                &BundleOrdering::default(),
+                |_| usize::MAX,
+                |_| true,
+                |_| ReputationStatus::OK
+            ),
+            vec![uo_sender, uo_other]
+        );
+
+        assert!(is_entity_conflict(
+            &uo_paymaster.sender,
+            &[],
+            &HashSet::new(),
+            &HashSet::from([uo_paymaster.sender]),
+        ));
+        assert!(!is_entity_conflict(
+            &Address::random(),
+            &[Address::random()],
+            &HashSet::from([Address::random()]),
+            &HashSet::from([Address::random()]),
+        ));
+    }
+
+    #[test]
+    fn failed_op_entities() {
+        let factory = Address::random();
+        let paymaster = Address::random();
+        let uo = uo(100, 10)
+            .init_code(factory.as_bytes().to_vec().into())
+            .paymaster_and_data(paymaster.as_bytes().to_vec().into());
+
+        assert_eq!(
+            failed_op_entity(&uo, "AA13 initCode failed or OOG"),
+            Some(factory)
+        );
+        assert_eq!(
+            failed_op_entity(&uo, "AA23 reverted (or OOG)"),
+            Some(uo.sender)
+        );
+        assert_eq!(
+            failed_op_entity(&uo, "AA31 paymaster deposit too low"),
+            Some(paymaster)
+        );
+        assert_eq!(failed_op_entity(&uo, "AA95 out of gas"), None);
+        assert_eq!(
+            failed_op_entity(
+                &uo.clone().init_code(Default::default()),
+                "AA13 initCode failed or OOG"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn validation_error_entities() {
+        let factory = Address::random();
+        let paymaster = Address::random();
+        let uo = uo(100, 10)
+            .init_code(factory.as_bytes().to_vec().into())
+            .paymaster_and_data(paymaster.as_bytes().to_vec().into());
+
+        assert_eq!(
+            validation_error_entity(
+                &uo,
+                &SimulationCheckError::ForbiddenOpcode {
+                    entity: PAYMASTER.into(),
+                    opcode: "GASPRICE".into(),
+                }
+                .into()
+            ),
+            Some(paymaster)
+        );
+        assert_eq!(
+            validation_error_entity(
+                &uo,
+                &SimulationCheckError::Unstaked {
+                    entity: FACTORY.into(),
+                    message: "accessed storage".into(),
+                }
+                .into()
+            ),
+            Some(factory)
+        );
+        assert_eq!(
+            validation_error_entity(
+                &uo,
+                &SimulationCheckError::Validation {
+                    message: "AA24 signature error".into(),
+                }
+                .into()
+            ),
+            Some(uo.sender)
+        );
+        assert_eq!(
+            validation_error_entity(&uo, &SimulationCheckError::OutOfGas {}.into()),
+            None
+        );
+    }
+
+    #[test]
+    fn prefund_payers() {
+        let paymaster = Address::random();
+        let uo = uo(10, 1);
+        assert_eq!(prefund_payer(&uo), uo.sender);
+
+        let uo = uo.paymaster_and_data(paymaster.as_bytes().to_vec().into());
+        assert_eq!(prefund_payer(&uo), paymaster);
+    }
+
+    #[test]
+    fn reverted_op_entities() {
+        let sender = Address::random();
+        let paymaster = Address::random();
+
+        assert_eq!(reverted_op_entity(sender, Address::zero(), true), sender);
+        assert_eq!(reverted_op_entity(sender, paymaster, true), sender);
+        assert_eq!(reverted_op_entity(sender, paymaster, false), paymaster);
+        assert_eq!(reverted_op_entity(sender, Address::zero(), false), sender);
+    }
+}
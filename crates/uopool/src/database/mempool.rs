@@ -1,22 +1,21 @@
-use super::utils::{WrapAddress, WrapCodeHash, WrapUserOperation, WrapUserOperationHash};
+use super::{
+    env::{DBError, Env},
+    utils::{WrapAddress, WrapCodeHash, WrapU64, WrapUserOperation, WrapUserOperationHash},
+};
 use crate::mempool::Mempool;
 use ethers::types::{Address, U256};
 use reth_db::{
     cursor::{DbCursorRO, DbDupCursorRO},
-    database::{Database, DatabaseGAT},
+    database::Database,
     dupsort,
-    mdbx::{
-        tx::{self, Tx},
-        DatabaseFlags, Environment, EnvironmentFlags, EnvironmentKind, Geometry, Mode, PageSize,
-        SyncMode, RO, RW,
-    },
+    mdbx::EnvironmentKind,
     table,
     table::DupSort,
     transaction::{DbTx, DbTxMut},
     Error, TableType,
 };
 use silius_primitives::{simulation::CodeHash, UserOperation, UserOperationHash};
-use std::{fmt::Display, path::PathBuf};
+use std::path::PathBuf;
 
 table!(
     /// UserOperation DB
@@ -34,93 +33,81 @@ dupsort!(
     ( CodeHashDB ) WrapUserOperationHash | [WrapAddress] WrapCodeHash
 );
 
+table!(
+    /// ExpiresAt DB
+    /// Expiry time (in seconds) of the user operation, so it survives restarts.
+    ( ExpiresAtDB ) WrapUserOperationHash | WrapU64
+);
+
+table!(
+    /// InsertionSeq DB
+    /// Insertion sequence of the user operation, so the pool keeps its order across restarts.
+    ( InsertionSeqDB ) WrapUserOperationHash | WrapU64
+);
+
 /// Default tables that should be present inside database.
-pub const TABLES: [(TableType, &str); 3] = [
+pub const TABLES: [(TableType, &str); 5] = [
     (TableType::Table, UserOperationDB::const_name()),
     (TableType::DupSort, SenderUserOperationDB::const_name()),
     (TableType::DupSort, CodeHashDB::const_name()),
+    (TableType::Table, ExpiresAtDB::const_name()),
+    (TableType::Table, InsertionSeqDB::const_name()),
 ];
 
 impl DupSort for SenderUserOperationDB {
     type SubKey = WrapAddress;
 }
 
-#[derive(Debug)]
-pub struct Env<E: EnvironmentKind> {
-    /// Libmdbx-sys environment.
-    pub inner: Environment<E>,
-}
-
 #[derive(Debug)]
 pub struct DatabaseMempool<E: EnvironmentKind> {
     _path: PathBuf,
     env: Env<E>,
-}
-
-impl<'a, E: EnvironmentKind> DatabaseGAT<'a> for Env<E> {
-    type TX = tx::Tx<'a, RO, E>;
-    type TXMut = tx::Tx<'a, RW, E>;
-}
-
-impl<E: EnvironmentKind> Database for Env<E> {
-    fn tx(&self) -> Result<<Self as DatabaseGAT<'_>>::TX, Error> {
-        Ok(Tx::new(
-            self.inner
-                .begin_ro_txn()
-                .map_err(|e| Error::InitTransaction(e.into()))?,
-        ))
-    }
-
-    fn tx_mut(&self) -> Result<<Self as DatabaseGAT<'_>>::TXMut, Error> {
-        Ok(Tx::new(
-            self.inner
-                .begin_rw_txn()
-                .map_err(|e| Error::InitTransaction(e.into()))?,
-        ))
-    }
-}
-
-#[derive(Debug, PartialEq, Eq)]
-pub enum DBError {
-    DBInternalError(Error),
-    NotFound,
-}
-
-impl From<Error> for DBError {
-    fn from(value: Error) -> Self {
-        DBError::DBInternalError(value)
-    }
-}
-
-impl Display for DBError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
-    }
+    /// Next insertion sequence, read from the database on the first insert
+    next_seq: Option<u64>,
 }
 
 impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
     type UserOperations = Vec<UserOperation>;
     type CodeHashes = Vec<CodeHash>;
-    type Error = DBError;
+    type Error = anyhow::Error;
     fn add(
         &mut self,
         uo: UserOperation,
         ep: &Address,
         chain_id: &U256,
-    ) -> Result<UserOperationHash, DBError> {
+    ) -> anyhow::Result<UserOperationHash> {
         let hash = uo.hash(ep, chain_id);
         let tx = self.env.tx_mut()?;
 
         let uo_hash_wrap: WrapUserOperationHash = hash.into();
         let uo_wrap: WrapUserOperation = uo.clone().into();
 
-        tx.put::<UserOperationDB>(uo_hash_wrap, uo_wrap.clone())?;
+        tx.put::<UserOperationDB>(uo_hash_wrap.clone(), uo_wrap.clone())?;
         tx.put::<SenderUserOperationDB>(uo.sender.into(), uo_wrap)?;
-        tx.commit()?;
+        if tx.get::<InsertionSeqDB>(uo_hash_wrap.clone())?.is_none() {
+            let seq = match self.next_seq {
+                Some(seq) => seq,
+                None => {
+                    let mut cursor = tx.cursor_read::<InsertionSeqDB>()?;
+                    cursor
+                        .walk(Some(WrapUserOperationHash::default()))?
+                        .map(|a| a.map(|(_, seq)| u64::from(seq) + 1))
+                        .collect::<Result<Vec<_>, _>>()?
+                        .into_iter()
+                        .max()
+                        .unwrap_or_default()
+                }
+            };
+            tx.put::<InsertionSeqDB>(uo_hash_wrap, seq.into())?;
+            tx.commit()?;
+            self.next_seq = Some(seq + 1);
+        } else {
+            tx.commit()?;
+        }
         Ok(hash)
     }
 
-    fn get(&self, uo_hash: &UserOperationHash) -> Result<Option<UserOperation>, DBError> {
+    fn get(&self, uo_hash: &UserOperationHash) -> anyhow::Result<Option<UserOperation>> {
         let uo_hash_wrap: WrapUserOperationHash = (*uo_hash).into();
 
         let tx = self.env.tx()?;
@@ -206,22 +193,48 @@ impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
         Ok(())
     }
 
-    fn remove(&mut self, uo_hash: &UserOperationHash) -> Result<(), DBError> {
+    fn set_expires_at(
+        &mut self,
+        uo_hash: &UserOperationHash,
+        expires_at: u64,
+    ) -> anyhow::Result<(), Self::Error> {
+        let tx = self.env.tx_mut()?;
+        tx.put::<ExpiresAtDB>((*uo_hash).into(), expires_at.into())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_expires_at(&self, uo_hash: &UserOperationHash) -> Option<u64> {
+        self.env
+            .tx()
+            .and_then(|tx| {
+                let res = tx.get::<ExpiresAtDB>((*uo_hash).into())?;
+                tx.commit()?;
+                Ok(res)
+            })
+            .ok()
+            .flatten()
+            .map(Into::into)
+    }
+
+    fn remove(&mut self, uo_hash: &UserOperationHash) -> anyhow::Result<()> {
         let uo_hash_wrap: WrapUserOperationHash = (*uo_hash).into();
 
         let tx = self.env.tx_mut()?;
         if let Some(uo) = tx.get::<UserOperationDB>(uo_hash_wrap.clone())? {
             tx.delete::<UserOperationDB>(uo_hash_wrap.clone(), None)?;
             tx.delete::<SenderUserOperationDB>(uo.0.sender.into(), Some(uo))?;
-            tx.delete::<CodeHashDB>(uo_hash_wrap, None)?;
+            tx.delete::<CodeHashDB>(uo_hash_wrap.clone(), None)?;
+            tx.delete::<ExpiresAtDB>(uo_hash_wrap.clone(), None)?;
+            tx.delete::<InsertionSeqDB>(uo_hash_wrap, None)?;
             tx.commit()?;
             Ok(())
         } else {
-            Err(DBError::NotFound)
+            Err(DBError::NotFound.into())
         }
     }
 
-    fn get_sorted(&self) -> Result<Self::UserOperations, DBError> {
+    fn get_sorted(&self) -> anyhow::Result<Self::UserOperations> {
         self.env
             .tx()
             .and_then(|tx| {
@@ -239,7 +252,7 @@ impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
                 });
                 Ok(uos)
             })
-            .map_err(DBError::DBInternalError)
+            .map_err(|err| DBError::DBInternalError(err).into())
     }
 
     fn get_all(&self) -> Self::UserOperations {
@@ -247,12 +260,17 @@ impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
             .tx()
             .and_then(|tx| {
                 let mut c = tx.cursor_read::<UserOperationDB>()?;
-                let res: Vec<UserOperation> = c
+                let mut res: Vec<(Option<u64>, UserOperation)> = c
                     .walk(Some(WrapUserOperationHash::default()))?
-                    .map(|a| a.map(|(_, v)| v.into()))
+                    .map(|a| {
+                        a.and_then(|(k, v)| {
+                            Ok((tx.get::<InsertionSeqDB>(k)?.map(Into::into), v.into()))
+                        })
+                    })
                     .collect::<Result<Vec<_>, _>>()?;
                 tx.commit()?;
-                Ok(res)
+                res.sort_by_key(|(seq, _)| *seq);
+                Ok(res.into_iter().map(|(_, uo)| uo).collect())
             })
             .unwrap_or_else(|_| vec![])
     }
@@ -263,72 +281,30 @@ impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
             .and_then(|tx| {
                 tx.clear::<UserOperationDB>()?;
                 tx.clear::<SenderUserOperationDB>()?;
+                tx.clear::<CodeHashDB>()?;
+                tx.clear::<ExpiresAtDB>()?;
+                tx.clear::<InsertionSeqDB>()?;
                 tx.commit()
             })
             .expect("Clear database failed");
+        self.next_seq = None;
     }
 }
-fn default_page_size() -> usize {
-    let os_page_size = page_size::get();
-
-    // source: https://gitflic.ru/project/erthink/libmdbx/blob?file=mdbx.h#line-num-821
-    let libmdbx_max_page_size = 0x10000;
-
-    // May lead to errors if it's reduced further because of the potential size of the
-    // data.
-    let min_page_size = 4096;
-
-    os_page_size.clamp(min_page_size, libmdbx_max_page_size)
-}
 
 impl<E: EnvironmentKind> DatabaseMempool<E> {
     pub fn new(path: PathBuf) -> anyhow::Result<Self> {
-        let env = Environment::new()
-            .set_max_dbs(TABLES.len())
-            .set_geometry(Geometry {
-                size: Some(0..(1024 * 1024 * 1024 * 1024 * 4)), // TODO: reevaluate (4 tb)
-                growth_step: Some(1024 * 1024 * 256),           // TODO: reevaluate (256 mb)
-                shrink_threshold: None,
-                page_size: Some(PageSize::Set(default_page_size())),
-            })
-            .set_flags(EnvironmentFlags {
-                mode: Mode::ReadWrite {
-                    sync_mode: SyncMode::Durable,
-                },
-                no_rdahead: true, // TODO: reevaluate
-                coalesce: true,
-                ..Default::default()
-            })
-            .open(path.as_path())
-            .map_err(|e| Error::DatabaseLocation(e.into()))?;
+        let env = Env::open(path.as_path(), TABLES.len())?;
 
         Ok(Self {
             _path: path,
-            env: Env { inner: env },
+            env,
+            next_seq: None,
         })
     }
 
     /// Creates all the defined tables, if necessary.
     pub fn create_tables(&self) -> Result<(), Error> {
-        let tx = self
-            .env
-            .inner
-            .begin_rw_txn()
-            .map_err(|e| Error::InitTransaction(e.into()))?;
-
-        for (table_type, table) in TABLES {
-            let flags = match table_type {
-                TableType::Table => DatabaseFlags::default(),
-                TableType::DupSort => DatabaseFlags::DUP_SORT,
-            };
-
-            tx.create_db(Some(table), flags)
-                .map_err(|e| Error::TableCreation(e.into()))?;
-        }
-
-        tx.commit().map_err(|e| Error::Commit(e.into()))?;
-
-        Ok(())
+        self.env.create_tables(&TABLES)
     }
 }
 
@@ -349,4 +325,36 @@ mod tests {
             .expect("Create mdbx database tables failed");
         mempool_test_case(mempool, "NotFound");
     }
+
+    #[tokio::test]
+    async fn database_mempool_insertion_order() {
+        let dir = TempDir::new("test-userop-db").unwrap().into_path();
+        let ep = Address::random();
+        let chain_id = U256::from(5);
+
+        let mut mempool: DatabaseMempool<NoWriteMap> = DatabaseMempool::new(dir.clone()).unwrap();
+        mempool
+            .create_tables()
+            .expect("Create mdbx database tables failed");
+        let mut uos: Vec<UserOperation> = (0..5).map(|_| UserOperation::random()).collect();
+        for uo in uos.iter() {
+            mempool.add(uo.clone(), &ep, &chain_id).unwrap();
+        }
+        assert_eq!(mempool.get_all(), uos);
+        drop(mempool);
+
+        // the order survives a restart and new user operations go last
+        let mut mempool: DatabaseMempool<NoWriteMap> = DatabaseMempool::new(dir).unwrap();
+        mempool
+            .create_tables()
+            .expect("Create mdbx database tables failed");
+        let uo = UserOperation::random();
+        mempool.add(uo.clone(), &ep, &chain_id).unwrap();
+        uos.push(uo);
+        assert_eq!(mempool.get_all(), uos);
+
+        let uo_hash = uos.remove(1).hash(&ep, &chain_id);
+        mempool.remove(&uo_hash).unwrap();
+        assert_eq!(mempool.get_all(), uos);
+    }
 }
@@ -1,2 +1,4 @@
+mod env;
 pub mod mempool;
+pub mod reputation;
 mod utils;
@@ -0,0 +1,423 @@
+use super::{
+    env::{DBError, Env},
+    utils::{WrapAddress, WrapReputationEntry},
+};
+use crate::reputation::Reputation;
+use ethers::types::{Address, U256};
+use reth_db::{
+    cursor::DbCursorRO,
+    database::Database,
+    mdbx::EnvironmentKind,
+    table,
+    transaction::{DbTx, DbTxMut},
+    Error, TableType,
+};
+use silius_primitives::reputation::{
+    ReputationEntry, ReputationError, ReputationStatus, StakeInfo,
+};
+use std::{collections::HashSet, path::PathBuf};
+
+table!(
+    /// Reputation DB
+    ( EntitiesReputationDB ) WrapAddress | WrapReputationEntry
+);
+
+/// Default tables that should be present inside database.
+pub const TABLES: [(TableType, &str); 1] = [(TableType::Table, EntitiesReputationDB::const_name())];
+
+#[derive(Debug)]
+pub struct DatabaseReputation<E: EnvironmentKind> {
+    _path: PathBuf,
+    env: Env<E>,
+
+    min_inclusion_denominator: u64,
+    throttling_slack: u64,
+    ban_slack: u64,
+    hourly_decay_denominator: u64,
+    min_stake: U256,
+    min_unstake_delay: U256,
+
+    whitelist: HashSet<Address>,
+    blacklist: HashSet<Address>,
+}
+
+impl<E: EnvironmentKind> DatabaseReputation<E> {
+    pub fn new(path: PathBuf) -> anyhow::Result<Self> {
+        let env = Env::open(path.as_path(), TABLES.len())?;
+
+        Ok(Self {
+            _path: path,
+            env,
+            min_inclusion_denominator: 0,
+            throttling_slack: 0,
+            ban_slack: 0,
+            hourly_decay_denominator: 0,
+            min_stake: U256::zero(),
+            min_unstake_delay: U256::zero(),
+            whitelist: HashSet::new(),
+            blacklist: HashSet::new(),
+        })
+    }
+
+    /// Creates all the defined tables, if necessary.
+    pub fn create_tables(&self) -> Result<(), Error> {
+        self.env.create_tables(&TABLES)
+    }
+
+    fn get_entry(&self, addr: &Address) -> Result<Option<ReputationEntry>, DBError> {
+        let tx = self.env.tx()?;
+        let res = tx.get::<EntitiesReputationDB>((*addr).into())?;
+        tx.commit()?;
+
+        Ok(res.map(|ent| ent.into()))
+    }
+
+    fn update_entry<F: FnOnce(&mut ReputationEntry)>(
+        &mut self,
+        addr: &Address,
+        f: F,
+    ) -> Result<(), DBError> {
+        let tx = self.env.tx_mut()?;
+        let mut ent: ReputationEntry = tx
+            .get::<EntitiesReputationDB>((*addr).into())?
+            .map(|ent| ent.into())
+            .unwrap_or(ReputationEntry {
+                address: *addr,
+                uo_seen: 0,
+                uo_included: 0,
+                status: ReputationStatus::OK,
+            });
+        f(&mut ent);
+        tx.put::<EntitiesReputationDB>((*addr).into(), ent.into())?;
+        tx.commit()?;
+
+        Ok(())
+    }
+}
+
+impl<E: EnvironmentKind> Reputation for DatabaseReputation<E> {
+    type ReputationEntries = Vec<ReputationEntry>;
+
+    fn init(
+        &mut self,
+        min_inclusion_denominator: u64,
+        throttling_slack: u64,
+        ban_slack: u64,
+        hourly_decay_denominator: u64,
+        min_stake: U256,
+        min_unstake_delay: U256,
+    ) {
+        self.min_inclusion_denominator = min_inclusion_denominator;
+        self.throttling_slack = throttling_slack;
+        self.ban_slack = ban_slack;
+        self.hourly_decay_denominator = hourly_decay_denominator;
+        self.min_stake = min_stake;
+        self.min_unstake_delay = min_unstake_delay;
+    }
+
+    fn get(&mut self, addr: &Address) -> ReputationEntry {
+        if let Ok(Some(ent)) = self.get_entry(addr) {
+            return ent;
+        }
+
+        let _ = self.update_entry(addr, |_| {});
+
+        ReputationEntry {
+            address: *addr,
+            uo_seen: 0,
+            uo_included: 0,
+            status: ReputationStatus::OK,
+        }
+    }
+
+    fn increment_seen(&mut self, addr: &Address) {
+        let _ = self.update_entry(addr, |ent| ent.uo_seen += 1);
+    }
+
+    fn increment_included(&mut self, addr: &Address) {
+        let _ = self.update_entry(addr, |ent| ent.uo_included += 1);
+    }
+
+    fn update_hourly(&mut self) -> Result<(), ReputationError> {
+        let d = self.hourly_decay_denominator;
+        if d == 0 {
+            return Ok(());
+        }
+
+        self.env
+            .tx_mut()
+            .and_then(|tx| {
+                let mut cursor = tx.cursor_read::<EntitiesReputationDB>()?;
+                let entries = cursor
+                    .walk(Some(WrapAddress::default()))?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                for (addr, ent) in entries {
+                    let mut ent: ReputationEntry = ent.into();
+                    ent.uo_seen = ent.uo_seen * (d - 1) / d;
+                    ent.uo_included = ent.uo_included * (d - 1) / d;
+
+                    if ent.uo_seen > 0 || ent.uo_included > 0 {
+                        tx.put::<EntitiesReputationDB>(addr, ent.into())?;
+                    } else {
+                        tx.delete::<EntitiesReputationDB>(addr, None)?;
+                    }
+                }
+
+                tx.commit()
+            })
+            .map_err(|err| ReputationError::UnknownError {
+                message: format!("Update reputation database failed: {err}"),
+            })
+    }
+
+    fn add_whitelist(&mut self, addr: &Address) -> bool {
+        self.whitelist.insert(*addr)
+    }
+
+    fn remove_whitelist(&mut self, addr: &Address) -> bool {
+        self.whitelist.remove(addr)
+    }
+
+    fn is_whitelist(&self, addr: &Address) -> bool {
+        self.whitelist.contains(addr)
+    }
+
+    fn add_blacklist(&mut self, addr: &Address) -> bool {
+        self.blacklist.insert(*addr)
+    }
+
+    fn remove_blacklist(&mut self, addr: &Address) -> bool {
+        self.blacklist.remove(addr)
+    }
+
+    fn is_blacklist(&self, addr: &Address) -> bool {
+        self.blacklist.contains(addr)
+    }
+
+    fn get_status(&self, addr: &Address) -> ReputationStatus {
+        if self.is_whitelist(addr) {
+            return ReputationStatus::OK;
+        }
+
+        if self.is_blacklist(addr) {
+            return ReputationStatus::BANNED;
+        }
+
+        match self.get_entry(addr) {
+            Ok(Some(ent)) => {
+                // nothing is expected to be included before the reputation is initialized
+                let min_expected_included = ent
+                    .uo_seen
+                    .checked_div(self.min_inclusion_denominator)
+                    .unwrap_or_default();
+                if min_expected_included <= ent.uo_included + self.throttling_slack {
+                    ReputationStatus::OK
+                } else if min_expected_included <= ent.uo_included + self.ban_slack {
+                    ReputationStatus::THROTTLED
+                } else {
+                    ReputationStatus::BANNED
+                }
+            }
+            _ => ReputationStatus::OK,
+        }
+    }
+
+    fn update_handle_ops_reverted(&mut self, addr: &Address) {
+        let _ = self.update_entry(addr, |ent| {
+            ent.uo_seen = 100;
+            ent.uo_included = 0;
+        });
+    }
+
+    fn update_op_reverted(&mut self, addr: &Address, penalty: u64) {
+        let seen = penalty.saturating_mul(self.min_inclusion_denominator);
+        let _ = self.update_entry(addr, |ent| {
+            ent.uo_seen = ent.uo_seen.saturating_add(seen);
+            ent.uo_included = ent.uo_included.saturating_sub(penalty);
+        });
+    }
+
+    fn verify_stake(&self, title: &str, info: Option<StakeInfo>) -> Result<(), ReputationError> {
+        if let Some(info) = info {
+            if self.is_whitelist(&info.address) {
+                return Ok(());
+            }
+
+            if self.get_status(&info.address) == ReputationStatus::BANNED {
+                return Err(ReputationError::EntityBanned {
+                    address: info.address,
+                    title: title.to_string(),
+                });
+            }
+
+            let err = if info.stake < self.min_stake {
+                ReputationError::StakeTooLow {
+                    address: info.address,
+                    title: title.to_string(),
+                    min_stake: self.min_stake,
+                    min_unstake_delay: self.min_unstake_delay,
+                }
+            } else if info.unstake_delay < self.min_unstake_delay {
+                ReputationError::UnstakeDelayTooLow {
+                    address: info.address,
+                    title: title.to_string(),
+                    min_stake: self.min_stake,
+                    min_unstake_delay: self.min_unstake_delay,
+                }
+            } else {
+                return Ok(());
+            };
+
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    fn set(&mut self, entries: Self::ReputationEntries) -> Result<(), ReputationError> {
+        self.env
+            .tx_mut()
+            .and_then(|tx| {
+                for en in entries {
+                    tx.put::<EntitiesReputationDB>(en.address.into(), en.into())?;
+                }
+                tx.commit()
+            })
+            .map_err(|err| ReputationError::UnknownError {
+                message: format!("Set reputation database failed: {err}"),
+            })
+    }
+
+    fn get_all(&self) -> Self::ReputationEntries {
+        self.env
+            .tx()
+            .and_then(|tx| {
+                let mut cursor = tx.cursor_read::<EntitiesReputationDB>()?;
+                let res: Vec<ReputationEntry> = cursor
+                    .walk(Some(WrapAddress::default()))?
+                    .map(|a| a.map(|(_, v)| v.into()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                tx.commit()?;
+                Ok(res)
+            })
+            .unwrap_or_else(|_| vec![])
+    }
+
+    fn clear(&mut self) {
+        self.env
+            .tx_mut()
+            .and_then(|tx| {
+                tx.clear::<EntitiesReputationDB>()?;
+                tx.commit()
+            })
+            .expect("Clear database failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_db::mdbx::NoWriteMap;
+    use silius_primitives::reputation::{
+        BAN_SLACK, HOURLY_DECAY_DENOMINATOR, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+    };
+    use tempdir::TempDir;
+
+    #[test]
+    fn database_reputation() {
+        let dir = TempDir::new("test-reputation-db").unwrap();
+        let mut reputation: DatabaseReputation<NoWriteMap> =
+            DatabaseReputation::new(dir.into_path()).unwrap();
+        reputation
+            .create_tables()
+            .expect("Create mdbx database tables failed");
+        reputation.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            HOURLY_DECAY_DENOMINATOR,
+            U256::from(1),
+            U256::from(0),
+        );
+
+        let addr = Address::random();
+        assert_eq!(
+            reputation.get(&addr),
+            ReputationEntry {
+                address: addr,
+                uo_seen: 0,
+                uo_included: 0,
+                status: ReputationStatus::OK,
+            }
+        );
+
+        for _ in 0..48 {
+            reputation.increment_seen(&addr);
+        }
+        reputation.increment_included(&addr);
+        assert_eq!(reputation.get(&addr).uo_seen, 48);
+        assert_eq!(reputation.get(&addr).uo_included, 1);
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::OK);
+
+        reputation.update_hourly().unwrap();
+        assert_eq!(reputation.get(&addr).uo_seen, 46);
+        assert_eq!(reputation.get(&addr).uo_included, 0);
+
+        reputation.update_handle_ops_reverted(&addr);
+        for _ in 0..250 {
+            reputation.increment_seen(&addr);
+        }
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::THROTTLED);
+        for _ in 0..500 {
+            reputation.increment_seen(&addr);
+        }
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::BANNED);
+
+        assert!(reputation.add_whitelist(&addr));
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::OK);
+
+        let entries = reputation.get_all();
+        reputation.clear();
+        assert!(reputation.get_all().is_empty());
+        reputation.set(entries.clone()).unwrap();
+        assert_eq!(reputation.get_all(), entries);
+    }
+
+    #[test]
+    fn database_reputation_status() {
+        let dir = TempDir::new("test-reputation-db").unwrap();
+        let mut reputation: DatabaseReputation<NoWriteMap> =
+            DatabaseReputation::new(dir.into_path()).unwrap();
+        reputation
+            .create_tables()
+            .expect("Create mdbx database tables failed");
+        let addr = Address::random();
+        let stake_info = Some(StakeInfo {
+            address: addr,
+            stake: U256::from(1),
+            unstake_delay: U256::from(1),
+        });
+
+        // the status doesn't panic before the reputation is initialized
+        for _ in 0..1000 {
+            reputation.increment_seen(&addr);
+        }
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::OK);
+
+        // the stake check uses the status computed from the counters, not the stored one
+        reputation.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            HOURLY_DECAY_DENOMINATOR,
+            U256::from(1),
+            U256::from(0),
+        );
+        assert_eq!(reputation.get(&addr).status, ReputationStatus::OK);
+        assert!(matches!(
+            reputation.verify_stake("paymaster", stake_info),
+            Err(ReputationError::EntityBanned { .. })
+        ));
+    }
+}
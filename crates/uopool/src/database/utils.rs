@@ -5,7 +5,11 @@ use ethers::{
 };
 use reth_db::table::{Compress, Decode, Decompress, Encode};
 use serde::{Deserialize, Serialize};
-use silius_primitives::{simulation::CodeHash, UserOperation, UserOperationHash};
+use silius_primitives::{
+    reputation::{ReputationEntry, ReputationStatus},
+    simulation::CodeHash,
+    UserOperation, UserOperationHash,
+};
 
 macro_rules! construct_wrap_hash {
     ($type:ty, $name:ident, $n_bytes:expr ) => {
@@ -100,3 +104,54 @@ construct_wrap_hash!(UserOperationHash, WrapUserOperationHash, 32);
 
 construct_wrap_struct!(CodeHash, WrapCodeHash);
 construct_wrap_struct!(UserOperation, WrapUserOperation);
+construct_wrap_struct!(u64, WrapU64);
+
+/// Reputation entry (encoded as the tuple of address, seen, included and status)
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WrapReputationEntry(pub ReputationEntry);
+
+impl Compress for WrapReputationEntry {
+    type Compressed = Bytes;
+    fn compress(self) -> Self::Compressed {
+        let status: u8 = match self.0.status {
+            ReputationStatus::OK => 0,
+            ReputationStatus::THROTTLED => 1,
+            ReputationStatus::BANNED => 2,
+        };
+        (self.0.address, self.0.uo_seen, self.0.uo_included, status)
+            .encode()
+            .into()
+    }
+}
+
+impl Decompress for WrapReputationEntry {
+    fn decompress<B: Into<prost::bytes::Bytes>>(value: B) -> Result<Self, reth_db::Error> {
+        let (address, uo_seen, uo_included, status) =
+            <(Address, u64, u64, u8)>::decode(value.into())
+                .map_err(|_e| reth_db::Error::DecodeError)?;
+        let status = match status {
+            0 => ReputationStatus::OK,
+            1 => ReputationStatus::THROTTLED,
+            2 => ReputationStatus::BANNED,
+            _ => return Err(reth_db::Error::DecodeError),
+        };
+        Ok(Self(ReputationEntry {
+            address,
+            uo_seen,
+            uo_included,
+            status,
+        }))
+    }
+}
+
+impl From<ReputationEntry> for WrapReputationEntry {
+    fn from(value: ReputationEntry) -> Self {
+        Self(value)
+    }
+}
+
+impl From<WrapReputationEntry> for ReputationEntry {
+    fn from(value: WrapReputationEntry) -> Self {
+        value.0
+    }
+}
@@ -1,16 +1,27 @@
 #![allow(dead_code)]
 
+pub mod bundle;
 mod database;
+mod lifecycle;
 mod memory;
 mod mempool;
+mod penalty;
+mod pre_verification_gas;
 mod reputation;
 mod uopool;
 mod utils;
 pub mod validate;
 
-pub use database::mempool::DatabaseMempool;
+pub use database::{mempool::DatabaseMempool, reputation::DatabaseReputation};
+pub use lifecycle::{Clock, Lifecycle, SystemClock};
 pub use memory::{mempool::MemoryMempool, reputation::MemoryReputation};
-pub use mempool::{mempool_id, MempoolId};
-pub use reputation::Reputation;
-pub use uopool::UoPool;
-pub use utils::Overhead;
+pub use mempool::{mempool_id, Mempool, MempoolBox, MempoolId};
+pub use penalty::SenderPenalty;
+pub use pre_verification_gas::{
+    pre_verification_gas_calculator, ArbitrumCalculator, OptimismCalculator,
+    PreVerificationGasCalculator,
+};
+pub use reputation::{Reputation, ReputationBox};
+pub use reth_db::mdbx::WriteMap;
+pub use uopool::{UoPool, VecCh, VecUo};
+pub use utils::{Overhead, OverheadBuilder};
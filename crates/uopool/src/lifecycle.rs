@@ -0,0 +1,486 @@
+use ethers::types::{H256, U256};
+use silius_primitives::{
+    uopool::{
+        EvictionEvent, EvictionReason, UserOperationState, UserOperationStatus, DEFAULT_UO_TTL,
+    },
+    UserOperationHash,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+
+const EVENTS_CHANNEL_CAPACITY: usize = 1024;
+/// Max number of received, rejected and evicted user operations whose states are kept each (the oldest are
+/// forgotten first)
+const MAX_UNTRACKED: usize = 10_000;
+
+/// Source of the current time (in seconds) used for expiry of user operations
+pub trait Clock: Send + Sync + Debug {
+    fn now(&self) -> u64;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// Map of user operations forgetting the oldest inserted ones over the capacity
+#[derive(Debug)]
+struct CappedMap<V> {
+    capacity: usize,
+    seq: u64,
+    entries: HashMap<UserOperationHash, (u64, V)>,
+    // insertion order, entries removed or inserted again since are skipped by their sequence number
+    order: VecDeque<(u64, UserOperationHash)>,
+}
+
+impl<V> CappedMap<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seq: 0,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, uo_hash: &UserOperationHash) -> Option<&V> {
+        self.entries.get(uo_hash).map(|(_, v)| v)
+    }
+
+    fn contains(&self, uo_hash: &UserOperationHash) -> bool {
+        self.entries.contains_key(uo_hash)
+    }
+
+    fn insert(&mut self, uo_hash: UserOperationHash, v: V) {
+        self.seq += 1;
+        self.entries.insert(uo_hash, (self.seq, v));
+        self.order.push_back((self.seq, uo_hash));
+
+        while self.order.len() > self.capacity {
+            if let Some((seq, uo_hash)) = self.order.pop_front() {
+                if self.entries.get(&uo_hash).map(|(s, _)| *s) == Some(seq) {
+                    self.entries.remove(&uo_hash);
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, uo_hash: &UserOperationHash) {
+        self.entries.remove(uo_hash);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Tracks the lifecycle of user operations in the mempool and notifies subscribers about evictions
+///
+/// The states of the user operations no longer tracked (received, rejected and evicted) are kept for at most
+/// [MAX_UNTRACKED] user operations each.
+#[derive(Debug)]
+pub struct Lifecycle {
+    clock: Arc<dyn Clock>,
+    ttl: u64,
+    expiry_buffer: u64,
+    expires_at: HashMap<UserOperationHash, u64>,
+    statuses: HashMap<UserOperationHash, UserOperationStatus>,
+    evicted: CappedMap<UserOperationStatus>,
+    received: CappedMap<()>,
+    submitted: HashMap<UserOperationHash, H256>,
+    rejected: CappedMap<String>,
+    events: broadcast::Sender<EvictionEvent>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock), DEFAULT_UO_TTL)
+    }
+}
+
+impl Lifecycle {
+    pub fn new(clock: Arc<dyn Clock>, ttl: u64) -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CHANNEL_CAPACITY);
+        Self {
+            clock,
+            ttl,
+            expiry_buffer: 0,
+            expires_at: HashMap::new(),
+            statuses: HashMap::new(),
+            evicted: CappedMap::new(MAX_UNTRACKED),
+            received: CappedMap::new(MAX_UNTRACKED),
+            submitted: HashMap::new(),
+            rejected: CappedMap::new(MAX_UNTRACKED),
+            events,
+        }
+    }
+
+    /// User operations expire this many seconds before their `validUntil`
+    pub fn with_expiry_buffer(mut self, expiry_buffer: u64) -> Self {
+        self.expiry_buffer = expiry_buffer;
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EvictionEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn get_status(&self, uo_hash: &UserOperationHash) -> Option<UserOperationStatus> {
+        self.statuses
+            .get(uo_hash)
+            .or_else(|| self.evicted.get(uo_hash))
+            .copied()
+    }
+
+    /// Status of the user operation known to the bundler (the on-chain inclusion isn't tracked here)
+    pub fn get_state(&self, uo_hash: &UserOperationHash) -> Option<UserOperationState> {
+        if let Some(tx_hash) = self.submitted.get(uo_hash) {
+            return Some(UserOperationState::Submitted {
+                transaction_hash: *tx_hash,
+            });
+        }
+
+        match self.get_status(uo_hash) {
+            Some(UserOperationStatus::Pending) => return Some(UserOperationState::Pending),
+            Some(status) => {
+                return Some(UserOperationState::Rejected {
+                    error: format!("{status:?} in the mempool"),
+                })
+            }
+            None => (),
+        }
+
+        if let Some(error) = self.rejected.get(uo_hash) {
+            return Some(UserOperationState::Rejected {
+                error: error.clone(),
+            });
+        }
+
+        self.received
+            .contains(uo_hash)
+            .then_some(UserOperationState::Received)
+    }
+
+    /// Marks the user operation as received (before it's validated)
+    pub fn receive(&mut self, uo_hash: &UserOperationHash) {
+        self.received.insert(*uo_hash, ());
+    }
+
+    /// Marks the user operation as rejected by the validation
+    pub fn reject(&mut self, uo_hash: &UserOperationHash, error: String) {
+        self.received.remove(uo_hash);
+        self.rejected.insert(*uo_hash, error);
+    }
+
+    /// Marks the tracked user operation as part of the sent (not yet mined) bundle transaction
+    pub fn submit(&mut self, uo_hash: &UserOperationHash, tx_hash: H256) {
+        if self.expires_at.contains_key(uo_hash) {
+            self.submitted.insert(*uo_hash, tx_hash);
+        }
+    }
+
+    /// Whether the user operation is part of the sent (not yet mined) bundle transaction
+    pub fn is_submitted(&self, uo_hash: &UserOperationHash) -> bool {
+        self.submitted.contains_key(uo_hash)
+    }
+
+    /// Starts tracking a user operation that was added to the mempool
+    ///
+    /// The user operation expires after the TTL or at its `validUntil` (minus the expiry buffer),
+    /// whichever comes first. Returns the expiry time (in seconds).
+    pub fn track(&mut self, uo_hash: &UserOperationHash, valid_until: Option<U256>) -> u64 {
+        let mut expires_at = self.clock.now().saturating_add(self.ttl);
+        if let Some(valid_until) = valid_until {
+            let valid_until = valid_until.saturating_sub(U256::from(self.expiry_buffer));
+            if valid_until < U256::from(expires_at) {
+                expires_at = valid_until.as_u64();
+            }
+        }
+
+        self.restore(uo_hash, expires_at);
+        expires_at
+    }
+
+    /// Tracks again a user operation restored from the database with its previously computed expiry time
+    pub fn restore(&mut self, uo_hash: &UserOperationHash, expires_at: u64) {
+        self.expires_at.insert(*uo_hash, expires_at);
+        self.statuses.insert(*uo_hash, UserOperationStatus::Pending);
+        self.evicted.remove(uo_hash);
+        self.received.remove(uo_hash);
+    }
+
+    /// Stops tracking a user operation without notifying subscribers (e.g., it was replaced)
+    pub fn untrack(&mut self, uo_hash: &UserOperationHash) {
+        self.expires_at.remove(uo_hash);
+        self.statuses.remove(uo_hash);
+        self.evicted.remove(uo_hash);
+        self.submitted.remove(uo_hash);
+    }
+
+    /// Marks a tracked user operation as invalidated and notifies subscribers
+    pub fn invalidate(&mut self, uo_hash: &UserOperationHash) {
+        self.evict(uo_hash, EvictionReason::Invalidated);
+    }
+
+    /// Marks a tracked user operation as dropped from the full mempool and notifies subscribers
+    pub fn discard(&mut self, uo_hash: &UserOperationHash) {
+        self.evict(uo_hash, EvictionReason::Dropped);
+    }
+
+    /// Returns hashes of all user operations that expired, marking them as expired and notifying subscribers
+    pub fn expire(&mut self) -> Vec<UserOperationHash> {
+        let now = self.clock.now();
+        let expired: Vec<UserOperationHash> = self
+            .expires_at
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(uo_hash, _)| *uo_hash)
+            .collect();
+
+        for uo_hash in expired.iter() {
+            self.evict(uo_hash, EvictionReason::Expired);
+        }
+
+        expired
+    }
+
+    pub fn clear(&mut self) {
+        self.expires_at.clear();
+        self.statuses.clear();
+        self.evicted.clear();
+        self.received.clear();
+        self.submitted.clear();
+        self.rejected.clear();
+    }
+
+    fn evict(&mut self, uo_hash: &UserOperationHash, reason: EvictionReason) {
+        if self.expires_at.remove(uo_hash).is_none() {
+            return;
+        }
+
+        self.statuses.remove(uo_hash);
+        self.evicted.insert(*uo_hash, reason.into());
+        self.submitted.remove(uo_hash);
+        // no subscribers is not an error
+        let _ = self.events.send(EvictionEvent {
+            hash: *uo_hash,
+            reason,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockClock;
+    use ethers::types::H256;
+
+    #[test]
+    fn lifecycle_expiry() {
+        let clock = Arc::new(MockClock::default());
+        let mut lifecycle = Lifecycle::new(clock.clone(), 100);
+        let mut events = lifecycle.subscribe();
+
+        let uo_hash: UserOperationHash = H256::random().into();
+        let uo_hash_valid_until: UserOperationHash = H256::random().into();
+        let uo_hash_invalid: UserOperationHash = H256::random().into();
+
+        assert_eq!(lifecycle.track(&uo_hash, None), 100);
+        assert_eq!(lifecycle.track(&uo_hash_valid_until, Some(50.into())), 50);
+        lifecycle.track(&uo_hash_invalid, None);
+        assert_eq!(
+            lifecycle.get_status(&uo_hash),
+            Some(UserOperationStatus::Pending)
+        );
+
+        clock.advance(50);
+        assert_eq!(lifecycle.expire(), vec![uo_hash_valid_until]);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            EvictionEvent {
+                hash: uo_hash_valid_until,
+                reason: EvictionReason::Expired
+            }
+        );
+
+        lifecycle.invalidate(&uo_hash_invalid);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            EvictionEvent {
+                hash: uo_hash_invalid,
+                reason: EvictionReason::Invalidated
+            }
+        );
+        assert_eq!(
+            lifecycle.get_status(&uo_hash_invalid),
+            Some(UserOperationStatus::Invalidated)
+        );
+
+        clock.advance(49);
+        assert!(lifecycle.expire().is_empty());
+        assert_eq!(
+            lifecycle.get_status(&uo_hash),
+            Some(UserOperationStatus::Pending)
+        );
+
+        clock.advance(1);
+        assert_eq!(lifecycle.expire(), vec![uo_hash]);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            EvictionEvent {
+                hash: uo_hash,
+                reason: EvictionReason::Expired
+            }
+        );
+        assert_eq!(
+            lifecycle.get_status(&uo_hash),
+            Some(UserOperationStatus::Expired)
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn lifecycle_expiry_buffer() {
+        let clock = Arc::new(MockClock::default());
+        let mut lifecycle = Lifecycle::new(clock.clone(), 100).with_expiry_buffer(10);
+
+        let uo_hash: UserOperationHash = H256::random().into();
+        lifecycle.track(&uo_hash, Some(50.into()));
+
+        clock.advance(39);
+        assert!(lifecycle.expire().is_empty());
+
+        clock.advance(1);
+        assert_eq!(lifecycle.expire(), vec![uo_hash]);
+    }
+
+    #[test]
+    fn lifecycle_states() {
+        let mut lifecycle = Lifecycle::default();
+        let uo_hash: UserOperationHash = H256::random().into();
+        let uo_hash_rejected: UserOperationHash = H256::random().into();
+        let tx_hash = H256::random();
+
+        assert_eq!(lifecycle.get_state(&uo_hash), None);
+
+        lifecycle.receive(&uo_hash);
+        assert_eq!(
+            lifecycle.get_state(&uo_hash),
+            Some(UserOperationState::Received)
+        );
+
+        // only tracked user operations can be submitted
+        lifecycle.submit(&uo_hash, tx_hash);
+        assert_eq!(
+            lifecycle.get_state(&uo_hash),
+            Some(UserOperationState::Received)
+        );
+
+        lifecycle.track(&uo_hash, None);
+        assert_eq!(
+            lifecycle.get_state(&uo_hash),
+            Some(UserOperationState::Pending)
+        );
+
+        lifecycle.submit(&uo_hash, tx_hash);
+        assert_eq!(
+            lifecycle.get_state(&uo_hash),
+            Some(UserOperationState::Submitted {
+                transaction_hash: tx_hash
+            })
+        );
+
+        lifecycle.invalidate(&uo_hash);
+        assert_eq!(
+            lifecycle.get_state(&uo_hash),
+            Some(UserOperationState::Rejected {
+                error: "Invalidated in the mempool".into()
+            })
+        );
+
+        lifecycle.receive(&uo_hash_rejected);
+        lifecycle.reject(&uo_hash_rejected, "invalid nonce".into());
+        assert_eq!(
+            lifecycle.get_state(&uo_hash_rejected),
+            Some(UserOperationState::Rejected {
+                error: "invalid nonce".into()
+            })
+        );
+
+        lifecycle.clear();
+        assert_eq!(lifecycle.get_state(&uo_hash_rejected), None);
+    }
+
+    #[test]
+    fn lifecycle_bounded() {
+        let mut lifecycle = Lifecycle::default();
+        let uo_hashes: Vec<UserOperationHash> =
+            (0..=MAX_UNTRACKED).map(|_| H256::random().into()).collect();
+
+        // the oldest evicted user operation is forgotten over the cap
+        for uo_hash in uo_hashes.iter() {
+            lifecycle.track(uo_hash, None);
+            lifecycle.invalidate(uo_hash);
+        }
+        assert!(lifecycle.statuses.is_empty());
+        assert_eq!(lifecycle.get_status(&uo_hashes[0]), None);
+        assert_eq!(
+            lifecycle.get_status(&uo_hashes[1]),
+            Some(UserOperationStatus::Invalidated)
+        );
+
+        // received again, the user operation is forgotten after the ones received before it
+        for uo_hash in uo_hashes.iter().take(MAX_UNTRACKED) {
+            lifecycle.receive(uo_hash);
+        }
+        lifecycle.receive(&uo_hashes[0]);
+        lifecycle.receive(&uo_hashes[MAX_UNTRACKED]);
+        assert!(lifecycle.received.contains(&uo_hashes[0]));
+        assert!(!lifecycle.received.contains(&uo_hashes[1]));
+        assert!(lifecycle.received.contains(&uo_hashes[MAX_UNTRACKED]));
+    }
+
+    #[test]
+    fn lifecycle_states_bounded() {
+        let mut lifecycle = Lifecycle::default();
+        let uo_hash_received: UserOperationHash = H256::random().into();
+        let uo_hash_rejected: UserOperationHash = H256::random().into();
+        let uo_hash_expired: UserOperationHash = H256::random().into();
+
+        lifecycle.receive(&uo_hash_received);
+        lifecycle.reject(&uo_hash_rejected, "invalid nonce".into());
+        lifecycle.track(&uo_hash_expired, Some(0.into()));
+        lifecycle.expire();
+        assert_eq!(
+            lifecycle.get_state(&uo_hash_expired),
+            Some(UserOperationState::Rejected {
+                error: "Expired in the mempool".into()
+            })
+        );
+
+        // states of the user operations seen after them push them out
+        for _ in 0..MAX_UNTRACKED {
+            let uo_hash: UserOperationHash = H256::random().into();
+            lifecycle.receive(&uo_hash);
+            lifecycle.reject(&uo_hash, "invalid nonce".into());
+            lifecycle.track(&uo_hash, None);
+            lifecycle.invalidate(&uo_hash);
+        }
+        assert_eq!(lifecycle.get_state(&uo_hash_received), None);
+        assert_eq!(lifecycle.get_state(&uo_hash_rejected), None);
+        assert_eq!(lifecycle.get_state(&uo_hash_expired), None);
+    }
+}
@@ -2,7 +2,7 @@ use crate::mempool::Mempool;
 use educe::Educe;
 use ethers::types::{Address, U256};
 use silius_primitives::{simulation::CodeHash, UserOperation, UserOperationHash};
-use std::collections::{HashMap, HashSet};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 #[derive(Default, Educe)]
 #[educe(Debug)]
@@ -10,6 +10,9 @@ pub struct MemoryMempool {
     user_operations: HashMap<UserOperationHash, UserOperation>, // user_operation_hash -> user_operation
     user_operations_by_sender: HashMap<Address, HashSet<UserOperationHash>>, // sender -> user_operations
     code_hashes_by_user_operation: HashMap<UserOperationHash, Vec<CodeHash>>, // user_operation_hash -> (contract_address -> code_hash)
+    insertion_order: HashMap<UserOperationHash, u64>, // user_operation_hash -> sequence number of the insertion
+    expires_at: HashMap<UserOperationHash, u64>, // user_operation_hash -> expiry time (in seconds)
+    next_insertion: u64,
 }
 
 impl Mempool for MemoryMempool {
@@ -30,6 +33,10 @@ impl Mempool for MemoryMempool {
             .or_insert_with(Default::default)
             .insert(uo_hash);
         self.user_operations.insert(uo_hash, uo);
+        if let Entry::Vacant(entry) = self.insertion_order.entry(uo_hash) {
+            entry.insert(self.next_insertion);
+            self.next_insertion += 1;
+        }
 
         Ok(uo_hash)
     }
@@ -79,6 +86,19 @@ impl Mempool for MemoryMempool {
         }
     }
 
+    fn set_expires_at(
+        &mut self,
+        uo_hash: &UserOperationHash,
+        expires_at: u64,
+    ) -> anyhow::Result<(), Self::Error> {
+        self.expires_at.insert(*uo_hash, expires_at);
+        Ok(())
+    }
+
+    fn get_expires_at(&self, uo_hash: &UserOperationHash) -> Option<u64> {
+        self.expires_at.get(uo_hash).copied()
+    }
+
     fn remove(&mut self, uo_hash: &UserOperationHash) -> anyhow::Result<()> {
         let uo: UserOperation;
 
@@ -99,6 +119,8 @@ impl Mempool for MemoryMempool {
         }
 
         self.code_hashes_by_user_operation.remove(uo_hash);
+        self.insertion_order.remove(uo_hash);
+        self.expires_at.remove(uo_hash);
 
         Ok(())
     }
@@ -116,13 +138,18 @@ impl Mempool for MemoryMempool {
     }
 
     fn get_all(&self) -> Self::UserOperations {
-        self.user_operations.values().cloned().collect()
+        let mut uos: Vec<(&UserOperationHash, &UserOperation)> =
+            self.user_operations.iter().collect();
+        uos.sort_by_key(|(uo_hash, _)| self.insertion_order.get(*uo_hash));
+        uos.into_iter().map(|(_, uo)| uo.clone()).collect()
     }
 
     fn clear(&mut self) {
         self.user_operations.clear();
         self.user_operations_by_sender.clear();
         self.code_hashes_by_user_operation.clear();
+        self.insertion_order.clear();
+        self.expires_at.clear();
     }
 }
 
@@ -137,4 +164,28 @@ mod tests {
         let mempool = MemoryMempool::default();
         mempool_test_case(mempool, "User operation not found");
     }
+
+    #[test]
+    fn memory_mempool_insertion_order() {
+        let mut mempool = MemoryMempool::default();
+        let ep = Address::random();
+        let chain_id = U256::from(5);
+
+        let uos: Vec<UserOperation> = (0..10).map(|_| UserOperation::random()).collect();
+        let uo_hashes: Vec<UserOperationHash> = uos
+            .iter()
+            .map(|uo| mempool.add(uo.clone(), &ep, &chain_id).unwrap())
+            .collect();
+        assert_eq!(mempool.get_all(), uos);
+
+        mempool.remove(&uo_hashes[3]).unwrap();
+        mempool.add(uos[3].clone(), &ep, &chain_id).unwrap();
+        assert_eq!(
+            mempool.get_all(),
+            [&uos[..3], &uos[4..], &uos[3..4]].concat()
+        );
+
+        mempool.clear();
+        assert!(mempool.get_all().is_empty());
+    }
 }
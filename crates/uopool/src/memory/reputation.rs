@@ -12,6 +12,7 @@ pub struct MemoryReputation {
     min_inclusion_denominator: u64,
     throttling_slack: u64,
     ban_slack: u64,
+    hourly_decay_denominator: u64,
     min_stake: U256,
     min_unstake_delay: U256,
 
@@ -43,12 +44,14 @@ impl Reputation for MemoryReputation {
         min_inclusion_denominator: u64,
         throttling_slack: u64,
         ban_slack: u64,
+        hourly_decay_denominator: u64,
         min_stake: U256,
         min_unstake_delay: U256,
     ) {
         self.min_inclusion_denominator = min_inclusion_denominator;
         self.throttling_slack = throttling_slack;
         self.ban_slack = ban_slack;
+        self.hourly_decay_denominator = hourly_decay_denominator;
         self.min_stake = min_stake;
         self.min_unstake_delay = min_unstake_delay;
     }
@@ -84,13 +87,20 @@ impl Reputation for MemoryReputation {
         }
     }
 
-    fn update_hourly(&mut self) {
+    fn update_hourly(&mut self) -> Result<(), ReputationError> {
+        let d = self.hourly_decay_denominator;
+        if d == 0 {
+            return Ok(());
+        }
+
         for (_, ent) in self.entities.iter_mut() {
-            ent.uo_seen = ent.uo_seen * 23 / 24;
-            ent.uo_included = ent.uo_included * 23 / 24;
+            ent.uo_seen = ent.uo_seen * (d - 1) / d;
+            ent.uo_included = ent.uo_included * (d - 1) / d;
         }
         self.entities
             .retain(|_, ent| ent.uo_seen > 0 || ent.uo_included > 0);
+
+        Ok(())
     }
 
     fn add_whitelist(&mut self, addr: &Address) -> bool {
@@ -128,7 +138,11 @@ impl Reputation for MemoryReputation {
 
         match self.entities.get(addr) {
             Some(ent) => {
-                let min_expected_included = ent.uo_seen / self.min_inclusion_denominator;
+                // nothing is expected to be included before the reputation is initialized
+                let min_expected_included = ent
+                    .uo_seen
+                    .checked_div(self.min_inclusion_denominator)
+                    .unwrap_or_default();
                 if min_expected_included <= ent.uo_included + self.throttling_slack {
                     ReputationStatus::OK
                 } else if min_expected_included <= ent.uo_included + self.ban_slack {
@@ -149,19 +163,26 @@ impl Reputation for MemoryReputation {
         }
     }
 
+    fn update_op_reverted(&mut self, addr: &Address, penalty: u64) {
+        let seen = penalty.saturating_mul(self.min_inclusion_denominator);
+        self.set(addr);
+        if let Some(ent) = self.entities.get_mut(addr) {
+            ent.uo_seen = ent.uo_seen.saturating_add(seen);
+            ent.uo_included = ent.uo_included.saturating_sub(penalty);
+        }
+    }
+
     fn verify_stake(&self, title: &str, info: Option<StakeInfo>) -> Result<(), ReputationError> {
         if let Some(info) = info {
             if self.is_whitelist(&info.address) {
                 return Ok(());
             }
 
-            if let Some(ent) = self.entities.get(&info.address) {
-                if ent.status == ReputationStatus::BANNED {
-                    return Err(ReputationError::EntityBanned {
-                        address: info.address,
-                        title: title.to_string(),
-                    });
-                }
+            if self.get_status(&info.address) == ReputationStatus::BANNED {
+                return Err(ReputationError::EntityBanned {
+                    address: info.address,
+                    title: title.to_string(),
+                });
             }
 
             let err = if info.stake < self.min_stake {
@@ -188,10 +209,12 @@ impl Reputation for MemoryReputation {
         Ok(())
     }
 
-    fn set(&mut self, entries: Self::ReputationEntries) {
+    fn set(&mut self, entries: Self::ReputationEntries) -> Result<(), ReputationError> {
         for en in entries {
             self.entities.insert(en.address, en);
         }
+
+        Ok(())
     }
 
     fn get_all(&self) -> Self::ReputationEntries {
@@ -207,7 +230,7 @@ impl Reputation for MemoryReputation {
 mod tests {
     use super::*;
     use silius_primitives::reputation::{
-        BAN_SLACK, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+        BAN_SLACK, HOURLY_DECAY_DENOMINATOR, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
     };
 
     #[tokio::test]
@@ -217,6 +240,7 @@ mod tests {
             MIN_INCLUSION_RATE_DENOMINATOR,
             THROTTLING_SLACK,
             BAN_SLACK,
+            HOURLY_DECAY_DENOMINATOR,
             U256::from(1),
             U256::from(0),
         );
@@ -281,4 +305,35 @@ mod tests {
         }
         assert_eq!(reputation.get_status(&addrs[3]), ReputationStatus::BANNED);
     }
+
+    #[tokio::test]
+    async fn op_reverted() {
+        let mut reputation: MemoryReputation = MemoryReputation::default();
+        reputation.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            HOURLY_DECAY_DENOMINATOR,
+            U256::from(1),
+            U256::from(0),
+        );
+        let addr = Address::random();
+        for _ in 0..5 {
+            reputation.increment_seen(&addr);
+            reputation.increment_included(&addr);
+        }
+
+        reputation.update_op_reverted(&addr, 10);
+        assert_eq!(reputation.get(&addr).uo_included, 0);
+        assert_eq!(reputation.get(&addr).uo_seen, 105);
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::OK);
+
+        reputation.update_op_reverted(&addr, 10);
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::THROTTLED);
+
+        for _ in 0..4 {
+            reputation.update_op_reverted(&addr, 10);
+        }
+        assert_eq!(reputation.get_status(&addr), ReputationStatus::BANNED);
+    }
 }
@@ -43,9 +43,17 @@ pub trait Mempool: Debug {
         hashes: &Self::CodeHashes,
     ) -> Result<(), Self::Error>;
     fn get_code_hashes(&self, uo_hash: &UserOperationHash) -> Self::CodeHashes;
+    /// Stores the time (in seconds) the user operation expires at, so the expiry survives restarts
+    fn set_expires_at(
+        &mut self,
+        uo_hash: &UserOperationHash,
+        expires_at: u64,
+    ) -> Result<(), Self::Error>;
+    fn get_expires_at(&self, uo_hash: &UserOperationHash) -> Option<u64>;
     fn remove(&mut self, uo_hash: &UserOperationHash) -> Result<(), Self::Error>;
     // Get UserOperations sorted by max_priority_fee_per_gas without dup sender
     fn get_sorted(&self) -> Result<Self::UserOperations, Self::Error>;
+    // Get all UserOperations (in the insertion order if the mempool keeps it)
     fn get_all(&self) -> Self::UserOperations;
     fn clear(&mut self);
 }
@@ -0,0 +1,106 @@
+use crate::lifecycle::Clock;
+use ethers::types::Address;
+use silius_primitives::sanity::SanityCheckError;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+/// Temporarily rejects user operations from senders whose user operations recently failed simulation
+#[derive(Debug)]
+pub struct SenderPenalty {
+    clock: Arc<dyn Clock>,
+    /// Time window (in seconds) in which failures are counted
+    window: u64,
+    /// Number of failures in the window after which the sender is rejected
+    max_failures: usize,
+    failures: Mutex<HashMap<Address, Vec<u64>>>,
+}
+
+impl SenderPenalty {
+    pub fn new(clock: Arc<dyn Clock>, window: u64, max_failures: usize) -> Self {
+        Self {
+            clock,
+            window,
+            max_failures,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a failed validation of the sender's user operation
+    pub fn record_failure(&self, sender: &Address) {
+        let now = self.clock.now();
+        let mut failures = self.failures.lock().expect("Sender penalty lock poisoned");
+        let entry = failures.entry(*sender).or_default();
+        entry.retain(|ts| ts + self.window > now);
+        entry.push(now);
+    }
+
+    /// Checks whether the sender is allowed to submit new user operations
+    pub fn check(&self, sender: &Address) -> Result<(), SanityCheckError> {
+        let now = self.clock.now();
+        let mut failures = self.failures.lock().expect("Sender penalty lock poisoned");
+
+        if let Some(entry) = failures.get_mut(sender) {
+            entry.retain(|ts| ts + self.window > now);
+
+            if entry.is_empty() {
+                failures.remove(sender);
+            } else if entry.len() >= self.max_failures {
+                let oldest = entry[entry.len() - self.max_failures];
+                return Err(SanityCheckError::SenderPenalized {
+                    sender: *sender,
+                    retry_after: (oldest + self.window).saturating_sub(now),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn clear(&self) {
+        self.failures
+            .lock()
+            .expect("Sender penalty lock poisoned")
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockClock;
+
+    #[test]
+    fn sender_penalty() {
+        let clock = Arc::new(MockClock::default());
+        let penalty = SenderPenalty::new(clock.clone(), 10, 2);
+        let sender = Address::random();
+        let sender_other = Address::random();
+
+        penalty.record_failure(&sender);
+        assert!(penalty.check(&sender).is_ok());
+
+        clock.advance(2);
+        penalty.record_failure(&sender);
+        assert!(matches!(
+            penalty.check(&sender),
+            Err(SanityCheckError::SenderPenalized { retry_after: 8, .. })
+        ));
+        assert!(penalty.check(&sender_other).is_ok());
+
+        // first failure is out of the window
+        clock.advance(8);
+        assert!(penalty.check(&sender).is_ok());
+
+        penalty.record_failure(&sender);
+        assert!(matches!(
+            penalty.check(&sender),
+            Err(SanityCheckError::SenderPenalized { retry_after: 2, .. })
+        ));
+
+        clock.advance(10);
+        assert!(penalty.check(&sender).is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    types::{Address, BlockNumber, Bytes, TransactionRequest, U256},
+    utils::id,
+};
+use silius_contracts::entry_point::EntryPointAPI;
+use silius_primitives::{chain::L1GasOracle, ChainSpec, UserOperation};
+use std::sync::Arc;
+
+/// Arbitrum node interface precompile
+pub const ARBITRUM_NODE_INTERFACE: &str = "0x00000000000000000000000000000000000000C8";
+/// Optimism (OP stack) gas price oracle predeploy
+pub const OPTIMISM_GAS_PRICE_ORACLE: &str = "0x420000000000000000000000000000000000000F";
+
+/// Calculates the part of the pre-verification gas that pays for posting the user operation to L1
+#[async_trait::async_trait]
+pub trait PreVerificationGasCalculator: Send + Sync {
+    async fn calculate_l1_gas(
+        &self,
+        uo: &UserOperation,
+        entry_point: &Address,
+    ) -> anyhow::Result<U256>;
+}
+
+/// Returns the calculator for the chain (`None` for chains without an L1 data fee, e.g., mainnet)
+pub fn pre_verification_gas_calculator<M: Middleware + 'static>(
+    chain_spec: &ChainSpec,
+    eth_client: Arc<M>,
+) -> Option<Arc<dyn PreVerificationGasCalculator>> {
+    match chain_spec.l1_gas_oracle {
+        L1GasOracle::Arbitrum => Some(Arc::new(ArbitrumCalculator::new(eth_client))),
+        L1GasOracle::Optimism => Some(Arc::new(OptimismCalculator::new(eth_client))),
+        L1GasOracle::None => None,
+    }
+}
+
+/// Calldata of the bundle transaction containing just the user operation
+fn handle_ops_calldata<M: Middleware + 'static>(
+    eth_client: Arc<M>,
+    uo: &UserOperation,
+    entry_point: &Address,
+) -> anyhow::Result<Bytes> {
+    EntryPointAPI::new(*entry_point, eth_client)
+        .handle_ops(vec![uo.clone().into()], Address::zero())
+        .calldata()
+        .ok_or_else(|| anyhow::anyhow!("Failed to encode handleOps calldata"))
+}
+
+async fn call<M: Middleware + 'static>(
+    eth_client: &Arc<M>,
+    to: &str,
+    data: Vec<u8>,
+    output: &[ParamType],
+) -> anyhow::Result<Vec<Token>> {
+    let tx = TransactionRequest::new()
+        .to(to.parse::<Address>()?)
+        .data(data);
+    let res = eth_client
+        .call(&tx.into(), None)
+        .await
+        .map_err(|err| anyhow::anyhow!("Gas oracle call failed: {err:?}"))?;
+    Ok(abi::decode(output, &res)?)
+}
+
+/// Uses `gasEstimateL1Component` of the Arbitrum node interface, which returns the L1 component in L2 gas
+pub struct ArbitrumCalculator<M: Middleware + 'static> {
+    eth_client: Arc<M>,
+}
+
+impl<M: Middleware + 'static> ArbitrumCalculator<M> {
+    pub fn new(eth_client: Arc<M>) -> Self {
+        Self { eth_client }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware + 'static> PreVerificationGasCalculator for ArbitrumCalculator<M> {
+    async fn calculate_l1_gas(
+        &self,
+        uo: &UserOperation,
+        entry_point: &Address,
+    ) -> anyhow::Result<U256> {
+        let calldata = handle_ops_calldata(self.eth_client.clone(), uo, entry_point)?;
+        let data = [
+            id("gasEstimateL1Component(address,bool,bytes)").to_vec(),
+            abi::encode(&[
+                Token::Address(*entry_point),
+                Token::Bool(false),
+                Token::Bytes(calldata.to_vec()),
+            ]),
+        ]
+        .concat();
+
+        let res = call(
+            &self.eth_client,
+            ARBITRUM_NODE_INTERFACE,
+            data,
+            &[
+                ParamType::Uint(64),
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+            ],
+        )
+        .await?;
+
+        res.into_iter()
+            .next()
+            .and_then(Token::into_uint)
+            .ok_or_else(|| anyhow::anyhow!("Invalid gasEstimateL1Component response"))
+    }
+}
+
+/// Uses `getL1Fee` of the OP stack gas price oracle and converts the fee (in wei) to L2 gas using the gas
+/// price of the user operation
+pub struct OptimismCalculator<M: Middleware + 'static> {
+    eth_client: Arc<M>,
+}
+
+impl<M: Middleware + 'static> OptimismCalculator<M> {
+    pub fn new(eth_client: Arc<M>) -> Self {
+        Self { eth_client }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware + 'static> PreVerificationGasCalculator for OptimismCalculator<M> {
+    async fn calculate_l1_gas(
+        &self,
+        uo: &UserOperation,
+        entry_point: &Address,
+    ) -> anyhow::Result<U256> {
+        let calldata = handle_ops_calldata(self.eth_client.clone(), uo, entry_point)?;
+        let data = [
+            id("getL1Fee(bytes)").to_vec(),
+            abi::encode(&[Token::Bytes(calldata.to_vec())]),
+        ]
+        .concat();
+
+        let l1_fee = call(
+            &self.eth_client,
+            OPTIMISM_GAS_PRICE_ORACLE,
+            data,
+            &[ParamType::Uint(256)],
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(Token::into_uint)
+        .ok_or_else(|| anyhow::anyhow!("Invalid getL1Fee response"))?;
+
+        let base_fee_per_gas = self
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await
+            .map_err(|err| anyhow::anyhow!("Getting latest block failed: {err:?}"))?
+            .and_then(|block| block.base_fee_per_gas)
+            .unwrap_or_default();
+        let gas_price = uo.effective_gas_price(base_fee_per_gas);
+
+        if gas_price.is_zero() {
+            return Ok(U256::zero());
+        }
+
+        Ok(l1_fee / gas_price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::AbiEncode,
+        providers::Provider,
+        types::{Block, H256},
+    };
+    use silius_primitives::Chain;
+
+    #[tokio::test]
+    async fn arbitrum_l1_gas() {
+        let (eth_client, mock) = Provider::mocked();
+        let calculator = ArbitrumCalculator::new(Arc::new(eth_client));
+
+        mock.push::<Bytes, _>(Bytes::from(
+            (
+                100_000_u64,
+                U256::from(100_000_000),
+                U256::from(20_000_000_000_u64),
+            )
+                .encode(),
+        ))
+        .unwrap();
+        assert_eq!(
+            calculator
+                .calculate_l1_gas(&UserOperation::default(), &Address::random())
+                .await
+                .unwrap(),
+            U256::from(100_000)
+        );
+    }
+
+    #[tokio::test]
+    async fn optimism_l1_gas() {
+        let (eth_client, mock) = Provider::mocked();
+        let calculator = OptimismCalculator::new(Arc::new(eth_client));
+        let uo = UserOperation::default()
+            .max_fee_per_gas(3_000_000_000_u64.into())
+            .max_priority_fee_per_gas(1_000_000_000.into());
+
+        // responses are popped from the back
+        mock.push(Block::<H256> {
+            base_fee_per_gas: Some(1_000_000_000.into()),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Bytes, _>(Bytes::from(U256::from(4_000_000_000_000_u64).encode()))
+            .unwrap();
+        assert_eq!(
+            calculator
+                .calculate_l1_gas(&uo, &Address::random())
+                .await
+                .unwrap(),
+            U256::from(2_000)
+        );
+    }
+
+    #[test]
+    fn calculator_selection() {
+        let (eth_client, _) = Provider::mocked();
+        let eth_client = Arc::new(eth_client);
+
+        for (chain_id, is_some) in [(1_u64, false), (42161, true), (10, true)] {
+            assert_eq!(
+                pre_verification_gas_calculator(
+                    &ChainSpec::for_chain(&Chain::from(chain_id)),
+                    eth_client.clone()
+                )
+                .is_some(),
+                is_some
+            );
+        }
+    }
+}
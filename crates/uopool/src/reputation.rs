@@ -15,13 +15,14 @@ pub trait Reputation: Debug {
         min_inclusion_denominator: u64,
         throttling_slack: u64,
         ban_slack: u64,
+        hourly_decay_denominator: u64,
         min_stake: U256,
         min_unstake_delay: U256,
     );
     fn get(&mut self, addr: &Address) -> ReputationEntry;
     fn increment_seen(&mut self, addr: &Address);
     fn increment_included(&mut self, addr: &Address);
-    fn update_hourly(&mut self);
+    fn update_hourly(&mut self) -> Result<(), ReputationError>;
     fn add_whitelist(&mut self, addr: &Address) -> bool;
     fn remove_whitelist(&mut self, addr: &Address) -> bool;
     fn is_whitelist(&self, addr: &Address) -> bool;
@@ -30,6 +31,7 @@ pub trait Reputation: Debug {
     fn is_blacklist(&self, addr: &Address) -> bool;
     fn get_status(&self, addr: &Address) -> ReputationStatus;
     fn update_handle_ops_reverted(&mut self, addr: &Address);
+    fn update_op_reverted(&mut self, addr: &Address, penalty: u64);
     fn verify_stake(&self, title: &str, info: Option<StakeInfo>) -> Result<(), ReputationError>;
 
     // Try to get the reputation status from a sequence of bytes which the first 20 bytes should be the address
@@ -44,7 +46,7 @@ pub trait Reputation: Debug {
         }
     }
 
-    fn set(&mut self, entries: Self::ReputationEntries);
+    fn set(&mut self, entries: Self::ReputationEntries) -> Result<(), ReputationError>;
     fn get_all(&self) -> Self::ReputationEntries;
     fn clear(&mut self);
 }
@@ -1,42 +1,73 @@
 use crate::{
+    bundle::{
+        create_bundle, failed_op_entity, is_entity_conflict, prefund_payer, reverted_op_entity,
+        validation_error_entity, BundleLimits, BundleOrdering, UnstakedEntityCaps,
+        DEFAULT_MAX_BUNDLE_GAS, HANDLE_OPS_GAS_OVERHEAD,
+    },
+    lifecycle::Lifecycle,
     mempool::MempoolBox,
     mempool_id,
+    penalty::SenderPenalty,
+    pre_verification_gas::pre_verification_gas_calculator,
     reputation::ReputationBox,
-    utils::calculate_call_gas_limit,
+    utils::{calculate_call_gas_limit, calculate_valid_gas, filter_user_operation_logs},
     validate::{
-        UserOperationValidationOutcome, UserOperationValidator, UserOperationValidatorMode,
+        retry::RetryPolicy, UserOperationValidationOutcome, UserOperationValidator,
+        UserOperationValidatorMode,
     },
-    MempoolId, Overhead,
+    MemoryMempool, MempoolId, Overhead,
 };
 use anyhow::format_err;
 use ethers::{
+    abi::{AbiEncode, RawLog},
+    contract::EthEvent,
     prelude::LogMeta,
     providers::Middleware,
-    types::{Address, BlockNumber, U256, U64},
+    types::{spoof, Address, BlockNumber, H160, H256, U256, U64},
 };
 use silius_contracts::{
-    entry_point::{EntryPointAPIEvents, EntryPointErr, UserOperationEventFilter},
-    utils::parse_from_input_data,
+    entry_point::{
+        EntryPointAPIEvents, EntryPointErr, UserOperationEventFilter,
+        UserOperationRevertReasonFilter,
+    },
+    utils::{parse_from_input_data, parse_simulate_validation_revert},
     EntryPoint,
 };
 use silius_primitives::{
+    bundler::{KnownAccounts, SubmissionMode},
     get_address,
-    reputation::{ReputationEntry, ReputationStatus, THROTTLED_MAX_INCLUDE},
-    simulation::{CodeHash, SimulationCheckError},
-    uopool::{AddError, ValidationError},
-    Chain, UserOperation, UserOperationByHash, UserOperationGasEstimation, UserOperationHash,
-    UserOperationReceipt,
+    metrics::METRICS,
+    reputation::{
+        ReputationEntry, ReputationError, ReputationStatus, StakeInfo, THROTTLED_MAX_INCLUDE,
+    },
+    sanity::SanityCheckError,
+    simulation::{
+        decode_revert_reason, CodeHash, SimulateValidationRevert, SimulationCheckError,
+        FAILED_OP_SELECTOR,
+    },
+    uopool::{
+        AddError, DropReason, EvictionEvent, InclusionEvent, NonceStatus, OrderingPolicy,
+        P2pOpsPolicy, UserOperationState, UserOperationStatus, ValidationError, GAS_INCREASE_PERC,
+    },
+    CallGasEstimationMethod, Chain, ChainSpec, UserOperation, UserOperationByHash,
+    UserOperationGasEstimation, UserOperationHash, UserOperationReceipt,
 };
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     sync::Arc,
 };
-use tracing::trace;
+use tokio::sync::broadcast;
+use tracing::{info, trace, warn};
 
 pub type VecUo = Vec<UserOperation>;
 pub type VecCh = Vec<CodeHash>;
 
 const LATEST_SCAN_DEPTH: u64 = 1000;
+/// Max number of blocks tracked for reorgs
+const MAX_REORG_DEPTH: u64 = 64;
+/// Beneficiary of the bundle simulation (`eth_call` doesn't transfer the funds, any non-zero address works)
+const BUNDLE_SIMULATION_BENEFICIARY: Address = H160([0x01; 20]);
+const INCLUSION_EVENTS_CHANNEL_CAPACITY: usize = 1024;
 
 pub struct UoPool<M: Middleware + 'static, V: UserOperationValidator> {
     pub id: MempoolId,
@@ -47,6 +78,36 @@ pub struct UoPool<M: Middleware + 'static, V: UserOperationValidator> {
     pub eth_client: Arc<M>,
     pub max_verification_gas: U256,
     pub chain: Chain,
+    pub lifecycle: Lifecycle,
+    pub sender_penalty: Option<SenderPenalty>,
+    pub gas_increase_perc: U256,
+    pub p2p_ops_policy: P2pOpsPolicy,
+    pub overhead: Overhead,
+    pub max_mempool_size: Option<usize>,
+    pub max_revalidations: Option<usize>,
+    pub revert_penalty: Option<u64>,
+    pub max_bundle_gas: U256,
+    pub max_ops_per_bundle: Option<usize>,
+    pub bundle_ordering: OrderingPolicy,
+    pub unstaked_entity_caps: Option<UnstakedEntityCaps>,
+    pub retry_policy: RetryPolicy,
+    pub confirmation_blocks: u64,
+    // the reputation is persisted here on shutdown (and restored on start)
+    reputation_db: Option<ReputationBox<Vec<ReputationEntry>>>,
+    // user operations received over P2P (provenance marker)
+    p2p_uos: HashSet<UserOperationHash>,
+    // user operations selected for the latest bundle (with their valid until and aggregator)
+    bundled: HashMap<UserOperationHash, (U256, Option<Address>)>,
+    // storage slots accessed during the 2nd validation of the user operations selected for the latest bundle
+    bundled_slots: HashMap<UserOperationHash, HashMap<Address, HashSet<H256>>>,
+    // user operations dropped while creating the latest bundle (with the reasons)
+    bundle_dropped: Vec<(UserOperationHash, String)>,
+    // hashes of the latest blocks seen by the pool (used to detect reorgs)
+    recent_blocks: BTreeMap<U64, H256>,
+    // user operations of the pool included on chain with fewer than the confirmation blocks (kept out of bundles)
+    mined: HashSet<UserOperationHash>,
+    // notifications about user operations of the pool included on chain
+    inclusions: broadcast::Sender<InclusionEvent>,
 }
 
 impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
@@ -60,18 +121,146 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
         max_verification_gas: U256,
         chain: Chain,
     ) -> Self {
+        let chain_spec = ChainSpec::known(chain.id()).unwrap_or_default();
         Self {
             id: mempool_id(&entry_point.address(), &chain.id().into()),
             entry_point,
             validator,
             mempool,
             reputation,
+            overhead: Overhead::from(&chain_spec).with_calculator(pre_verification_gas_calculator(
+                &chain_spec,
+                eth_client.clone(),
+            )),
             eth_client,
             max_verification_gas,
             chain,
+            lifecycle: Lifecycle::default(),
+            sender_penalty: None,
+            gas_increase_perc: GAS_INCREASE_PERC.into(),
+            p2p_ops_policy: P2pOpsPolicy::default(),
+            max_mempool_size: None,
+            max_revalidations: None,
+            revert_penalty: None,
+            max_bundle_gas: DEFAULT_MAX_BUNDLE_GAS.into(),
+            max_ops_per_bundle: None,
+            bundle_ordering: OrderingPolicy::default(),
+            unstaked_entity_caps: None,
+            retry_policy: RetryPolicy::default(),
+            confirmation_blocks: 0,
+            reputation_db: None,
+            p2p_uos: HashSet::new(),
+            bundled: HashMap::new(),
+            bundled_slots: HashMap::new(),
+            bundle_dropped: vec![],
+            recent_blocks: BTreeMap::new(),
+            mined: HashSet::new(),
+            inclusions: broadcast::channel(INCLUSION_EVENTS_CHANNEL_CAPACITY).0,
         }
     }
 
+    /// Uses the pre-verification gas overhead and L1 data fee of the chain spec
+    pub fn with_chain_spec(mut self, chain_spec: &ChainSpec) -> Self {
+        self.overhead = Overhead::from(chain_spec).with_calculator(
+            pre_verification_gas_calculator(chain_spec, self.eth_client.clone()),
+        );
+        self
+    }
+
+    pub fn with_gas_increase_perc(mut self, gas_increase_perc: U256) -> Self {
+        self.gas_increase_perc = gas_increase_perc;
+        self
+    }
+
+    pub fn with_p2p_ops_policy(mut self, p2p_ops_policy: P2pOpsPolicy) -> Self {
+        self.p2p_ops_policy = p2p_ops_policy;
+        self
+    }
+
+    pub fn with_overhead(mut self, overhead: Overhead) -> Self {
+        self.overhead = overhead;
+        self
+    }
+
+    /// Limits the number of user operations in the mempool (see [UoPool::make_room])
+    pub fn with_max_mempool_size(mut self, max_mempool_size: usize) -> Self {
+        self.max_mempool_size = Some(max_mempool_size);
+        self
+    }
+
+    /// Limits the number of user operations re-validated (second validation) when building a bundle
+    pub fn with_max_revalidations(mut self, max_revalidations: usize) -> Self {
+        self.max_revalidations = Some(max_revalidations);
+        self
+    }
+
+    /// Limits the gas of the bundle (including the `handleOps` overhead)
+    pub fn with_max_bundle_gas(mut self, max_bundle_gas: U256) -> Self {
+        self.max_bundle_gas = max_bundle_gas;
+        self
+    }
+
+    /// Limits the number of user operations in the bundle
+    pub fn with_max_ops_per_bundle(mut self, max_ops_per_bundle: usize) -> Self {
+        self.max_ops_per_bundle = Some(max_ops_per_bundle);
+        self
+    }
+
+    /// Order in which the user operations of the mempool are selected into the bundle
+    pub fn with_bundle_ordering(mut self, bundle_ordering: OrderingPolicy) -> Self {
+        self.bundle_ordering = bundle_ordering;
+        self
+    }
+
+    /// Limits the number of user operations of the unstaked paymasters and factories in the bundle
+    pub fn with_unstaked_entity_caps(mut self, unstaked_entity_caps: UnstakedEntityCaps) -> Self {
+        self.unstaked_entity_caps = Some(unstaked_entity_caps);
+        self
+    }
+
+    /// Penalizes the reputation of the entity responsible for a user operation of the pool reverting on chain
+    /// (see [Reputation::update_op_reverted](crate::Reputation::update_op_reverted))
+    pub fn with_revert_penalty(mut self, revert_penalty: u64) -> Self {
+        self.revert_penalty = Some(revert_penalty);
+        self
+    }
+
+    /// Reports user operations as [Included](UserOperationState::Included) only after the number of
+    /// confirmations (the block of the bundle counts as the first one), [Mined](UserOperationState::Mined) before
+    ///
+    /// Until then, the user operations stay in the pool out of the bundles and the inclusion isn't notified. Reorgs
+    /// are tracked at least as deep, so the user operations of the orphaned blocks are re-admitted before they're
+    /// final.
+    pub fn with_confirmation_blocks(mut self, confirmation_blocks: u64) -> Self {
+        self.confirmation_blocks = confirmation_blocks;
+        self
+    }
+
+    /// Persists the reputation to the database on shutdown (see [UoPool::shutdown])
+    pub fn with_reputation_db(
+        mut self,
+        reputation_db: ReputationBox<Vec<ReputationEntry>>,
+    ) -> Self {
+        self.reputation_db = Some(reputation_db);
+        self
+    }
+
+    /// Timeout and retries of the simulation calls of the gas estimation
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_lifecycle(mut self, lifecycle: Lifecycle) -> Self {
+        self.lifecycle = lifecycle;
+        self
+    }
+
+    pub fn with_sender_penalty(mut self, sender_penalty: SenderPenalty) -> Self {
+        self.sender_penalty = Some(sender_penalty);
+        self
+    }
+
     pub fn entry_point_address(&self) -> Address {
         self.entry_point.address()
     }
@@ -80,24 +269,253 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
         self.mempool.get_all()
     }
 
+    /// Reputation entries with the current status (computed from the counters, allowlist and denylist)
     pub fn get_reputation(&self) -> Vec<ReputationEntry> {
-        self.reputation.get_all()
+        self.reputation
+            .get_all()
+            .into_iter()
+            .map(|ent| ReputationEntry {
+                status: self.reputation.get_status(&ent.address),
+                ..ent
+            })
+            .collect()
     }
 
-    pub fn set_reputation(&mut self, reputation: Vec<ReputationEntry>) {
-        self.reputation.set(reputation);
+    pub fn set_reputation(
+        &mut self,
+        reputation: Vec<ReputationEntry>,
+    ) -> Result<(), ReputationError> {
+        self.reputation.set(reputation)
     }
 
     pub fn clear(&mut self) {
         self.mempool.clear();
         self.reputation.clear();
+        self.lifecycle.clear();
+        self.p2p_uos.clear();
+        self.bundled.clear();
+        self.bundled_slots.clear();
+        self.bundle_dropped.clear();
+        self.mined.clear();
+        if let Some(sender_penalty) = self.sender_penalty.as_ref() {
+            sender_penalty.clear();
+        }
+    }
+
+    /// Restores the user operations persisted in the mempool database (e.g., after a restart)
+    ///
+    /// Each stored user operation is checked against the current chain state: user operations with stale
+    /// nonces or failing the sanity checks are dropped, the rest are tracked again with their persisted expiry.
+    /// User operations that couldn't be checked (e.g., the node is unreachable) are kept. Returns the number of
+    /// restored user operations.
+    pub async fn load_from_db(&mut self) -> anyhow::Result<usize> {
+        let ep = self.entry_point.address();
+        let chain_id: U256 = self.chain.id().into();
+        let uos = self.mempool.get_all();
+        let mut restored = 0;
+
+        // the user operations are validated against a copy of the mempool, the stored ones are only removed
+        // when they are dropped
+        let mut mempool: MempoolBox<VecUo, VecCh> = Box::<MemoryMempool>::default();
+        for uo in uos.iter() {
+            mempool.add(uo.clone(), &ep, &chain_id)?;
+        }
+
+        for uo in uos {
+            let uo_hash = uo.hash(&ep, &chain_id);
+            let expires_at = self.mempool.get_expires_at(&uo_hash);
+
+            match self.get_nonce_status(&uo).await {
+                Ok(NonceStatus::Stale) => {
+                    trace!(
+                        "Dropping user operation {uo_hash:?} with stale nonce from the database"
+                    );
+                    mempool.remove(&uo_hash)?;
+                    self.mempool.remove(&uo_hash)?;
+                    continue;
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    warn!(
+                        "Keeping unchecked user operation {uo_hash:?} from the database: {err:?}"
+                    );
+                    self.restore_user_operation(&uo_hash, expires_at)?;
+                    restored += 1;
+                    continue;
+                }
+            }
+
+            // taken out during the validation, so the user operation isn't treated as a replacement of itself
+            mempool.remove(&uo_hash)?;
+            let res = self
+                .validator
+                .validate_user_operation(
+                    &uo,
+                    &mempool,
+                    &self.reputation,
+                    UserOperationValidatorMode::Sanity.into(),
+                )
+                .await;
+
+            match res {
+                Err(err) if !is_transient(&err) => {
+                    trace!(
+                        "Dropping invalid user operation {uo_hash:?} from the database: {err:?}"
+                    );
+                    self.mempool.remove(&uo_hash)?;
+                }
+                res => {
+                    if let Err(err) = res {
+                        warn!("Keeping unchecked user operation {uo_hash:?} from the database: {err:?}");
+                    }
+                    mempool.add(uo, &ep, &chain_id)?;
+                    self.restore_user_operation(&uo_hash, expires_at)?;
+                    restored += 1;
+                }
+            }
+        }
+
+        Ok(restored)
+    }
+
+    /// Tracks again the user operation kept in the mempool, with its persisted expiry if there's one
+    fn restore_user_operation(
+        &mut self,
+        uo_hash: &UserOperationHash,
+        expires_at: Option<u64>,
+    ) -> anyhow::Result<()> {
+        let expires_at = match expires_at {
+            Some(expires_at) => {
+                self.lifecycle.restore(uo_hash, expires_at);
+                expires_at
+            }
+            None => self.lifecycle.track(uo_hash, None),
+        };
+        self.mempool.set_expires_at(uo_hash, expires_at)?;
+        Ok(())
+    }
+
+    /// Restores the reputation persisted in the reputation database on the last shutdown, returns the number of
+    /// restored entries
+    pub fn load_reputation_from_db(&mut self) -> Result<usize, ReputationError> {
+        let entries = self
+            .reputation_db
+            .as_ref()
+            .map(|reputation_db| reputation_db.get_all())
+            .unwrap_or_default();
+        let restored = entries.len();
+        self.reputation.set(entries)?;
+        Ok(restored)
+    }
+
+    /// Prepares the pool for the shutdown
+    ///
+    /// User operations selected for a bundle that wasn't sent are released back to the pending set, so they're
+    /// bundled again after the restart. The reputation is flushed to the reputation database (the mempool database
+    /// is written on every change). Returns the number of released user operations.
+    pub fn shutdown(&mut self) -> usize {
+        let lifecycle = &self.lifecycle;
+        let bundled = self.bundled.len();
+        self.bundled
+            .retain(|uo_hash, _| lifecycle.is_submitted(uo_hash));
+        let released = bundled - self.bundled.len();
+
+        if let Some(reputation_db) = self.reputation_db.as_mut() {
+            if let Err(err) = reputation_db.set(self.reputation.get_all()) {
+                warn!("Persisting the reputation failed: {err:?}");
+            }
+        }
+
+        released
+    }
+
+    /// Subscribes to notifications about user operations evicted from the pool
+    pub fn subscribe(&self) -> broadcast::Receiver<EvictionEvent> {
+        self.lifecycle.subscribe()
+    }
+
+    /// Subscribes to notifications about user operations of the pool included on chain
+    pub fn subscribe_inclusions(&self) -> broadcast::Receiver<InclusionEvent> {
+        self.inclusions.subscribe()
+    }
+
+    pub fn get_user_operation_status(
+        &self,
+        uo_hash: &UserOperationHash,
+    ) -> Option<UserOperationStatus> {
+        self.lifecycle.get_status(uo_hash)
+    }
+
+    /// Status of the user operation, the user operations not known to the pool (or submitted in the bundle) are
+    /// looked up on chain
+    pub async fn get_user_operation_state(
+        &self,
+        uo_hash: &UserOperationHash,
+    ) -> anyhow::Result<UserOperationState> {
+        let state = self.lifecycle.get_state(uo_hash);
+
+        if matches!(state, None | Some(UserOperationState::Submitted { .. }))
+            || self.mined.contains(uo_hash)
+        {
+            if let Some(uo_by_hash) = self.get_user_operation_by_hash(uo_hash).await? {
+                if self.confirmation_blocks > 0 {
+                    let block_num = self.eth_client.get_block_number().await?;
+                    let confirmations = (block_num + 1)
+                        .saturating_sub(uo_by_hash.block_number)
+                        .as_u64();
+                    if confirmations < self.confirmation_blocks {
+                        return Ok(UserOperationState::Mined {
+                            block_hash: uo_by_hash.block_hash,
+                            block_number: uo_by_hash.block_number,
+                            transaction_hash: uo_by_hash.transaction_hash,
+                            confirmations,
+                        });
+                    }
+                }
+                return Ok(UserOperationState::Included {
+                    block_hash: uo_by_hash.block_hash,
+                    block_number: uo_by_hash.block_number,
+                    transaction_hash: uo_by_hash.transaction_hash,
+                });
+            }
+        }
+
+        Ok(state.unwrap_or(UserOperationState::NotFound))
+    }
+
+    /// Marks the user operation as received by the bundler (before it's validated)
+    pub fn receive_user_operation(&mut self, uo_hash: &UserOperationHash) {
+        self.lifecycle.receive(uo_hash);
+    }
+
+    /// Records the error of the user operation rejected by the validation
+    pub fn reject_user_operation(&mut self, uo_hash: &UserOperationHash, err: &ValidationError) {
+        self.lifecycle.reject(uo_hash, format!("{err:?}"));
+    }
+
+    /// Marks the user operations as part of the sent bundle transaction (until it's mined)
+    pub fn submit_user_operations(&mut self, uo_hashes: &[UserOperationHash], tx_hash: H256) {
+        for uo_hash in uo_hashes {
+            self.lifecycle.submit(uo_hash, tx_hash);
+        }
     }
 
     pub async fn validate_user_operation(
         &self,
         uo: &UserOperation,
     ) -> Result<UserOperationValidationOutcome, ValidationError> {
-        self.validator
+        if let Some(Err(err)) = self
+            .sender_penalty
+            .as_ref()
+            .map(|sender_penalty| sender_penalty.check(&uo.sender))
+        {
+            let err = ValidationError::from(err);
+            METRICS.uos_rejected.inc(err.name());
+            return Err(err);
+        }
+
+        let res = self
+            .validator
             .validate_user_operation(
                 uo,
                 &self.mempool,
@@ -106,7 +524,19 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                     | UserOperationValidatorMode::Simulation
                     | UserOperationValidatorMode::SimulationTrace,
             )
-            .await
+            .await;
+
+        if let Err(err) = &res {
+            METRICS.uos_rejected.inc(err.name());
+        }
+
+        if let (Some(sender_penalty), Err(ValidationError::Simulation(_))) =
+            (self.sender_penalty.as_ref(), &res)
+        {
+            sender_penalty.record_failure(&uo.sender);
+        }
+
+        res
     }
 
     /// Adds a single validated user operation into the pool
@@ -115,12 +545,229 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
         uo: UserOperation,
         res: Option<UserOperationValidationOutcome>,
     ) -> Result<UserOperationHash, AddError> {
-        let res = res.unwrap_or(self.validate_user_operation(&uo).await?);
+        let uo_hash_received = uo.hash(&self.entry_point.address(), &self.chain.id().into());
+        let res = match res {
+            Some(res) => res,
+            None => match self.validate_user_operation(&uo).await {
+                Ok(res) => res,
+                Err(err) => {
+                    self.reject_user_operation(&uo_hash_received, &err);
+                    return Err(err.into());
+                }
+            },
+        };
 
-        if let Some(uo_hash) = res.prev_hash {
-            self.remove_user_operation(&uo_hash);
+        let uo_hash = match res.prev_hash {
+            Some(uo_prev_hash) => self.replace_user_operation(uo, &uo_prev_hash, res),
+            // the user operation is dropped only once the new one is in the mempool
+            None => match self.make_room(&uo).await {
+                Ok(uo_dropped) => self.insert_user_operation(uo, res).map(|uo_hash| {
+                    if let Some((uo_hash_dropped, sender)) = uo_dropped {
+                        self.evict_user_operation(&uo_hash_dropped, &sender);
+                    }
+                    uo_hash
+                }),
+                Err(err) => Err(err),
+            },
+        };
+
+        match &uo_hash {
+            Ok(_) => METRICS.uos_accepted.inc(),
+            Err(AddError::Verification(err)) => {
+                METRICS.uos_rejected.inc(err.name());
+                self.reject_user_operation(&uo_hash_received, err);
+            }
+            Err(AddError::MempoolError { .. }) => METRICS.uos_rejected.inc("MempoolError"),
+        }
+
+        uo_hash
+    }
+
+    /// Selects the user operation dropped to make room for the new user operation if the mempool is full
+    ///
+    /// Only the user operation with the highest nonce of each sender (and nonce key) can be dropped, so no nonce
+    /// gap is left behind, and user operations of the new user operation's sender (and nonce key) are kept. Of
+    /// those, the one with the lowest effective gas price is dropped, user operations of throttled or banned
+    /// entities go first. User operations selected for the latest bundle are never dropped. Fails with
+    /// [SanityCheckError::MempoolFull] if the new user operation doesn't pay more than the user operation that
+    /// would be dropped.
+    pub async fn make_room(
+        &self,
+        uo: &UserOperation,
+    ) -> Result<Option<(UserOperationHash, Address)>, AddError> {
+        let max_mempool_size = match self.max_mempool_size {
+            Some(max_mempool_size) => max_mempool_size,
+            None => return Ok(None),
+        };
+
+        let uos = self.mempool.get_all();
+        if uos.len() < max_mempool_size {
+            return Ok(None);
+        }
+
+        // chains without EIP-1559 have no base fee
+        let base_fee = self
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await
+            .map_err(|err| AddError::Verification(SanityCheckError::from(err).into()))?
+            .and_then(|block| block.base_fee_per_gas)
+            .unwrap_or_default();
+        let price = uo.effective_gas_price(base_fee);
+        let (ep, chain_id) = (self.entry_point.address(), self.chain.id().into());
+
+        let mut last: HashMap<(Address, U256), UserOperation> = HashMap::new();
+        for uo_other in uos {
+            let key = (uo_other.sender, uo_other.nonce_key());
+            if key == (uo.sender, uo.nonce_key()) {
+                continue;
+            }
+            match last.get(&key) {
+                Some(uo_last) if uo_last.nonce >= uo_other.nonce => (),
+                _ => {
+                    last.insert(key, uo_other);
+                }
+            }
+        }
+
+        let uo_dropped = last
+            .into_values()
+            .map(|uo| (uo.hash(&ep, &chain_id), uo))
+            .filter(|(uo_hash, _)| !self.bundled.contains_key(uo_hash))
+            .map(|(uo_hash, uo)| {
+                (
+                    uo_hash,
+                    uo.sender,
+                    self.entities_ok(&uo),
+                    uo.effective_gas_price(base_fee),
+                )
+            })
+            .min_by_key(|(uo_hash, _, ok, price)| (*ok, *price, *uo_hash));
+
+        match uo_dropped {
+            Some((uo_hash, sender, ok, price_dropped)) if !ok || price_dropped < price => {
+                Ok(Some((uo_hash, sender)))
+            }
+            uo_dropped => Err(AddError::Verification(
+                SanityCheckError::MempoolFull {
+                    effective_gas_price: price,
+                    min_effective_gas_price: uo_dropped
+                        .map(|(_, _, _, price)| price)
+                        .unwrap_or(U256::MAX),
+                }
+                .into(),
+            )),
         }
+    }
+
+    /// Drops the user operation selected by [UoPool::make_room] from the full mempool
+    fn evict_user_operation(&mut self, uo_hash: &UserOperationHash, sender: &Address) {
+        trace!(
+            "Dropping user operation {uo_hash:?} from the full mempool {}",
+            self.id
+        );
+        self.remove_user_operation(uo_hash);
+        self.lifecycle.discard(uo_hash);
+        report_drop(uo_hash, sender, DropReason::Evicted);
+    }
+
+    /// Whether none of the entities of the user operation is throttled or banned
+    fn entities_ok(&self, uo: &UserOperation) -> bool {
+        [
+            self.reputation.get_status(&uo.sender),
+            self.reputation.get_status_from_bytes(&uo.init_code),
+            self.reputation
+                .get_status_from_bytes(&uo.paymaster_and_data),
+        ]
+        .iter()
+        .all(|status| *status == ReputationStatus::OK)
+    }
+
+    /// Adds a single user operation received over P2P into the pool (depending on the P2P ops policy)
+    pub async fn add_p2p_user_operation(
+        &mut self,
+        uo: UserOperation,
+        res: Option<UserOperationValidationOutcome>,
+    ) -> Result<UserOperationHash, AddError> {
+        if self.p2p_ops_policy == P2pOpsPolicy::Ignore {
+            return Err(AddError::MempoolError {
+                message: "User operations received over P2P are ignored".into(),
+            });
+        }
+
+        let uo_hash = self.add_user_operation(uo, res).await?;
+        self.p2p_uos.insert(uo_hash);
 
+        Ok(uo_hash)
+    }
+
+    /// Replaces the user operation with the same sender and nonce (replacement-by-fee)
+    ///
+    /// The replacement has to increase both max fee per gas and max priority fee per gas by at least
+    /// `gas_increase_perc` percent, and the replaced user operation must not be selected for a bundle.
+    /// The replaced user operation is removed only after the new one is added into the pool.
+    pub fn replace_user_operation(
+        &mut self,
+        uo: UserOperation,
+        uo_prev_hash: &UserOperationHash,
+        res: UserOperationValidationOutcome,
+    ) -> Result<UserOperationHash, AddError> {
+        if self.bundled.contains_key(uo_prev_hash) {
+            return Err(AddError::Verification(
+                SanityCheckError::SenderVerification {
+                    sender: uo.sender,
+                    message: "couldn't replace user operation (already selected for a bundle)"
+                        .into(),
+                }
+                .into(),
+            ));
+        }
+
+        if let Some(uo_prev) =
+            self.mempool
+                .get(uo_prev_hash)
+                .map_err(|err| AddError::MempoolError {
+                    message: err.to_string(),
+                })?
+        {
+            if uo.sender != uo_prev.sender || uo.nonce != uo_prev.nonce {
+                return Err(AddError::MempoolError {
+                    message: format!(
+                        "User operation {uo_prev_hash:?} doesn't have the same sender and nonce"
+                    ),
+                });
+            }
+
+            if uo.max_fee_per_gas
+                < calculate_valid_gas(uo_prev.max_fee_per_gas, self.gas_increase_perc)
+                || uo.max_priority_fee_per_gas
+                    < calculate_valid_gas(uo_prev.max_priority_fee_per_gas, self.gas_increase_perc)
+            {
+                return Err(AddError::Verification(
+                    SanityCheckError::SenderVerification {
+                        sender: uo.sender,
+                        message: "couldn't replace user operation (gas increase too low)".into(),
+                    }
+                    .into(),
+                ));
+            }
+        }
+
+        let sender = uo.sender;
+        let uo_hash = self.insert_user_operation(uo, res)?;
+
+        self.remove_user_operation(uo_prev_hash);
+        self.lifecycle.untrack(uo_prev_hash);
+        report_drop(uo_prev_hash, &sender, DropReason::Replaced);
+
+        Ok(uo_hash)
+    }
+
+    fn insert_user_operation(
+        &mut self,
+        uo: UserOperation,
+        res: UserOperationValidationOutcome,
+    ) -> Result<UserOperationHash, AddError> {
         match self.mempool.add(
             uo.clone(),
             &self.entry_point.address(),
@@ -131,6 +778,8 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                 if let Some(code_hashes) = res.code_hashes {
                     let _ = self.mempool.set_code_hashes(&uo_hash, &code_hashes);
                 }
+                let expires_at = self.lifecycle.track(&uo_hash, res.valid_until);
+                let _ = self.mempool.set_expires_at(&uo_hash, expires_at);
 
                 trace!("User operation {uo:?} added to the mempool {}", self.id);
 
@@ -151,27 +800,201 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
         }
     }
 
-    pub fn get_sorted_user_operations(&self) -> anyhow::Result<Vec<UserOperation>> {
-        self.mempool.get_sorted()
+    /// Simulates the validation of the user operation and returns the decoded and raw revert data
+    pub async fn simulate_user_operation(
+        &self,
+        uo: &UserOperation,
+    ) -> Result<SimulateValidationRevert, SimulationCheckError> {
+        let data = self
+            .entry_point
+            .simulate_validation_revert_data(uo.clone())
+            .await
+            .map_err(|err| match err {
+                EntryPointErr::JsonRpcError(err) => SimulationCheckError::Validation {
+                    message: err.message,
+                },
+                _ => SimulationCheckError::UnknownError {
+                    message: format!("{err:?}"),
+                },
+            })?;
+
+        Ok(parse_simulate_validation_revert(data))
+    }
+
+    /// Compares the nonce of the user operation with the on-chain nonce of the sender (`getNonce` of the
+    /// entry point for the nonce key)
+    pub async fn get_nonce_status(&self, uo: &UserOperation) -> anyhow::Result<NonceStatus> {
+        let nonce = self
+            .entry_point
+            .get_nonce(&uo.sender, uo.nonce_key())
+            .await
+            .map_err(|err| format_err!("Getting nonce of {:?} failed: {err:?}", uo.sender))?;
+
+        Ok(match uo.nonce.cmp(&nonce) {
+            std::cmp::Ordering::Less => NonceStatus::Stale,
+            std::cmp::Ordering::Equal => NonceStatus::Ready,
+            std::cmp::Ordering::Greater => NonceStatus::Queued,
+        })
+    }
+
+    /// Candidates of the next bundle, selected from the mempool by [create_bundle]
+    ///
+    /// The candidates are ordered by the [OrderingPolicy], with the lowest nonce of each sender and within the
+    /// caps of the unstaked entities. Gas is limited only by the block gas limit here, the max gas and number of
+    /// user operations of the bundle are enforced by [UoPool::bundle_user_operations] (on the user operations
+    /// passing the second validation).
+    pub async fn get_sorted_user_operations(&self) -> anyhow::Result<Vec<UserOperation>> {
+        let (ep, chain_id) = (self.entry_point.address(), self.chain.id().into());
+        let uos: Vec<UserOperation> = self
+            .mempool
+            .get_all()
+            .into_iter()
+            .filter(|uo| !self.mined.contains(&uo.hash(&ep, &chain_id)))
+            .collect();
+        if uos.is_empty() {
+            return Ok(uos);
+        }
+
+        let block = self
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or_else(|| format_err!("No latest block"))?;
+
+        let mut staked = HashSet::new();
+        if self.unstaked_entity_caps.is_some() {
+            let entities: HashSet<Address> = uos
+                .iter()
+                .flat_map(|uo| {
+                    [
+                        get_address(&uo.paymaster_and_data),
+                        get_address(&uo.init_code),
+                    ]
+                })
+                .flatten()
+                .collect();
+            for entity in entities {
+                let info = self
+                    .entry_point
+                    .get_deposit_info(&entity)
+                    .await
+                    .map_err(|err| {
+                        format_err!("Getting deposit info of {entity:?} failed with error: {err:?}")
+                    })?;
+                // the stake being withdrawn doesn't count
+                let stake = if info.withdraw_time == 0 {
+                    U256::from(info.stake)
+                } else {
+                    U256::zero()
+                };
+                let stake_info = StakeInfo {
+                    address: entity,
+                    stake,
+                    unstake_delay: U256::from(info.unstake_delay_sec),
+                };
+                if self
+                    .reputation
+                    .verify_stake("entity", Some(stake_info))
+                    .is_ok()
+                {
+                    staked.insert(entity);
+                }
+            }
+        }
+
+        let limits = BundleLimits {
+            block_gas_limit: block.gas_limit,
+            block_gas_fraction_perc: 100.into(),
+            max_bundle_gas: U256::MAX,
+            max_uos: usize::MAX,
+            unstaked_entity_caps: self.unstaked_entity_caps,
+        };
+        let ordering = BundleOrdering {
+            policy: self.bundle_ordering,
+            entry_point: self.entry_point.address(),
+            chain_id: self.chain.id().into(),
+        };
+        Ok(create_bundle(
+            uos,
+            block.base_fee_per_gas.unwrap_or_default(),
+            &limits,
+            &ordering,
+            |_| usize::MAX,
+            |entity| staked.contains(entity),
+            |entity| self.reputation.get_status(entity),
+        ))
     }
 
     pub async fn bundle_user_operations(
         &mut self,
         uos: Vec<UserOperation>,
+        submission_mode: SubmissionMode,
     ) -> anyhow::Result<Vec<UserOperation>> {
         let mut uos_valid = vec![];
         let mut senders = HashSet::new();
-        let mut gas_total = U256::zero();
+        let mut gas_total = U256::from(HANDLE_OPS_GAS_OVERHEAD);
         let mut paymaster_dep = HashMap::new();
         let mut staked_entity_c = HashMap::new();
+        let mut entities = HashSet::new();
+        let mut deployed = HashSet::new();
+        let mut revalidations = 0;
+
+        self.bundled.clear();
+        self.bundled_slots.clear();
+        self.bundle_dropped.clear();
 
         for uo in uos {
+            if self
+                .max_ops_per_bundle
+                .map_or(false, |max| uos_valid.len() >= max)
+            {
+                trace!("Reached the max number of user operations in the bundle");
+                break;
+            }
+
             if senders.contains(&uo.sender) {
                 continue;
             }
 
             let uo_hash = uo.hash(&self.entry_point.address(), &self.chain.id().into());
 
+            // sender of one user operation can't be an entity of another one in the same bundle
+            let uo_entities: Vec<Address> = [
+                get_address(&uo.paymaster_and_data),
+                get_address(&uo.init_code),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if is_entity_conflict(&uo.sender, &uo_entities, &senders, &entities) {
+                trace!("User operation {uo_hash:?} conflicts with user operations in the bundle");
+                continue;
+            }
+
+            if self.p2p_ops_policy == P2pOpsPolicy::RelayOnly && self.p2p_uos.contains(&uo_hash) {
+                continue;
+            }
+
+            // only user operations with the next nonce can be executed, the rest waits for predecessors
+            match self.get_nonce_status(&uo).await? {
+                NonceStatus::Ready => (),
+                NonceStatus::Queued => {
+                    trace!("User operation {uo_hash:?} is queued until its predecessors land");
+                    continue;
+                }
+                NonceStatus::Stale => {
+                    self.mempool.remove(&uo_hash).map_err(|err| {
+                        format_err!(
+                            "Removing a user operation {uo_hash:?} with stale nonce failed with error: {err:?}",
+                        )
+                    })?;
+                    self.lifecycle.invalidate(&uo_hash);
+                    report_drop(&uo_hash, &uo.sender, DropReason::RevalidationFailed);
+                    self.bundle_dropped.push((uo_hash, "Stale nonce".into()));
+                    continue;
+                }
+            }
+
             let p_opt = get_address(&uo.paymaster_and_data.0);
             let f_opt = get_address(&uo.init_code.0);
 
@@ -194,6 +1017,10 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                             "Removing a banned user operation {uo_hash:?} failed with error: {err:?}",
                         )
                     })?;
+                    self.lifecycle.invalidate(&uo_hash);
+                    report_drop(&uo_hash, &uo.sender, DropReason::Evicted);
+                    self.bundle_dropped
+                        .push((uo_hash, "Paymaster or factory is banned".into()));
                     continue;
                 }
                 (ReputationStatus::THROTTLED, _) if p_c > THROTTLED_MAX_INCLUDE => {
@@ -205,6 +1032,15 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                 _ => (),
             };
 
+            if self
+                .max_revalidations
+                .map_or(false, |max| revalidations >= max)
+            {
+                trace!("Reached the max number of re-validations {revalidations} for the bundle");
+                break;
+            }
+            revalidations += 1;
+
             let val_out = self
                 .validator
                 .validate_user_operation(
@@ -215,6 +1051,9 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                         | UserOperationValidatorMode::SimulationTrace,
                 )
                 .await;
+            let mut valid_until = U256::MAX;
+            let mut aggregator = None;
+            let mut accessed_slots = None;
 
             match val_out {
                 Ok(val_out) => {
@@ -222,28 +1061,51 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                         continue;
                     }
 
-                    // TODO
-                    // it would be better to use estimate_gas instead of call_gas_limit
-                    // The result of call_gas_limit is usesally higher and less user op would be included
-                    let gas_cost = val_out
-                        .verification_gas_limit
-                        .saturating_add(uo.call_gas_limit);
-                    let gas_total_new = gas_total.saturating_add(gas_cost);
-                    if gas_total_new.gt(&self.max_verification_gas) {
+                    if let Some(a) = val_out.aggregator {
+                        if is_entity_conflict(&uo.sender, &[a], &senders, &entities) {
+                            continue;
+                        }
+                    }
+
+                    // code touched during the validation would be changed by a preceding user operation
+                    if let Some(address) = val_out
+                        .code_hashes
+                        .iter()
+                        .flatten()
+                        .map(|h| h.address)
+                        .find(|a| deployed.contains(a))
+                    {
+                        trace!("User operation {uo_hash:?} touches {address:?} deployed earlier in the bundle");
+                        continue;
+                    }
+
+                    let gas_total_new = gas_total.saturating_add(uo.max_gas());
+                    if gas_total_new > self.max_bundle_gas {
+                        trace!("User operation {uo_hash:?} would exceed the max gas of the bundle");
                         break;
                     }
 
+                    // the payer has to prefund the max gas cost, otherwise the user operation fails with AA21/AA31
+                    let required_prefund = uo.required_prefund();
                     if let Some(p) = p_opt {
                         let balance = match paymaster_dep.get(&p) {
                             Some(n) => *n,
-                            None => self.entry_point.balance_of(&p).await.map_err(|err| {
-                                format_err!(
-                                    "Getting balance of paymaster {p:?} failed with error: {err:?}",
-                                )
-                            })?,
+                            None => {
+                                let balance = self.entry_point.balance_of(&p).await.map_err(|err| {
+                                    format_err!(
+                                        "Getting balance of paymaster {p:?} failed with error: {err:?}",
+                                    )
+                                })?;
+                                if balance < required_prefund {
+                                    self.remove_underfunded(&uo, balance, required_prefund)?;
+                                    continue;
+                                }
+                                balance
+                            }
                         };
 
-                        if balance.lt(&val_out.pre_fund) {
+                        // deposit is used up by the preceding user operations of the bundle
+                        if balance < required_prefund {
                             continue;
                         }
 
@@ -251,7 +1113,28 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                             .entry(p)
                             .and_modify(|c| *c += 1)
                             .or_insert(1);
-                        paymaster_dep.insert(p, balance.saturating_sub(val_out.pre_fund));
+                        paymaster_dep.insert(p, balance.saturating_sub(required_prefund));
+                    } else if !required_prefund.is_zero() {
+                        // the sender pays the missing deposit during the validation
+                        let deposit =
+                            self.entry_point
+                                .balance_of(&uo.sender)
+                                .await
+                                .map_err(|err| {
+                                    format_err!(
+                                        "Getting deposit of sender {:?} failed with error: {err:?}",
+                                        uo.sender
+                                    )
+                                })?;
+                        let balance = self.eth_client.get_balance(uo.sender, None).await?;
+                        if deposit.saturating_add(balance) < required_prefund {
+                            self.remove_underfunded(
+                                &uo,
+                                deposit.saturating_add(balance),
+                                required_prefund,
+                            )?;
+                            continue;
+                        }
                     }
 
                     if let Some(f) = f_opt {
@@ -262,22 +1145,150 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                     }
 
                     gas_total = gas_total_new;
+                    if let Some(v) = val_out.valid_until {
+                        valid_until = v;
+                    }
+                    aggregator = val_out.aggregator;
+                    accessed_slots = val_out.accessed_slots;
                 }
-                Err(_) => {
+                Err(err) => {
+                    warn!(
+                        "Dropping user operation {uo_hash:?} failing the 2nd validation: {err:?}"
+                    );
+                    if let Some(entity) = validation_error_entity(&uo, &err) {
+                        self.reputation.update_handle_ops_reverted(&entity);
+                    }
                     self.mempool.remove(&uo_hash).map_err(|err| {
                         format_err!(
                             "Removing a user operation {uo_hash:?} with 2nd failed simulation failed with error: {err:?}",
                         )
                     })?;
+                    self.lifecycle.invalidate(&uo_hash);
+                    report_drop(&uo_hash, &uo.sender, DropReason::RevalidationFailed);
+                    self.bundle_dropped
+                        .push((uo_hash, format!("2nd validation failed: {err:?}")));
                     continue;
                 }
             }
 
+            if !uo.init_code.is_empty() {
+                deployed.insert(uo.sender);
+            }
             uos_valid.push(uo.clone());
             senders.insert(uo.sender);
+            entities.extend(uo_entities.into_iter().chain(aggregator));
+            self.bundled.insert(uo_hash, (valid_until, aggregator));
+            if let Some(slots) = accessed_slots {
+                self.bundled_slots.insert(uo_hash, slots);
+            }
         }
 
-        Ok(uos_valid)
+        self.simulate_bundle(uos_valid, submission_mode).await
+    }
+
+    /// Removes the user operation whose payer can't prefund it from the mempool
+    fn remove_underfunded(
+        &mut self,
+        uo: &UserOperation,
+        balance: U256,
+        required_prefund: U256,
+    ) -> anyhow::Result<()> {
+        let uo_hash = uo.hash(&self.entry_point.address(), &self.chain.id().into());
+        let err = SanityCheckError::InsufficientPrefund {
+            payer: prefund_payer(uo),
+            balance,
+            required_prefund,
+        };
+        warn!("Dropping underfunded user operation {uo_hash:?}: {err:?}");
+
+        self.mempool.remove(&uo_hash).map_err(|err| {
+            format_err!(
+                "Removing an underfunded user operation {uo_hash:?} failed with error: {err:?}",
+            )
+        })?;
+        self.lifecycle.invalidate(&uo_hash);
+        report_drop(&uo_hash, &uo.sender, DropReason::RevalidationFailed);
+        self.bundle_dropped.push((uo_hash, format!("{err:?}")));
+        Ok(())
+    }
+
+    /// Simulates the whole bundle with `handleOps` and drops user operations the bundle fails on
+    ///
+    /// User operations valid on their own can still fail together (shared state, cumulative gas). For each
+    /// `FailedOp`, the user operation is dropped, the responsible entity is penalized and the rest of the bundle is
+    /// simulated again, until the bundle simulates cleanly or is empty. User operations with aggregators are
+    /// submitted with `handleAggregatedOps` and aren't part of the simulation.
+    ///
+    /// With [SubmissionMode::AllOrNothing], any `FailedOp` fails the whole bundle and the user operations are left
+    /// in the mempool unchanged.
+    async fn simulate_bundle(
+        &mut self,
+        uos: Vec<UserOperation>,
+        submission_mode: SubmissionMode,
+    ) -> anyhow::Result<Vec<UserOperation>> {
+        let ep = self.entry_point.address();
+        let chain_id = U256::from(self.chain.id());
+        let mut uos = uos;
+
+        loop {
+            let uos_sim: Vec<UserOperation> = uos
+                .iter()
+                .filter(|uo| {
+                    self.get_bundled_aggregator(&uo.hash(&ep, &chain_id))
+                        .is_none()
+                })
+                .cloned()
+                .collect();
+            if uos_sim.is_empty() {
+                return Ok(uos);
+            }
+
+            let failed_op = match self
+                .entry_point
+                .handle_ops(uos_sim.clone(), BUNDLE_SIMULATION_BENEFICIARY)
+                .await
+            {
+                Ok(()) => return Ok(uos),
+                Err(EntryPointErr::FailedOp(failed_op)) => failed_op,
+                Err(err) => return Err(format_err!("Simulating the bundle failed: {err:?}")),
+            };
+
+            if failed_op.op_index >= U256::from(uos_sim.len()) {
+                return Err(format_err!(
+                    "Simulating the bundle failed with invalid op index {}",
+                    failed_op.op_index
+                ));
+            }
+            let uo = &uos_sim[failed_op.op_index.as_usize()];
+            let uo_hash = uo.hash(&ep, &chain_id);
+            if submission_mode == SubmissionMode::AllOrNothing {
+                return Err(format_err!(
+                    "Bundle fails on user operation {uo_hash:?}: {}",
+                    failed_op.reason
+                ));
+            }
+            warn!(
+                "Dropping user operation {uo_hash:?} failing in the bundle: {}",
+                failed_op.reason
+            );
+
+            if let Some(entity) = failed_op_entity(uo, &failed_op.reason) {
+                self.reputation.update_handle_ops_reverted(&entity);
+            }
+            self.mempool.remove(&uo_hash).map_err(|err| {
+                format_err!(
+                    "Removing a user operation {uo_hash:?} failing in the bundle failed with error: {err:?}",
+                )
+            })?;
+            self.lifecycle.invalidate(&uo_hash);
+            report_drop(&uo_hash, &uo.sender, DropReason::RevalidationFailed);
+            self.bundle_dropped.push((
+                uo_hash,
+                format!("Failing in the bundle: {}", failed_op.reason),
+            ));
+            self.bundled.remove(&uo_hash);
+            uos.retain(|u| u.hash(&ep, &chain_id) != uo_hash);
+        }
     }
 
     pub async fn base_fee_per_gas(&self) -> anyhow::Result<U256> {
@@ -291,84 +1302,157 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
             .ok_or(format_err!("No base fee found"))
     }
 
+    /// Estimates the gas of the user operation, the simulations run with the state overrides (if any)
     pub async fn estimate_user_operation_gas(
         &self,
         uo: &UserOperation,
+        state_override: Option<&spoof::State>,
     ) -> Result<UserOperationGasEstimation, SimulationCheckError> {
-        let val_out = self
-            .validator
-            .validate_user_operation(
-                uo,
-                &self.mempool,
-                &self.reputation,
-                UserOperationValidatorMode::SimulationTrace.into(),
-            )
-            .await
-            .map_err(|err| match err {
-                ValidationError::Sanity(_) => SimulationCheckError::UnknownError {
-                    message: "Unknown error".to_string(),
-                },
-                ValidationError::Simulation(err) => err,
-            })?;
-
-        match self.entry_point.simulate_execution(uo.clone()).await {
-            Ok(_) => {}
-            Err(err) => {
-                return Err(match err {
-                    EntryPointErr::JsonRpcError(err) => SimulationCheckError::Execution {
-                        message: err.message,
-                    },
-                    _ => SimulationCheckError::UnknownError {
-                        message: format!("{err:?}"),
-                    },
-                })
+        let mode = UserOperationValidatorMode::SimulationTrace.into();
+        let val_out = match state_override {
+            Some(state_override) => {
+                self.validator
+                    .validate_user_operation_with_state_override(
+                        uo,
+                        &self.mempool,
+                        &self.reputation,
+                        mode,
+                        state_override,
+                    )
+                    .await
+            }
+            None => {
+                self.validator
+                    .validate_user_operation(uo, &self.mempool, &self.reputation, mode)
+                    .await
             }
         }
+        .map_err(|err| match err {
+            ValidationError::Sanity(_) => SimulationCheckError::UnknownError {
+                message: "Unknown error".to_string(),
+            },
+            ValidationError::Simulation(err) => err,
+        })?;
 
-        let exec_res = match self.entry_point.simulate_handle_op(uo.clone()).await {
-            Ok(res) => res,
-            Err(err) => {
-                return Err(match err {
-                    EntryPointErr::JsonRpcError(err) => SimulationCheckError::Execution {
-                        message: err.message,
-                    },
-                    _ => SimulationCheckError::UnknownError {
-                        message: format!("{err:?}"),
-                    },
-                })
-            }
+        let entry_point = match state_override {
+            Some(state_override) => self
+                .entry_point
+                .clone()
+                .with_state_override(state_override.clone()),
+            None => self.entry_point.clone(),
         };
 
-        let base_fee_per_gas =
-            self.base_fee_per_gas()
-                .await
-                .map_err(|err| SimulationCheckError::UnknownError {
-                    message: err.to_string(),
+        self.retry_policy
+            .call(|| entry_point.simulate_execution(uo.clone()))
+            .await
+            .map_err(execution_error)?;
+
+        // the execution of deployed accounts can be simulated directly, so the call gas is binary searched
+        if uo.init_code.is_empty() {
+            return Ok(UserOperationGasEstimation {
+                pre_verification_gas: self.estimate_pre_verification_gas(uo).await?,
+                verification_gas_limit: val_out.verification_gas_limit,
+                call_gas_limit: self.estimate_call_gas(&entry_point, uo).await?,
+                call_gas_estimation_method: Some(CallGasEstimationMethod::EthCall),
+            });
+        }
+
+        let (call_gas_limit, method) = match self
+            .retry_policy
+            .call(|| entry_point.simulate_handle_op(uo.clone()))
+            .await
+        {
+            Ok(exec_res) => {
+                let base_fee_per_gas = self.base_fee_per_gas().await.map_err(|err| {
+                    SimulationCheckError::UnknownError {
+                        message: err.to_string(),
+                    }
                 })?;
-        let call_gas_limit = calculate_call_gas_limit(
-            exec_res.paid,
-            exec_res.pre_op_gas,
-            uo.max_fee_per_gas
-                .min(uo.max_priority_fee_per_gas + base_fee_per_gas),
-        );
+                (
+                    calculate_call_gas_limit(
+                        exec_res.paid,
+                        exec_res.pre_op_gas,
+                        uo.effective_gas_price(base_fee_per_gas),
+                    ),
+                    CallGasEstimationMethod::SimulateHandleOp,
+                )
+            }
+            Err(EntryPointErr::NotSupported(message)) => {
+                trace!("Falling back to call gas estimation with eth_call: {message}");
+                (
+                    self.estimate_call_gas(&entry_point, uo).await?,
+                    CallGasEstimationMethod::EthCall,
+                )
+            }
+            Err(err) => return Err(execution_error(err)),
+        };
 
         Ok(UserOperationGasEstimation {
-            pre_verification_gas: Overhead::default().calculate_pre_verification_gas(uo),
+            pre_verification_gas: self.estimate_pre_verification_gas(uo).await?,
             verification_gas_limit: val_out.verification_gas_limit,
             call_gas_limit,
+            call_gas_estimation_method: Some(method),
         })
     }
 
+    async fn estimate_pre_verification_gas(
+        &self,
+        uo: &UserOperation,
+    ) -> Result<U256, SimulationCheckError> {
+        self.overhead
+            .estimate_pre_verification_gas(uo, &self.entry_point.address())
+            .await
+            .map_err(|err| SimulationCheckError::UnknownError {
+                message: err.to_string(),
+            })
+    }
+
+    /// Binary searches the tightest call gas limit between the intrinsic gas and the block gas limit
+    async fn estimate_call_gas(
+        &self,
+        entry_point: &EntryPoint<M>,
+        uo: &UserOperation,
+    ) -> Result<U256, SimulationCheckError> {
+        let max_gas = self
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(SimulationCheckError::UnknownError {
+                message: "No block found".to_string(),
+            })?
+            .gas_limit;
+
+        entry_point
+            .estimate_call_gas(uo.clone(), max_gas)
+            .await
+            .map_err(|err| match execution_error(err) {
+                SimulationCheckError::Execution {
+                    message,
+                    reason,
+                    revert_data,
+                } => SimulationCheckError::RevertedAtMaxGas {
+                    max_gas,
+                    message,
+                    reason,
+                    revert_data,
+                },
+                err => err,
+            })
+    }
+
+    /// Finds the `UserOperationEvent` of the user operation in the latest [LATEST_SCAN_DEPTH] blocks
     pub async fn get_user_operation_event_meta(
         &self,
         uo_hash: &UserOperationHash,
     ) -> anyhow::Result<Option<(UserOperationEventFilter, LogMeta)>> {
         let mut event: Option<(UserOperationEventFilter, LogMeta)> = None;
+        let block_num = self.eth_client.get_block_number().await?;
         let filter = self
             .entry_point
             .entry_point_api()
             .event::<UserOperationEventFilter>()
-            .topic1(uo_hash.0);
+            .topic1(uo_hash.0)
+            .from_block(block_num.saturating_sub(U64::from(LATEST_SCAN_DEPTH)));
         let res: Vec<(UserOperationEventFilter, LogMeta)> = filter.query_with_meta().await?;
         // It is possible have two same user operatation in same bundle
         // see https://twitter.com/leekt216/status/1636414866662785024
@@ -378,38 +1462,44 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
         Ok(event)
     }
 
+    /// Resolves the included user operation from the `UserOperationEvent` log
+    ///
+    /// The user operation is decoded from the call data of the bundle transaction and matched by its hash.
+    /// Returns `None` if the user operation wasn't included in the latest [LATEST_SCAN_DEPTH] blocks.
     pub async fn get_user_operation_by_hash(
         &self,
         uo_hash: &UserOperationHash,
-    ) -> anyhow::Result<UserOperationByHash> {
-        let event = self.get_user_operation_event_meta(uo_hash).await?;
+    ) -> anyhow::Result<Option<UserOperationByHash>> {
+        let log_meta = match self.get_user_operation_event_meta(uo_hash).await? {
+            Some((_, log_meta)) => log_meta,
+            None => return Ok(None),
+        };
 
-        if let Some((event, log_meta)) = event {
-            if let Some((uo, ep)) = self
-                .eth_client
-                .get_transaction(log_meta.transaction_hash)
-                .await?
-                .and_then(|tx| {
-                    let uos = parse_from_input_data(tx.input)?;
-                    let ep = tx.to?;
-                    uos.iter()
-                        .find(|uo| uo.sender == event.sender && uo.nonce == event.nonce)
-                        .map(|uo| (uo.clone(), ep))
-                })
-            {
-                return Ok(UserOperationByHash {
-                    user_operation: uo,
-                    entry_point: ep,
-                    transaction_hash: log_meta.transaction_hash,
-                    block_hash: log_meta.block_hash,
-                    block_number: log_meta.block_number,
-                });
-            }
-        }
+        let ep = self.entry_point.address();
+        let chain_id = U256::from(self.chain.id());
 
-        Err(format_err!("No user operation found"))
+        Ok(self
+            .eth_client
+            .get_transaction(log_meta.transaction_hash)
+            .await?
+            .and_then(|tx| parse_from_input_data(tx.input))
+            .and_then(|uos| {
+                uos.into_iter()
+                    .find(|uo| uo.hash(&ep, &chain_id) == *uo_hash)
+            })
+            .map(|uo| UserOperationByHash {
+                user_operation: uo,
+                entry_point: ep,
+                transaction_hash: log_meta.transaction_hash,
+                block_hash: log_meta.block_hash,
+                block_number: log_meta.block_number,
+            }))
     }
 
+    /// Assembles the receipt of the included user operation from the `UserOperationEvent` log
+    ///
+    /// The logs of the receipt are the logs of the bundle transaction emitted during the execution of the user
+    /// operation, and the reason is decoded from the `UserOperationRevertReason` event if the execution reverted.
     pub async fn get_user_operation_receipt(
         &self,
         uo_hash: &UserOperationHash,
@@ -422,7 +1512,20 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                 .get_transaction_receipt(log_meta.transaction_hash)
                 .await?
             {
-                let uo = self.get_user_operation_by_hash(uo_hash).await?;
+                let logs = filter_user_operation_logs(&tx_receipt.logs, uo_hash)
+                    .ok_or(format_err!("No user operation event found in the receipt"))?;
+
+                let reason = logs
+                    .iter()
+                    .find_map(|log| {
+                        UserOperationRevertReasonFilter::decode_log(&RawLog::from(log.clone())).ok()
+                    })
+                    .map(|event| {
+                        decode_revert_reason(&event.revert_reason)
+                            .unwrap_or_else(|| event.revert_reason.to_string())
+                    })
+                    .unwrap_or_default();
+
                 return Ok(UserOperationReceipt {
                     user_operation_hash: *uo_hash,
                     sender: event.sender,
@@ -430,10 +1533,14 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                     actual_gas_cost: event.actual_gas_cost,
                     actual_gas_used: event.actual_gas_used,
                     success: event.success,
-                    tx_receipt: tx_receipt.clone(),
-                    logs: tx_receipt.logs.into_iter().collect(),
-                    paymaster: get_address(&uo.user_operation.paymaster_and_data),
-                    reason: String::new(), // TODO: this must be set to revert reason
+                    tx_receipt,
+                    logs,
+                    paymaster: if event.paymaster.is_zero() {
+                        None
+                    } else {
+                        Some(event.paymaster)
+                    },
+                    reason,
                 });
             }
         }
@@ -441,8 +1548,124 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
         Err(format_err!("No user operation found"))
     }
 
+    /// Detects reorgs of the blocks seen by the pool and re-admits user operations of the orphaned blocks
+    ///
+    /// The new chain is walked back through the parent hashes until a block seen before. User operations of
+    /// bundles in the orphaned blocks are decoded from the transactions and re-inserted unless their nonce is
+    /// consumed in the new chain (or they are already in the pool). Returns hashes of the re-admitted user
+    /// operations.
+    pub async fn handle_reorgs(&mut self) -> anyhow::Result<Vec<UserOperationHash>> {
+        let mut block = self
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(format_err!("No block found"))?;
+        let latest = block.number.ok_or(format_err!("No block number"))?;
+        // user operations of the blocks that aren't final yet must be re-admitted
+        let max_depth = MAX_REORG_DEPTH.max(self.confirmation_blocks);
+
+        // blocks above the new head
+        let mut orphaned: Vec<H256> = self
+            .recent_blocks
+            .split_off(&(latest + U64::one()))
+            .into_values()
+            .collect();
+
+        for _ in 0..max_depth {
+            let number = block.number.ok_or(format_err!("No block number"))?;
+            let hash = block.hash.ok_or(format_err!("No block hash"))?;
+
+            match self.recent_blocks.insert(number, hash) {
+                // the chains are the same from here
+                Some(seen) if seen == hash => break,
+                Some(seen) => orphaned.push(seen),
+                None => (),
+            }
+
+            // no blocks seen before this one
+            if number.is_zero() || self.recent_blocks.range(..number).next_back().is_none() {
+                break;
+            }
+
+            block = self
+                .eth_client
+                .get_block(block.parent_hash)
+                .await?
+                .ok_or(format_err!("No block {:?} found", block.parent_hash))?;
+        }
+
+        self.recent_blocks = self
+            .recent_blocks
+            .split_off(&latest.saturating_sub(U64::from(max_depth - 1)));
+
+        let ep = self.entry_point.address();
+        let chain_id = U256::from(self.chain.id());
+        let mut uo_hashes = vec![];
+
+        for block_hash in orphaned {
+            let block = match self.eth_client.get_block_with_txs(block_hash).await? {
+                Some(block) => block,
+                None => {
+                    warn!("Orphaned block {block_hash:?} not found");
+                    continue;
+                }
+            };
+
+            for uo in block
+                .transactions
+                .into_iter()
+                .filter(|tx| tx.to == Some(ep))
+                .filter_map(|tx| parse_from_input_data(tx.input))
+                .flatten()
+            {
+                let uo_hash = uo.hash(&ep, &chain_id);
+
+                if self.mempool.get(&uo_hash)?.is_some() {
+                    continue;
+                }
+
+                // included in the new chain (or replaced)
+                if self.get_nonce_status(&uo).await? == NonceStatus::Stale {
+                    continue;
+                }
+
+                if let Err(err) = self
+                    .validator
+                    .validate_user_operation(
+                        &uo,
+                        &self.mempool,
+                        &self.reputation,
+                        UserOperationValidatorMode::Sanity.into(),
+                    )
+                    .await
+                {
+                    trace!("Dropping invalid user operation {uo_hash:?} of the orphaned block: {err:?}");
+                    report_drop(&uo_hash, &uo.sender, DropReason::ReorgLost);
+                    continue;
+                }
+
+                self.mempool.add(uo, &ep, &chain_id)?;
+                let expires_at = self.lifecycle.track(&uo_hash, None);
+                let _ = self.mempool.set_expires_at(&uo_hash, expires_at);
+                uo_hashes.push(uo_hash);
+            }
+        }
+
+        Ok(uo_hashes)
+    }
+
     pub async fn handle_past_events(&mut self) -> anyhow::Result<()> {
+        match self.handle_reorgs().await {
+            Ok(uo_hashes) if !uo_hashes.is_empty() => {
+                info!("Re-admitted user operations {uo_hashes:?} of reorged blocks")
+            }
+            Err(err) => warn!("Handling reorgs failed: {err:?}"),
+            _ => (),
+        }
+
         let block_num = self.eth_client.get_block_number().await?;
+        // rebuilt from the events of the current chain, so the user operations of orphaned blocks are released
+        let mut mined = HashSet::new();
         let block_st = std::cmp::max(
             1u64,
             block_num
@@ -452,12 +1675,59 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
         );
 
         let filter = self.entry_point.events().from_block(block_st);
-        let events = filter.query().await?;
+        let events = filter.query_with_meta().await?;
 
-        for event in events {
+        // user operations with reverted execution (the revert reason is emitted before the user operation event)
+        let execution_reverted = events
+            .iter()
+            .filter_map(|(event, _)| match event {
+                EntryPointAPIEvents::UserOperationRevertReasonFilter(event) => {
+                    Some(event.user_op_hash)
+                }
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        for (event, meta) in events {
             match event {
                 EntryPointAPIEvents::UserOperationEventFilter(uo_event) => {
+                    let confirmations = (block_num + 1).saturating_sub(meta.block_number).as_u64();
+                    if confirmations < self.confirmation_blocks {
+                        mined.insert(uo_event.user_op_hash.into());
+                        continue;
+                    }
+
+                    // only user operations still in the pool, so each inclusion is notified (and penalized) once
+                    if let Ok(Some(_)) = self.mempool.get(&uo_event.user_op_hash.into()) {
+                        if let (false, Some(penalty)) = (uo_event.success, self.revert_penalty) {
+                            let entity = reverted_op_entity(
+                                uo_event.sender,
+                                uo_event.paymaster,
+                                execution_reverted.contains(&uo_event.user_op_hash),
+                            );
+                            warn!(
+                                "User operation {:?} reverted on chain, penalizing {entity:?}",
+                                uo_event.user_op_hash
+                            );
+                            self.reputation.update_op_reverted(&entity, penalty);
+                        }
+
+                        // no subscribers is not an error
+                        let _ = self.inclusions.send(InclusionEvent {
+                            user_op_hash: uo_event.user_op_hash.into(),
+                            sender: uo_event.sender,
+                            success: uo_event.success,
+                            actual_gas_cost: uo_event.actual_gas_cost,
+                            tx_hash: meta.transaction_hash,
+                        });
+                        report_drop(
+                            &uo_event.user_op_hash.into(),
+                            &uo_event.sender,
+                            DropReason::Bundled,
+                        );
+                    }
                     self.remove_user_operation(&uo_event.user_op_hash.into());
+                    self.lifecycle.untrack(&uo_event.user_op_hash.into());
                     self.reputation.increment_included(&uo_event.sender);
                     self.reputation.increment_included(&uo_event.paymaster);
                     // TODO: include event aggregator
@@ -468,18 +1738,149 @@ impl<M: Middleware + 'static, V: UserOperationValidator> UoPool<M, V> {
                 _ => (),
             }
         }
+        self.mined = mined;
 
         Ok(())
     }
 
+    /// Returns `validUntil` of the user operation selected for the latest bundle
+    pub fn get_bundled_valid_until(&self, uo_hash: &UserOperationHash) -> Option<U256> {
+        self.bundled
+            .get(uo_hash)
+            .map(|(valid_until, _)| *valid_until)
+    }
+
+    /// Returns the signature aggregator of the user operation selected for the latest bundle
+    pub fn get_bundled_aggregator(&self, uo_hash: &UserOperationHash) -> Option<Address> {
+        self.bundled
+            .get(uo_hash)
+            .and_then(|(_, aggregator)| *aggregator)
+    }
+
+    /// Storage preconditions (`knownAccounts`) of the user operations of the latest bundle
+    ///
+    /// The preconditions are the current values of the storage slots accessed during the 2nd validation of the
+    /// user operations (merged across the bundle), so the conditional bundle transaction isn't included if any of
+    /// them changes.
+    pub async fn get_bundled_known_accounts(
+        &self,
+        uos: &[UserOperation],
+    ) -> anyhow::Result<KnownAccounts> {
+        let mut slots: BTreeMap<Address, BTreeSet<H256>> = BTreeMap::new();
+        for uo in uos {
+            let uo_hash = uo.hash(&self.entry_point.address(), &self.chain.id().into());
+            for (address, uo_slots) in self.bundled_slots.get(&uo_hash).into_iter().flatten() {
+                slots.entry(*address).or_default().extend(uo_slots);
+            }
+        }
+
+        let mut known_accounts = KnownAccounts::default();
+        for (address, slots) in slots {
+            for slot in slots {
+                let value = self
+                    .eth_client
+                    .get_storage_at(address, slot, None)
+                    .await
+                    .map_err(|err| {
+                        format_err!(
+                            "Getting storage slot {slot:?} of {address:?} failed with error: {err:?}"
+                        )
+                    })?;
+                known_accounts.insert_slot(address, slot, value);
+            }
+        }
+
+        Ok(known_accounts)
+    }
+
+    /// Returns the user operations dropped from the mempool while creating the latest bundle with the reasons
+    pub fn get_bundle_dropped(&self) -> &[(UserOperationHash, String)] {
+        &self.bundle_dropped
+    }
+
     pub fn remove_user_operation(&mut self, uo_hash: &UserOperationHash) -> Option<()> {
         self.mempool.remove(uo_hash).ok();
+        self.p2p_uos.remove(uo_hash);
         None
     }
 
+    /// Removes user operations that outlived their TTL or `validUntil` from the pool
+    pub fn remove_expired_user_operations(&mut self) -> Vec<UserOperationHash> {
+        let uo_hashes = self.lifecycle.expire();
+        for uo_hash in uo_hashes.iter() {
+            if let Ok(Some(uo)) = self.mempool.get(uo_hash) {
+                report_drop(uo_hash, &uo.sender, DropReason::Expired);
+            }
+            self.remove_user_operation(uo_hash);
+        }
+        uo_hashes
+    }
+
+    /// Removes the user operations of the sent bundle from the pool
     pub fn remove_user_operations(&mut self, uo_hashes: Vec<UserOperationHash>) {
         for uo_hash in uo_hashes {
+            if let Ok(Some(uo)) = self.mempool.get(&uo_hash) {
+                report_drop(&uo_hash, &uo.sender, DropReason::Bundled);
+            }
             self.remove_user_operation(&uo_hash);
+            self.lifecycle.untrack(&uo_hash);
+            self.bundled.remove(&uo_hash);
+        }
+    }
+}
+
+/// Reports the user operation leaving the mempool
+///
+/// Emits the structured `silius::uopool::drop` event (hash, sender and reason) subscribers can aggregate, the
+/// dropped user operations metric is labeled by the same reason.
+fn report_drop(uo_hash: &UserOperationHash, sender: &Address, reason: DropReason) {
+    let reason: &'static str = reason.into();
+    METRICS.uos_dropped.inc(reason);
+    info!(
+        target: "silius::uopool::drop",
+        uo_hash = ?uo_hash,
+        sender = ?sender,
+        reason,
+        "User operation dropped from the mempool"
+    );
+}
+
+/// Whether the validation failed because of the node (the user operation itself wasn't checked)
+fn is_transient(err: &ValidationError) -> bool {
+    matches!(
+        err,
+        ValidationError::Sanity(
+            SanityCheckError::Timeout { .. } | SanityCheckError::MiddlewareError { .. }
+        ) | ValidationError::Simulation(
+            SimulationCheckError::Timeout { .. } | SimulationCheckError::MiddlewareError { .. }
+        )
+    )
+}
+
+/// Converts the error of the execution (simulation) into the simulation error with the decoded revert reason
+fn execution_error(err: EntryPointErr) -> SimulationCheckError {
+    match err {
+        EntryPointErr::JsonRpcError(err) => {
+            let revert_data = err.as_revert_data().unwrap_or_default();
+            SimulationCheckError::Execution {
+                reason: decode_revert_reason(&revert_data),
+                message: err.message,
+                revert_data,
+            }
         }
+        EntryPointErr::FailedOp(err) => SimulationCheckError::Execution {
+            message: err.reason.clone(),
+            revert_data: [
+                FAILED_OP_SELECTOR.to_vec(),
+                (err.op_index, err.reason.clone()).encode(),
+            ]
+            .concat()
+            .into(),
+            reason: Some(err.reason),
+        },
+        EntryPointErr::Timeout(message) => SimulationCheckError::Timeout { message },
+        _ => SimulationCheckError::UnknownError {
+            message: format!("{err:?}"),
+        },
     }
 }
@@ -1,28 +1,34 @@
-use ethers::types::{u256_from_f64_saturating, Address, H256, U256};
-use silius_primitives::{simulation::CodeHash, UserOperation};
-use std::{collections::HashMap, ops::Deref};
-
-pub fn equal_code_hashes(hashes: &Vec<CodeHash>, hashes_prev: &Vec<CodeHash>) -> bool {
-    if hashes_prev.len() != hashes.len() {
-        return false;
-    }
+use crate::pre_verification_gas::PreVerificationGasCalculator;
+use ethers::{
+    contract::EthEvent,
+    types::{u256_from_f64_saturating, Address, Log, H256, U256},
+};
+use silius_contracts::entry_point::{BeforeExecutionFilter, UserOperationEventFilter};
+use silius_primitives::{simulation::CodeHash, ChainSpec, UserOperation, UserOperationHash};
+use std::{collections::HashMap, ops::Deref, sync::Arc};
 
+/// Returns the first address whose code hash differs between the two validations
+///
+/// Addresses touched by only one of the validations count as changed.
+pub fn changed_code_hash(hashes: &[CodeHash], hashes_prev: &[CodeHash]) -> Option<Address> {
     let hashes_map = hashes
         .iter()
         .map(|h: &CodeHash| (h.address, h.hash))
         .collect::<HashMap<Address, H256>>();
+    let hashes_prev_map = hashes_prev
+        .iter()
+        .map(|h: &CodeHash| (h.address, h.hash))
+        .collect::<HashMap<Address, H256>>();
 
-    for hash_prev in hashes_prev {
-        if let Some(hash) = hashes_map.get(&hash_prev.address) {
-            if hash != &hash_prev.hash {
-                return false;
-            }
-        } else {
-            return false;
-        }
-    }
-
-    true
+    hashes_prev
+        .iter()
+        .find(|h| hashes_map.get(&h.address) != Some(&h.hash))
+        .or_else(|| {
+            hashes
+                .iter()
+                .find(|h| !hashes_prev_map.contains_key(&h.address))
+        })
+        .map(|h| h.address)
 }
 
 // https://github.com/eth-infinitism/bundler/blob/main/packages/sdk/src/calcPreVerificationGas.ts#L44-L52
@@ -34,6 +40,7 @@ pub struct Overhead {
     pub non_zero_byte: U256,
     pub bundle_size: U256,
     pub sig_size: U256,
+    pub calculator: Option<Arc<dyn PreVerificationGasCalculator>>,
 }
 
 impl Default for Overhead {
@@ -46,11 +53,39 @@ impl Default for Overhead {
             non_zero_byte: U256::from(16),
             bundle_size: U256::from(1),
             sig_size: U256::from(65),
+            calculator: None,
+        }
+    }
+}
+
+/// Overhead constants of the chain (without the L1 data fee calculator)
+impl From<&ChainSpec> for Overhead {
+    fn from(chain_spec: &ChainSpec) -> Self {
+        Self {
+            fixed: chain_spec.fixed_overhead,
+            per_user_op: chain_spec.per_user_op_overhead,
+            per_user_op_word: chain_spec.per_user_op_word_overhead,
+            zero_byte: chain_spec.zero_byte_gas,
+            non_zero_byte: chain_spec.non_zero_byte_gas,
+            ..Default::default()
         }
     }
 }
 
 impl Overhead {
+    pub fn builder() -> OverheadBuilder {
+        OverheadBuilder::default()
+    }
+
+    /// Adds the L1 data fee of the user operation to the pre-verification gas (used on L2s)
+    pub fn with_calculator(
+        mut self,
+        calculator: Option<Arc<dyn PreVerificationGasCalculator>>,
+    ) -> Self {
+        self.calculator = calculator;
+        self
+    }
+
     pub fn calculate_pre_verification_gas(&self, uo: &UserOperation) -> U256 {
         let uo_pack = uo.pack();
         let call_data: U256 = U256::from(
@@ -73,25 +108,246 @@ impl Overhead {
                 + (self.per_user_op_word.as_u128() as f64) * len_in_word,
         )
     }
+
+    /// Calculates the pre-verification gas including the L1 data fee (if the calculator is set)
+    pub async fn estimate_pre_verification_gas(
+        &self,
+        uo: &UserOperation,
+        entry_point: &Address,
+    ) -> anyhow::Result<U256> {
+        let pre_gas = self.calculate_pre_verification_gas(uo);
+        match &self.calculator {
+            Some(calculator) => {
+                Ok(pre_gas.saturating_add(calculator.calculate_l1_gas(uo, entry_point).await?))
+            }
+            None => Ok(pre_gas),
+        }
+    }
+}
+
+/// Builder of the [Overhead](Overhead) which starts from the default values
+#[derive(Default)]
+pub struct OverheadBuilder {
+    overhead: Overhead,
+}
+
+impl OverheadBuilder {
+    pub fn fixed(mut self, fixed: U256) -> Self {
+        self.overhead.fixed = fixed;
+        self
+    }
+
+    pub fn per_user_op(mut self, per_user_op: U256) -> Self {
+        self.overhead.per_user_op = per_user_op;
+        self
+    }
+
+    pub fn per_user_op_word(mut self, per_user_op_word: U256) -> Self {
+        self.overhead.per_user_op_word = per_user_op_word;
+        self
+    }
+
+    pub fn zero_byte(mut self, zero_byte: U256) -> Self {
+        self.overhead.zero_byte = zero_byte;
+        self
+    }
+
+    pub fn non_zero_byte(mut self, non_zero_byte: U256) -> Self {
+        self.overhead.non_zero_byte = non_zero_byte;
+        self
+    }
+
+    pub fn bundle_size(mut self, bundle_size: U256) -> Self {
+        self.overhead.bundle_size = bundle_size;
+        self
+    }
+
+    pub fn sig_size(mut self, sig_size: U256) -> Self {
+        self.overhead.sig_size = sig_size;
+        self
+    }
+
+    pub fn calculator(mut self, calculator: Arc<dyn PreVerificationGasCalculator>) -> Self {
+        self.overhead.calculator = Some(calculator);
+        self
+    }
+
+    pub fn build(self) -> Overhead {
+        self.overhead
+    }
 }
 
 pub fn calculate_valid_gas(gas_price: U256, gas_incr_perc: U256) -> U256 {
-    let gas_price = gas_price.as_u64() as f64;
-    let gas_incr_perc = gas_incr_perc.as_u64() as f64;
-    ((gas_price * (1.0 + gas_incr_perc / 100.0)).ceil() as u64).into()
+    let gas_price = gas_price.saturating_mul(U256::from(100).saturating_add(gas_incr_perc));
+    gas_price.saturating_add(U256::from(99)) / 100
 }
 
 pub fn calculate_call_gas_limit(paid: U256, pre_op_gas: U256, fee_per_gas: U256) -> U256 {
     paid / fee_per_gas - pre_op_gas + Overhead::default().fixed
 }
 
+/// Filters the logs of the bundle transaction emitted during the execution of the user operation
+///
+/// Execution of the user operations starts after the `BeforeExecution` event and the logs of each user operation
+/// are followed by its `UserOperationEvent`, so the logs of the user operation are between the previous
+/// `UserOperationEvent` (or `BeforeExecution`) and its own `UserOperationEvent`. Returns `None` if the logs don't
+/// contain the `UserOperationEvent` of the user operation.
+pub fn filter_user_operation_logs(logs: &[Log], uo_hash: &UserOperationHash) -> Option<Vec<Log>> {
+    let before_execution = BeforeExecutionFilter::signature();
+    let uo_event = UserOperationEventFilter::signature();
+
+    let mut start: Option<usize> = None;
+    let mut end: Option<usize> = None;
+    for (i, log) in logs.iter().enumerate() {
+        match log.topics.first() {
+            Some(topic) if *topic == before_execution && end.is_none() => start = Some(i),
+            Some(topic) if *topic == uo_event => {
+                if log.topics.get(1) == Some(&uo_hash.0) {
+                    end = Some(i);
+                } else if end.is_none() {
+                    start = Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let end = end?;
+    let start = start.map_or(0, |start| start + 1);
+    Some(logs[start..end].to_vec())
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::mempool::Mempool;
-    use ethers::types::{Address, Bytes, H256, U256};
-    use silius_primitives::{UserOperation, UserOperationHash};
-    use std::fmt::Debug;
+    use crate::{
+        lifecycle::Clock,
+        mempool::{Mempool, MempoolBox},
+        reputation::ReputationBox,
+        uopool::{VecCh, VecUo},
+        MemoryMempool, MemoryReputation,
+    };
+    use ethers::{
+        providers::{MockProvider, Provider},
+        types::{Address, Bytes, H256, U256},
+    };
+    use silius_primitives::{
+        reputation::ReputationEntry, simulation::CodeHash, UserOperation, UserOperationHash,
+    };
+    use std::{
+        fmt::Debug,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    #[derive(Debug, Default)]
+    pub struct MockClock(AtomicU64);
+
+    impl MockClock {
+        pub fn advance(&self, secs: u64) {
+            self.0.fetch_add(secs, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    /// Empty mempool and reputation plus a mocked provider the checks are run against
+    pub struct MockContext {
+        pub mempool: MempoolBox<VecUo, VecCh>,
+        pub reputation: ReputationBox<Vec<ReputationEntry>>,
+        pub eth_client: Arc<Provider<MockProvider>>,
+        pub mock: MockProvider,
+    }
+
+    impl Default for MockContext {
+        fn default() -> Self {
+            let (eth_client, mock) = Provider::mocked();
+            Self {
+                mempool: Box::<MemoryMempool>::default(),
+                reputation: Box::<MemoryReputation>::default(),
+                eth_client: Arc::new(eth_client),
+                mock,
+            }
+        }
+    }
+
+    #[test]
+    fn user_operation_logs() {
+        let log = |topics: Vec<H256>| Log {
+            topics,
+            ..Default::default()
+        };
+        let uo_event = |uo_hash: &UserOperationHash| {
+            log(vec![UserOperationEventFilter::signature(), uo_hash.0])
+        };
+        let uo_hash: UserOperationHash = H256::random().into();
+        let uo_hash_other: UserOperationHash = H256::random().into();
+        let account_log = log(vec![H256::random()]);
+        let logs = vec![
+            log(vec![H256::random()]),
+            log(vec![BeforeExecutionFilter::signature()]),
+            log(vec![H256::random()]),
+            uo_event(&uo_hash_other),
+            account_log.clone(),
+            account_log.clone(),
+            uo_event(&uo_hash),
+            log(vec![H256::random()]),
+        ];
+
+        assert_eq!(
+            filter_user_operation_logs(&logs, &uo_hash),
+            Some(vec![account_log.clone(), account_log])
+        );
+        assert_eq!(
+            filter_user_operation_logs(&logs, &uo_hash_other),
+            Some(vec![logs[2].clone()])
+        );
+        assert_eq!(
+            filter_user_operation_logs(&logs, &H256::random().into()),
+            None
+        );
+    }
+
+    #[test]
+    fn code_hash_changes() {
+        let code_hash = |address: Address, hash: u64| CodeHash {
+            address,
+            hash: H256::from_low_u64_be(hash),
+        };
+        let (a, b, c) = (Address::random(), Address::random(), Address::random());
+        let hashes = vec![code_hash(a, 1), code_hash(b, 2)];
+
+        assert_eq!(changed_code_hash(&hashes, &hashes), None);
+        assert_eq!(
+            changed_code_hash(&[code_hash(b, 2), code_hash(a, 1)], &hashes),
+            None
+        );
+        assert_eq!(
+            changed_code_hash(&[code_hash(a, 1), code_hash(b, 3)], &hashes),
+            Some(b)
+        );
+        assert_eq!(changed_code_hash(&[code_hash(a, 1)], &hashes), Some(b));
+        assert_eq!(
+            changed_code_hash(
+                &[code_hash(a, 1), code_hash(b, 2), code_hash(c, 3)],
+                &hashes
+            ),
+            Some(c)
+        );
+    }
+
+    #[test]
+    fn valid_gas_calculation() {
+        assert_eq!(calculate_valid_gas(100.into(), 10.into()), 110.into());
+        assert_eq!(calculate_valid_gas(101.into(), 10.into()), 112.into());
+        assert_eq!(
+            calculate_valid_gas(U256::from(u64::MAX), 10.into()),
+            U256::from(u64::MAX) * 110 / 100 + 1
+        );
+    }
 
     #[test]
     fn pre_verification_gas_calculation() {
@@ -111,6 +367,17 @@ pub mod tests {
         };
 
         assert_eq!(gas_oh.calculate_pre_verification_gas(&uo), 45340.into());
+
+        let gas_oh = Overhead::builder().build();
+        assert_eq!(gas_oh.calculate_pre_verification_gas(&uo), 45340.into());
+
+        // zero bytes priced higher
+        let gas_oh = Overhead::builder().zero_byte(5.into()).build();
+        assert!(gas_oh.calculate_pre_verification_gas(&uo) > 45340.into());
+
+        // mainnet defaults of the chain spec
+        let gas_oh = Overhead::from(&ChainSpec::default());
+        assert_eq!(gas_oh.calculate_pre_verification_gas(&uo), 45340.into());
     }
 
     pub fn mempool_test_case<T>(mut mempool: T, not_found_error_message: &str)
@@ -162,7 +429,11 @@ pub mod tests {
         assert_eq!(mempool.get_all_by_sender(&senders[1]).len(), 2);
         assert_eq!(mempool.get_all_by_sender(&senders[2]).len(), 3);
 
+        mempool.set_expires_at(&uo_hash, 1_000).unwrap();
+        assert_eq!(mempool.get_expires_at(&uo_hash), Some(1_000));
+
         assert_eq!(mempool.remove(&uo_hash).unwrap(), ());
+        assert_eq!(mempool.get_expires_at(&uo_hash), None);
         assert_eq!(
             mempool
                 .remove(&H256::random().into())
@@ -0,0 +1,56 @@
+use ethers::types::U64;
+use silius_contracts::{entry_point::SimulateValidationResult, tracer::JsTracerFrame};
+use silius_primitives::UserOperationHash;
+use std::collections::HashMap;
+
+/// Max number of user operations cached at the same block (the cache is cleared when it's full)
+const MAX_CACHED_USER_OPERATIONS: usize = 10_000;
+
+/// Result of the validation simulation (and the parsed trace if the user operation was traced)
+#[derive(Clone, Debug)]
+pub struct SimulationCacheEntry {
+    pub sim_res: SimulateValidationResult,
+    pub js_trace: Option<JsTracerFrame>,
+}
+
+/// Results of the validation simulation of user operations at the latest block
+///
+/// User operations are validated on admission and again before bundling, so the results are cached by
+/// `(user operation hash, block number)` to skip the repeated `simulateValidation` and `debug_traceCall`. All
+/// entries are dropped once the block advances, as the state the simulation depends on may have changed.
+#[derive(Debug, Default)]
+pub struct SimulationCache {
+    block_number: U64,
+    entries: HashMap<UserOperationHash, SimulationCacheEntry>,
+}
+
+impl SimulationCache {
+    fn advance(&mut self, block_number: U64) {
+        if block_number != self.block_number {
+            self.block_number = block_number;
+            self.entries.clear();
+        }
+    }
+
+    pub fn get(
+        &mut self,
+        uo_hash: &UserOperationHash,
+        block_number: U64,
+    ) -> Option<SimulationCacheEntry> {
+        self.advance(block_number);
+        self.entries.get(uo_hash).cloned()
+    }
+
+    pub fn insert(
+        &mut self,
+        uo_hash: UserOperationHash,
+        block_number: U64,
+        entry: SimulationCacheEntry,
+    ) {
+        self.advance(block_number);
+        if self.entries.len() >= MAX_CACHED_USER_OPERATIONS {
+            self.entries.clear();
+        }
+        self.entries.insert(uo_hash, entry);
+    }
+}
@@ -1,20 +1,33 @@
 use crate::{
     mempool::MempoolBox,
-    reputation::ReputationBox,
+    reputation::{Reputation, ReputationBox},
     uopool::{VecCh, VecUo},
+    MemoryMempool, MemoryReputation,
 };
 use enumset::{EnumSet, EnumSetType};
-use ethers::{providers::Middleware, types::U256};
+use ethers::{
+    providers::Middleware,
+    types::{spoof, Address, H256, U256},
+};
+use retry::RetryPolicy;
 use silius_contracts::{entry_point::SimulateValidationResult, tracer::JsTracerFrame, EntryPoint};
 use silius_primitives::{
-    reputation::{ReputationEntry, StakeInfo},
+    reputation::{
+        ReputationEntry, StakeInfo, BAN_SLACK, HOURLY_DECAY_DENOMINATOR,
+        MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+    },
     sanity::SanityCheckError,
     simulation::{CodeHash, SimulationCheckError, NUMBER_LEVELS},
     uopool::ValidationError,
     Chain, UserOperation, UserOperationHash,
 };
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
+mod cache;
+pub mod retry;
 pub mod sanity;
 pub mod simulation;
 pub mod simulation_trace;
@@ -28,8 +41,11 @@ pub struct UserOperationValidationOutcome {
     pub verification_gas_limit: U256,
     // Simulation
     pub valid_after: Option<U256>,
+    pub valid_until: Option<U256>,
+    pub aggregator: Option<Address>,
     // Simulation trace
     pub code_hashes: Option<Vec<CodeHash>>,
+    pub accessed_slots: Option<HashMap<Address, HashSet<H256>>>,
 }
 
 #[derive(EnumSetType, Debug)]
@@ -48,6 +64,47 @@ pub trait UserOperationValidator: Send + Sync {
         reputation: &ReputationBox<Vec<ReputationEntry>>,
         mode: EnumSet<UserOperationValidatorMode>,
     ) -> Result<UserOperationValidationOutcome, ValidationError>;
+
+    /// Validates the user operation with the state overrides applied to the simulations (e.g. for the gas
+    /// estimation), validators not simulating against the execution client validate without them
+    async fn validate_user_operation_with_state_override(
+        &self,
+        uo: &UserOperation,
+        mempool: &MempoolBox<VecUo, VecCh>,
+        reputation: &ReputationBox<Vec<ReputationEntry>>,
+        mode: EnumSet<UserOperationValidatorMode>,
+        _state_override: &spoof::State,
+    ) -> Result<UserOperationValidationOutcome, ValidationError> {
+        self.validate_user_operation(uo, mempool, reputation, mode)
+            .await
+    }
+}
+
+/// Validates the user operation standalone, without the mempool (nothing is added to the pool or submitted)
+///
+/// The checks run against an empty mempool and reputation, so the outcome (pre-fund, valid time range, aggregator)
+/// depends on the user operation alone. Entities are staked if they meet `min_stake` and `min_unstake_delay`.
+pub async fn validate_user_operation<V: UserOperationValidator>(
+    validator: &V,
+    uo: &UserOperation,
+    min_stake: U256,
+    min_unstake_delay: U256,
+    mode: EnumSet<UserOperationValidatorMode>,
+) -> Result<UserOperationValidationOutcome, ValidationError> {
+    let mempool: MempoolBox<VecUo, VecCh> = Box::<MemoryMempool>::default();
+    let mut reputation: ReputationBox<Vec<ReputationEntry>> = Box::<MemoryReputation>::default();
+    reputation.init(
+        MIN_INCLUSION_RATE_DENOMINATOR,
+        THROTTLING_SLACK,
+        BAN_SLACK,
+        HOURLY_DECAY_DENOMINATOR,
+        min_stake,
+        min_unstake_delay,
+    );
+
+    validator
+        .validate_user_operation(uo, &mempool, &reputation, mode)
+        .await
 }
 
 pub struct SanityHelper<'a, M: Middleware + 'static> {
@@ -56,6 +113,7 @@ pub struct SanityHelper<'a, M: Middleware + 'static> {
     eth_client: Arc<M>,
     entry_point: EntryPoint<M>,
     chain: Chain,
+    retry_policy: RetryPolicy,
 }
 
 #[async_trait::async_trait]
@@ -106,3 +164,73 @@ pub trait SimulationTraceCheck<M: Middleware>: Send + Sync {
         helper: &mut SimulationTraceHelper<M>,
     ) -> Result<(), SimulationCheckError>;
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::providers::{MockProvider, Provider};
+    use silius_primitives::consts::entry_point::ADDRESS;
+
+    impl MockContext {
+        fn entry_point(&self) -> EntryPoint<Provider<MockProvider>> {
+            EntryPoint::new(self.eth_client.clone(), ADDRESS.parse().unwrap())
+        }
+
+        pub fn sanity_helper(&self) -> SanityHelper<'_, Provider<MockProvider>> {
+            SanityHelper {
+                mempool: &self.mempool,
+                reputation: &self.reputation,
+                eth_client: self.eth_client.clone(),
+                entry_point: self.entry_point(),
+                chain: Chain::from(1337_u64),
+                retry_policy: RetryPolicy::default(),
+            }
+        }
+
+        pub fn simulation_trace_helper<'a>(
+            &'a self,
+            simulate_validation_result: &'a SimulateValidationResult,
+            js_trace: &'a JsTracerFrame,
+        ) -> SimulationTraceHelper<'a, Provider<MockProvider>> {
+            SimulationTraceHelper {
+                mempool: &self.mempool,
+                reputation: &self.reputation,
+                eth_client: self.eth_client.clone(),
+                entry_point: self.entry_point(),
+                chain: Chain::from(1337_u64),
+                simulate_validation_result,
+                js_trace,
+                stake_info: None,
+                code_hashes: None,
+            }
+        }
+
+        pub fn simulation_helper<'a>(
+            &'a self,
+            simulate_validation_result: &'a SimulateValidationResult,
+        ) -> SimulationHelper<'a, Provider<MockProvider>> {
+            SimulationHelper {
+                mempool: &self.mempool,
+                reputation: &self.reputation,
+                eth_client: self.eth_client.clone(),
+                entry_point: self.entry_point(),
+                chain: Chain::from(1337_u64),
+                simulate_validation_result,
+                valid_after: None,
+            }
+        }
+    }
+
+    pub fn mock_simulate_validation_result(
+        valid_after: u64,
+        valid_until: u64,
+    ) -> SimulateValidationResult {
+        let mut sim_res = SimulateValidationResult::ValidationResult(Default::default());
+        if let SimulateValidationResult::ValidationResult(res) = &mut sim_res {
+            res.return_info.3 = valid_after;
+            res.return_info.4 = valid_until;
+        }
+        sim_res
+    }
+}
@@ -0,0 +1,134 @@
+use silius_contracts::entry_point::EntryPointErr;
+use std::{future::Future, time::Duration};
+use tracing::debug;
+
+/// Default timeout of a single simulation call to the execution client (in milliseconds)
+pub const DEFAULT_SIMULATION_TIMEOUT: u64 = 10_000;
+/// Default number of retries of a simulation call failing with a transient error
+pub const DEFAULT_SIMULATION_MAX_RETRIES: u32 = 3;
+/// Default delay before the first retry (doubled on every next retry)
+pub const DEFAULT_SIMULATION_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Timeout and retry policy shared by the calls to the execution client during the validation
+///
+/// Each attempt is limited by the timeout. Transient errors (timeouts, network errors, rate limits) are retried
+/// with exponential backoff up to `max_retries` times, other errors (reverts, failed operations) are returned
+/// right away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub timeout: Duration,
+    pub max_retries: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(DEFAULT_SIMULATION_TIMEOUT),
+            max_retries: DEFAULT_SIMULATION_MAX_RETRIES,
+            backoff: DEFAULT_SIMULATION_BACKOFF,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(timeout: Duration, max_retries: u32) -> Self {
+        Self {
+            timeout,
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    /// Calls the entry point with the policy
+    ///
+    /// Returns [EntryPointErr::Timeout] if the last attempt timed out.
+    pub async fn call<T, F, Fut>(&self, mut f: F) -> Result<T, EntryPointErr>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, EntryPointErr>>,
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let res = match tokio::time::timeout(self.timeout, f()).await {
+                Ok(res) => res,
+                Err(_) => Err(EntryPointErr::Timeout(format!(
+                    "Call to the execution client timed out after {:?}",
+                    self.timeout
+                ))),
+            };
+
+            match res {
+                Err(err) if err.is_transient() && attempt < self.max_retries => {
+                    debug!("Retrying call to the execution client in {backoff:?}: {err:?}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::JsonRpcError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn retry_policy() {
+        let policy = RetryPolicy {
+            timeout: Duration::from_millis(50),
+            max_retries: 2,
+            backoff: Duration::from_millis(1),
+        };
+
+        // transient error is retried until the call succeeds
+        let calls = AtomicU32::new(0);
+        let res = policy
+            .call(|| async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(EntryPointErr::JsonRpcError(JsonRpcError {
+                        code: 429,
+                        message: "Too Many Requests".into(),
+                        data: None,
+                    }))
+                } else {
+                    Ok(1)
+                }
+            })
+            .await;
+        assert_eq!(res.unwrap(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // revert isn't retried
+        let calls = AtomicU32::new(0);
+        let res: Result<(), _> = policy
+            .call(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(EntryPointErr::JsonRpcError(JsonRpcError {
+                    code: 3,
+                    message: "execution reverted".into(),
+                    data: Some("0x".into()),
+                }))
+            })
+            .await;
+        assert!(matches!(res, Err(EntryPointErr::JsonRpcError(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // timeout after the retries are exhausted
+        let calls = AtomicU32::new(0);
+        let res: Result<(), _> = policy
+            .call(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok(())
+            })
+            .await;
+        assert!(matches!(res, Err(EntryPointErr::Timeout(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::validate::{
+    utils::extract_aggregator, SanityCheck, SanityHelper, SimulationCheck, SimulationHelper,
+};
+use ethers::{providers::Middleware, types::Address};
+use silius_primitives::{
+    consts::entities::{ACCOUNT, AGGREGATOR, FACTORY, PAYMASTER},
+    get_address,
+    sanity::SanityCheckError,
+    simulation::SimulationCheckError,
+    UserOperation,
+};
+use std::collections::HashSet;
+
+/// Operator configured allowlist and denylist of entities (sender, factory, paymaster and aggregator)
+///
+/// Denied entities are rejected right away. When the allowlist isn't empty, every entity of the user operation
+/// has to be listed in it. The aggregator is known only after the simulation, so it's checked as a simulation check.
+#[derive(Clone, Debug, Default)]
+pub struct AccessListCheck {
+    pub allowlist: HashSet<Address>,
+    pub denylist: HashSet<Address>,
+}
+
+impl AccessListCheck {
+    pub fn new(allowlist: HashSet<Address>, denylist: HashSet<Address>) -> Self {
+        Self {
+            allowlist,
+            denylist,
+        }
+    }
+
+    /// Whether the entity may use the bundler
+    pub fn is_allowed(&self, address: &Address) -> bool {
+        !self.denylist.contains(address)
+            && (self.allowlist.is_empty() || self.allowlist.contains(address))
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for AccessListCheck {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        _helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        let entities = [
+            (ACCOUNT, Some(uo.sender)),
+            (FACTORY, get_address(&uo.init_code)),
+            (PAYMASTER, get_address(&uo.paymaster_and_data)),
+        ];
+
+        for (entity, address) in entities {
+            if let Some(address) = address {
+                if !self.is_allowed(&address) {
+                    return Err(SanityCheckError::EntityBanned {
+                        entity: entity.into(),
+                        address,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationCheck<M> for AccessListCheck {
+    async fn check_user_operation(
+        &self,
+        _uo: &UserOperation,
+        helper: &mut SimulationHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        if let Some(info) = extract_aggregator(helper.simulate_validation_result) {
+            if !self.is_allowed(&info.address) {
+                return Err(SimulationCheckError::EntityBanned {
+                    entity: AGGREGATOR.into(),
+                    address: info.address,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::types::{Bytes, U256};
+    use silius_contracts::entry_point::SimulateValidationResult;
+
+    #[tokio::test]
+    async fn access_list() {
+        let ctx = MockContext::default();
+
+        let sender = Address::random();
+        let factory = Address::random();
+        let paymaster = Address::random();
+        let uo = UserOperation::default()
+            .sender(sender)
+            .init_code(Bytes::from([factory.as_bytes(), &[0xfe; 4]].concat()))
+            .paymaster_and_data(Bytes::from(paymaster.as_bytes().to_vec()));
+
+        let mut helper = ctx.sanity_helper();
+
+        // no restrictions
+        let check = AccessListCheck::default();
+        assert!(SanityCheck::check_user_operation(&check, &uo, &mut helper)
+            .await
+            .is_ok());
+
+        // denied paymaster
+        let check = AccessListCheck::new(HashSet::new(), HashSet::from([paymaster]));
+        assert!(matches!(
+            SanityCheck::check_user_operation(&check, &uo, &mut helper).await,
+            Err(SanityCheckError::EntityBanned { entity, address }) if entity == PAYMASTER && address == paymaster
+        ));
+
+        // factory not in the allowlist
+        let check = AccessListCheck::new(HashSet::from([sender, paymaster]), HashSet::new());
+        assert!(matches!(
+            SanityCheck::check_user_operation(&check, &uo, &mut helper).await,
+            Err(SanityCheckError::EntityBanned { entity, address }) if entity == FACTORY && address == factory
+        ));
+
+        let check =
+            AccessListCheck::new(HashSet::from([sender, factory, paymaster]), HashSet::new());
+        assert!(SanityCheck::check_user_operation(&check, &uo, &mut helper)
+            .await
+            .is_ok());
+
+        // denied aggregator
+        let aggregator = Address::random();
+        let mut sim_res =
+            SimulateValidationResult::ValidationResultWithAggregation(Default::default());
+        if let SimulateValidationResult::ValidationResultWithAggregation(res) = &mut sim_res {
+            res.aggregator_info = (aggregator, (U256::from(1), U256::from(86400)));
+        }
+        let mut helper = ctx.simulation_helper(&sim_res);
+        let check = AccessListCheck::new(HashSet::new(), HashSet::from([aggregator]));
+        assert!(matches!(
+            SimulationCheck::check_user_operation(&check, &uo, &mut helper).await,
+            Err(SimulationCheckError::EntityBanned { address, .. }) if address == aggregator
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::{providers::Middleware, utils::id};
+use silius_primitives::{batch::decode_inner_calls, sanity::SanityCheckError, UserOperation};
+
+/// Bundle methods of the entry point, a user operation never has a reason to submit a bundle
+const HANDLE_OPS_FUNCTIONS: [&str; 2] = [
+    "handleOps((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)[],address)",
+    "handleAggregatedOps(((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)[],address,bytes)[],address)",
+];
+
+/// Entry point methods the account may call during the execution (deposit, stake and nonce management)
+const ENTRY_POINT_ALLOWED_FUNCTIONS: [&str; 6] = [
+    "depositTo(address)",
+    "addStake(uint32)",
+    "unlockStake()",
+    "withdrawStake(address)",
+    "withdrawTo(address,uint256)",
+    "incrementNonce(uint192)",
+];
+
+/// Rejects user operations whose call data calls back into the entry point or submits a bundle
+///
+/// Only the inner calls of the common account executions (`execute` and `executeBatch`) are inspected, other
+/// call data is let through. Calls of `handleOps` (of any entry point) are rejected, calls of the entry point are
+/// allowed only for the deposit, stake and nonce management (and plain value transfers, which deposit).
+pub struct CallDataTargets;
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for CallDataTargets {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        let calls = match decode_inner_calls(&uo.call_data) {
+            Some(calls) => calls,
+            None => return Ok(()),
+        };
+        let ep = helper.entry_point.address();
+
+        for call in calls {
+            let selector = call.data.get(..4);
+
+            if selector.map_or(false, |selector| {
+                HANDLE_OPS_FUNCTIONS
+                    .iter()
+                    .any(|f| id(f).as_slice() == selector)
+            }) {
+                return Err(SanityCheckError::SuspiciousCallData {
+                    sender: uo.sender,
+                    target: call.to,
+                    message: "call data submits a bundle (handleOps)".into(),
+                });
+            }
+
+            if call.to == ep
+                && !call.data.is_empty()
+                && !selector.map_or(false, |selector| {
+                    ENTRY_POINT_ALLOWED_FUNCTIONS
+                        .iter()
+                        .any(|f| id(f).as_slice() == selector)
+                })
+            {
+                return Err(SanityCheckError::SuspiciousCallData {
+                    sender: uo.sender,
+                    target: call.to,
+                    message: "call data calls back into the entry point".into(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::{encode, Token},
+        providers::{MockProvider, Provider},
+        types::{Address, Bytes},
+    };
+    use silius_primitives::{
+        batch::{EXECUTE, EXECUTE_BATCH},
+        consts::entry_point::ADDRESS,
+    };
+
+    fn execute(to: Address, data: Vec<u8>) -> Bytes {
+        [
+            id(EXECUTE).to_vec(),
+            encode(&[
+                Token::Address(to),
+                Token::Uint(0.into()),
+                Token::Bytes(data),
+            ]),
+        ]
+        .concat()
+        .into()
+    }
+
+    fn execute_batch(calls: Vec<(Address, Vec<u8>)>) -> Bytes {
+        let (to, data): (Vec<_>, Vec<_>) = calls
+            .into_iter()
+            .map(|(to, data)| (Token::Address(to), Token::Bytes(data)))
+            .unzip();
+        [
+            id(EXECUTE_BATCH).to_vec(),
+            encode(&[Token::Array(to), Token::Array(data)]),
+        ]
+        .concat()
+        .into()
+    }
+
+    async fn check(
+        call_data: Bytes,
+        helper: &mut SanityHelper<'_, Provider<MockProvider>>,
+    ) -> Result<(), SanityCheckError> {
+        let uo = UserOperation::default().call_data(call_data);
+        CallDataTargets.check_user_operation(&uo, helper).await
+    }
+
+    #[tokio::test]
+    async fn call_data_targets() {
+        let ctx = MockContext::default();
+        let mut helper = ctx.sanity_helper();
+        let ep: Address = ADDRESS.parse().unwrap();
+        let handle_ops = [id(HANDLE_OPS_FUNCTIONS[0]).to_vec(), vec![0; 64]].concat();
+
+        // handleOps of any entry point
+        assert!(matches!(
+            check(execute(Address::random(), handle_ops.clone()), &mut helper).await,
+            Err(SanityCheckError::SuspiciousCallData { .. })
+        ));
+        assert!(matches!(
+            check(
+                execute_batch(vec![(Address::random(), vec![]), (ep, handle_ops)]),
+                &mut helper
+            )
+            .await,
+            Err(SanityCheckError::SuspiciousCallData { target, .. }) if target == ep
+        ));
+
+        // other entry point methods
+        let get_nonce = [id("getNonce(address,uint192)").to_vec(), vec![0; 64]].concat();
+        assert!(matches!(
+            check(execute(ep, get_nonce.clone()), &mut helper).await,
+            Err(SanityCheckError::SuspiciousCallData { .. })
+        ));
+
+        // deposit and plain transfer to the entry point, other methods of other contracts
+        let deposit_to = [id("depositTo(address)").to_vec(), vec![0; 32]].concat();
+        assert!(check(execute(ep, deposit_to), &mut helper).await.is_ok());
+        assert!(check(execute(ep, vec![]), &mut helper).await.is_ok());
+        assert!(check(
+            execute_batch(vec![
+                (Address::random(), get_nonce),
+                (Address::random(), vec![])
+            ]),
+            &mut helper
+        )
+        .await
+        .is_ok());
+
+        // call data of other accounts isn't inspected
+        assert!(check(vec![0xfe; 100].into(), &mut helper).await.is_ok());
+    }
+}
@@ -2,11 +2,18 @@ use crate::{
     utils::calculate_call_gas_limit,
     validate::{SanityCheck, SanityHelper},
 };
-use ethers::{providers::Middleware, types::BlockNumber};
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, U256},
+};
 use silius_contracts::entry_point::EntryPointErr;
 use silius_primitives::{sanity::SanityCheckError, UserOperation};
 
-pub struct CallGas;
+/// Executes the user operation via `simulateHandleOp` and checks that `call_gas_limit` covers the gas
+/// used by the execution phase, increased by the safety margin (in percent)
+pub struct CallGas {
+    pub safety_margin_perc: U256,
+}
 
 #[async_trait::async_trait]
 impl<M: Middleware> SanityCheck<M> for CallGas {
@@ -15,13 +22,18 @@ impl<M: Middleware> SanityCheck<M> for CallGas {
         uo: &UserOperation,
         helper: &mut SanityHelper<M>,
     ) -> Result<(), SanityCheckError> {
-        let exec_res = match helper.entry_point.simulate_handle_op(uo.clone()).await {
+        let exec_res = match helper
+            .retry_policy
+            .call(|| helper.entry_point.simulate_handle_op(uo.clone()))
+            .await
+        {
             Ok(res) => res,
             Err(err) => {
                 return Err(match err {
                     EntryPointErr::FailedOp(f) => {
                         SanityCheckError::Validation { message: f.reason }
                     }
+                    EntryPointErr::Timeout(message) => SanityCheckError::Timeout { message },
                     _ => SanityCheckError::UnknownError {
                         message: format!("{err:?}"),
                     },
@@ -48,10 +60,13 @@ impl<M: Middleware> SanityCheck<M> for CallGas {
         let call_gas_limit = calculate_call_gas_limit(
             exec_res.paid,
             exec_res.pre_op_gas,
-            uo.max_fee_per_gas
-                .min(uo.max_priority_fee_per_gas + base_fee_per_gas),
+            uo.effective_gas_price(base_fee_per_gas),
         );
 
+        let call_gas_limit = call_gas_limit
+            .saturating_mul(U256::from(100).saturating_add(self.safety_margin_perc))
+            / 100;
+
         if uo.call_gas_limit >= call_gas_limit {
             return Ok(());
         }
@@ -62,3 +77,94 @@ impl<M: Middleware> SanityCheck<M> for CallGas {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::{encode, Token},
+        providers::{JsonRpcError, MockProvider, MockResponse, Provider},
+        types::{Block, Bytes, H256},
+        utils::id,
+    };
+
+    // execution of the user operation pays 100k gas on top of the pre-op gas (at the gas price of 1)
+    const PRE_OP_GAS: u64 = 50_000;
+    const PAID: u64 = 150_000;
+
+    async fn check(
+        helper: &mut SanityHelper<'_, Provider<MockProvider>>,
+        mock: &MockProvider,
+        safety_margin_perc: U256,
+        call_gas_limit: U256,
+    ) -> Result<(), SanityCheckError> {
+        // responses are popped in reverse: simulateHandleOp reverts with the execution result, latest block
+        mock.push(Block::<H256> {
+            base_fee_per_gas: Some(U256::zero()),
+            ..Default::default()
+        })
+        .unwrap();
+        let execution_result: Bytes = [
+            id("ExecutionResult(uint256,uint256,uint48,uint48,bool,bytes)").to_vec(),
+            encode(&[
+                Token::Uint(PRE_OP_GAS.into()),
+                Token::Uint(PAID.into()),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::zero()),
+                Token::Bool(true),
+                Token::Bytes(vec![]),
+            ]),
+        ]
+        .concat()
+        .into();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: "execution reverted".into(),
+            data: Some(serde_json::to_value(execution_result).unwrap()),
+        }));
+
+        let uo = UserOperation::default()
+            .call_gas_limit(call_gas_limit)
+            .max_fee_per_gas(1.into())
+            .max_priority_fee_per_gas(1.into());
+        CallGas { safety_margin_perc }
+            .check_user_operation(&uo, helper)
+            .await
+    }
+
+    #[tokio::test]
+    async fn call_gas_safety_margin() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let mut helper = ctx.sanity_helper();
+        let call_gas_limit = calculate_call_gas_limit(PAID.into(), PRE_OP_GAS.into(), 1.into());
+
+        assert!(check(&mut helper, mock, U256::zero(), call_gas_limit)
+            .await
+            .is_ok());
+        assert!(matches!(
+            check(&mut helper, mock, U256::zero(), call_gas_limit - 1).await,
+            Err(SanityCheckError::LowCallGasLimit { call_gas_limit_expected, .. })
+                if call_gas_limit_expected == call_gas_limit
+        ));
+
+        // the margin raises the accepted call gas limit
+        let with_margin = call_gas_limit * 110 / 100;
+        assert!(matches!(
+            check(&mut helper, mock, 10.into(), call_gas_limit).await,
+            Err(SanityCheckError::LowCallGasLimit { call_gas_limit_expected, .. })
+                if call_gas_limit_expected == with_margin
+        ));
+        assert!(check(&mut helper, mock, 10.into(), with_margin)
+            .await
+            .is_ok());
+
+        // huge margin saturates instead of overflowing
+        assert!(matches!(
+            check(&mut helper, mock, U256::MAX, call_gas_limit).await,
+            Err(SanityCheckError::LowCallGasLimit { call_gas_limit_expected, .. })
+                if call_gas_limit_expected == U256::MAX / 100
+        ));
+    }
+}
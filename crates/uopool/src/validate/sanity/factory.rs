@@ -0,0 +1,186 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::{providers::Middleware, types::U256};
+use silius_primitives::{
+    consts::entities::FACTORY,
+    eip7702::is_delegation_init_code,
+    get_address,
+    reputation::{ReputationStatus, StakeInfo, THROTTLED_MAX_INCLUDE},
+    sanity::SanityCheckError,
+    UserOperation,
+};
+
+/// Max number of user operations of an unstaked factory in the mempool
+pub const MAX_UOS_PER_UNSTAKED_FACTORY: usize = 1;
+
+/// Verifies the factory of user operations with init code and limits user operations of unstaked factories in the mempool
+pub struct Factory {
+    pub max_uos_per_unstaked_factory: usize,
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for Factory {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        // EIP-7702 marker isn't a factory
+        if uo.init_code.is_empty() || is_delegation_init_code(&uo.init_code) {
+            return Ok(());
+        }
+
+        let factory = match get_address(&uo.init_code) {
+            Some(factory) => factory,
+            None => {
+                return Err(SanityCheckError::FactoryVerification {
+                    init_code: uo.init_code.clone(),
+                })
+            }
+        };
+
+        let code = helper.eth_client.get_code(factory, None).await?;
+        let status = helper.reputation.get_status(&factory);
+        if code.is_empty() || status == ReputationStatus::BANNED {
+            return Err(SanityCheckError::FactoryVerification {
+                init_code: uo.init_code.clone(),
+            });
+        }
+
+        // user operations of the same sender are replaced rather than added
+        let uos = helper
+            .mempool
+            .get_all()
+            .iter()
+            .filter(|uo_pool| {
+                uo_pool.sender != uo.sender && get_address(&uo_pool.init_code) == Some(factory)
+            })
+            .count();
+
+        if status == ReputationStatus::THROTTLED && uos as u64 >= THROTTLED_MAX_INCLUDE {
+            return Err(SanityCheckError::FactoryThrottled { factory });
+        }
+
+        if uos >= self.max_uos_per_unstaked_factory {
+            let info = helper
+                .entry_point
+                .get_deposit_info(&factory)
+                .await
+                .map_err(|_| SanityCheckError::UnknownError {
+                    message: "Couldn't retrieve deposit info from entry point".to_string(),
+                })?;
+
+            // the stake being withdrawn doesn't count
+            let stake = if info.withdraw_time == 0 {
+                U256::from(info.stake)
+            } else {
+                U256::zero()
+            };
+            if helper
+                .reputation
+                .verify_stake(
+                    FACTORY,
+                    Some(StakeInfo {
+                        address: factory,
+                        stake,
+                        unstake_delay: U256::from(info.unstake_delay_sec),
+                    }),
+                )
+                .is_err()
+            {
+                return Err(SanityCheckError::FactoryNotStaked {
+                    factory,
+                    uos_in_mempool: uos,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::AbiEncode,
+        types::{Address, Bytes},
+    };
+    use silius_primitives::{
+        consts::entry_point::ADDRESS,
+        reputation::{
+            BAN_SLACK, HOURLY_DECAY_DENOMINATOR, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+        },
+    };
+
+    fn deposit_info(stake: u64) -> Bytes {
+        (U256::zero(), stake > 0, U256::from(stake), 86400_u32, 0_u64)
+            .encode()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn factory() {
+        let mut ctx = MockContext::default();
+        ctx.reputation.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            HOURLY_DECAY_DENOMINATOR,
+            U256::from(1),
+            U256::from(0),
+        );
+        let check = Factory {
+            max_uos_per_unstaked_factory: 1,
+        };
+
+        let factory = Address::random();
+        let init_code: Bytes = [factory.as_bytes(), &[0xfe; 4]].concat().into();
+        let uo = UserOperation::default()
+            .sender(Address::random())
+            .init_code(init_code.clone());
+
+        {
+            let mut helper = ctx.sanity_helper();
+
+            // no init code
+            assert!(check
+                .check_user_operation(&UserOperation::default(), &mut helper)
+                .await
+                .is_ok());
+
+            // factory not deployed
+            ctx.mock.push::<Bytes, _>(Bytes::default()).unwrap();
+            assert!(matches!(
+                check.check_user_operation(&uo, &mut helper).await,
+                Err(SanityCheckError::FactoryVerification { .. })
+            ));
+
+            ctx.mock.push::<Bytes, _>(vec![0xfe; 100].into()).unwrap();
+            assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+        }
+
+        ctx.mempool
+            .add(uo, &ADDRESS.parse().unwrap(), &U256::from(1337))
+            .unwrap();
+        let uo = UserOperation::default()
+            .sender(Address::random())
+            .init_code(init_code);
+        let mut helper = ctx.sanity_helper();
+
+        // unstaked factory already has a user operation in the mempool
+        ctx.mock.push::<Bytes, _>(deposit_info(0)).unwrap();
+        ctx.mock.push::<Bytes, _>(vec![0xfe; 100].into()).unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::FactoryNotStaked {
+                uos_in_mempool: 1,
+                ..
+            })
+        ));
+
+        ctx.mock.push::<Bytes, _>(deposit_info(1)).unwrap();
+        ctx.mock.push::<Bytes, _>(vec![0xfe; 100].into()).unwrap();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::{providers::Middleware, types::U256};
+use silius_primitives::{sanity::SanityCheckError, UserOperation};
+
+/// Bounds of the priority fee accepted into the mempool
+///
+/// Also enforces `max_priority_fee_per_gas <= max_fee_per_gas`, since the entry point caps the priority
+/// fee by the max fee and the bundler would be silently underpaid.
+pub struct FeeBounds {
+    pub min_priority_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for FeeBounds {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        _helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        if uo.max_priority_fee_per_gas > uo.max_fee_per_gas {
+            return Err(SanityCheckError::HighMaxPriorityFeePerGas {
+                max_priority_fee_per_gas: uo.max_priority_fee_per_gas,
+                max_fee_per_gas: uo.max_fee_per_gas,
+            });
+        }
+
+        if uo.max_priority_fee_per_gas < self.min_priority_fee_per_gas {
+            return Err(SanityCheckError::PriorityFeeTooLow {
+                max_priority_fee_per_gas: uo.max_priority_fee_per_gas,
+                min_priority_fee_per_gas: self.min_priority_fee_per_gas,
+            });
+        }
+
+        if let Some(max_priority_fee_per_gas) = self.max_priority_fee_per_gas {
+            if uo.max_priority_fee_per_gas > max_priority_fee_per_gas {
+                return Err(SanityCheckError::PriorityFeeTooHigh {
+                    max_priority_fee_per_gas: uo.max_priority_fee_per_gas,
+                    max_allowed_priority_fee_per_gas: max_priority_fee_per_gas,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+
+    #[tokio::test]
+    async fn fee_bounds() {
+        let ctx = MockContext::default();
+        let mut helper = ctx.sanity_helper();
+        let check = FeeBounds {
+            min_priority_fee_per_gas: 10.into(),
+            max_priority_fee_per_gas: Some(100.into()),
+        };
+
+        let uo = UserOperation::default()
+            .max_fee_per_gas(200.into())
+            .max_priority_fee_per_gas(50.into());
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        let uo = UserOperation::default()
+            .max_fee_per_gas(40.into())
+            .max_priority_fee_per_gas(50.into());
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::HighMaxPriorityFeePerGas { .. })
+        ));
+
+        let uo = UserOperation::default()
+            .max_fee_per_gas(200.into())
+            .max_priority_fee_per_gas(5.into());
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::PriorityFeeTooLow { .. })
+        ));
+
+        let uo = UserOperation::default()
+            .max_fee_per_gas(200.into())
+            .max_priority_fee_per_gas(150.into());
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::PriorityFeeTooHigh { .. })
+        ));
+
+        // no upper bound
+        let check = FeeBounds {
+            max_priority_fee_per_gas: None,
+            ..check
+        };
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+    }
+}
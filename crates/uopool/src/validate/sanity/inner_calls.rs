@@ -0,0 +1,77 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::providers::Middleware;
+use silius_primitives::{batch::decode_batch, sanity::SanityCheckError, UserOperation};
+
+/// Rejects batch user operations with too many inner calls (they can blow the bundle's gas and trace budget)
+pub struct InnerCalls {
+    pub max_inner_calls: usize,
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for InnerCalls {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        _helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        if let Some(calls) = decode_batch(&uo.call_data) {
+            if calls.len() > self.max_inner_calls {
+                return Err(SanityCheckError::TooManyInnerCalls {
+                    sender: uo.sender,
+                    inner_calls: calls.len(),
+                    max_inner_calls: self.max_inner_calls,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::{encode, Token},
+        types::{Address, Bytes},
+        utils::id,
+    };
+    use silius_primitives::batch::EXECUTE_BATCH;
+
+    fn batch_call_data(n: usize) -> Bytes {
+        [
+            id(EXECUTE_BATCH).to_vec(),
+            encode(&[
+                Token::Array(vec![Token::Address(Address::random()); n]),
+                Token::Array(vec![Token::Bytes(vec![]); n]),
+            ]),
+        ]
+        .concat()
+        .into()
+    }
+
+    #[tokio::test]
+    async fn inner_calls() {
+        let ctx = MockContext::default();
+        let mut helper = ctx.sanity_helper();
+        let check = InnerCalls { max_inner_calls: 3 };
+
+        let uo = UserOperation::default().call_data(batch_call_data(4));
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::TooManyInnerCalls {
+                inner_calls: 4,
+                max_inner_calls: 3,
+                ..
+            })
+        ));
+
+        let uo = UserOperation::default().call_data(batch_call_data(3));
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        // not a batch
+        let uo = UserOperation::default().call_data(vec![0xfe; 100].into());
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+    }
+}
@@ -1,13 +1,8 @@
 use crate::validate::{SanityCheck, SanityHelper};
-use ethers::{
-    providers::Middleware,
-    types::{BlockNumber, U256},
-};
+use ethers::{providers::Middleware, types::BlockNumber};
 use silius_primitives::{sanity::SanityCheckError, UserOperation};
 
-pub struct MaxFee {
-    pub min_priority_fee_per_gas: U256,
-}
+pub struct MaxFee;
 
 #[async_trait::async_trait]
 impl<M: Middleware> SanityCheck<M> for MaxFee {
@@ -16,13 +11,6 @@ impl<M: Middleware> SanityCheck<M> for MaxFee {
         uo: &UserOperation,
         helper: &mut SanityHelper<M>,
     ) -> Result<(), SanityCheckError> {
-        if uo.max_priority_fee_per_gas > uo.max_fee_per_gas {
-            return Err(SanityCheckError::HighMaxPriorityFeePerGas {
-                max_priority_fee_per_gas: uo.max_priority_fee_per_gas,
-                max_fee_per_gas: uo.max_fee_per_gas,
-            });
-        }
-
         let block = helper
             .eth_client
             .get_block(BlockNumber::Latest)
@@ -46,13 +34,6 @@ impl<M: Middleware> SanityCheck<M> for MaxFee {
             });
         }
 
-        if uo.max_priority_fee_per_gas < self.min_priority_fee_per_gas {
-            return Err(SanityCheckError::LowMaxPriorityFeePerGas {
-                max_priority_fee_per_gas: uo.max_priority_fee_per_gas,
-                min_priority_fee_per_gas: self.min_priority_fee_per_gas,
-            });
-        }
-
         Ok(())
     }
 }
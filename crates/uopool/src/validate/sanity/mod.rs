@@ -1,6 +1,17 @@
+pub mod access_list;
+pub mod call_data;
 pub mod call_gas;
+pub mod factory;
+pub mod fee_bounds;
+pub mod inner_calls;
 pub mod max_fee;
+pub mod nonce;
 pub mod paymaster;
+pub mod paymaster_gas;
+pub mod paymaster_signature;
 pub mod sender;
+pub mod sender_code_size;
 pub mod sender_uos;
+pub mod signature_shape;
+pub mod size;
 pub mod verification_gas;
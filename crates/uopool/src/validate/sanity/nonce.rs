@@ -0,0 +1,91 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::providers::Middleware;
+use silius_primitives::{sanity::SanityCheckError, UserOperation};
+
+/// Checks the nonce of the user operation against the next nonce of the sender (`getNonce` of the entry point)
+///
+/// The nonce is split into a 192-bit key and a 64-bit sequence and the sequence is compared with the next
+/// sequence under the same key, so senders using parallel nonce channels aren't rejected. User operations with
+/// already used sequences are rejected, higher sequences are accepted and queued until their predecessors land.
+pub struct NonceCheck;
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for NonceCheck {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        let key = uo.nonce_key();
+        let nonce = helper
+            .entry_point
+            .get_nonce(&uo.sender, key)
+            .await
+            .map_err(|err| SanityCheckError::UnknownError {
+                message: format!("Getting nonce of {:?} failed: {err:?}", uo.sender),
+            })?;
+
+        let expected_sequence = nonce.low_u64();
+        if uo.nonce_sequence() < expected_sequence {
+            return Err(SanityCheckError::InvalidNonce {
+                sender: uo.sender,
+                key,
+                sequence: uo.nonce_sequence(),
+                expected_sequence,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::AbiEncode,
+        types::{Address, Bytes, U256},
+    };
+
+    #[tokio::test]
+    async fn nonce_check() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let mut helper = ctx.sanity_helper();
+        let key = U256::from(5) << 64;
+        let uo = UserOperation::default().sender(Address::random());
+
+        // next sequence under the key is 2
+        for _ in 0..3 {
+            mock.push::<Bytes, _>(Bytes::from((key + U256::from(2)).encode()))
+                .unwrap();
+        }
+
+        // next and queued future sequence
+        for sequence in [2, 4] {
+            assert!(NonceCheck
+                .check_user_operation(&uo.clone().nonce(key + U256::from(sequence)), &mut helper)
+                .await
+                .is_ok());
+        }
+
+        match NonceCheck
+            .check_user_operation(&uo.clone().nonce(key + U256::from(1)), &mut helper)
+            .await
+        {
+            Err(SanityCheckError::InvalidNonce {
+                sender,
+                key: k,
+                sequence,
+                expected_sequence,
+            }) => {
+                assert_eq!(sender, uo.sender);
+                assert_eq!(k, U256::from(5));
+                assert_eq!(sequence, 1);
+                assert_eq!(expected_sequence, 2);
+            }
+            res => panic!("unexpected result: {res:?}"),
+        }
+    }
+}
@@ -1,10 +1,18 @@
-use crate::validate::{SanityCheck, SanityHelper};
+use crate::validate::{utils::verify_stake, SanityCheck, SanityHelper};
 use ethers::{providers::Middleware, types::U256};
 use silius_primitives::{
-    get_address, reputation::ReputationStatus, sanity::SanityCheckError, UserOperation,
+    consts::entities::PAYMASTER,
+    get_address,
+    reputation::{ReputationStatus, StakeInfo},
+    sanity::SanityCheckError,
+    UserOperation,
 };
 
-pub struct Paymaster;
+/// Verifies that the paymaster is deployed, not banned, staked and has enough deposit to pay for the user operation
+pub struct Paymaster {
+    pub min_stake: U256,
+    pub min_unstake_delay: U256,
+}
 
 #[async_trait::async_trait]
 impl<M: Middleware> SanityCheck<M> for Paymaster {
@@ -13,34 +21,129 @@ impl<M: Middleware> SanityCheck<M> for Paymaster {
         uo: &UserOperation,
         helper: &mut SanityHelper<M>,
     ) -> Result<(), SanityCheckError> {
-        if !uo.paymaster_and_data.is_empty() {
-            if let Some(addr) = get_address(&uo.paymaster_and_data) {
-                let code = helper.eth_client.get_code(addr, None).await?;
-
-                if !code.is_empty() {
-                    let deposit_info =
-                        helper
-                            .entry_point
-                            .get_deposit_info(&addr)
-                            .await
-                            .map_err(|_| SanityCheckError::UnknownError {
-                                message: "Couldn't retrieve deposit info from entry point"
-                                    .to_string(),
-                            })?;
-
-                    if U256::from(deposit_info.deposit) >= uo.max_fee_per_gas
-                        && helper.reputation.get_status(&addr) != ReputationStatus::BANNED
-                    {
-                        return Ok(());
-                    }
-                }
+        if uo.paymaster_and_data.is_empty() {
+            return Ok(());
+        }
+
+        let addr = match get_address(&uo.paymaster_and_data) {
+            Some(addr) => addr,
+            None => {
+                return Err(SanityCheckError::PaymasterVerification {
+                    paymaster_and_data: uo.paymaster_and_data.clone(),
+                })
             }
+        };
 
+        let code = helper.eth_client.get_code(addr, None).await?;
+        if code.is_empty() || helper.reputation.get_status(&addr) == ReputationStatus::BANNED {
             return Err(SanityCheckError::PaymasterVerification {
                 paymaster_and_data: uo.paymaster_and_data.clone(),
             });
         }
 
+        let deposit_info = helper
+            .entry_point
+            .get_deposit_info(&addr)
+            .await
+            .map_err(|_| SanityCheckError::UnknownError {
+                message: "Couldn't retrieve deposit info from entry point".to_string(),
+            })?;
+
+        verify_stake(
+            PAYMASTER,
+            StakeInfo {
+                address: addr,
+                stake: U256::from(deposit_info.stake),
+                unstake_delay: U256::from(deposit_info.unstake_delay_sec),
+            },
+            deposit_info.withdraw_time,
+            self.min_stake,
+            self.min_unstake_delay,
+        )?;
+
+        let required_deposit = uo.required_prefund();
+        let deposit = U256::from(deposit_info.deposit);
+        if deposit < required_deposit {
+            return Err(SanityCheckError::PaymasterDepositTooLow {
+                paymaster: addr,
+                deposit,
+                required_deposit,
+            });
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::AbiEncode,
+        types::{Address, Bytes},
+    };
+
+    fn deposit_info(deposit: u64, stake: u64) -> Bytes {
+        (
+            U256::from(deposit),
+            true,
+            U256::from(stake),
+            86400_u32,
+            0_u64,
+        )
+            .encode()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn paymaster() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let mut helper = ctx.sanity_helper();
+        let check = Paymaster {
+            min_stake: 100.into(),
+            min_unstake_delay: 86400.into(),
+        };
+
+        // no paymaster
+        let uo = UserOperation::default();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        // total gas is 100 + 100 * 3 + 100 = 500
+        let uo = UserOperation::default()
+            .call_gas_limit(100.into())
+            .verification_gas_limit(100.into())
+            .pre_verification_gas(100.into())
+            .max_fee_per_gas(10.into())
+            .paymaster_and_data(Address::random().as_bytes().to_vec().into());
+
+        mock.push::<Bytes, _>(deposit_info(5_000, 50)).unwrap();
+        mock.push::<Bytes, _>(vec![0xfe; 100].into()).unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::StakeTooLow { .. })
+        ));
+
+        mock.push::<Bytes, _>(deposit_info(4_999, 100)).unwrap();
+        mock.push::<Bytes, _>(vec![0xfe; 100].into()).unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::PaymasterDepositTooLow {
+                required_deposit,
+                ..
+            }) if required_deposit == U256::from(5_000)
+        ));
+
+        mock.push::<Bytes, _>(deposit_info(5_000, 100)).unwrap();
+        mock.push::<Bytes, _>(vec![0xfe; 100].into()).unwrap();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        // paymaster not deployed
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::PaymasterVerification { .. })
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::{providers::Middleware, types::U256};
+use silius_primitives::{
+    sanity::SanityCheckError, PackedUserOperation, PackedUserOperationError, PaymasterAndData,
+    UserOperation,
+};
+
+/// Default max paymaster verification gas limit of the packed user operation
+pub const DEFAULT_MAX_PAYMASTER_VERIFICATION_GAS: u64 = 5_000_000;
+/// Default max paymaster post-op gas limit of the packed user operation
+pub const DEFAULT_MAX_PAYMASTER_POST_OP_GAS: u64 = 5_000_000;
+
+/// Checks the structured paymaster and data of the packed user operation (entry point v0.7)
+///
+/// The paymaster and data has to hold the paymaster address and both 16-byte gas limits, which have to be non-zero
+/// and within the maxima. The check takes the packed user operation, since the gas limits are dropped when it's
+/// converted into the v0.6 user operation.
+///
+/// As a sanity check, the v0.6 user operation is checked in its packed form, where both paymaster gas limits are
+/// the verification gas limit (the paymaster's validation and post-op run with it on entry point v0.6).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PaymasterGasLimits {
+    pub max_verification_gas: U256,
+    pub max_post_op_gas: U256,
+}
+
+impl Default for PaymasterGasLimits {
+    fn default() -> Self {
+        Self {
+            max_verification_gas: DEFAULT_MAX_PAYMASTER_VERIFICATION_GAS.into(),
+            max_post_op_gas: DEFAULT_MAX_PAYMASTER_POST_OP_GAS.into(),
+        }
+    }
+}
+
+impl PaymasterGasLimits {
+    pub fn check_packed_user_operation(
+        &self,
+        uo: &PackedUserOperation,
+    ) -> Result<(), SanityCheckError> {
+        let paymaster_and_data = match PaymasterAndData::parse(&uo.paymaster_and_data) {
+            Ok(Some(p)) => p,
+            Ok(None) => return Ok(()),
+            Err(PackedUserOperationError::MalformedPaymasterAndData { paymaster_and_data }) => {
+                return Err(SanityCheckError::MalformedPaymasterData {
+                    message: format!(
+                        "{} bytes is shorter than the paymaster address and gas limits",
+                        paymaster_and_data.len()
+                    ),
+                    paymaster_and_data,
+                })
+            }
+        };
+
+        for (field, gas_limit, max_gas_limit) in [
+            (
+                "verification gas limit",
+                paymaster_and_data.paymaster_verification_gas_limit,
+                self.max_verification_gas,
+            ),
+            (
+                "post-op gas limit",
+                paymaster_and_data.paymaster_post_op_gas_limit,
+                self.max_post_op_gas,
+            ),
+        ] {
+            if gas_limit.is_zero() || gas_limit > max_gas_limit {
+                return Err(SanityCheckError::InvalidPaymasterGasLimit {
+                    field: field.into(),
+                    gas_limit,
+                    max_gas_limit,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for PaymasterGasLimits {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        _helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        self.check_packed_user_operation(&PackedUserOperation::from(uo.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::{
+        validate_user_operation, validator::StandardUserOperationValidator,
+        UserOperationValidatorMode,
+    };
+    use ethers::{providers::Provider, types::Address};
+    use silius_contracts::EntryPoint;
+    use silius_primitives::{
+        consts::entry_point::ADDRESS, uopool::ValidationError, Chain, UserOperation,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn paymaster_gas_limits() {
+        let check = PaymasterGasLimits::default();
+        let uo = |paymaster_and_data: Vec<u8>| PackedUserOperation {
+            paymaster_and_data: paymaster_and_data.into(),
+            ..Default::default()
+        };
+        let paymaster_and_data = |verification_gas: u128, post_op_gas: u128| {
+            [
+                Address::random().as_bytes(),
+                &verification_gas.to_be_bytes(),
+                &post_op_gas.to_be_bytes(),
+                &[0xab; 4],
+            ]
+            .concat()
+        };
+
+        // no paymaster
+        assert!(check
+            .check_packed_user_operation(&PackedUserOperation::from(UserOperation::default()))
+            .is_ok());
+        assert!(check
+            .check_packed_user_operation(&uo(paymaster_and_data(100_000, 50_000)))
+            .is_ok());
+
+        assert!(matches!(
+            check.check_packed_user_operation(&uo(Address::random().as_bytes().to_vec())),
+            Err(SanityCheckError::MalformedPaymasterData { paymaster_and_data, .. })
+                if paymaster_and_data.len() == 20
+        ));
+        assert!(matches!(
+            check.check_packed_user_operation(&uo(paymaster_and_data(100_000, 0))),
+            Err(SanityCheckError::InvalidPaymasterGasLimit { gas_limit, .. }) if gas_limit.is_zero()
+        ));
+        assert!(matches!(
+            check.check_packed_user_operation(&uo(paymaster_and_data(5_000_001, 50_000))),
+            Err(SanityCheckError::InvalidPaymasterGasLimit { max_gas_limit, .. })
+                if max_gas_limit == U256::from(DEFAULT_MAX_PAYMASTER_VERIFICATION_GAS)
+        ));
+    }
+
+    #[tokio::test]
+    async fn paymaster_gas_limits_validation() {
+        let (eth_client, _) = Provider::mocked();
+        let eth_client = Arc::new(eth_client);
+        let validator = StandardUserOperationValidator::new(
+            eth_client.clone(),
+            EntryPoint::new(eth_client.clone(), ADDRESS.parse().unwrap()),
+            Chain::from(1337_u64),
+        )
+        .with_sanity_check(PaymasterGasLimits::default());
+        let validate = |uo: UserOperation| {
+            let validator = &validator;
+            async move {
+                validate_user_operation(
+                    validator,
+                    &uo,
+                    U256::zero(),
+                    U256::zero(),
+                    UserOperationValidatorMode::Sanity.into(),
+                )
+                .await
+            }
+        };
+        let uo = UserOperation::default()
+            .sender(Address::random())
+            .paymaster_and_data(Address::random().as_bytes().to_vec().into());
+
+        assert!(matches!(
+            validate(uo.clone().paymaster_and_data(vec![0xab; 19].into())).await,
+            Err(ValidationError::Sanity(
+                SanityCheckError::MalformedPaymasterData { .. }
+            ))
+        ));
+        assert!(matches!(
+            validate(uo.clone().verification_gas_limit(5_000_001.into())).await,
+            Err(ValidationError::Sanity(SanityCheckError::InvalidPaymasterGasLimit { gas_limit, .. }))
+                if gas_limit == 5_000_001.into()
+        ));
+
+        // passes the sanity check, the simulation isn't answered by the mock
+        assert!(matches!(
+            validate(uo.verification_gas_limit(100_000.into())).await,
+            Err(ValidationError::Simulation(_))
+        ));
+    }
+}
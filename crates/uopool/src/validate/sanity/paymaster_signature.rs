@@ -0,0 +1,162 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::{
+    abi::{self, Token},
+    providers::Middleware,
+    types::{Address, Signature, H256, U256},
+    utils::keccak256,
+};
+use silius_primitives::{get_address, sanity::SanityCheckError, UserOperation};
+use std::ops::Deref;
+
+/// Offset of the sponsorship signature in the paymaster and data (paymaster address, validUntil and validAfter)
+const SIGNATURE_OFFSET: usize = 20 + 32 + 32;
+
+/// Verifies the sponsorship signature of the operator's own verifying paymaster
+///
+/// The paymaster and data is expected in the layout of the verifying paymaster:
+/// `paymaster | abi.encode(validUntil, validAfter) | signature`.
+pub struct PaymasterSignature {
+    pub paymaster: Address,
+    pub signer: Address,
+}
+
+impl PaymasterSignature {
+    /// Hash of the sponsorship data that is signed by the paymaster signer (same as `getHash` of the paymaster)
+    pub fn get_hash(
+        &self,
+        uo: &UserOperation,
+        chain_id: u64,
+        valid_until: U256,
+        valid_after: U256,
+    ) -> H256 {
+        keccak256(abi::encode(&[
+            Token::Address(uo.sender),
+            Token::Uint(uo.nonce),
+            Token::FixedBytes(keccak256(uo.init_code.deref()).to_vec()),
+            Token::FixedBytes(keccak256(uo.call_data.deref()).to_vec()),
+            Token::Uint(uo.call_gas_limit),
+            Token::Uint(uo.verification_gas_limit),
+            Token::Uint(uo.pre_verification_gas),
+            Token::Uint(uo.max_fee_per_gas),
+            Token::Uint(uo.max_priority_fee_per_gas),
+            Token::Uint(chain_id.into()),
+            Token::Address(self.paymaster),
+            Token::Uint(valid_until),
+            Token::Uint(valid_after),
+        ]))
+        .into()
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for PaymasterSignature {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        if get_address(&uo.paymaster_and_data) != Some(self.paymaster) {
+            return Ok(());
+        }
+
+        let err = SanityCheckError::InvalidPaymasterSignature {
+            paymaster: self.paymaster,
+            signer: self.signer,
+        };
+
+        if uo.paymaster_and_data.len() <= SIGNATURE_OFFSET {
+            return Err(err);
+        }
+
+        let valid_until = U256::from_big_endian(&uo.paymaster_and_data[20..52]);
+        let valid_after = U256::from_big_endian(&uo.paymaster_and_data[52..SIGNATURE_OFFSET]);
+        let hash = self.get_hash(uo, helper.chain.id(), valid_until, valid_after);
+
+        match Signature::try_from(&uo.paymaster_and_data[SIGNATURE_OFFSET..]) {
+            // the paymaster uses the signed message hash (EIP-191)
+            Ok(sig) if sig.recover(hash.as_bytes()).ok() == Some(self.signer) => Ok(()),
+            _ => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+        types::Bytes,
+    };
+
+    async fn sponsor(
+        check: &PaymasterSignature,
+        uo: UserOperation,
+        wallet: &LocalWallet,
+    ) -> UserOperation {
+        let (valid_until, valid_after) = (U256::from(u64::MAX), U256::zero());
+        let hash = check.get_hash(&uo, 1337, valid_until, valid_after);
+        let sig = wallet.sign_message(hash.as_bytes()).await.unwrap();
+
+        let paymaster_and_data: Bytes = [
+            check.paymaster.as_bytes().to_vec(),
+            abi::encode(&[Token::Uint(valid_until), Token::Uint(valid_after)]),
+            sig.to_vec(),
+        ]
+        .concat()
+        .into();
+        uo.paymaster_and_data(paymaster_and_data)
+    }
+
+    #[tokio::test]
+    async fn paymaster_signature() {
+        let ctx = MockContext::default();
+        let mut helper = ctx.sanity_helper();
+
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let check = PaymasterSignature {
+            paymaster: Address::random(),
+            signer: wallet.address(),
+        };
+        let uo = UserOperation::default()
+            .sender(Address::random())
+            .call_gas_limit(100_000.into());
+
+        let uo_signed = sponsor(&check, uo.clone(), &wallet).await;
+        assert!(check
+            .check_user_operation(&uo_signed, &mut helper)
+            .await
+            .is_ok());
+
+        // signed by a different key
+        let uo_forged = sponsor(&check, uo.clone(), &LocalWallet::new(&mut thread_rng())).await;
+        assert!(matches!(
+            check.check_user_operation(&uo_forged, &mut helper).await,
+            Err(SanityCheckError::InvalidPaymasterSignature { .. })
+        ));
+
+        // signature over a different user operation
+        let uo_changed = uo_signed.clone().call_gas_limit(200_000.into());
+        assert!(matches!(
+            check.check_user_operation(&uo_changed, &mut helper).await,
+            Err(SanityCheckError::InvalidPaymasterSignature { .. })
+        ));
+
+        // missing signature
+        let uo_unsigned = uo
+            .clone()
+            .paymaster_and_data(check.paymaster.as_bytes().to_vec().into());
+        assert!(matches!(
+            check.check_user_operation(&uo_unsigned, &mut helper).await,
+            Err(SanityCheckError::InvalidPaymasterSignature { .. })
+        ));
+
+        // other paymasters are not checked
+        let uo_other = uo.paymaster_and_data(Address::random().as_bytes().to_vec().into());
+        assert!(check
+            .check_user_operation(&uo_other, &mut helper)
+            .await
+            .is_ok());
+    }
+}
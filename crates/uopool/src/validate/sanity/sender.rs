@@ -1,7 +1,15 @@
 use crate::validate::{SanityCheck, SanityHelper};
 use ethers::providers::Middleware;
-use silius_primitives::{sanity::SanityCheckError, UserOperation};
+use silius_primitives::{
+    eip7702::{delegate_address, is_delegation_init_code},
+    sanity::SanityCheckError,
+    UserOperation,
+};
 
+/// Requires either the deployed sender or the init code (but not both)
+///
+/// EIP-7702 delegated sender (the delegation designator as its code) counts as deployed. Its init code may be the
+/// EIP-7702 marker, authorization lists aren't included in the bundles, so the delegation has to be already set.
 pub struct SenderOrInitCode;
 
 #[async_trait::async_trait]
@@ -12,6 +20,13 @@ impl<M: Middleware> SanityCheck<M> for SenderOrInitCode {
         helper: &mut SanityHelper<M>,
     ) -> Result<(), SanityCheckError> {
         let code = helper.eth_client.get_code(uo.sender, None).await?;
+        if is_delegation_init_code(&uo.init_code) {
+            if delegate_address(&code).is_none() {
+                return Err(SanityCheckError::SenderNotDelegated { sender: uo.sender });
+            }
+            return Ok(());
+        }
+
         if (code.is_empty() && uo.init_code.is_empty())
             || (!code.is_empty() && !uo.init_code.is_empty())
         {
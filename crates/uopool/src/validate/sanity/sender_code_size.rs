@@ -0,0 +1,69 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::providers::Middleware;
+use silius_primitives::{eip7702::delegate_address, sanity::SanityCheckError, UserOperation};
+
+/// Rejects user operations of deployed senders with suspiciously small code (e.g., broken proxies)
+pub struct SenderCodeSize {
+    pub min_code_size: usize,
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for SenderCodeSize {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        // counterfactual sender (not yet deployed)
+        if !uo.init_code.is_empty() {
+            return Ok(());
+        }
+
+        let code = helper.eth_client.get_code(uo.sender, None).await?;
+        // code of the EIP-7702 delegated sender is the delegation designator
+        if !code.is_empty() && code.len() < self.min_code_size && delegate_address(&code).is_none()
+        {
+            return Err(SanityCheckError::SenderCodeTooSmall {
+                sender: uo.sender,
+                code_size: code.len(),
+                min_code_size: self.min_code_size,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::types::{Address, Bytes};
+
+    #[tokio::test]
+    async fn sender_code_size() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let mut helper = ctx.sanity_helper();
+        let check = SenderCodeSize { min_code_size: 50 };
+        let uo = UserOperation::default().sender(Address::random());
+
+        // minimal proxy pointing nowhere
+        mock.push::<Bytes, _>(Bytes::from(vec![0xfe; 10])).unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SanityCheckError::SenderCodeTooSmall {
+                code_size: 10,
+                min_code_size: 50,
+                ..
+            })
+        ));
+
+        mock.push::<Bytes, _>(Bytes::from(vec![0xfe; 500])).unwrap();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        // counterfactual sender doesn't query the code
+        let uo = uo.init_code(vec![0xfe; 20].into());
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+    }
+}
@@ -7,6 +7,11 @@ use silius_primitives::{
     consts::entities::ACCOUNT, reputation::StakeInfo, sanity::SanityCheckError, UserOperation,
 };
 
+/// Default max number of user operations of an unstaked sender in the mempool
+pub const DEFAULT_MAX_UOS_PER_UNSTAKED_SENDER: usize = 4;
+
+/// Verifies replacements of user operations of the sender and limits the number of user operations of
+/// unstaked senders in the mempool
 pub struct SenderUos {
     pub max_uos_per_unstaked_sender: usize,
     pub gas_increase_perc: U256,
@@ -49,9 +54,9 @@ impl<M: Middleware> SanityCheck<M> for SenderUos {
                 }
             }
             None => {
-                if helper.mempool.get_number_by_sender(&uo.sender)
-                    >= self.max_uos_per_unstaked_sender
-                {
+                // user operations removed from the mempool (included, expired or replaced) aren't counted
+                let uos = helper.mempool.get_number_by_sender(&uo.sender);
+                if uos >= self.max_uos_per_unstaked_sender {
                     let info = helper
                         .entry_point
                         .get_deposit_info(&uo.sender)
@@ -69,9 +74,10 @@ impl<M: Middleware> SanityCheck<M> for SenderUos {
                     ) {
                         Ok(_) => {}
                         Err(_) => {
-                            return Err(SanityCheckError::SenderVerification {
+                            return Err(SanityCheckError::SenderOverLimit {
                                 sender: uo.sender,
-                                message: "has too many user operations in the mempool".into(),
+                                uos_in_mempool: uos,
+                                max_uos: self.max_uos_per_unstaked_sender,
                             });
                         }
                     }
@@ -82,3 +88,92 @@ impl<M: Middleware> SanityCheck<M> for SenderUos {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::AbiEncode,
+        types::{Address, Bytes},
+    };
+    use silius_primitives::{
+        consts::entry_point::ADDRESS,
+        reputation::{
+            BAN_SLACK, HOURLY_DECAY_DENOMINATOR, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+        },
+    };
+
+    fn deposit_info(stake: u64) -> Bytes {
+        (U256::zero(), stake > 0, U256::from(stake), 86400_u32, 0_u64)
+            .encode()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn sender_over_limit() {
+        let mut ctx = MockContext::default();
+        ctx.reputation.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            HOURLY_DECAY_DENOMINATOR,
+            U256::from(1),
+            U256::from(0),
+        );
+        let check = SenderUos {
+            max_uos_per_unstaked_sender: 2,
+            gas_increase_perc: U256::from(10),
+        };
+
+        let sender = Address::random();
+        let mut uo_hashes = vec![];
+        for nonce in 0..2 {
+            uo_hashes.push(
+                ctx.mempool
+                    .add(
+                        UserOperation::default().sender(sender).nonce(nonce.into()),
+                        &ADDRESS.parse().unwrap(),
+                        &U256::from(1337),
+                    )
+                    .unwrap(),
+            );
+        }
+        let uo = UserOperation::default().sender(sender).nonce(2.into());
+
+        {
+            let mut helper = ctx.sanity_helper();
+
+            // unstaked sender
+            ctx.mock.push::<Bytes, _>(deposit_info(0)).unwrap();
+            assert!(matches!(
+                check.check_user_operation(&uo, &mut helper).await,
+                Err(SanityCheckError::SenderOverLimit {
+                    uos_in_mempool: 2,
+                    max_uos: 2,
+                    ..
+                })
+            ));
+
+            // staked sender is exempt
+            ctx.mock.push::<Bytes, _>(deposit_info(1)).unwrap();
+            assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+            // replacement doesn't count as a new user operation
+            let uo_replacement = UserOperation::default()
+                .sender(sender)
+                .nonce(1.into())
+                .max_fee_per_gas(1.into())
+                .max_priority_fee_per_gas(1.into());
+            assert!(check
+                .check_user_operation(&uo_replacement, &mut helper)
+                .await
+                .is_ok());
+        }
+
+        // user operation removed from the mempool (e.g., included or expired)
+        ctx.mempool.remove(&uo_hashes[0]).unwrap();
+        let mut helper = ctx.sanity_helper();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256},
+    utils::keccak256,
+};
+use silius_primitives::{
+    get_address,
+    sanity::{SanityCheckError, SignatureShape},
+    UserOperation,
+};
+use std::collections::HashMap;
+
+/// Rejects user operations whose signature doesn't have the shape expected by the account type
+///
+/// The account type is identified by the factory of the counterfactual sender, or by the code hash of the
+/// deployed sender (the code is only fetched if any code hash is configured). Senders of other types aren't
+/// checked, so the check is cheap enough to run before the simulation.
+#[derive(Clone, Debug, Default)]
+pub struct SignatureShapeCheck {
+    pub factories: HashMap<Address, SignatureShape>,
+    pub code_hashes: HashMap<H256, SignatureShape>,
+}
+
+impl SignatureShapeCheck {
+    pub fn is_empty(&self) -> bool {
+        self.factories.is_empty() && self.code_hashes.is_empty()
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for SignatureShapeCheck {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        let shape = match get_address(&uo.init_code) {
+            Some(factory) => self.factories.get(&factory),
+            None if !self.code_hashes.is_empty() => {
+                let code = helper.eth_client.get_code(uo.sender, None).await?;
+                self.code_hashes.get(&H256::from(keccak256(&code)))
+            }
+            None => None,
+        };
+
+        match shape.map(|shape| shape.check(&uo.signature)) {
+            Some(Err(message)) => Err(SanityCheckError::InvalidSignatureFormat {
+                sender: uo.sender,
+                message,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::types::Bytes;
+
+    #[tokio::test]
+    async fn signature_shape() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let mut helper = ctx.sanity_helper();
+        let factory = Address::random();
+        let code = Bytes::from(vec![0xfe; 45]);
+        let check = SignatureShapeCheck {
+            factories: HashMap::from([(factory, SignatureShape::Ecdsa { prefix: 0 })]),
+            code_hashes: HashMap::from([(
+                H256::from(keccak256(&code)),
+                SignatureShape::Length(96),
+            )]),
+        };
+        let counterfactual = UserOperation::default()
+            .init_code([factory.as_bytes(), &[1; 4]].concat().into())
+            .signature(vec![1; 64].into());
+
+        // counterfactual sender of the factory
+        assert!(matches!(
+            check
+                .check_user_operation(&counterfactual, &mut helper)
+                .await,
+            Err(SanityCheckError::InvalidSignatureFormat { .. })
+        ));
+        assert!(check
+            .check_user_operation(
+                &counterfactual
+                    .clone()
+                    .signature([vec![1; 64], vec![27]].concat().into()),
+                &mut helper
+            )
+            .await
+            .is_ok());
+        // other factories aren't checked
+        assert!(check
+            .check_user_operation(
+                &counterfactual.init_code(Address::random().as_bytes().to_vec().into()),
+                &mut helper
+            )
+            .await
+            .is_ok());
+
+        // deployed sender of the code hash
+        mock.push::<Bytes, _>(code.clone()).unwrap();
+        assert!(matches!(
+            check
+                .check_user_operation(
+                    &UserOperation::default().signature(vec![1; 65].into()),
+                    &mut helper
+                )
+                .await,
+            Err(SanityCheckError::InvalidSignatureFormat { .. })
+        ));
+        mock.push::<Bytes, _>(code).unwrap();
+        assert!(check
+            .check_user_operation(
+                &UserOperation::default().signature(vec![1; 96].into()),
+                &mut helper
+            )
+            .await
+            .is_ok());
+
+        // other deployed senders aren't checked
+        mock.push::<Bytes, _>(vec![0xfe; 100].into()).unwrap();
+        assert!(check
+            .check_user_operation(
+                &UserOperation::default().signature(vec![1; 65].into()),
+                &mut helper
+            )
+            .await
+            .is_ok());
+    }
+}
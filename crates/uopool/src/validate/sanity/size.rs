@@ -0,0 +1,127 @@
+use crate::validate::{SanityCheck, SanityHelper};
+use ethers::providers::Middleware;
+use silius_primitives::{sanity::SanityCheckError, UserOperation};
+
+/// Default max size of the `init_code` in bytes (covers factories deploying the full account bytecode)
+pub const DEFAULT_MAX_INIT_CODE_SIZE: usize = 64 * 1024;
+/// Default max size of the `call_data` in bytes
+pub const DEFAULT_MAX_CALL_DATA_SIZE: usize = 64 * 1024;
+/// Default max size of the `paymaster_and_data` in bytes
+pub const DEFAULT_MAX_PAYMASTER_AND_DATA_SIZE: usize = 16 * 1024;
+/// Default max size of the packed user operation in bytes
+pub const DEFAULT_MAX_USER_OPERATION_SIZE: usize = 128 * 1024;
+
+/// Rejects user operations with oversized dynamic fields
+///
+/// Large payloads inflate the calldata cost of the bundle and can be used to grief the bundler.
+pub struct SizeCheck {
+    pub max_init_code_size: usize,
+    pub max_call_data_size: usize,
+    pub max_paymaster_and_data_size: usize,
+    pub max_user_operation_size: usize,
+}
+
+impl Default for SizeCheck {
+    fn default() -> Self {
+        Self {
+            max_init_code_size: DEFAULT_MAX_INIT_CODE_SIZE,
+            max_call_data_size: DEFAULT_MAX_CALL_DATA_SIZE,
+            max_paymaster_and_data_size: DEFAULT_MAX_PAYMASTER_AND_DATA_SIZE,
+            max_user_operation_size: DEFAULT_MAX_USER_OPERATION_SIZE,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SanityCheck<M> for SizeCheck {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        _helper: &mut SanityHelper<M>,
+    ) -> Result<(), SanityCheckError> {
+        for (field, size, max) in [
+            ("init_code", uo.init_code.len(), self.max_init_code_size),
+            ("call_data", uo.call_data.len(), self.max_call_data_size),
+            (
+                "paymaster_and_data",
+                uo.paymaster_and_data.len(),
+                self.max_paymaster_and_data_size,
+            ),
+        ] {
+            if size > max {
+                return Err(SanityCheckError::OpSizeTooLarge {
+                    field: field.into(),
+                    size,
+                    max,
+                });
+            }
+        }
+
+        let size = uo.pack().len();
+        if size > self.max_user_operation_size {
+            return Err(SanityCheckError::OpSizeTooLarge {
+                field: "user_operation".into(),
+                size,
+                max: self.max_user_operation_size,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::types::Bytes;
+
+    #[tokio::test]
+    async fn size_check() {
+        let ctx = MockContext::default();
+        let mut helper = ctx.sanity_helper();
+        let check = SizeCheck {
+            max_init_code_size: 100,
+            max_call_data_size: 200,
+            max_paymaster_and_data_size: 50,
+            max_user_operation_size: 1_000,
+        };
+        let bytes = |len: usize| Bytes::from(vec![0xff; len]);
+
+        let uo = UserOperation::default()
+            .init_code(bytes(100))
+            .call_data(bytes(200))
+            .paymaster_and_data(bytes(50));
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        for (uo, field, size, max) in [
+            (uo.clone().init_code(bytes(101)), "init_code", 101, 100),
+            (uo.clone().call_data(bytes(201)), "call_data", 201, 200),
+            (
+                uo.clone().paymaster_and_data(bytes(51)),
+                "paymaster_and_data",
+                51,
+                50,
+            ),
+            (
+                uo.clone().signature(bytes(600)),
+                "user_operation",
+                uo.clone().signature(bytes(600)).pack().len(),
+                1_000,
+            ),
+        ] {
+            match check.check_user_operation(&uo, &mut helper).await {
+                Err(SanityCheckError::OpSizeTooLarge {
+                    field: f,
+                    size: s,
+                    max: m,
+                }) => {
+                    assert_eq!(f, field);
+                    assert_eq!(s, size);
+                    assert_eq!(m, max);
+                }
+                res => panic!("unexpected result: {res:?}"),
+            }
+        }
+    }
+}
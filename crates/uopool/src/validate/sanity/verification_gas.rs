@@ -7,6 +7,7 @@ use silius_primitives::{sanity::SanityCheckError, UserOperation};
 
 pub struct VerificationGas {
     pub max_verification_gas: U256,
+    pub overhead: Overhead,
 }
 
 #[async_trait::async_trait]
@@ -14,7 +15,7 @@ impl<M: Middleware> SanityCheck<M> for VerificationGas {
     async fn check_user_operation(
         &self,
         uo: &UserOperation,
-        _helper: &mut SanityHelper<M>,
+        helper: &mut SanityHelper<M>,
     ) -> Result<(), SanityCheckError> {
         if uo.verification_gas_limit > self.max_verification_gas {
             return Err(SanityCheckError::HighVerificationGasLimit {
@@ -23,7 +24,13 @@ impl<M: Middleware> SanityCheck<M> for VerificationGas {
             });
         }
 
-        let pre_gas = Overhead::default().calculate_pre_verification_gas(uo);
+        let pre_gas = self
+            .overhead
+            .estimate_pre_verification_gas(uo, &helper.entry_point.address())
+            .await
+            .map_err(|err| SanityCheckError::UnknownError {
+                message: err.to_string(),
+            })?;
         if uo.pre_verification_gas < pre_gas {
             return Err(SanityCheckError::LowPreVerificationGas {
                 pre_verification_gas: uo.pre_verification_gas,
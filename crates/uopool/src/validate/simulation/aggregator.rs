@@ -0,0 +1,140 @@
+use crate::validate::{utils::extract_aggregator, SimulationCheck, SimulationHelper};
+use ethers::providers::Middleware;
+use silius_contracts::Aggregator as AggregatorContract;
+use silius_primitives::{
+    consts::entities::AGGREGATOR, reputation::ReputationStatus, simulation::SimulationCheckError,
+    UserOperation,
+};
+
+/// Verifies the aggregator of user operations with aggregated signatures
+///
+/// The aggregator must be deployed, staked and not banned, and must accept the signature of the user
+/// operation (`validateUserOpSignature`).
+pub struct Aggregator;
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationCheck<M> for Aggregator {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SimulationHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        let info = match extract_aggregator(helper.simulate_validation_result) {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        let aggregator = info.address;
+
+        if helper.reputation.get_status(&aggregator) == ReputationStatus::BANNED {
+            return Err(SimulationCheckError::Aggregator {
+                aggregator,
+                message: "is banned".into(),
+            });
+        }
+
+        if helper
+            .eth_client
+            .get_code(aggregator, None)
+            .await?
+            .is_empty()
+        {
+            return Err(SimulationCheckError::Aggregator {
+                aggregator,
+                message: "is unknown".into(),
+            });
+        }
+
+        if let Err(err) = helper.reputation.verify_stake(AGGREGATOR, Some(info)) {
+            return Err(SimulationCheckError::Aggregator {
+                aggregator,
+                message: format!("is not staked: {err:?}"),
+            });
+        }
+
+        AggregatorContract::new(helper.eth_client.clone(), aggregator)
+            .validate_user_op_signature(uo.clone())
+            .await
+            .map_err(|err| SimulationCheckError::Aggregator {
+                aggregator,
+                message: format!("rejected the signature: {err:?}"),
+            })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::AbiEncode,
+        types::{Address, Bytes, U256},
+    };
+    use silius_contracts::entry_point::SimulateValidationResult;
+    use silius_primitives::reputation::{
+        BAN_SLACK, HOURLY_DECAY_DENOMINATOR, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+    };
+
+    fn sim_res_with_aggregator(aggregator: Address, stake: u64) -> SimulateValidationResult {
+        let mut sim_res =
+            SimulateValidationResult::ValidationResultWithAggregation(Default::default());
+        if let SimulateValidationResult::ValidationResultWithAggregation(res) = &mut sim_res {
+            res.aggregator_info = (aggregator, (U256::from(stake), U256::from(86400)));
+        }
+        sim_res
+    }
+
+    #[tokio::test]
+    async fn aggregator() {
+        let mut ctx = MockContext::default();
+        ctx.reputation.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            HOURLY_DECAY_DENOMINATOR,
+            U256::from(1),
+            U256::from(0),
+        );
+        let uo = UserOperation::default();
+        let aggregator = Address::random();
+
+        // no aggregator
+        let sim_res = SimulateValidationResult::ValidationResult(Default::default());
+        let mut helper = ctx.simulation_helper(&sim_res);
+        assert!(Aggregator
+            .check_user_operation(&uo, &mut helper)
+            .await
+            .is_ok());
+
+        // unknown aggregator
+        let sim_res = sim_res_with_aggregator(aggregator, 1);
+        let mut helper = ctx.simulation_helper(&sim_res);
+        ctx.mock.push(Bytes::default()).unwrap();
+        assert!(matches!(
+            Aggregator.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::Aggregator { message, .. }) if message == "is unknown"
+        ));
+
+        // unstaked aggregator
+        let sim_res = sim_res_with_aggregator(aggregator, 0);
+        let mut helper = ctx.simulation_helper(&sim_res);
+        ctx.mock.push(Bytes::from(vec![0xfe])).unwrap();
+        assert!(matches!(
+            Aggregator.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::Aggregator { .. })
+        ));
+
+        // staked aggregator accepting the signature (responses are popped from the back)
+        let sim_res = sim_res_with_aggregator(aggregator, 1);
+        let mut helper = ctx.simulation_helper(&sim_res);
+        ctx.mock
+            .push::<Bytes, _>(Bytes::from(Bytes::default().encode()))
+            .unwrap();
+        ctx.mock.push(Bytes::from(vec![0xfe])).unwrap();
+        assert!(Aggregator
+            .check_user_operation(&uo, &mut helper)
+            .await
+            .is_ok());
+    }
+}
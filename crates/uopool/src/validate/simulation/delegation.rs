@@ -0,0 +1,77 @@
+use crate::validate::{SimulationCheck, SimulationHelper};
+use ethers::providers::Middleware;
+use silius_primitives::{
+    eip7702::delegate_address, simulation::SimulationCheckError, UserOperation,
+};
+
+/// Verifies the delegation designator of the EIP-7702 delegated sender
+///
+/// The delegate has to be deployed code (not an EOA or another delegated account, which isn't followed).
+pub struct Delegation;
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationCheck<M> for Delegation {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SimulationHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        let code = helper.eth_client.get_code(uo.sender, None).await?;
+        let delegate = match delegate_address(&code) {
+            Some(delegate) => delegate,
+            None => return Ok(()),
+        };
+
+        let delegate_code = helper.eth_client.get_code(delegate, None).await?;
+        if delegate_code.is_empty() || delegate_address(&delegate_code).is_some() {
+            return Err(SimulationCheckError::InvalidDelegation {
+                sender: uo.sender,
+                delegate,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{utils::tests::MockContext, validate::tests::mock_simulate_validation_result};
+    use ethers::types::{Address, Bytes};
+    use silius_primitives::eip7702::DELEGATION_PREFIX;
+
+    #[tokio::test]
+    async fn delegation() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let sim_res = mock_simulate_validation_result(0, u64::MAX);
+        let mut helper = ctx.simulation_helper(&sim_res);
+        let uo = UserOperation::default().sender(Address::random());
+        let delegation =
+            Bytes::from([&DELEGATION_PREFIX[..], Address::random().as_bytes()].concat());
+
+        // regular smart account
+        mock.push::<Bytes, _>(Bytes::from(vec![0xfe; 100])).unwrap();
+        assert!(Delegation
+            .check_user_operation(&uo, &mut helper)
+            .await
+            .is_ok());
+
+        // responses are popped from the back
+        mock.push::<Bytes, _>(Bytes::from(vec![0xfe; 100])).unwrap();
+        mock.push::<Bytes, _>(delegation.clone()).unwrap();
+        assert!(Delegation
+            .check_user_operation(&uo, &mut helper)
+            .await
+            .is_ok());
+
+        // delegated to an EOA
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+        mock.push::<Bytes, _>(delegation).unwrap();
+        assert!(matches!(
+            Delegation.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::InvalidDelegation { sender, .. }) if sender == uo.sender
+        ));
+    }
+}
@@ -1,2 +1,7 @@
+pub mod aggregator;
+pub mod delegation;
+pub mod prefund;
 pub mod signature;
+pub mod time_range;
 pub mod timestamp;
+pub mod verification_gas;
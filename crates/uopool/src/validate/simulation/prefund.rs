@@ -0,0 +1,119 @@
+use crate::validate::{utils::extract_pre_fund, SimulationCheck, SimulationHelper};
+use ethers::{providers::Middleware, types::U256};
+use silius_primitives::{get_address, simulation::SimulationCheckError, UserOperation};
+
+/// Requires the payer (paymaster or sender) to have funds exceeding the required prefund by a safety factor
+/// (to absorb base fee spikes between the validation and the inclusion)
+pub struct Prefund {
+    /// Required prefund is multiplied by this percentage (e.g., 120 requires 20% more funds)
+    pub safety_factor_perc: U256,
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationCheck<M> for Prefund {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SimulationHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        let required_prefund = extract_pre_fund(helper.simulate_validation_result)
+            .saturating_mul(self.safety_factor_perc)
+            / U256::from(100);
+
+        let (payer, funds) = if let Some(paymaster) = get_address(&uo.paymaster_and_data) {
+            let deposit = helper
+                .entry_point
+                .balance_of(&paymaster)
+                .await
+                .map_err(|err| SimulationCheckError::UnknownError {
+                    message: format!(
+                        "Couldn't retrieve deposit of paymaster {paymaster:?}: {err:?}"
+                    ),
+                })?;
+            (paymaster, deposit)
+        } else {
+            // the entry point takes the missing funds from the sender's balance
+            let deposit = helper
+                .entry_point
+                .balance_of(&uo.sender)
+                .await
+                .map_err(|err| SimulationCheckError::UnknownError {
+                    message: format!(
+                        "Couldn't retrieve deposit of sender {:?}: {err:?}",
+                        uo.sender
+                    ),
+                })?;
+            let balance = helper.eth_client.get_balance(uo.sender, None).await?;
+            (uo.sender, deposit.saturating_add(balance))
+        };
+
+        if funds < required_prefund {
+            return Err(SimulationCheckError::InsufficientPrefund {
+                payer,
+                funds,
+                required_prefund,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{utils::tests::MockContext, validate::tests::mock_simulate_validation_result};
+    use ethers::{
+        abi::AbiEncode,
+        types::{Address, Bytes},
+    };
+    use silius_contracts::entry_point::SimulateValidationResult;
+
+    #[tokio::test]
+    async fn prefund() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let check = Prefund {
+            safety_factor_perc: 120.into(),
+        };
+
+        let mut sim_res = mock_simulate_validation_result(0, u64::MAX);
+        if let SimulateValidationResult::ValidationResult(res) = &mut sim_res {
+            res.return_info.1 = 1_000.into();
+        }
+        let mut helper = ctx.simulation_helper(&sim_res);
+
+        // paymaster deposit covers the prefund but not the buffered one
+        let uo = UserOperation::default()
+            .paymaster_and_data(Address::random().as_bytes().to_vec().into());
+        mock.push::<Bytes, _>(U256::from(1_100).encode().into())
+            .unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::InsufficientPrefund {
+                funds,
+                required_prefund,
+                ..
+            }) if funds == U256::from(1_100) && required_prefund == U256::from(1_200)
+        ));
+
+        mock.push::<Bytes, _>(U256::from(1_200).encode().into())
+            .unwrap();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        // sender deposit and balance are combined
+        let uo = UserOperation::default().sender(Address::random());
+        mock.push(U256::from(700)).unwrap();
+        mock.push::<Bytes, _>(U256::from(500).encode().into())
+            .unwrap();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        mock.push(U256::from(600)).unwrap();
+        mock.push::<Bytes, _>(U256::from(500).encode().into())
+            .unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::InsufficientPrefund { .. })
+        ));
+    }
+}
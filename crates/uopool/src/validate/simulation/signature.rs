@@ -1,7 +1,9 @@
 use crate::validate::{SimulationCheck, SimulationHelper};
 use ethers::providers::Middleware;
-use silius_contracts::entry_point::SimulateValidationResult;
-use silius_primitives::{simulation::SimulationCheckError, UserOperation};
+use silius_primitives::{
+    simulation::{SimulationCheckError, ValidationResultInfo},
+    UserOperation,
+};
 
 pub struct Signature;
 
@@ -12,12 +14,9 @@ impl<M: Middleware> SimulationCheck<M> for Signature {
         _uo: &UserOperation,
         helper: &mut SimulationHelper<M>,
     ) -> Result<(), SimulationCheckError> {
-        let sig_check = match helper.simulate_validation_result {
-            SimulateValidationResult::ValidationResult(res) => res.return_info.2,
-            SimulateValidationResult::ValidationResultWithAggregation(res) => res.return_info.2,
-        };
+        let info = ValidationResultInfo::from(helper.simulate_validation_result);
 
-        if sig_check {
+        if info.return_info.sig_failed {
             return Err(SimulationCheckError::Signature {});
         }
 
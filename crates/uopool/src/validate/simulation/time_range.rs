@@ -0,0 +1,93 @@
+use crate::validate::{utils::extract_timestamps, SimulationCheck, SimulationHelper};
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, U256},
+};
+use silius_primitives::{simulation::SimulationCheckError, UserOperation};
+
+/// Rejects user operations whose `validAfter`/`validUntil` range (returned from the validation)
+/// doesn't contain the current block timestamp plus the buffer
+pub struct TimeRange {
+    pub buffer: u64,
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationCheck<M> for TimeRange {
+    async fn check_user_operation(
+        &self,
+        _uo: &UserOperation,
+        helper: &mut SimulationHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        let (valid_after, valid_until) = extract_timestamps(helper.simulate_validation_result);
+
+        let block = helper
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(SimulationCheckError::UnknownError {
+                message: "Failed to get latest block".to_string(),
+            })?;
+        let timestamp = block.timestamp.saturating_add(U256::from(self.buffer));
+
+        if valid_until <= timestamp {
+            return Err(SimulationCheckError::Expired {
+                valid_until,
+                timestamp,
+            });
+        }
+
+        if valid_after > timestamp {
+            return Err(SimulationCheckError::NotYetValid {
+                valid_after,
+                timestamp,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{utils::tests::MockContext, validate::tests::mock_simulate_validation_result};
+    use ethers::types::{Block, H256};
+
+    fn mock_block(timestamp: u64) -> Block<H256> {
+        Block {
+            timestamp: timestamp.into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn time_range() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let check = TimeRange { buffer: 10 };
+        let uo = UserOperation::default();
+
+        // expires within the buffer
+        let sim_res = mock_simulate_validation_result(0, 1_005);
+        let mut helper = ctx.simulation_helper(&sim_res);
+        mock.push(mock_block(1_000)).unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::Expired { .. })
+        ));
+
+        // becomes valid after the buffer
+        let sim_res = mock_simulate_validation_result(1_020, u64::MAX);
+        let mut helper = ctx.simulation_helper(&sim_res);
+        mock.push(mock_block(1_000)).unwrap();
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::NotYetValid { .. })
+        ));
+
+        let sim_res = mock_simulate_validation_result(1_005, 1_020);
+        let mut helper = ctx.simulation_helper(&sim_res);
+        mock.push(mock_block(1_000)).unwrap();
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::validate::{utils::extract_verification_gas_limit, SimulationCheck, SimulationHelper};
+use ethers::{providers::Middleware, types::U256};
+use silius_primitives::{simulation::SimulationCheckError, UserOperation};
+
+/// Rejects user operations declaring the verification gas limit far above the gas used by the validation
+/// (squatting the mempool and distorting the gas accounting of the bundles)
+///
+/// The gas actually used is known only after the simulation, so this is a simulation check.
+pub struct VerificationGasUsage {
+    /// Verification gas limit may be at most this percentage of the used gas (e.g., 300 allows 3x the usage)
+    pub max_overdeclare_perc: U256,
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationCheck<M> for VerificationGasUsage {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SimulationHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        // pre op gas of the entry point includes the pre verification gas
+        let verification_gas_used =
+            extract_verification_gas_limit(helper.simulate_validation_result)
+                .saturating_sub(uo.pre_verification_gas);
+        let max_verification_gas_limit =
+            verification_gas_used.saturating_mul(self.max_overdeclare_perc) / U256::from(100);
+
+        if uo.verification_gas_limit > max_verification_gas_limit {
+            return Err(SimulationCheckError::VerificationGasOverDeclared {
+                verification_gas_limit: uo.verification_gas_limit,
+                verification_gas_used,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{utils::tests::MockContext, validate::tests::mock_simulate_validation_result};
+    use silius_contracts::entry_point::SimulateValidationResult;
+
+    #[tokio::test]
+    async fn verification_gas_usage() {
+        let ctx = MockContext::default();
+        let check = VerificationGasUsage {
+            max_overdeclare_perc: 300.into(),
+        };
+
+        let mut sim_res = mock_simulate_validation_result(0, u64::MAX);
+        if let SimulateValidationResult::ValidationResult(res) = &mut sim_res {
+            res.return_info.0 = 150_000.into();
+        }
+        let mut helper = ctx.simulation_helper(&sim_res);
+
+        // 100k of verification gas used
+        let uo = UserOperation::default()
+            .pre_verification_gas(50_000.into())
+            .verification_gas_limit(300_000.into());
+        assert!(check.check_user_operation(&uo, &mut helper).await.is_ok());
+
+        let uo = uo.verification_gas_limit(300_001.into());
+        assert!(matches!(
+            check.check_user_operation(&uo, &mut helper).await,
+            Err(SimulationCheckError::VerificationGasOverDeclared {
+                verification_gas_used,
+                ..
+            }) if verification_gas_used == U256::from(100_000)
+        ));
+    }
+}
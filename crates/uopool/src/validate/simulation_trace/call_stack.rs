@@ -93,18 +93,7 @@ impl<M: Middleware> SimulationTraceCheck<M> for CallStack {
         let mut calls: Vec<CallEntry> = vec![];
         self.parse_call_stack(helper.js_trace, &mut calls)?;
 
-        let call = calls.iter().find(|call| {
-            call.to.unwrap_or_default() == helper.entry_point.address()
-                && call.from.unwrap_or_default() != helper.entry_point.address()
-                && (call.method.is_some()
-                    && call.method.clone().unwrap_or_default() != *"depositTo")
-        });
-        if call.is_some() {
-            return Err(SimulationCheckError::CallStack {
-                message: format!("Illegal call into entry point during validation {call:?}"),
-            });
-        }
-
+        // calls into the entry point are checked by EntryPointCalls
         for (i, stake_info) in helper.stake_info.unwrap_or_default().iter().enumerate() {
             if LEVEL_TO_ENTITY[i] == PAYMASTER {
                 let call = calls.iter().find(|call| {
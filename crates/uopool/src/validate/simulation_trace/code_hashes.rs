@@ -1,5 +1,5 @@
 use crate::{
-    utils::equal_code_hashes,
+    utils::changed_code_hash,
     validate::{SimulationTraceCheck, SimulationTraceHelper},
 };
 use ethers::{
@@ -14,6 +14,10 @@ use silius_primitives::{
 use std::sync::Arc;
 use tokio::task::JoinSet;
 
+/// Checks that the code of the addresses touched during the validation doesn't change
+///
+/// The code hashes of the addresses touched in the 1st validation are stored with the user operation and
+/// compared with those of the 2nd validation (before bundling), any redeployed code invalidates the user operation.
 pub struct CodeHashes;
 
 impl CodeHashes {
@@ -78,10 +82,8 @@ impl<M: Middleware> SimulationTraceCheck<M> for CodeHashes {
             Ok(true) => {
                 // 2nd simulation
                 let hashes_prev = helper.mempool.get_code_hashes(&uo_hash);
-                if !equal_code_hashes(hashes, &hashes_prev) {
-                    return Err(SimulationCheckError::CodeHashes {
-                        message: "Modified code hashes after 1st simulation".to_string(),
-                    });
+                if let Some(address) = changed_code_hash(hashes, &hashes_prev) {
+                    return Err(SimulationCheckError::CodeHashChanged { address });
                 } else {
                     helper.code_hashes = Some(hashes.to_vec());
                 }
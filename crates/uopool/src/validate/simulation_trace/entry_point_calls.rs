@@ -0,0 +1,116 @@
+use crate::validate::{SimulationTraceCheck, SimulationTraceHelper};
+use ethers::{providers::Middleware, utils::id};
+use silius_primitives::{
+    simulation::{SimulationCheckError, RETURN_OPCODE, REVERT_OPCODE},
+    UserOperation,
+};
+
+/// Entry point method the entities are allowed to call during the validation
+const DEPOSIT_TO_FUNCTION: &str = "depositTo(address)";
+
+/// Rejects user operations whose entities call back into the entry point during the validation
+///
+/// Only `depositTo` (and plain value transfers without call data) are allowed, any other entry point
+/// method is forbidden.
+pub struct EntryPointCalls;
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationTraceCheck<M> for EntryPointCalls {
+    async fn check_user_operation(
+        &self,
+        _uo: &UserOperation,
+        helper: &mut SimulationTraceHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        let ep = helper.entry_point.address();
+        let deposit_to = id(DEPOSIT_TO_FUNCTION);
+
+        for call in helper.js_trace.calls.iter() {
+            // exits of call frames
+            if call.typ == *REVERT_OPCODE || call.typ == *RETURN_OPCODE {
+                continue;
+            }
+
+            if call.to != Some(ep) || call.from == Some(ep) {
+                continue;
+            }
+
+            if let Some(selector) = call.method.as_ref() {
+                if !selector.is_empty() && selector.as_ref() != deposit_to.as_slice() {
+                    return Err(SimulationCheckError::ForbiddenEntryPointCall {
+                        from: call.from.unwrap_or_default(),
+                        selector: selector.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::types::{Address, Bytes};
+    use silius_contracts::{
+        entry_point::SimulateValidationResult,
+        tracer::{Call, JsTracerFrame},
+    };
+    use silius_primitives::consts::entry_point::ADDRESS;
+
+    async fn check(calls: Vec<Call>) -> Result<(), SimulationCheckError> {
+        let ctx = MockContext::default();
+        let sim_res = SimulateValidationResult::ValidationResult(Default::default());
+        let js_trace = JsTracerFrame {
+            calls,
+            ..Default::default()
+        };
+        let mut helper = ctx.simulation_trace_helper(&sim_res, &js_trace);
+
+        EntryPointCalls
+            .check_user_operation(&UserOperation::default(), &mut helper)
+            .await
+    }
+
+    fn call(from: Address, to: Address, method: &[u8]) -> Call {
+        Call {
+            typ: "CALL".into(),
+            from: Some(from),
+            to: Some(to),
+            method: Some(Bytes::from(method.to_vec())),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn forbidden_entry_point_calls() {
+        let ep: Address = ADDRESS.parse().unwrap();
+        let account = Address::random();
+        let deposit_to = id(DEPOSIT_TO_FUNCTION);
+        let handle_ops = id("handleOps((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)[],address)");
+
+        // entry point calling the account, deposit and value transfer into the entry point
+        assert!(check(vec![
+            call(ep, account, &id("validateUserOp(bytes32)")),
+            call(account, ep, &deposit_to),
+            call(account, ep, &[]),
+            call(account, Address::random(), &handle_ops),
+        ])
+        .await
+        .is_ok());
+
+        match check(vec![
+            call(account, ep, &deposit_to),
+            call(account, ep, &handle_ops),
+        ])
+        .await
+        {
+            Err(SimulationCheckError::ForbiddenEntryPointCall { from, selector }) => {
+                assert_eq!(from, account);
+                assert_eq!(selector, Bytes::from(handle_ops.to_vec()));
+            }
+            res => panic!("unexpected result: {res:?}"),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 pub mod call_stack;
 pub mod code_hashes;
+pub mod entry_point_calls;
 pub mod gas;
 pub mod opcodes;
+pub mod sender_deployment;
 pub mod storage_access;
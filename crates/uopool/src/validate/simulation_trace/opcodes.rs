@@ -2,11 +2,24 @@ use crate::validate::{SimulationTraceCheck, SimulationTraceHelper};
 use ethers::providers::Middleware;
 use silius_primitives::{
     consts::entities::FACTORY,
-    simulation::{SimulationCheckError, CREATE2_OPCODE, FORBIDDEN_OPCODES, LEVEL_TO_ENTITY},
+    simulation::{
+        is_forbidden_precompile, EnabledEntities, SimulationCheckError, CREATE2_OPCODE,
+        FORBIDDEN_OPCODES, GAS_OPCODE, LEVEL_TO_ENTITY,
+    },
     UserOperation,
 };
 
-pub struct Opcodes;
+/// Checks the opcodes used and the precompiles called by the entities during validation
+///
+/// The tracer counts the `GAS` opcode only when it isn't immediately followed by a `*CALL` opcode
+/// (the only allowed usage), so any counted `GAS` opcode is a violation. Calls into the precompile range
+/// are only allowed for the precompiles 0x01-0x09.
+///
+/// The entities toggle all of these rules (forbidden opcodes, `GAS`, `CREATE2` and precompiles) per entity type.
+#[derive(Default)]
+pub struct Opcodes {
+    pub entities: EnabledEntities,
+}
 
 #[async_trait::async_trait]
 impl<M: Middleware> SimulationTraceCheck<M> for Opcodes {
@@ -15,15 +28,43 @@ impl<M: Middleware> SimulationTraceCheck<M> for Opcodes {
         _uo: &UserOperation,
         helper: &mut SimulationTraceHelper<M>,
     ) -> Result<(), SimulationCheckError> {
-        for (i, _) in LEVEL_TO_ENTITY.iter().enumerate() {
+        for (i, entity) in LEVEL_TO_ENTITY.iter().enumerate() {
+            if !self.entities.is_enabled(entity) {
+                continue;
+            }
+
             if let Some(l) = helper.js_trace.number_levels.get(i) {
-                for op in l.opcodes.keys() {
-                    if FORBIDDEN_OPCODES.contains(op) {
-                        return Err(SimulationCheckError::ForbiddenOpcode {
-                            entity: LEVEL_TO_ENTITY[i].to_string(),
-                            opcode: op.clone(),
-                        });
-                    }
+                if l.opcodes.contains_key(&*GAS_OPCODE) {
+                    return Err(SimulationCheckError::InvalidGasOpcode {
+                        entity: LEVEL_TO_ENTITY[i].to_string(),
+                    });
+                }
+
+                // sort the opcodes so the same one is always reported
+                let mut ops = l
+                    .opcodes
+                    .keys()
+                    .filter(|op| FORBIDDEN_OPCODES.contains(*op))
+                    .collect::<Vec<_>>();
+                ops.sort();
+                if let Some(op) = ops.first() {
+                    return Err(SimulationCheckError::ForbiddenOpcode {
+                        entity: LEVEL_TO_ENTITY[i].to_string(),
+                        opcode: op.to_string(),
+                    });
+                }
+
+                let mut precompiles = l
+                    .contract_size
+                    .keys()
+                    .filter(|addr| is_forbidden_precompile(addr))
+                    .collect::<Vec<_>>();
+                precompiles.sort();
+                if let Some(address) = precompiles.first() {
+                    return Err(SimulationCheckError::ForbiddenPrecompile {
+                        entity: LEVEL_TO_ENTITY[i].to_string(),
+                        address: **address,
+                    });
                 }
             }
 
@@ -43,3 +84,217 @@ impl<M: Middleware> SimulationTraceCheck<M> for Opcodes {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::types::{Address, GethTrace};
+    use serde_json::json;
+    use silius_contracts::{
+        entry_point::SimulateValidationResult,
+        tracer::{JsTracerFrame, Level},
+    };
+    use silius_primitives::consts::entities::{ACCOUNT, PAYMASTER};
+
+    fn level(opcodes: &[(&str, u64)]) -> Level {
+        Level {
+            opcodes: opcodes.iter().map(|(op, c)| (op.to_string(), *c)).collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn check(levels: Vec<Level>) -> Result<(), SimulationCheckError> {
+        check_with(&Opcodes::default(), levels).await
+    }
+
+    async fn check_with(check: &Opcodes, levels: Vec<Level>) -> Result<(), SimulationCheckError> {
+        let ctx = MockContext::default();
+        let sim_res = SimulateValidationResult::ValidationResult(Default::default());
+        let js_trace = JsTracerFrame {
+            number_levels: levels,
+            ..Default::default()
+        };
+        let mut helper = ctx.simulation_trace_helper(&sim_res, &js_trace);
+
+        check
+            .check_user_operation(&UserOperation::default(), &mut helper)
+            .await
+    }
+
+    #[tokio::test]
+    async fn forbidden_opcodes() {
+        assert!(check(vec![
+            level(&[("CREATE2", 1), ("CALL", 2)]),
+            level(&[("SLOAD", 1)]),
+            level(&[])
+        ])
+        .await
+        .is_ok());
+
+        for (levels, entity, opcode) in [
+            (
+                vec![level(&[("COINBASE", 1)]), level(&[]), level(&[])],
+                FACTORY,
+                "COINBASE",
+            ),
+            (
+                vec![
+                    level(&[]),
+                    level(&[("TIMESTAMP", 1), ("GASPRICE", 1)]),
+                    level(&[]),
+                ],
+                ACCOUNT,
+                "GASPRICE",
+            ),
+            (
+                vec![level(&[]), level(&[]), level(&[("SELFBALANCE", 1)])],
+                PAYMASTER,
+                "SELFBALANCE",
+            ),
+            (
+                vec![level(&[("CREATE2", 2)]), level(&[]), level(&[])],
+                FACTORY,
+                "CREATE2",
+            ),
+            (
+                vec![level(&[]), level(&[("CREATE2", 1)]), level(&[])],
+                ACCOUNT,
+                "CREATE2",
+            ),
+        ] {
+            match check(levels).await {
+                Err(SimulationCheckError::ForbiddenOpcode {
+                    entity: e,
+                    opcode: o,
+                }) => {
+                    assert_eq!(e, entity);
+                    assert_eq!(o, opcode);
+                }
+                res => panic!("Unexpected result: {res:?}"),
+            }
+        }
+
+        // opcode rules disabled for the paymaster
+        let check = Opcodes {
+            entities: EnabledEntities::except(&[PAYMASTER.into()]).unwrap(),
+        };
+        let levels = || vec![level(&[]), level(&[]), level(&[("SELFBALANCE", 1)])];
+        assert!(check_with(&check, levels()).await.is_ok());
+        let levels = vec![level(&[]), level(&[("SELFBALANCE", 1)]), level(&[])];
+        assert!(check_with(&check, levels).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn gas_opcode() {
+        // GAS followed by CALL isn't counted by the tracer
+        let levels: Vec<Level> = serde_json::from_value(json!([
+            {"access": {}, "opcodes": {"CALL": 1}, "contractSize": {}},
+            {"access": {}, "opcodes": {"STATICCALL": 2, "SLOAD": 1}, "contractSize": {}},
+            {"access": {}, "opcodes": {}, "contractSize": {}},
+        ]))
+        .unwrap();
+        assert!(check(levels).await.is_ok());
+
+        // GAS followed by SSTORE in the account validation
+        let levels: Vec<Level> = serde_json::from_value(json!([
+            {"access": {}, "opcodes": {}, "contractSize": {}},
+            {"access": {}, "opcodes": {"GAS": 1, "SSTORE": 1}, "contractSize": {}},
+            {"access": {}, "opcodes": {}, "contractSize": {}},
+        ]))
+        .unwrap();
+        match check(levels).await {
+            Err(SimulationCheckError::InvalidGasOpcode { entity }) => assert_eq!(entity, ACCOUNT),
+            res => panic!("Unexpected result: {res:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn forbidden_precompiles() {
+        // ecrecover (0x01) and sha256 (0x02)
+        let levels: Vec<Level> = serde_json::from_value(json!([
+            {"access": {}, "opcodes": {}, "contractSize": {}},
+            {
+                "access": {},
+                "opcodes": {"STATICCALL": 2},
+                "contractSize": {
+                    "0x0000000000000000000000000000000000000001": 0,
+                    "0x0000000000000000000000000000000000000002": 0,
+                },
+            },
+            {"access": {}, "opcodes": {}, "contractSize": {}},
+        ]))
+        .unwrap();
+        assert!(check(levels).await.is_ok());
+
+        // point evaluation (0x0a) and P256 verification (0x0100) in the paymaster validation
+        let levels: Vec<Level> = serde_json::from_value(json!([
+            {"access": {}, "opcodes": {}, "contractSize": {}},
+            {"access": {}, "opcodes": {}, "contractSize": {}},
+            {
+                "access": {},
+                "opcodes": {"STATICCALL": 2},
+                "contractSize": {
+                    "0x0000000000000000000000000000000000000100": 0,
+                    "0x000000000000000000000000000000000000000a": 0,
+                },
+            },
+        ]))
+        .unwrap();
+        match check(levels).await {
+            Err(SimulationCheckError::ForbiddenPrecompile { entity, address }) => {
+                assert_eq!(entity, PAYMASTER);
+                assert_eq!(address, Address::from_low_u64_be(0x0a));
+            }
+            res => panic!("Unexpected result: {res:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn native_tracer_conformance() {
+        let (ep, sender) = (Address::random(), Address::random());
+        // levels of the native traces of the account validation with the nested calls
+        let native_levels = |calls: serde_json::Value| {
+            JsTracerFrame::try_from_native(
+                GethTrace::Unknown(json!({
+                    "type": "CALL",
+                    "from": Address::zero(),
+                    "to": ep,
+                    "input": "0xee219423",
+                    "error": "execution reverted",
+                    "calls": [{ "type": "CALL", "from": ep, "to": sender, "input": "0x3a871cdd", "calls": calls }]
+                })),
+                GethTrace::Unknown(json!({})),
+                sender,
+                None,
+            )
+            .unwrap()
+            .number_levels
+        };
+
+        // opcodes traced as call frames are enforced the same way on both paths
+        for opcode in ["CREATE", "CREATE2", "SELFDESTRUCT"] {
+            let js_res = check(vec![level(&[]), level(&[(opcode, 1)]), level(&[])]).await;
+            let native_res = check(native_levels(json!([
+                { "type": opcode, "from": sender, "to": Address::random(), "input": "0x" }
+            ])))
+            .await;
+            assert_eq!(format!("{js_res:?}"), format!("{native_res:?}"));
+            assert!(matches!(
+                native_res,
+                Err(SimulationCheckError::ForbiddenOpcode { entity, opcode: o }) if entity == ACCOUNT && o == opcode
+            ));
+        }
+
+        // other forbidden opcodes and GAS aren't traced by the native tracers, so the rules aren't enforced
+        assert!(
+            check(vec![level(&[]), level(&[("TIMESTAMP", 1)]), level(&[])])
+                .await
+                .is_err()
+        );
+        assert!(check(vec![level(&[]), level(&[("GAS", 1)]), level(&[])])
+            .await
+            .is_err());
+        assert!(check(native_levels(json!([]))).await.is_ok());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::validate::{SimulationTraceCheck, SimulationTraceHelper};
+use ethers::providers::Middleware;
+use silius_contracts::tracer::Call;
+use silius_primitives::{
+    get_address,
+    simulation::{SimulationCheckError, CREATE_OPCODE, RETURN_OPCODE, REVERT_OPCODE},
+    UserOperation,
+};
+
+/// Checks that the factory of the user operation deploys the sender
+///
+/// With non-empty `init_code`, the validation trace must contain a successful `CREATE`/`CREATE2` frame
+/// which deployed non-empty code at the `sender` address. Otherwise the factory computes a different
+/// address than the `sender` and the user operation always fails.
+pub struct SenderDeployment;
+
+#[async_trait::async_trait]
+impl<M: Middleware> SimulationTraceCheck<M> for SenderDeployment {
+    async fn check_user_operation(
+        &self,
+        uo: &UserOperation,
+        helper: &mut SimulationTraceHelper<M>,
+    ) -> Result<(), SimulationCheckError> {
+        // sender is already deployed
+        if uo.init_code.is_empty() {
+            return Ok(());
+        }
+
+        let mut st: Vec<&Call> = vec![];
+        for call in helper.js_trace.calls.iter() {
+            if call.typ == *REVERT_OPCODE || call.typ == *RETURN_OPCODE {
+                if let Some(top) = st.pop() {
+                    if top.typ.contains(CREATE_OPCODE.as_str())
+                        && top.to == Some(uo.sender)
+                        && call.typ == *RETURN_OPCODE
+                        && !call.data.as_ref().map_or(true, |code| code.is_empty())
+                    {
+                        return Ok(());
+                    }
+                }
+            } else {
+                st.push(call);
+            }
+        }
+
+        Err(SimulationCheckError::SenderNotDeployed {
+            sender: uo.sender,
+            factory: get_address(&uo.init_code).unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::types::{Address, Bytes};
+    use silius_contracts::{entry_point::SimulateValidationResult, tracer::JsTracerFrame};
+
+    async fn check(uo: &UserOperation, calls: Vec<Call>) -> Result<(), SimulationCheckError> {
+        let ctx = MockContext::default();
+        let sim_res = SimulateValidationResult::ValidationResult(Default::default());
+        let js_trace = JsTracerFrame {
+            calls,
+            ..Default::default()
+        };
+        let mut helper = ctx.simulation_trace_helper(&sim_res, &js_trace);
+
+        SenderDeployment.check_user_operation(uo, &mut helper).await
+    }
+
+    fn enter(typ: &str, from: Address, to: Address) -> Call {
+        Call {
+            typ: typ.into(),
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        }
+    }
+
+    fn exit(typ: &str, data: &[u8]) -> Call {
+        Call {
+            typ: typ.into(),
+            data: Some(Bytes::from(data.to_vec())),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn sender_deployment() {
+        let factory = Address::random();
+        let sender_creator = Address::random();
+        let sender = Address::random();
+        let uo = UserOperation::default()
+            .sender(sender)
+            .init_code(Bytes::from(
+                [factory.as_bytes(), &[0x01, 0x02][..]].concat(),
+            ));
+
+        // sender already deployed
+        assert!(check(&uo.clone().init_code(Bytes::default()), vec![])
+            .await
+            .is_ok());
+
+        // factory deployed the sender
+        assert!(check(
+            &uo,
+            vec![
+                enter("CALL", sender_creator, factory),
+                enter("CREATE2", factory, sender),
+                exit("RETURN", &[0x60, 0x80]),
+                exit("RETURN", &[]),
+                enter("CALL", Address::random(), sender),
+                exit("RETURN", &[]),
+            ]
+        )
+        .await
+        .is_ok());
+
+        // factory deployed a different address, reverted or deployed empty code
+        for calls in [
+            vec![
+                enter("CALL", sender_creator, factory),
+                enter("CREATE2", factory, Address::random()),
+                exit("RETURN", &[0x60, 0x80]),
+                exit("RETURN", &[]),
+            ],
+            vec![enter("CREATE2", factory, sender), exit("REVERT", &[])],
+            vec![enter("CREATE", factory, sender), exit("RETURN", &[])],
+            vec![],
+        ] {
+            match check(&uo, calls).await {
+                Err(SimulationCheckError::SenderNotDeployed {
+                    sender: s,
+                    factory: f,
+                }) => {
+                    assert_eq!(s, sender);
+                    assert_eq!(f, factory);
+                }
+                res => panic!("unexpected result: {res:?}"),
+            }
+        }
+    }
+}
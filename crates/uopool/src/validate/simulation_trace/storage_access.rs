@@ -1,74 +1,25 @@
-use crate::validate::{utils::extract_stake_info, SimulationTraceCheck, SimulationTraceHelper};
-use ethers::{
-    providers::Middleware,
-    types::{Address, Bytes, U256},
-    utils::keccak256,
+use crate::validate::{
+    utils::{extract_stake_info, SlotAssociation},
+    SimulationTraceCheck, SimulationTraceHelper,
 };
+use ethers::providers::Middleware;
 use silius_primitives::{
-    reputation::StakeInfo,
-    simulation::{SimulationCheckError, LEVEL_TO_ENTITY, NUMBER_LEVELS},
+    consts::entities::PAYMASTER,
+    simulation::{EnabledEntities, SimulationCheckError, LEVEL_TO_ENTITY},
     UserOperation,
 };
-use std::collections::{HashMap, HashSet};
 
-pub struct StorageAccess;
-
-impl StorageAccess {
-    fn parse_slots(
-        &self,
-        keccak: Vec<Bytes>,
-        info: &[StakeInfo; NUMBER_LEVELS],
-        slots: &mut HashMap<Address, HashSet<Bytes>>,
-    ) {
-        for kecc in keccak {
-            for entity in info {
-                if entity.address.is_zero() {
-                    continue;
-                }
-
-                let addr_b =
-                    Bytes::from([vec![0; 12], entity.address.to_fixed_bytes().to_vec()].concat());
-
-                if kecc.starts_with(&addr_b) {
-                    let k = keccak256(kecc.clone());
-                    slots
-                        .entry(entity.address)
-                        .or_insert(HashSet::new())
-                        .insert(k.into());
-                }
-            }
-        }
-    }
-
-    fn associated_with_slot(
-        &self,
-        addr: &Address,
-        slot: &String,
-        slots: &HashMap<Address, HashSet<Bytes>>,
-    ) -> Result<bool, SimulationCheckError> {
-        if *slot == addr.to_string() {
-            return Ok(true);
-        }
-
-        if !slots.contains_key(addr) {
-            return Ok(false);
-        }
-
-        let slot_num = U256::from_str_radix(slot, 16)
-            .map_err(|_| SimulationCheckError::StorageAccess { slot: slot.clone() })?;
-
-        if let Some(slots) = slots.get(addr) {
-            for slot in slots {
-                let slot_ent_num = U256::from(slot.as_ref());
-
-                if slot_num >= slot_ent_num && slot_num < (slot_ent_num + 128) {
-                    return Ok(true);
-                }
-            }
-        }
-
-        Ok(false)
-    }
+/// Checks the storage accessed during the validation against the storage rules of ERC-7562
+///
+/// Entities can access their own storage and the slots associated with the sender (mappings keyed by the sender),
+/// the slots associated with the entity itself are allowed only if the entity is staked. Unstaked paymasters are
+/// stricter, they can access only their own storage (the stake is the deposit info in the `simulateValidation`
+/// result).
+///
+/// The entities toggle all of these rules (including the unstaked paymaster restriction) per entity type.
+#[derive(Default)]
+pub struct StorageAccess {
+    pub entities: EnabledEntities,
 }
 
 #[async_trait::async_trait]
@@ -82,19 +33,38 @@ impl<M: Middleware> SimulationTraceCheck<M> for StorageAccess {
             helper.stake_info = Some(extract_stake_info(uo, helper.simulate_validation_result));
         }
 
-        let mut slots = HashMap::new();
-        self.parse_slots(
-            helper.js_trace.keccak.clone(),
-            &helper.stake_info.unwrap_or_default(),
-            &mut slots,
-        );
+        let sender_association = SlotAssociation::new(uo.sender, &helper.js_trace.keccak);
 
         let mut slot_staked = String::new();
 
         for (i, stake_info) in helper.stake_info.unwrap_or_default().iter().enumerate() {
+            if !self.entities.is_enabled(LEVEL_TO_ENTITY[i]) {
+                continue;
+            }
+
+            let entity_association =
+                SlotAssociation::new(stake_info.address, &helper.js_trace.keccak);
+            let unstaked_paymaster = LEVEL_TO_ENTITY[i] == PAYMASTER
+                && !stake_info.address.is_zero()
+                && stake_info.stake.is_zero();
+
             if let Some(l) = helper.js_trace.number_levels.get(i) {
                 for (addr, acc) in &l.access {
-                    if *addr == uo.sender || *addr == helper.entry_point.address() {
+                    if *addr == helper.entry_point.address() {
+                        continue;
+                    }
+
+                    if unstaked_paymaster && *addr != stake_info.address {
+                        if let Some(slot) = acc.reads.keys().chain(acc.writes.keys()).next() {
+                            return Err(SimulationCheckError::PaymasterStorageViolation {
+                                paymaster: stake_info.address,
+                                slot: slot.clone(),
+                                address: *addr,
+                            });
+                        }
+                    }
+
+                    if *addr == uo.sender {
                         continue;
                     }
 
@@ -106,16 +76,19 @@ impl<M: Middleware> SimulationTraceCheck<M> for StorageAccess {
                     ]
                     .concat()
                     {
-                        if self.associated_with_slot(&uo.sender, &slot, &slots)? {
+                        if sender_association.is_associated(&slot) {
                             if !uo.init_code.is_empty() {
                                 slot_staked = slot.clone();
                             }
                         } else if *addr == stake_info.address
-                            || self.associated_with_slot(&stake_info.address, &slot, &slots)?
+                            || entity_association.is_associated(&slot)
                         {
                             slot_staked = slot.clone();
                         } else {
-                            return Err(SimulationCheckError::StorageAccess { slot });
+                            return Err(SimulationCheckError::StorageAccessViolation {
+                                slot,
+                                address: *addr,
+                            });
                         }
                     }
 
@@ -132,3 +105,357 @@ impl<M: Middleware> SimulationTraceCheck<M> for StorageAccess {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::MockContext;
+    use ethers::{
+        abi::AbiEncode,
+        types::{Address, Bytes, GethTrace, H256, U256},
+        utils::keccak256,
+    };
+    use silius_contracts::{
+        entry_point::SimulateValidationResult,
+        tracer::{JsTracerFrame, Level, ReadsAndWrites},
+    };
+    use silius_primitives::{
+        consts::entities::FACTORY, reputation::StakeInfo, simulation::NUMBER_LEVELS,
+    };
+    use std::collections::HashMap;
+
+    // slot of the mapping (at slot 0) keyed by the address
+    fn mapping_slot(addr: &Address) -> (Bytes, String) {
+        let kecc = Bytes::from((*addr, U256::zero()).encode());
+        let slot = format!("{:?}", H256::from(keccak256(&kecc)));
+        (kecc, slot)
+    }
+
+    fn level(addr: Address, slot: &str) -> Level {
+        Level {
+            access: HashMap::from([(
+                addr,
+                ReadsAndWrites {
+                    reads: HashMap::from([(slot.to_string(), 1)]),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        }
+    }
+
+    async fn check(
+        uo: &UserOperation,
+        stake_info: [StakeInfo; NUMBER_LEVELS],
+        js_trace: JsTracerFrame,
+    ) -> Result<(), SimulationCheckError> {
+        check_with(&StorageAccess::default(), uo, stake_info, js_trace).await
+    }
+
+    async fn check_with(
+        check: &StorageAccess,
+        uo: &UserOperation,
+        stake_info: [StakeInfo; NUMBER_LEVELS],
+        js_trace: JsTracerFrame,
+    ) -> Result<(), SimulationCheckError> {
+        let ctx = MockContext::default();
+        let sim_res = SimulateValidationResult::ValidationResult(Default::default());
+        let mut helper = ctx.simulation_trace_helper(&sim_res, &js_trace);
+        helper.stake_info = Some(stake_info);
+
+        check.check_user_operation(uo, &mut helper).await
+    }
+
+    #[tokio::test]
+    async fn storage_access() {
+        let token = Address::random();
+        let paymaster = Address::random();
+        let uo = UserOperation::default()
+            .sender(Address::random())
+            .paymaster_and_data(paymaster.as_bytes().to_vec().into());
+        let (sender_kecc, sender_slot) = mapping_slot(&uo.sender);
+        let (paymaster_kecc, paymaster_slot) = mapping_slot(&paymaster);
+
+        let stake_info = |stake: u64| {
+            [
+                StakeInfo::default(),
+                StakeInfo {
+                    address: uo.sender,
+                    ..Default::default()
+                },
+                StakeInfo {
+                    address: paymaster,
+                    stake: stake.into(),
+                    unstake_delay: U256::zero(),
+                },
+            ]
+        };
+        let js_trace = |levels: Vec<Level>| JsTracerFrame {
+            number_levels: levels,
+            keccak: vec![sender_kecc.clone(), paymaster_kecc.clone()],
+            ..Default::default()
+        };
+
+        // account reads the balance of the sender in the token
+        assert!(check(
+            &uo,
+            stake_info(0),
+            js_trace(vec![
+                Level::default(),
+                level(token, &sender_slot),
+                Level::default()
+            ])
+        )
+        .await
+        .is_ok());
+
+        // account reads the slot that isn't associated with the sender
+        match check(
+            &uo,
+            stake_info(0),
+            js_trace(vec![
+                Level::default(),
+                level(token, "0x01"),
+                Level::default(),
+            ]),
+        )
+        .await
+        {
+            Err(SimulationCheckError::StorageAccessViolation { slot, address }) => {
+                assert_eq!(slot, "0x01");
+                assert_eq!(address, token);
+            }
+            res => panic!("Unexpected result: {res:?}"),
+        }
+
+        // unstaked factory reads the slot associated with the factory
+        let factory = Address::random();
+        let (factory_kecc, factory_slot) = mapping_slot(&factory);
+        let mut factory_stake_info = stake_info(0);
+        factory_stake_info[0].address = factory;
+        match check(
+            &uo,
+            factory_stake_info,
+            JsTracerFrame {
+                number_levels: vec![level(token, &factory_slot)],
+                keccak: vec![factory_kecc],
+                ..Default::default()
+            },
+        )
+        .await
+        {
+            Err(SimulationCheckError::Unstaked { entity, .. }) => {
+                assert_eq!(entity, FACTORY)
+            }
+            res => panic!("Unexpected result: {res:?}"),
+        }
+
+        // unstaked paymaster can access only its own storage
+        assert!(check(
+            &uo,
+            stake_info(0),
+            js_trace(vec![
+                Level::default(),
+                Level::default(),
+                level(paymaster, "0x01")
+            ])
+        )
+        .await
+        .is_ok());
+
+        for slot in [&paymaster_slot, &sender_slot] {
+            match check(
+                &uo,
+                stake_info(0),
+                js_trace(vec![Level::default(), Level::default(), level(token, slot)]),
+            )
+            .await
+            {
+                Err(SimulationCheckError::PaymasterStorageViolation {
+                    paymaster: violator,
+                    address,
+                    ..
+                }) => {
+                    assert_eq!(violator, paymaster);
+                    assert_eq!(address, token);
+                }
+                res => panic!("Unexpected result: {res:?}"),
+            }
+        }
+
+        match check(
+            &uo,
+            stake_info(0),
+            js_trace(vec![
+                Level::default(),
+                Level::default(),
+                level(uo.sender, "0x01"),
+            ]),
+        )
+        .await
+        {
+            Err(SimulationCheckError::PaymasterStorageViolation { address, .. }) => {
+                assert_eq!(address, uo.sender)
+            }
+            res => panic!("Unexpected result: {res:?}"),
+        }
+
+        // staked paymaster has relaxed access to the associated storage
+        for slot in [&paymaster_slot, &sender_slot] {
+            assert!(check(
+                &uo,
+                stake_info(1),
+                js_trace(vec![Level::default(), Level::default(), level(token, slot)])
+            )
+            .await
+            .is_ok());
+        }
+
+        // storage rules disabled for the trusted paymaster (but not for the account)
+        let check = StorageAccess {
+            entities: EnabledEntities::except(&[PAYMASTER.into()]).unwrap(),
+        };
+        assert!(check_with(
+            &check,
+            &uo,
+            stake_info(0),
+            js_trace(vec![
+                Level::default(),
+                Level::default(),
+                level(token, "0x01")
+            ])
+        )
+        .await
+        .is_ok());
+        assert!(check_with(
+            &check,
+            &uo,
+            stake_info(0),
+            js_trace(vec![
+                Level::default(),
+                level(token, "0x01"),
+                Level::default()
+            ])
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn native_tracer_conformance() {
+        let ep: Address = silius_primitives::consts::entry_point::ADDRESS
+            .parse()
+            .unwrap();
+        let token = Address::random();
+        let paymaster = Address::random();
+        let uo = UserOperation::default()
+            .sender(Address::random())
+            .paymaster_and_data(paymaster.as_bytes().to_vec().into());
+        let (sender_kecc, _) = mapping_slot(&uo.sender);
+        let sender_slot = H256::from(keccak256(&sender_kecc));
+        let other_slot = H256::from_low_u64_be(5);
+        let own_slot = H256::from_low_u64_be(1);
+        let stake_info = [
+            StakeInfo::default(),
+            StakeInfo {
+                address: uo.sender,
+                ..Default::default()
+            },
+            StakeInfo {
+                address: paymaster,
+                stake: 1.into(),
+                unstake_delay: U256::zero(),
+            },
+        ];
+
+        // the same validation traced by both tracers: the account reads its own storage, the paymaster reads its
+        // own storage and the token slot
+        let traces = |token_slot: H256| {
+            let js_slot = |slot: &H256| format!("{:x}", U256::from(slot.as_bytes()));
+            let js_trace = JsTracerFrame {
+                number_levels: vec![
+                    Level::default(),
+                    level(uo.sender, &js_slot(&own_slot)),
+                    Level {
+                        access: HashMap::from([
+                            (
+                                paymaster,
+                                ReadsAndWrites {
+                                    reads: HashMap::from([(js_slot(&own_slot), 1)]),
+                                    ..Default::default()
+                                },
+                            ),
+                            (
+                                token,
+                                ReadsAndWrites {
+                                    reads: HashMap::from([(js_slot(&token_slot), 1)]),
+                                    ..Default::default()
+                                },
+                            ),
+                        ]),
+                        ..Default::default()
+                    },
+                ],
+                keccak: vec![sender_kecc.clone()],
+                ..Default::default()
+            };
+
+            let call = serde_json::json!({
+                "type": "CALL",
+                "from": Address::zero(),
+                "to": ep,
+                "input": "0xee219423",
+                "error": "execution reverted",
+                "calls": [
+                    { "type": "CALL", "from": ep, "to": uo.sender, "input": "0x3a871cdd" },
+                    {
+                        "type": "CALL",
+                        "from": ep,
+                        "to": paymaster,
+                        "input": "0xf465c77e",
+                        "calls": [
+                            { "type": "STATICCALL", "from": paymaster, "to": token, "input": "0x70a08231" }
+                        ]
+                    }
+                ]
+            });
+            let prestate = serde_json::json!({
+                format!("{:?}", uo.sender): { "code": "0x6001", "storage": { format!("{own_slot:?}"): H256::zero() } },
+                format!("{paymaster:?}"): { "code": "0x6001", "storage": { format!("{own_slot:?}"): H256::zero() } },
+                format!("{token:?}"): { "code": "0x6001", "storage": { format!("{token_slot:?}"): H256::zero() } },
+            });
+            let native_trace = JsTracerFrame::try_from_native(
+                GethTrace::Unknown(call),
+                GethTrace::Unknown(prestate),
+                uo.sender,
+                Some(paymaster),
+            )
+            .unwrap();
+
+            (js_trace, native_trace)
+        };
+
+        for token_slot in [sender_slot, other_slot] {
+            let (js_trace, native_trace) = traces(token_slot);
+            assert_eq!(js_trace.number_levels, {
+                let mut levels = native_trace.number_levels.clone();
+                for l in levels.iter_mut() {
+                    l.contract_size.clear();
+                }
+                levels
+            });
+
+            let js_res = check(&uo, stake_info, js_trace).await;
+            let native_res = check(&uo, stake_info, native_trace).await;
+            assert_eq!(format!("{js_res:?}"), format!("{native_res:?}"));
+            if token_slot == other_slot {
+                assert!(matches!(
+                    native_res,
+                    Err(SimulationCheckError::StorageAccessViolation { address, .. }) if address == token
+                ));
+            } else {
+                assert!(native_res.is_ok());
+            }
+        }
+    }
+}
@@ -1,65 +1,359 @@
-use ethers::types::{Address, U256};
-use silius_contracts::entry_point::SimulateValidationResult;
+use ethers::{
+    types::{Address, BigEndianHash, Bytes, H256, U256},
+    utils::keccak256,
+};
+use silius_contracts::{
+    entry_point::SimulateValidationResult,
+    tracer::{JsTracerFrame, ReadsAndWrites},
+};
 use silius_primitives::{
-    get_address, reputation::StakeInfo, simulation::NUMBER_LEVELS, UserOperation,
+    get_address,
+    reputation::StakeInfo,
+    sanity::SanityCheckError,
+    simulation::{ValidationResultInfo, NUMBER_LEVELS},
+    UserOperation,
 };
+use std::collections::{HashMap, HashSet};
+
+/// Base slots of the mappings keyed by the entity checked even without the keccak preimage in the trace
+pub const MAPPING_BASE_SLOTS: u64 = 64;
+/// Number of slots of the mapping value (struct) associated with the entity
+pub const MAPPING_VALUE_SLOTS: u64 = 128;
 
 pub fn extract_verification_gas_limit(sim_res: &SimulateValidationResult) -> U256 {
-    match sim_res {
-        SimulateValidationResult::ValidationResult(res) => res.return_info.0,
-        SimulateValidationResult::ValidationResultWithAggregation(res) => res.return_info.0,
-    }
+    ValidationResultInfo::from(sim_res).return_info.pre_op_gas
 }
 
 pub fn extract_pre_fund(sim_res: &SimulateValidationResult) -> U256 {
-    match sim_res {
-        SimulateValidationResult::ValidationResult(res) => res.return_info.1,
-        SimulateValidationResult::ValidationResultWithAggregation(res) => res.return_info.1,
-    }
+    ValidationResultInfo::from(sim_res).return_info.prefund
 }
 
 pub fn extract_timestamps(sim_res: &SimulateValidationResult) -> (U256, U256) {
-    match sim_res {
-        SimulateValidationResult::ValidationResult(res) => {
-            (res.return_info.3.into(), res.return_info.4.into())
-        }
-        SimulateValidationResult::ValidationResultWithAggregation(res) => {
-            (res.return_info.3.into(), res.return_info.4.into())
-        }
-    }
+    let return_info = ValidationResultInfo::from(sim_res).return_info;
+    (
+        return_info.valid_after.into(),
+        return_info.valid_until.into(),
+    )
+}
+
+/// Returns the stake info of the aggregator if the account uses aggregated signatures
+pub fn extract_aggregator(sim_res: &SimulateValidationResult) -> Option<StakeInfo> {
+    ValidationResultInfo::from(sim_res)
+        .aggregator_info
+        .filter(|info| !info.aggregator.is_zero())
+        .map(|info| StakeInfo {
+            address: info.aggregator,
+            stake: info.stake_info.stake,
+            unstake_delay: info.stake_info.unstake_delay,
+        })
 }
 
 pub fn extract_stake_info(
     uo: &UserOperation,
     sim_res: &SimulateValidationResult,
 ) -> [StakeInfo; NUMBER_LEVELS] {
-    let (f_info, s_info, p_info) = match sim_res {
-        SimulateValidationResult::ValidationResult(res) => {
-            (res.factory_info, res.sender_info, res.paymaster_info)
-        }
-        SimulateValidationResult::ValidationResultWithAggregation(res) => {
-            (res.factory_info, res.sender_info, res.paymaster_info)
-        }
-    };
+    let info = ValidationResultInfo::from(sim_res);
 
     [
         // factory
         StakeInfo {
             address: get_address(&uo.init_code).unwrap_or(Address::zero()),
-            stake: f_info.0,
-            unstake_delay: f_info.1,
+            stake: info.factory_info.stake,
+            unstake_delay: info.factory_info.unstake_delay,
         },
         // account
         StakeInfo {
             address: uo.sender,
-            stake: s_info.0,
-            unstake_delay: s_info.1,
+            stake: info.sender_info.stake,
+            unstake_delay: info.sender_info.unstake_delay,
         },
         // paymaster
         StakeInfo {
             address: get_address(&uo.paymaster_and_data).unwrap_or(Address::zero()),
-            stake: p_info.0,
-            unstake_delay: p_info.1,
+            stake: info.paymaster_info.stake,
+            unstake_delay: info.paymaster_info.unstake_delay,
         },
     ]
 }
+
+/// Verifies the stake of the entity (from `getDepositInfo` of the entry point) against the configured minimums
+///
+/// The stake being withdrawn (non-zero `withdrawTime`) doesn't count.
+pub fn verify_stake(
+    entity: &str,
+    info: StakeInfo,
+    withdraw_time: u64,
+    min_stake: U256,
+    min_unstake_delay: U256,
+) -> Result<(), SanityCheckError> {
+    let stake = if withdraw_time == 0 {
+        info.stake
+    } else {
+        U256::zero()
+    };
+
+    if stake < min_stake {
+        return Err(SanityCheckError::StakeTooLow {
+            entity: entity.into(),
+            address: info.address,
+            stake,
+            min_stake,
+        });
+    }
+
+    if info.unstake_delay < min_unstake_delay {
+        return Err(SanityCheckError::UnstakeDelayTooShort {
+            entity: entity.into(),
+            address: info.address,
+            unstake_delay: info.unstake_delay,
+            min_unstake_delay,
+        });
+    }
+
+    Ok(())
+}
+
+/// Storage slots accessed (read or written) during the validation by the address of the contract
+///
+/// Slots that can't be parsed are left out.
+pub fn accessed_slots(frame: &JsTracerFrame) -> HashMap<Address, HashSet<H256>> {
+    let mut slots: HashMap<Address, HashSet<H256>> = HashMap::new();
+
+    for level in frame.number_levels.iter() {
+        for (addr, acc) in level.access.iter() {
+            for slot in acc.reads.keys().chain(acc.writes.keys()) {
+                if let Ok(slot) = U256::from_str_radix(slot, 16) {
+                    slots
+                        .entry(*addr)
+                        .or_default()
+                        .insert(H256::from_uint(&slot));
+                }
+            }
+        }
+    }
+
+    slots
+}
+
+/// Accessed storage slots associated with the entity
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AssociatedSlots {
+    /// Slots in the storage of the entity itself
+    pub own: HashSet<String>,
+    /// Slots of the mappings keyed by the entity in the storage of other contracts
+    pub mapping: HashSet<String>,
+}
+
+/// Derives the storage slots associated with the entity (ERC-7562)
+///
+/// A slot is associated with the entity if it's the address of the entity, or if it's `keccak256(A . x) + n` with `A`
+/// the entity address padded to 32 bytes and `n` in `[0, 128)` (the mapping keyed by the entity with the value
+/// occupying up to 128 slots). The base slots `x` are taken from the keccak preimages recorded by the tracer and the
+/// first [MAPPING_BASE_SLOTS] slots are always checked (mappings declared at the start of the storage layout).
+#[derive(Debug, Clone)]
+pub struct SlotAssociation {
+    pub entity: Address,
+    mapping_slots: HashSet<U256>,
+}
+
+impl SlotAssociation {
+    pub fn new(entity: Address, keccak: &[Bytes]) -> Self {
+        let mut mapping_slots = HashSet::new();
+
+        if !entity.is_zero() {
+            let entity_b = [[0; 12].as_slice(), entity.as_bytes()].concat();
+
+            for kecc in keccak.iter().filter(|kecc| kecc.starts_with(&entity_b)) {
+                mapping_slots.insert(U256::from(keccak256(kecc)));
+            }
+
+            for base in 0..MAPPING_BASE_SLOTS {
+                let mut base_b = [0; 32];
+                U256::from(base).to_big_endian(&mut base_b);
+                mapping_slots.insert(U256::from(keccak256(
+                    [entity_b.as_slice(), &base_b].concat(),
+                )));
+            }
+        }
+
+        Self {
+            entity,
+            mapping_slots,
+        }
+    }
+
+    fn is_mapping_slot(&self, slot: U256) -> bool {
+        self.mapping_slots
+            .iter()
+            .any(|base| slot >= *base && slot - *base < U256::from(MAPPING_VALUE_SLOTS))
+    }
+
+    /// Whether the slot (hex string from the trace) is associated with the entity
+    pub fn is_associated(&self, slot: &str) -> bool {
+        // slots that can't be parsed are never associated with the entity
+        let slot = match U256::from_str_radix(slot, 16) {
+            Ok(slot) => slot,
+            Err(_) => return false,
+        };
+
+        if self.entity.is_zero() {
+            return false;
+        }
+
+        // the slot is the address itself
+        slot == U256::from(self.entity.as_bytes()) || self.is_mapping_slot(slot)
+    }
+
+    /// Splits the accessed slots of the trace into the entity's own slots and the slots of mappings keyed by the
+    /// entity (slots that aren't associated with the entity are left out)
+    pub fn associated_slots(&self, access: &HashMap<Address, ReadsAndWrites>) -> AssociatedSlots {
+        let mut slots = AssociatedSlots::default();
+
+        for (addr, acc) in access {
+            for slot in acc.reads.keys().chain(acc.writes.keys()) {
+                if *addr == self.entity {
+                    slots.own.insert(slot.clone());
+                } else if self.is_associated(slot) {
+                    slots.mapping.insert(slot.clone());
+                }
+            }
+        }
+
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::AbiEncode;
+    use silius_contracts::tracer::Level;
+
+    fn mapping_slot(addr: &Address, base: u64, offset: u64) -> String {
+        let slot = U256::from(keccak256((*addr, U256::from(base)).encode())) + offset;
+        format!("{:?}", H256::from_uint(&slot))
+    }
+
+    #[test]
+    fn stake_verification() {
+        let info = StakeInfo {
+            address: Address::random(),
+            stake: 100.into(),
+            unstake_delay: 86400.into(),
+        };
+        assert!(verify_stake("paymaster", info, 0, 100.into(), 86400.into()).is_ok());
+        assert!(matches!(
+            verify_stake("paymaster", info, 0, 101.into(), 86400.into()),
+            Err(SanityCheckError::StakeTooLow { stake, .. }) if stake == U256::from(100)
+        ));
+        assert!(matches!(
+            verify_stake("factory", info, 0, 100.into(), 86401.into()),
+            Err(SanityCheckError::UnstakeDelayTooShort { entity, .. }) if entity == "factory"
+        ));
+
+        // stake being withdrawn
+        assert!(matches!(
+            verify_stake("paymaster", info, 1_700_000_000, 100.into(), 86400.into()),
+            Err(SanityCheckError::StakeTooLow { stake, .. }) if stake.is_zero()
+        ));
+    }
+
+    #[test]
+    fn slot_association() {
+        let sender = Address::random();
+        let token = Address::random();
+        let association = SlotAssociation::new(sender, &[]);
+
+        // mappings at the start of the storage layout (e.g. balanceOf at slot 0 or 3)
+        assert!(association.is_associated(&mapping_slot(&sender, 0, 0)));
+        assert!(association.is_associated(&mapping_slot(&sender, 3, 127)));
+        assert!(!association.is_associated(&mapping_slot(&sender, 3, 128)));
+        assert!(!association.is_associated(&mapping_slot(&token, 0, 0)));
+        assert!(association.is_associated(&format!("{:?}", H256::from(sender))));
+        assert!(!association.is_associated("0x01"));
+        assert!(!association.is_associated("not a slot"));
+
+        // mapping at a base slot known only from the keccak preimage of the trace
+        assert!(!association.is_associated(&mapping_slot(&sender, 1000, 1)));
+        let association =
+            SlotAssociation::new(sender, &[Bytes::from((sender, U256::from(1000)).encode())]);
+        assert!(association.is_associated(&mapping_slot(&sender, 1000, 1)));
+
+        // zero address (e.g. no paymaster) has no associated slots
+        assert!(!SlotAssociation::new(Address::zero(), &[]).is_associated("0x00"));
+
+        let access = HashMap::from([
+            (
+                sender,
+                ReadsAndWrites {
+                    reads: HashMap::from([("0x00".to_string(), 1)]),
+                    ..Default::default()
+                },
+            ),
+            (
+                token,
+                ReadsAndWrites {
+                    reads: HashMap::from([(mapping_slot(&sender, 0, 0), 1)]),
+                    writes: HashMap::from([("0x02".to_string(), 1)]),
+                },
+            ),
+        ]);
+        assert_eq!(
+            association.associated_slots(&access),
+            AssociatedSlots {
+                own: HashSet::from(["0x00".to_string()]),
+                mapping: HashSet::from([mapping_slot(&sender, 0, 0)]),
+            }
+        );
+    }
+
+    #[test]
+    fn accessed_slots_of_levels() {
+        let (account, token) = (Address::random(), Address::random());
+        let frame = JsTracerFrame {
+            number_levels: vec![
+                Level {
+                    access: HashMap::from([(
+                        account,
+                        ReadsAndWrites {
+                            reads: HashMap::from([("0x01".to_string(), 1)]),
+                            writes: HashMap::from([("not a slot".to_string(), 1)]),
+                        },
+                    )]),
+                    ..Default::default()
+                },
+                Level {
+                    access: HashMap::from([
+                        (
+                            account,
+                            ReadsAndWrites {
+                                writes: HashMap::from([("0x02".to_string(), 1)]),
+                                ..Default::default()
+                            },
+                        ),
+                        (
+                            token,
+                            ReadsAndWrites {
+                                reads: HashMap::from([("0x01".to_string(), 2)]),
+                                ..Default::default()
+                            },
+                        ),
+                    ]),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            accessed_slots(&frame),
+            HashMap::from([
+                (
+                    account,
+                    HashSet::from([H256::from_low_u64_be(1), H256::from_low_u64_be(2)])
+                ),
+                (token, HashSet::from([H256::from_low_u64_be(1)])),
+            ])
+        );
+    }
+}
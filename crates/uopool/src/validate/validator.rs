@@ -1,26 +1,64 @@
 use super::{
-    utils::{extract_pre_fund, extract_verification_gas_limit},
+    cache::{SimulationCache, SimulationCacheEntry},
+    retry::RetryPolicy,
+    sanity::{
+        call_gas::CallGas,
+        factory::{Factory, MAX_UOS_PER_UNSTAKED_FACTORY},
+        fee_bounds::FeeBounds,
+        max_fee::MaxFee,
+        nonce::NonceCheck,
+        paymaster::Paymaster,
+        paymaster_gas::PaymasterGasLimits,
+        sender::SenderOrInitCode,
+        sender_uos::{SenderUos, DEFAULT_MAX_UOS_PER_UNSTAKED_SENDER},
+        size::SizeCheck,
+        verification_gas::VerificationGas,
+    },
+    simulation::{
+        aggregator::Aggregator, delegation::Delegation, signature::Signature, timestamp::Timestamp,
+    },
+    simulation_trace::{
+        call_stack::CallStack, code_hashes::CodeHashes, entry_point_calls::EntryPointCalls,
+        gas::Gas, opcodes::Opcodes, sender_deployment::SenderDeployment,
+        storage_access::StorageAccess,
+    },
+    utils::{
+        accessed_slots, extract_aggregator, extract_pre_fund, extract_timestamps,
+        extract_verification_gas_limit,
+    },
     SanityCheck, SanityHelper, SimulationCheck, SimulationHelper, SimulationTraceCheck,
     SimulationTraceHelper, UserOperationValidationOutcome, UserOperationValidator,
     UserOperationValidatorMode,
 };
 use crate::{
     mempool::MempoolBox,
+    pre_verification_gas_calculator,
     reputation::ReputationBox,
     uopool::{VecCh, VecUo},
+    Overhead,
 };
 use enumset::EnumSet;
-use ethers::{providers::Middleware, types::GethTrace};
+use ethers::{
+    providers::Middleware,
+    types::{spoof, U256, U64},
+};
 use silius_contracts::{
     entry_point::{EntryPointErr, SimulateValidationResult},
     tracer::JsTracerFrame,
     EntryPoint,
 };
 use silius_primitives::{
-    reputation::ReputationEntry, simulation::SimulationCheckError, uopool::ValidationError, Chain,
-    UserOperation,
+    get_address,
+    metrics::METRICS,
+    reputation::ReputationEntry,
+    simulation::SimulationCheckError,
+    uopool::{ValidationError, GAS_INCREASE_PERC},
+    Chain, ChainSpec, UserOperation, UserOperationHash,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
 };
-use std::sync::Arc;
 
 pub struct StandardUserOperationValidator<M: Middleware + Clone + 'static> {
     eth_client: Arc<M>,
@@ -29,6 +67,9 @@ pub struct StandardUserOperationValidator<M: Middleware + Clone + 'static> {
     sanity_checks: Vec<Box<dyn SanityCheck<M>>>,
     simulation_checks: Vec<Box<dyn SimulationCheck<M>>>,
     simulation_trace_checks: Vec<Box<dyn SimulationTraceCheck<M>>>,
+    simulation_cache: Option<Mutex<SimulationCache>>,
+    retry_policy: RetryPolicy,
+    native_tracer: bool,
 }
 
 impl<M: Middleware + Clone + 'static> StandardUserOperationValidator<M> {
@@ -40,19 +81,156 @@ impl<M: Middleware + Clone + 'static> StandardUserOperationValidator<M> {
             sanity_checks: vec![],
             simulation_checks: vec![],
             simulation_trace_checks: vec![],
+            simulation_cache: None,
+            retry_policy: RetryPolicy::default(),
+            native_tracer: false,
+        }
+    }
+
+    /// Validator with the checks of the specification (sanity, simulation and simulation trace) with the default
+    /// limits, operator specific checks (access lists, operator paymaster, etc.) aren't included
+    pub fn new_canonical(
+        eth_client: Arc<M>,
+        entry_point: EntryPoint<M>,
+        chain: Chain,
+        max_verification_gas: U256,
+        min_stake: U256,
+        min_unstake_delay: U256,
+        min_priority_fee_per_gas: U256,
+    ) -> Self {
+        let chain_spec = ChainSpec::known(chain.id()).unwrap_or_default();
+        let overhead = Overhead::from(&chain_spec).with_calculator(
+            pre_verification_gas_calculator(&chain_spec, eth_client.clone()),
+        );
+
+        Self::new(eth_client, entry_point, chain)
+            .with_sanity_check(SenderOrInitCode)
+            .with_sanity_check(SizeCheck::default())
+            .with_sanity_check(NonceCheck)
+            .with_sanity_check(VerificationGas {
+                max_verification_gas,
+                overhead,
+            })
+            .with_sanity_check(Paymaster {
+                min_stake,
+                min_unstake_delay,
+            })
+            .with_sanity_check(PaymasterGasLimits {
+                max_verification_gas,
+                max_post_op_gas: max_verification_gas,
+            })
+            .with_sanity_check(CallGas {
+                safety_margin_perc: U256::zero(),
+            })
+            .with_sanity_check(MaxFee)
+            .with_sanity_check(FeeBounds {
+                min_priority_fee_per_gas: min_priority_fee_per_gas
+                    .max(chain_spec.min_priority_fee_per_gas),
+                max_priority_fee_per_gas: None,
+            })
+            .with_sanity_check(SenderUos {
+                max_uos_per_unstaked_sender: DEFAULT_MAX_UOS_PER_UNSTAKED_SENDER,
+                gas_increase_perc: GAS_INCREASE_PERC.into(),
+            })
+            .with_sanity_check(Factory {
+                max_uos_per_unstaked_factory: MAX_UOS_PER_UNSTAKED_FACTORY,
+            })
+            .with_simulation_check(Signature)
+            .with_simulation_check(Timestamp)
+            .with_simulation_check(Aggregator)
+            .with_simulation_check(Delegation)
+            .with_simulation_trace_check(Gas)
+            .with_simulation_trace_check(Opcodes::default())
+            .with_simulation_trace_check(StorageAccess::default())
+            .with_simulation_trace_check(CallStack)
+            .with_simulation_trace_check(EntryPointCalls)
+            .with_simulation_trace_check(SenderDeployment)
+            .with_simulation_trace_check(CodeHashes)
+    }
+
+    /// Caches the simulation results (and traces) of user operations until the block advances
+    pub fn with_simulation_cache(mut self) -> Self {
+        self.simulation_cache = Some(Mutex::new(SimulationCache::default()));
+        self
+    }
+
+    /// Timeout and retries of the simulation calls to the execution client
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Traces the validation with the native `callTracer` and `prestateTracer` instead of the JavaScript tracer
+    ///
+    /// Faster and supported by more nodes, but opcodes other than `CREATE*` and `SELFDESTRUCT` aren't traced, so
+    /// the forbidden opcode rules can't be enforced, and storage of mappings at base slots known only from keccak
+    /// preimages isn't associated with the entities.
+    pub fn with_native_tracer(mut self) -> Self {
+        self.native_tracer = true;
+        self
+    }
+
+    /// Key of the user operation in the simulation cache (none if the cache is disabled or the block number
+    /// can't be fetched)
+    async fn simulation_cache_key(&self, uo: &UserOperation) -> Option<(UserOperationHash, U64)> {
+        self.simulation_cache.as_ref()?;
+        let block_number = self.eth_client.get_block_number().await.ok()?;
+        Some((
+            uo.hash(&self.entry_point.address(), &self.chain.id().into()),
+            block_number,
+        ))
+    }
+
+    fn get_cached_simulation(
+        &self,
+        key: &Option<(UserOperationHash, U64)>,
+    ) -> Option<SimulationCacheEntry> {
+        let (cache, (uo_hash, block_number)) = self.simulation_cache.as_ref().zip(key.as_ref())?;
+        let entry = cache
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(uo_hash, *block_number);
+        METRICS
+            .simulation_cache
+            .inc(if entry.is_some() { "hit" } else { "miss" });
+        entry
+    }
+
+    fn cache_simulation(
+        &self,
+        key: &Option<(UserOperationHash, U64)>,
+        entry: SimulationCacheEntry,
+    ) {
+        if let Some((cache, (uo_hash, block_number))) =
+            self.simulation_cache.as_ref().zip(key.as_ref())
+        {
+            cache.lock().unwrap_or_else(|err| err.into_inner()).insert(
+                *uo_hash,
+                *block_number,
+                entry,
+            );
         }
     }
 
     async fn simulate_validation(
         &self,
+        entry_point: &EntryPoint<M>,
         uo: &UserOperation,
     ) -> Result<SimulateValidationResult, SimulationCheckError> {
-        match self.entry_point.simulate_validation(uo.clone()).await {
+        let start = Instant::now();
+        let res = self
+            .retry_policy
+            .call(|| entry_point.simulate_validation(uo.clone()))
+            .await;
+        METRICS.simulation.observe(start.elapsed());
+
+        match res {
             Ok(res) => Ok(res),
             Err(err) => match err {
                 EntryPointErr::FailedOp(f) => {
                     Err(SimulationCheckError::Validation { message: f.reason })
                 }
+                EntryPointErr::Timeout(message) => Err(SimulationCheckError::Timeout { message }),
                 _ => Err(SimulationCheckError::UnknownError {
                     message: "Error when simulating validation on entry point".to_string(),
                 }),
@@ -62,14 +240,37 @@ impl<M: Middleware + Clone + 'static> StandardUserOperationValidator<M> {
 
     async fn simulate_validation_trace(
         &self,
+        entry_point: &EntryPoint<M>,
         uo: &UserOperation,
-    ) -> Result<GethTrace, SimulationCheckError> {
-        match self.entry_point.simulate_validation_trace(uo.clone()).await {
-            Ok(trace) => Ok(trace),
+    ) -> Result<JsTracerFrame, SimulationCheckError> {
+        let res = if self.native_tracer {
+            self.retry_policy
+                .call(|| entry_point.simulate_validation_native_trace(uo.clone()))
+                .await
+                .map(|(call, prestate)| {
+                    JsTracerFrame::try_from_native(
+                        call,
+                        prestate,
+                        uo.sender,
+                        get_address(&uo.paymaster_and_data),
+                    )
+                })
+        } else {
+            self.retry_policy
+                .call(|| entry_point.simulate_validation_trace(uo.clone()))
+                .await
+                .map(JsTracerFrame::try_from)
+        };
+
+        match res {
+            Ok(frame) => frame.map_err(|error| SimulationCheckError::Validation {
+                message: error.to_string(),
+            }),
             Err(err) => match err {
                 EntryPointErr::FailedOp(f) => {
                     Err(SimulationCheckError::Validation { message: f.reason })
                 }
+                EntryPointErr::Timeout(message) => Err(SimulationCheckError::Timeout { message }),
                 _ => Err(SimulationCheckError::UnknownError {
                     message: "Error when simulating validation on entry point".to_string(),
                 }),
@@ -98,26 +299,34 @@ impl<M: Middleware + Clone + 'static> StandardUserOperationValidator<M> {
             .push(Box::new(simulation_trace_check));
         self
     }
-}
 
-#[async_trait::async_trait]
-impl<M: Middleware + Clone + 'static> UserOperationValidator for StandardUserOperationValidator<M> {
-    async fn validate_user_operation(
+    /// Runs the checks, the simulations run with the state overrides (if any) and then aren't cached
+    async fn validate(
         &self,
         uo: &UserOperation,
         mempool: &MempoolBox<VecUo, VecCh>,
         reputation: &ReputationBox<Vec<ReputationEntry>>,
         mode: EnumSet<UserOperationValidatorMode>,
+        state_override: Option<&spoof::State>,
     ) -> Result<UserOperationValidationOutcome, ValidationError> {
         let mut out: UserOperationValidationOutcome = Default::default();
 
+        let entry_point = match state_override {
+            Some(state_override) => self
+                .entry_point
+                .clone()
+                .with_state_override(state_override.clone()),
+            None => self.entry_point.clone(),
+        };
+
         if !self.sanity_checks.is_empty() && mode.contains(UserOperationValidatorMode::Sanity) {
             let mut sanity_helper = SanityHelper {
                 mempool,
                 reputation,
                 eth_client: self.eth_client.clone(),
-                entry_point: self.entry_point.clone(),
+                entry_point: entry_point.clone(),
                 chain: self.chain,
+                retry_policy: self.retry_policy,
             };
 
             for sanity_check in self.sanity_checks.iter() {
@@ -131,7 +340,17 @@ impl<M: Middleware + Clone + 'static> UserOperationValidator for StandardUserOpe
             out.prev_hash = Some(uo.hash(&self.entry_point.address(), &self.chain.id().into()));
         }
 
-        let sim_res = self.simulate_validation(uo).await?;
+        let cache_key = match state_override {
+            Some(_) => None,
+            None => self.simulation_cache_key(uo).await,
+        };
+        let cached = self.get_cached_simulation(&cache_key);
+
+        let sim_res = match cached.as_ref() {
+            Some(entry) => entry.sim_res.clone(),
+            None => self.simulate_validation(&entry_point, uo).await?,
+        };
+        let mut js_trace = cached.and_then(|entry| entry.js_trace);
 
         if !self.simulation_checks.is_empty()
             && mode.contains(UserOperationValidatorMode::Simulation)
@@ -140,7 +359,7 @@ impl<M: Middleware + Clone + 'static> UserOperationValidator for StandardUserOpe
                 mempool,
                 reputation,
                 eth_client: self.eth_client.clone(),
-                entry_point: self.entry_point.clone(),
+                entry_point: entry_point.clone(),
                 chain: self.chain,
                 simulate_validation_result: &sim_res,
                 valid_after: None,
@@ -155,25 +374,25 @@ impl<M: Middleware + Clone + 'static> UserOperationValidator for StandardUserOpe
 
         out.pre_fund = extract_pre_fund(&sim_res);
         out.verification_gas_limit = extract_verification_gas_limit(&sim_res);
+        out.valid_until = Some(extract_timestamps(&sim_res).1);
+        out.aggregator = extract_aggregator(&sim_res).map(|info| info.address);
 
         if !self.simulation_trace_checks.is_empty()
             && mode.contains(UserOperationValidatorMode::SimulationTrace)
         {
-            let geth_trace = self.simulate_validation_trace(uo).await?;
-            let js_trace: JsTracerFrame = JsTracerFrame::try_from(geth_trace).map_err(|error| {
-                SimulationCheckError::Validation {
-                    message: error.to_string(),
-                }
-            })?;
+            let frame = match js_trace.take() {
+                Some(frame) => frame,
+                None => self.simulate_validation_trace(&entry_point, uo).await?,
+            };
 
             let mut sim_helper = SimulationTraceHelper {
                 mempool,
                 reputation,
                 eth_client: self.eth_client.clone(),
-                entry_point: self.entry_point.clone(),
+                entry_point: entry_point.clone(),
                 chain: self.chain,
                 simulate_validation_result: &sim_res,
-                js_trace: &js_trace,
+                js_trace: &frame,
                 stake_info: None,
                 code_hashes: None,
             };
@@ -183,8 +402,145 @@ impl<M: Middleware + Clone + 'static> UserOperationValidator for StandardUserOpe
             }
 
             out.code_hashes = sim_helper.code_hashes;
+            out.accessed_slots = Some(accessed_slots(&frame));
+            js_trace = Some(frame);
         }
 
+        self.cache_simulation(&cache_key, SimulationCacheEntry { sim_res, js_trace });
+
         Ok(out)
     }
 }
+
+#[async_trait::async_trait]
+impl<M: Middleware + Clone + 'static> UserOperationValidator for StandardUserOperationValidator<M> {
+    async fn validate_user_operation(
+        &self,
+        uo: &UserOperation,
+        mempool: &MempoolBox<VecUo, VecCh>,
+        reputation: &ReputationBox<Vec<ReputationEntry>>,
+        mode: EnumSet<UserOperationValidatorMode>,
+    ) -> Result<UserOperationValidationOutcome, ValidationError> {
+        self.validate(uo, mempool, reputation, mode, None).await
+    }
+
+    async fn validate_user_operation_with_state_override(
+        &self,
+        uo: &UserOperation,
+        mempool: &MempoolBox<VecUo, VecCh>,
+        reputation: &ReputationBox<Vec<ReputationEntry>>,
+        mode: EnumSet<UserOperationValidatorMode>,
+        state_override: &spoof::State,
+    ) -> Result<UserOperationValidationOutcome, ValidationError> {
+        self.validate(uo, mempool, reputation, mode, Some(state_override))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{utils::tests::MockContext, validate::tests::mock_simulate_validation_result};
+    use ethers::{providers::Provider, types::Address};
+    use silius_primitives::consts::entry_point::ADDRESS;
+
+    #[tokio::test]
+    async fn simulation_cache() {
+        let ctx = MockContext::default();
+        let mock = &ctx.mock;
+        let validator = StandardUserOperationValidator::new(
+            ctx.eth_client.clone(),
+            EntryPoint::new(ctx.eth_client.clone(), ADDRESS.parse().unwrap()),
+            Chain::from(1337_u64),
+        )
+        .with_simulation_cache();
+        let uo = UserOperation::default().sender(Address::random());
+        let uo_hash = uo.hash(&ADDRESS.parse().unwrap(), &1337.into());
+
+        validator
+            .simulation_cache
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .insert(
+                uo_hash,
+                1.into(),
+                SimulationCacheEntry {
+                    sim_res: mock_simulate_validation_result(0, 100),
+                    js_trace: None,
+                },
+            );
+        let hits = METRICS.simulation_cache.get("hit");
+
+        // cache hit at the same block doesn't simulate again (the mock has no simulation response)
+        mock.push(U64::from(1)).unwrap();
+        let out = validator
+            .validate_user_operation(
+                &uo,
+                &ctx.mempool,
+                &ctx.reputation,
+                UserOperationValidatorMode::Simulation.into(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(out.valid_until, Some(100.into()));
+        assert!(METRICS.simulation_cache.get("hit") > hits);
+
+        // the cache is invalidated by the next block, so the simulation is called
+        mock.push(U64::from(2)).unwrap();
+        assert!(validator
+            .validate_user_operation(
+                &uo,
+                &ctx.mempool,
+                &ctx.reputation,
+                UserOperationValidatorMode::Simulation.into(),
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn standalone_validation() {
+        let (eth_client, mock) = Provider::mocked();
+        let eth_client = Arc::new(eth_client);
+        let validator = StandardUserOperationValidator::new(
+            eth_client.clone(),
+            EntryPoint::new(eth_client, ADDRESS.parse().unwrap()),
+            Chain::from(1337_u64),
+        )
+        .with_simulation_cache()
+        .with_simulation_check(Timestamp);
+        let uo = UserOperation::default().sender(Address::random());
+
+        validator
+            .simulation_cache
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .insert(
+                uo.hash(&ADDRESS.parse().unwrap(), &1337.into()),
+                1.into(),
+                SimulationCacheEntry {
+                    sim_res: mock_simulate_validation_result(4_000_000_000, u64::MAX),
+                    js_trace: None,
+                },
+            );
+
+        // validated without a mempool, the outcome carries the parsed validation result
+        mock.push(U64::from(1)).unwrap();
+        let out = crate::validate::validate_user_operation(
+            &validator,
+            &uo,
+            U256::from(1),
+            U256::zero(),
+            UserOperationValidatorMode::Simulation.into(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(out.valid_after, Some(4_000_000_000_u64.into()));
+        assert_eq!(out.valid_until, Some(u64::MAX.into()));
+        assert_eq!(out.aggregator, None);
+    }
+}
@@ -6,3 +6,5 @@ mod common;
 mod simulation_tests;
 #[cfg(test)]
 mod tracer_tests;
+#[cfg(test)]
+mod uopool_tests;
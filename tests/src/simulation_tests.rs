@@ -21,7 +21,8 @@ use ethers::{
 };
 use silius_contracts::EntryPoint;
 use silius_primitives::consts::entities::{ACCOUNT, FACTORY, PAYMASTER};
-use silius_primitives::simulation::SimulationCheckError;
+use silius_primitives::reputation::HOURLY_DECAY_DENOMINATOR;
+use silius_primitives::simulation::{EnabledEntities, SimulationCheckError};
 use silius_primitives::uopool::ValidationError;
 use silius_primitives::{Chain, UserOperation};
 use silius_uopool::validate::sanity::call_gas::CallGas;
@@ -41,7 +42,7 @@ use silius_uopool::validate::validator::StandardUserOperationValidator;
 use silius_uopool::validate::{
     UserOperationValidationOutcome, UserOperationValidator, UserOperationValidatorMode,
 };
-use silius_uopool::{mempool_id, MemoryMempool, MemoryReputation, Reputation, UoPool};
+use silius_uopool::{mempool_id, MemoryMempool, MemoryReputation, Overhead, Reputation, UoPool};
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
@@ -102,7 +103,14 @@ async fn setup(
     ep_map.insert(m_id, EntryPoint::new(client.clone(), ep.address));
     let mempools = Box::new(MemoryMempool::default());
     let mut reputation = Box::new(MemoryReputation::default());
-    reputation.init(10, 10, 10, 1u64.into(), 1u64.into());
+    reputation.init(
+        10,
+        10,
+        10,
+        HOURLY_DECAY_DENOMINATOR,
+        1u64.into(),
+        1u64.into(),
+    );
 
     let entry_point = EntryPoint::new(client.clone(), ep.address);
     let entry_point2 = EntryPoint::new(Arc::new(provider.clone()), ep.address);
@@ -113,12 +121,16 @@ async fn setup(
             .with_sanity_check(SenderOrInitCode {})
             .with_sanity_check(VerificationGas {
                 max_verification_gas: U256::from(1500000000_u64),
+                overhead: Overhead::default(),
+            })
+            .with_sanity_check(Paymaster {
+                min_stake: 1u64.into(),
+                min_unstake_delay: 1u64.into(),
             })
-            .with_sanity_check(Paymaster {})
-            .with_sanity_check(CallGas {})
-            .with_sanity_check(MaxFee {
-                min_priority_fee_per_gas: U256::from(1u64),
+            .with_sanity_check(CallGas {
+                safety_margin_perc: U256::zero(),
             })
+            .with_sanity_check(MaxFee)
             .with_sanity_check(SenderUos {
                 max_uos_per_unstaked_sender: MAX_UOS_PER_UNSTAKED_SENDER,
                 gas_increase_perc: GAS_INCREASE_PERC.into(),
@@ -126,8 +138,12 @@ async fn setup(
             .with_simulation_check(Signature {})
             .with_simulation_check(Timestamp {})
             .with_simulation_trace_check(Gas {})
-            .with_simulation_trace_check(Opcodes {})
-            .with_simulation_trace_check(StorageAccess {})
+            .with_simulation_trace_check(Opcodes {
+                entities: EnabledEntities::default(),
+            })
+            .with_simulation_trace_check(StorageAccess {
+                entities: EnabledEntities::default(),
+            })
             .with_simulation_trace_check(CallStack {})
             .with_simulation_trace_check(CodeHashes {});
 
@@ -490,7 +506,7 @@ async fn account_fail_to_read_allowance_of_address() {
     assert!(matches!(
         res,
         Err(ValidationError::Simulation(
-            SimulationCheckError::StorageAccess { .. }
+            SimulationCheckError::StorageAccessViolation { .. }
         ))
     ));
 }
@@ -513,7 +529,7 @@ async fn fail_to_access_other_address_struct_data() {
     assert!(matches!(
         res,
         Err(ValidationError::Simulation(
-            SimulationCheckError::StorageAccess { .. }
+            SimulationCheckError::StorageAccessViolation { .. }
         ))
     ));
 }
@@ -537,7 +553,7 @@ async fn fail_if_referencing_other_token_balance() -> anyhow::Result<()> {
     assert!(matches!(
         res,
         Err(ValidationError::Simulation(
-            SimulationCheckError::StorageAccess { .. }
+            SimulationCheckError::StorageAccessViolation { .. }
         ))
     ));
 
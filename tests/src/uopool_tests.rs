@@ -0,0 +1,1771 @@
+use anyhow::format_err;
+use enumset::EnumSet;
+use ethers::{
+    abi::{encode, AbiEncode, Token},
+    contract::EthEvent,
+    providers::{JsonRpcError, MockProvider, MockResponse, Provider},
+    types::{Address, Block, Bytes, Log, Transaction, TransactionReceipt, H256, U256, U64},
+};
+use silius_contracts::{
+    entry_point::{
+        BeforeExecutionFilter, UserOperationEventFilter, UserOperationRevertReasonFilter,
+    },
+    EntryPoint,
+};
+use silius_primitives::{
+    bundler::{AccountStorage, SubmissionMode},
+    consts::entry_point::ADDRESS,
+    reputation::{
+        ReputationEntry, ReputationStatus, BAN_SLACK, HOURLY_DECAY_DENOMINATOR,
+        MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+    },
+    sanity::SanityCheckError,
+    simulation::{CodeHash, SimulationCheckError, ERROR_SELECTOR},
+    uopool::{
+        AddError, InclusionEvent, OrderingPolicy, P2pOpsPolicy, UserOperationState,
+        UserOperationStatus, ValidationError,
+    },
+    CallGasEstimationMethod, Chain, UserOperation, UserOperationHash,
+};
+use silius_uopool::{
+    bundle::UnstakedEntityCaps,
+    validate::{
+        UserOperationValidationOutcome, UserOperationValidator, UserOperationValidatorMode,
+    },
+    DatabaseMempool, DatabaseReputation, MemoryMempool, MemoryReputation, Mempool, MempoolBox,
+    ReputationBox, UoPool, VecCh, VecUo, WriteMap,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+use tempdir::TempDir;
+
+struct MockValidator;
+
+/// Signature of the user operations rejected by the mock validator
+const INVALID_SIGNATURE: &[u8] = b"invalid";
+
+#[async_trait::async_trait]
+impl UserOperationValidator for MockValidator {
+    async fn validate_user_operation(
+        &self,
+        uo: &UserOperation,
+        _mempool: &MempoolBox<VecUo, VecCh>,
+        _reputation: &ReputationBox<Vec<ReputationEntry>>,
+        _mode: EnumSet<UserOperationValidatorMode>,
+    ) -> Result<UserOperationValidationOutcome, ValidationError> {
+        if uo.signature.as_ref() == INVALID_SIGNATURE {
+            return Err(SanityCheckError::Validation {
+                message: "invalid signature".into(),
+            }
+            .into());
+        }
+        Ok(UserOperationValidationOutcome::default())
+    }
+}
+
+/// Validator returning the storage slots accessed by the user operations of the senders
+struct SlotsValidator(HashMap<Address, HashMap<Address, HashSet<H256>>>);
+
+#[async_trait::async_trait]
+impl UserOperationValidator for SlotsValidator {
+    async fn validate_user_operation(
+        &self,
+        uo: &UserOperation,
+        _mempool: &MempoolBox<VecUo, VecCh>,
+        _reputation: &ReputationBox<Vec<ReputationEntry>>,
+        _mode: EnumSet<UserOperationValidatorMode>,
+    ) -> Result<UserOperationValidationOutcome, ValidationError> {
+        Ok(UserOperationValidationOutcome {
+            accessed_slots: self.0.get(&uo.sender).cloned(),
+            ..Default::default()
+        })
+    }
+}
+
+fn mock_uopool() -> (UoPool<Provider<MockProvider>, MockValidator>, MockProvider) {
+    mock_uopool_with(MockValidator)
+}
+
+fn mock_uopool_with<V: UserOperationValidator>(
+    validator: V,
+) -> (UoPool<Provider<MockProvider>, V>, MockProvider) {
+    let (eth_client, mock) = Provider::mocked();
+    let eth_client = Arc::new(eth_client);
+    let mut uopool = UoPool::new(
+        EntryPoint::new(eth_client.clone(), ADDRESS.parse().unwrap()),
+        validator,
+        Box::<MemoryMempool>::default(),
+        Box::<MemoryReputation>::default(),
+        eth_client,
+        U256::from(10_000_000),
+        Chain::from(1337_u64),
+    );
+    uopool.reputation.init(
+        MIN_INCLUSION_RATE_DENOMINATOR,
+        THROTTLING_SLACK,
+        BAN_SLACK,
+        HOURLY_DECAY_DENOMINATOR,
+        U256::zero(),
+        U256::zero(),
+    );
+    (uopool, mock)
+}
+
+#[tokio::test]
+async fn replace_user_operation() {
+    let (mut uopool, mock) = mock_uopool();
+
+    let uo = UserOperation::default()
+        .sender(Address::random())
+        .call_gas_limit(100_000.into())
+        .max_fee_per_gas(100.into())
+        .max_priority_fee_per_gas(10.into());
+    let uo_hash = uopool
+        .add_user_operation(uo.clone(), Some(Default::default()))
+        .await
+        .unwrap();
+
+    let res = UserOperationValidationOutcome {
+        prev_hash: Some(uo_hash),
+        ..Default::default()
+    };
+
+    let uo_low = uo
+        .clone()
+        .max_fee_per_gas(105.into())
+        .max_priority_fee_per_gas(11.into());
+    assert!(matches!(
+        uopool.add_user_operation(uo_low, Some(res.clone())).await,
+        Err(AddError::Verification(..))
+    ));
+    assert_eq!(uopool.get_all(), vec![uo.clone()]);
+
+    let uo_new = uo
+        .clone()
+        .max_fee_per_gas(110.into())
+        .max_priority_fee_per_gas(11.into());
+    let uo_new_hash = uopool
+        .add_user_operation(uo_new.clone(), Some(res))
+        .await
+        .unwrap();
+    assert_eq!(uopool.get_all(), vec![uo_new.clone()]);
+    assert_eq!(uopool.mempool.get_number_by_sender(&uo.sender), 1);
+    assert_eq!(uopool.get_user_operation_status(&uo_hash), None);
+    assert_eq!(
+        uopool.get_user_operation_status(&uo_new_hash),
+        Some(UserOperationStatus::Pending)
+    );
+
+    // simulation of the bundle
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    // balance and deposit of the sender covering the prefund
+    mock.push(U256::from(11_000_000)).unwrap();
+    mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+        .unwrap();
+    // on-chain nonce of the sender
+    mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+        .unwrap();
+    assert_eq!(
+        uopool
+            .bundle_user_operations(vec![uo_new.clone()], SubmissionMode::DropInvalid)
+            .await
+            .unwrap(),
+        vec![uo_new.clone()]
+    );
+
+    let res = UserOperationValidationOutcome {
+        prev_hash: Some(uo_new_hash),
+        ..Default::default()
+    };
+    let uo_bundled = uo_new
+        .clone()
+        .max_fee_per_gas(200.into())
+        .max_priority_fee_per_gas(20.into());
+    assert!(matches!(
+        uopool.add_user_operation(uo_bundled, Some(res)).await,
+        Err(AddError::Verification(..))
+    ));
+    assert_eq!(uopool.get_all(), vec![uo_new]);
+}
+
+#[tokio::test]
+async fn estimate_user_operation_gas_without_simulate_handle_op() {
+    let (uopool, mock) = mock_uopool();
+    let uo = UserOperation::default()
+        .sender(Address::random())
+        .init_code(Address::random().as_bytes().to_vec().into())
+        .max_fee_per_gas(100.into())
+        .max_priority_fee_per_gas(10.into());
+
+    // responses are returned in the reverse order
+    // binary search of the call gas with eth_call (every call succeeds)
+    for _ in 0..32 {
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    }
+    mock.push(Block::<H256> {
+        gas_limit: 30_000_000.into(),
+        ..Default::default()
+    })
+    .unwrap();
+    // simulateHandleOp reverts without data
+    mock.push_response(MockResponse::Error(JsonRpcError {
+        code: 3,
+        message: "execution reverted".into(),
+        data: None,
+    }));
+    // execution of the call data
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+
+    let gas = uopool.estimate_user_operation_gas(&uo, None).await.unwrap();
+    assert_eq!(
+        gas.call_gas_estimation_method,
+        Some(CallGasEstimationMethod::EthCall)
+    );
+    assert!(gas.call_gas_limit > U256::from(21_000));
+    assert!(gas.call_gas_limit <= U256::from(22_000));
+}
+
+#[tokio::test]
+async fn estimate_user_operation_gas_revert_reason() {
+    let (uopool, mock) = mock_uopool();
+    let uo = UserOperation::default().sender(Address::random());
+    let revert_data: Bytes = [
+        silius_primitives::simulation::ERROR_SELECTOR.to_vec(),
+        "insufficient funds".to_string().encode(),
+    ]
+    .concat()
+    .into();
+
+    // execution of the call data reverts
+    mock.push_response(MockResponse::Error(JsonRpcError {
+        code: 3,
+        message: "execution reverted: insufficient funds".into(),
+        data: Some(serde_json::to_value(&revert_data).unwrap()),
+    }));
+
+    match uopool.estimate_user_operation_gas(&uo, None).await {
+        Err(SimulationCheckError::Execution {
+            reason,
+            revert_data: data,
+            ..
+        }) => {
+            assert_eq!(reason, Some("insufficient funds".to_string()));
+            assert_eq!(data, revert_data);
+        }
+        Err(err) => panic!("unexpected estimation error: {err:?}"),
+        Ok(_) => panic!("estimation should fail"),
+    }
+}
+
+#[tokio::test]
+async fn estimate_user_operation_gas_binary_search() {
+    let (uopool, mock) = mock_uopool();
+    let uo = UserOperation::default()
+        .sender(Address::random())
+        .max_fee_per_gas(100.into())
+        .max_priority_fee_per_gas(10.into());
+    let out_of_gas = || {
+        MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: "execution reverted".into(),
+            data: None,
+        })
+    };
+    let block = Block::<H256> {
+        gas_limit: 1_000_000.into(),
+        ..Default::default()
+    };
+
+    // responses are returned in the reverse order
+    // binary search with the call succeeding only with at least 100k gas
+    let (mut lo, mut hi) = (21_000_u64, 1_000_000_u64);
+    let mut responses = vec![];
+    while hi - lo > 1_000 {
+        let mid = (lo + hi) / 2;
+        if mid >= 100_000 {
+            hi = mid;
+            responses.push(MockResponse::Value(
+                serde_json::to_value(Bytes::default()).unwrap(),
+            ));
+        } else {
+            lo = mid;
+            responses.push(out_of_gas());
+        }
+    }
+    for res in responses.into_iter().rev() {
+        mock.push_response(res);
+    }
+    // call with the block gas limit
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    mock.push(block.clone()).unwrap();
+    // execution of the call data
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+
+    let gas = uopool.estimate_user_operation_gas(&uo, None).await.unwrap();
+    assert_eq!(
+        gas.call_gas_estimation_method,
+        Some(CallGasEstimationMethod::EthCall)
+    );
+    assert_eq!(gas.call_gas_limit, U256::from(hi));
+    assert!(gas.call_gas_limit >= U256::from(100_000));
+    assert!(gas.call_gas_limit <= U256::from(101_000));
+
+    // reverts even with the block gas limit
+    mock.push_response(out_of_gas());
+    mock.push(block).unwrap();
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    assert!(matches!(
+        uopool.estimate_user_operation_gas(&uo, None).await,
+        Err(SimulationCheckError::RevertedAtMaxGas { max_gas, .. }) if max_gas == U256::from(1_000_000)
+    ));
+}
+
+#[tokio::test]
+async fn p2p_ops_policy() {
+    let (uopool, mock) = mock_uopool();
+    let mut uopool = uopool.with_p2p_ops_policy(P2pOpsPolicy::RelayOnly);
+
+    let uo_p2p = UserOperation::default().sender(Address::random());
+    let uo_local = UserOperation::default().sender(Address::random());
+    uopool
+        .add_p2p_user_operation(uo_p2p.clone(), Some(Default::default()))
+        .await
+        .unwrap();
+    uopool
+        .add_user_operation(uo_local.clone(), Some(Default::default()))
+        .await
+        .unwrap();
+
+    // pooled for relaying, but not bundled
+    assert_eq!(uopool.get_all().len(), 2);
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+        .unwrap();
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_p2p.clone(), uo_local.clone()],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_local]
+    );
+
+    let (uopool, _) = mock_uopool();
+    let mut uopool = uopool.with_p2p_ops_policy(P2pOpsPolicy::Ignore);
+    assert!(uopool
+        .add_p2p_user_operation(uo_p2p, Some(Default::default()))
+        .await
+        .is_err());
+    assert!(uopool.get_all().is_empty());
+}
+
+#[tokio::test]
+async fn bundle_entity_conflicts() {
+    let (mut uopool, mock) = mock_uopool();
+    let uo_sender = UserOperation::default().sender(Address::random());
+    // factory of the user operation is the sender of another user operation
+    let uo_deployed = UserOperation::default()
+        .sender(Address::random())
+        .init_code(uo_sender.sender.as_bytes().to_vec().into());
+    let uo_other = UserOperation::default().sender(Address::random());
+    for uo in [&uo_sender, &uo_deployed, &uo_other] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // simulation of the bundle and on-chain nonces of the non-conflicting user operations
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_sender.clone(), uo_deployed.clone(), uo_other.clone()],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_sender.clone(), uo_other]
+    );
+
+    // the other way around, the sender is a factory of the user operation already in the bundle
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+        .unwrap();
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_deployed.clone(), uo_sender],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_deployed]
+    );
+}
+
+#[tokio::test]
+async fn bundle_limits() {
+    let (uopool, mock) = mock_uopool();
+    let mut uopool = uopool.with_max_ops_per_bundle(2);
+    // each user operation uses 100_000 + 100_000 + 50_000 gas (on top of the handleOps overhead)
+    let uos: Vec<UserOperation> = (0..3)
+        .map(|_| {
+            UserOperation::default()
+                .sender(Address::random())
+                .call_gas_limit(100_000.into())
+                .verification_gas_limit(100_000.into())
+                .pre_verification_gas(50_000.into())
+        })
+        .collect();
+    for uo in uos.iter() {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // simulation of the bundle and on-chain nonces (responses are returned in the reverse order)
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+    assert_eq!(
+        uopool
+            .bundle_user_operations(uos.clone(), SubmissionMode::DropInvalid)
+            .await
+            .unwrap(),
+        vec![uos[0].clone(), uos[1].clone()]
+    );
+
+    // the second user operation exceeds the max gas of the bundle
+    uopool.max_ops_per_bundle = None;
+    uopool = uopool.with_max_bundle_gas(549_999.into());
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+    assert_eq!(
+        uopool
+            .bundle_user_operations(uos.clone(), SubmissionMode::DropInvalid)
+            .await
+            .unwrap(),
+        vec![uos[0].clone()]
+    );
+}
+
+#[tokio::test]
+async fn nonce_gap() {
+    let (mut uopool, mock) = mock_uopool();
+    let sender = Address::random();
+    let key = U256::from(1) << 64;
+    let uo_queued = UserOperation::default().sender(sender).nonce(1.into());
+    let uo_ready = UserOperation::default().sender(sender).nonce(key);
+    let uo_stale = UserOperation::default()
+        .sender(Address::random())
+        .nonce(0.into());
+    for uo in [&uo_queued, &uo_ready, &uo_stale] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // simulation of the bundle and on-chain nonces (responses are returned in the reverse order)
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    for nonce in [U256::from(1), key, U256::zero()] {
+        mock.push::<Bytes, _>(Bytes::from(nonce.encode())).unwrap();
+    }
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_queued.clone(), uo_ready.clone(), uo_stale.clone()],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_ready.clone()]
+    );
+    assert_eq!(uopool.get_all(), vec![uo_queued.clone(), uo_ready.clone()]);
+
+    // the predecessor landed, the queued user operation is promoted
+    uopool.remove_user_operations(vec![uo_ready.hash(&ADDRESS.parse().unwrap(), &1337.into())]);
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    mock.push::<Bytes, _>(Bytes::from(U256::from(1).encode()))
+        .unwrap();
+    assert_eq!(
+        uopool
+            .bundle_user_operations(vec![uo_queued.clone()], SubmissionMode::DropInvalid)
+            .await
+            .unwrap(),
+        vec![uo_queued]
+    );
+}
+
+#[tokio::test]
+async fn simulate_bundle() {
+    let (mut uopool, mock) = mock_uopool();
+    let uo_failing = UserOperation::default().sender(Address::random());
+    let uo_ok = UserOperation::default().sender(Address::random());
+    for uo in [&uo_failing, &uo_ok] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // the bundle fails on the first user operation, the rest of the bundle simulates cleanly
+    let revert_data: Bytes = [
+        silius_primitives::simulation::FAILED_OP_SELECTOR.to_vec(),
+        encode(&[
+            Token::Uint(U256::zero()),
+            Token::String("AA23 reverted".into()),
+        ]),
+    ]
+    .concat()
+    .into();
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    mock.push_response(MockResponse::Error(JsonRpcError {
+        code: 3,
+        message: "execution reverted".into(),
+        data: Some(serde_json::to_value(&revert_data).unwrap()),
+    }));
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_failing.clone(), uo_ok.clone()],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_ok.clone()]
+    );
+    assert_eq!(uopool.get_all(), vec![uo_ok]);
+    assert!(uopool
+        .get_reputation()
+        .iter()
+        .any(|ent| ent.address == uo_failing.sender && ent.uo_seen == 100));
+}
+
+#[tokio::test]
+async fn all_or_nothing() {
+    let (mut uopool, mock) = mock_uopool();
+    let uo_failing = UserOperation::default().sender(Address::random());
+    let uo_ok = UserOperation::default().sender(Address::random());
+    for uo in [&uo_failing, &uo_ok] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    let revert_data: Bytes = [
+        silius_primitives::simulation::FAILED_OP_SELECTOR.to_vec(),
+        encode(&[
+            Token::Uint(U256::zero()),
+            Token::String("AA23 reverted".into()),
+        ]),
+    ]
+    .concat()
+    .into();
+    mock.push_response(MockResponse::Error(JsonRpcError {
+        code: 3,
+        message: "execution reverted".into(),
+        data: Some(serde_json::to_value(&revert_data).unwrap()),
+    }));
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+    let reputation = uopool.get_reputation();
+
+    // the whole bundle fails and nothing is dropped or penalized
+    assert!(uopool
+        .bundle_user_operations(
+            vec![uo_failing.clone(), uo_ok.clone()],
+            SubmissionMode::AllOrNothing
+        )
+        .await
+        .is_err());
+    assert_eq!(uopool.get_all().len(), 2);
+    assert_eq!(uopool.get_reputation(), reputation);
+}
+
+#[tokio::test]
+async fn reputation_status() {
+    let (mut uopool, _) = mock_uopool();
+    let entry = |uo_seen: u64| ReputationEntry {
+        address: Address::from_low_u64_be(uo_seen),
+        uo_seen,
+        uo_included: 0,
+        status: ReputationStatus::OK,
+    };
+    uopool
+        .set_reputation(vec![entry(10), entry(200), entry(1000)])
+        .unwrap();
+
+    // the status is computed from the counters
+    let mut reputation = uopool.get_reputation();
+    reputation.sort_by_key(|ent| ent.uo_seen);
+    assert_eq!(
+        reputation.iter().map(|ent| ent.status).collect::<Vec<_>>(),
+        vec![
+            ReputationStatus::OK,
+            ReputationStatus::THROTTLED,
+            ReputationStatus::BANNED
+        ]
+    );
+}
+
+#[tokio::test]
+async fn insufficient_prefund() {
+    let (mut uopool, mock) = mock_uopool();
+    // max gas cost 1_000_000
+    let uo = |sender: Address| {
+        UserOperation::default()
+            .sender(sender)
+            .call_gas_limit(100_000.into())
+            .max_fee_per_gas(10.into())
+    };
+    let uo_paymaster =
+        uo(Address::random()).paymaster_and_data(Address::random().as_bytes().to_vec().into());
+    let uo_poor = uo(Address::random());
+    let uo_rich = uo(Address::random());
+    for uo in [&uo_paymaster, &uo_poor, &uo_rich] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    let encoded = |n: u64| Bytes::from(U256::from(n).encode());
+    // simulation of the bundle
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    // on-chain nonce, deposit and balance of the senders (in reverse)
+    mock.push(U256::from(500_000)).unwrap();
+    mock.push::<Bytes, _>(encoded(600_000)).unwrap();
+    mock.push::<Bytes, _>(encoded(0)).unwrap();
+    mock.push(U256::from(500_000)).unwrap();
+    mock.push::<Bytes, _>(encoded(0)).unwrap();
+    mock.push::<Bytes, _>(encoded(0)).unwrap();
+    // deposit of the paymaster and on-chain nonce of its sender
+    mock.push::<Bytes, _>(encoded(999_999)).unwrap();
+    mock.push::<Bytes, _>(encoded(0)).unwrap();
+
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_paymaster.clone(), uo_poor.clone(), uo_rich.clone()],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_rich.clone()]
+    );
+    assert_eq!(uopool.get_all(), vec![uo_rich]);
+}
+
+/// Validator failing the second validation of user operations of the senders (by the paymaster)
+struct RevalidationValidator {
+    failing: Vec<Address>,
+}
+
+#[async_trait::async_trait]
+impl UserOperationValidator for RevalidationValidator {
+    async fn validate_user_operation(
+        &self,
+        uo: &UserOperation,
+        _mempool: &MempoolBox<VecUo, VecCh>,
+        _reputation: &ReputationBox<Vec<ReputationEntry>>,
+        mode: EnumSet<UserOperationValidatorMode>,
+    ) -> Result<UserOperationValidationOutcome, ValidationError> {
+        if mode.contains(UserOperationValidatorMode::Simulation)
+            && self.failing.contains(&uo.sender)
+        {
+            return Err(SimulationCheckError::Validation {
+                message: "AA33 reverted (or OOG)".into(),
+            }
+            .into());
+        }
+        Ok(UserOperationValidationOutcome::default())
+    }
+}
+
+#[tokio::test]
+async fn revalidation() {
+    let paymaster = Address::random();
+    let uo_failing = UserOperation::default()
+        .sender(Address::random())
+        .paymaster_and_data(paymaster.as_bytes().to_vec().into());
+    let uo_ok = UserOperation::default().sender(Address::random());
+    let uo_over_limit = UserOperation::default().sender(Address::random());
+
+    let (eth_client, mock) = Provider::mocked();
+    let eth_client = Arc::new(eth_client);
+    let mut uopool = UoPool::new(
+        EntryPoint::new(eth_client.clone(), ADDRESS.parse().unwrap()),
+        RevalidationValidator {
+            failing: vec![uo_failing.sender],
+        },
+        Box::<MemoryMempool>::default(),
+        Box::<MemoryReputation>::default(),
+        eth_client,
+        U256::from(10_000_000),
+        Chain::from(1337_u64),
+    )
+    .with_max_revalidations(2);
+    for uo in [&uo_failing, &uo_ok, &uo_over_limit] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // simulation of the bundle and on-chain nonces
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    for _ in 0..3 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_failing.clone(), uo_ok.clone(), uo_over_limit.clone()],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_ok.clone()]
+    );
+
+    // the failing user operation is dropped and its paymaster penalized, the one over the limit stays
+    assert_eq!(uopool.get_all().len(), 2);
+    assert!(uopool
+        .get_all()
+        .iter()
+        .all(|uo| uo.sender != uo_failing.sender));
+    assert!(uopool
+        .get_reputation()
+        .iter()
+        .any(|ent| ent.address == paymaster && ent.uo_seen == 100));
+}
+
+/// Validator reporting the code hashes of the addresses touched by the senders
+struct CodeHashValidator {
+    touched: HashMap<Address, Address>,
+}
+
+#[async_trait::async_trait]
+impl UserOperationValidator for CodeHashValidator {
+    async fn validate_user_operation(
+        &self,
+        uo: &UserOperation,
+        _mempool: &MempoolBox<VecUo, VecCh>,
+        _reputation: &ReputationBox<Vec<ReputationEntry>>,
+        _mode: EnumSet<UserOperationValidatorMode>,
+    ) -> Result<UserOperationValidationOutcome, ValidationError> {
+        Ok(UserOperationValidationOutcome {
+            code_hashes: self.touched.get(&uo.sender).map(|address| {
+                vec![CodeHash {
+                    address: *address,
+                    hash: H256::zero(),
+                }]
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+#[tokio::test]
+async fn code_hash_changed_in_bundle() {
+    let uo_deploy = UserOperation::default()
+        .sender(Address::random())
+        .init_code(Address::random().as_bytes().to_vec().into());
+    let uo_touching = UserOperation::default().sender(Address::random());
+    let uo_other = UserOperation::default().sender(Address::random());
+
+    let (eth_client, mock) = Provider::mocked();
+    let eth_client = Arc::new(eth_client);
+    let mut uopool = UoPool::new(
+        EntryPoint::new(eth_client.clone(), ADDRESS.parse().unwrap()),
+        CodeHashValidator {
+            touched: HashMap::from([(uo_touching.sender, uo_deploy.sender)]),
+        },
+        Box::<MemoryMempool>::default(),
+        Box::<MemoryReputation>::default(),
+        eth_client,
+        U256::from(10_000_000),
+        Chain::from(1337_u64),
+    );
+    for uo in [&uo_deploy, &uo_touching, &uo_other] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // simulation of the bundle and on-chain nonces
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    for _ in 0..3 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+
+    // the code touched by the second user operation is deployed by the first one
+    assert_eq!(
+        uopool
+            .bundle_user_operations(
+                vec![uo_deploy.clone(), uo_touching.clone(), uo_other.clone()],
+                SubmissionMode::DropInvalid
+            )
+            .await
+            .unwrap(),
+        vec![uo_deploy.clone(), uo_other.clone()]
+    );
+    assert_eq!(uopool.get_all().len(), 3);
+}
+
+#[tokio::test]
+async fn get_bundled_known_accounts() {
+    let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+    let (s1, s2) = (H256::from_low_u64_be(1), H256::from_low_u64_be(2));
+    let uos = vec![
+        UserOperation::default().sender(Address::random()),
+        UserOperation::default().sender(Address::random()),
+    ];
+    let (mut uopool, mock) = mock_uopool_with(SlotsValidator(HashMap::from([
+        (uos[0].sender, HashMap::from([(a, HashSet::from([s1]))])),
+        (
+            uos[1].sender,
+            HashMap::from([(a, HashSet::from([s1, s2])), (b, HashSet::from([s1]))]),
+        ),
+    ])));
+    for uo in &uos {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // simulation of the bundle and on-chain nonces
+    mock.push::<Bytes, _>(Bytes::default()).unwrap();
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+            .unwrap();
+    }
+    assert_eq!(
+        uopool
+            .bundle_user_operations(uos.clone(), SubmissionMode::DropInvalid)
+            .await
+            .unwrap(),
+        uos
+    );
+
+    // each slot is read once (in the order of the addresses and slots)
+    for value in [30, 20, 10] {
+        mock.push(H256::from_low_u64_be(value)).unwrap();
+    }
+    let known_accounts = uopool.get_bundled_known_accounts(&uos).await.unwrap();
+    assert_eq!(
+        known_accounts.0,
+        BTreeMap::from([
+            (
+                a,
+                AccountStorage::SlotValues(BTreeMap::from([
+                    (s1, H256::from_low_u64_be(10)),
+                    (s2, H256::from_low_u64_be(20))
+                ]))
+            ),
+            (
+                b,
+                AccountStorage::SlotValues(BTreeMap::from([(s1, H256::from_low_u64_be(30))]))
+            ),
+        ])
+    );
+
+    // user operations without traced storage have no preconditions
+    assert!(uopool
+        .get_bundled_known_accounts(&[UserOperation::default()])
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn get_sorted_user_operations() {
+    let (mut uopool, mock) = mock_uopool();
+    uopool.reputation.init(
+        MIN_INCLUSION_RATE_DENOMINATOR,
+        THROTTLING_SLACK,
+        BAN_SLACK,
+        HOURLY_DECAY_DENOMINATOR,
+        U256::from(1),
+        U256::zero(),
+    );
+    let mut uopool = uopool.with_unstaked_entity_caps(UnstakedEntityCaps::default());
+    let (a, b, c) = (Address::random(), Address::random(), Address::random());
+    let paymaster = Address::random();
+    let uo = |sender: Address, nonce: u64, priority_fee: u64| {
+        UserOperation::default()
+            .sender(sender)
+            .nonce(nonce.into())
+            .max_fee_per_gas(1_000.into())
+            .max_priority_fee_per_gas(priority_fee.into())
+    };
+    let (uo_a, uo_a_next) = (uo(a, 0, 10), uo(a, 1, 50));
+    let uo_b = uo(b, 0, 40).paymaster_and_data(paymaster.as_bytes().to_vec().into());
+    let uo_c = uo(c, 0, 30).paymaster_and_data(paymaster.as_bytes().to_vec().into());
+    for uo in [&uo_a, &uo_a_next, &uo_b, &uo_c] {
+        uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // unstaked paymaster
+    mock.push::<Bytes, _>(
+        (U256::zero(), false, U256::zero(), 0_u32, 0_u64)
+            .encode()
+            .into(),
+    )
+    .unwrap();
+    let block = || Block::<H256> {
+        gas_limit: 30_000_000.into(),
+        base_fee_per_gas: Some(100.into()),
+        ..Default::default()
+    };
+    mock.push(block()).unwrap();
+
+    // the next nonce of the sender isn't a candidate even if it pays more, the unstaked paymaster backs
+    // only one user operation
+    assert_eq!(
+        uopool.get_sorted_user_operations().await.unwrap(),
+        vec![uo_b.clone(), uo_a.clone()]
+    );
+
+    uopool.bundle_ordering = OrderingPolicy::Fifo;
+    uopool.unstaked_entity_caps = None;
+    mock.push(block()).unwrap();
+    assert_eq!(
+        uopool.get_sorted_user_operations().await.unwrap(),
+        vec![uo_a, uo_b, uo_c]
+    );
+}
+
+#[tokio::test]
+async fn max_mempool_size() {
+    let (uopool, mock) = mock_uopool();
+    let mut uopool = uopool.with_max_mempool_size(3);
+    let (a, b, c, d, e) = (
+        Address::random(),
+        Address::random(),
+        Address::random(),
+        Address::random(),
+        Address::random(),
+    );
+    let uo = |sender: Address, nonce: u64, priority_fee: u64| {
+        UserOperation::default()
+            .sender(sender)
+            .nonce(nonce.into())
+            .max_fee_per_gas(200.into())
+            .max_priority_fee_per_gas(priority_fee.into())
+    };
+    let base_fee = || {
+        mock.push(Block::<H256> {
+            base_fee_per_gas: Some(100.into()),
+            ..Default::default()
+        })
+        .unwrap();
+    };
+
+    for uo in [uo(a, 0, 10), uo(a, 1, 40), uo(b, 0, 20)] {
+        uopool
+            .add_user_operation(uo, Some(Default::default()))
+            .await
+            .unwrap();
+    }
+
+    // pays less than the cheapest user operation that can be dropped (the cheapest one isn't the last of its
+    // sender)
+    base_fee();
+    match uopool
+        .add_user_operation(uo(c, 0, 15), Some(Default::default()))
+        .await
+    {
+        Err(AddError::Verification(ValidationError::Sanity(SanityCheckError::MempoolFull {
+            effective_gas_price,
+            min_effective_gas_price,
+        }))) => {
+            assert_eq!(effective_gas_price, U256::from(115));
+            assert_eq!(min_effective_gas_price, U256::from(120));
+        }
+        res => panic!("unexpected result: {res:?}"),
+    }
+    assert_eq!(uopool.get_all().len(), 3);
+
+    // the cheapest last user operation of a sender is dropped
+    base_fee();
+    let uo_b_hash = uo(b, 0, 20).hash(&ADDRESS.parse().unwrap(), &1337.into());
+    uopool
+        .add_user_operation(uo(c, 0, 30), Some(Default::default()))
+        .await
+        .unwrap();
+    assert_eq!(
+        uopool.get_user_operation_status(&uo_b_hash),
+        Some(UserOperationStatus::Dropped)
+    );
+
+    // the first user operation of the sender isn't dropped before the last one (no nonce gap)
+    for uo in [uo(d, 0, 50), uo(e, 0, 60)] {
+        base_fee();
+        uopool
+            .add_user_operation(uo, Some(Default::default()))
+            .await
+            .unwrap();
+    }
+    let mut uos_pool = uopool.get_all();
+    uos_pool.sort_by_key(|uo| uo.max_priority_fee_per_gas);
+    assert_eq!(uos_pool, vec![uo(a, 0, 10), uo(d, 0, 50), uo(e, 0, 60)]);
+
+    // user operations of the same sender aren't dropped for its next nonce
+    base_fee();
+    uopool
+        .add_user_operation(uo(a, 1, 100), Some(Default::default()))
+        .await
+        .unwrap();
+    let mut uos_pool = uopool.get_all();
+    uos_pool.sort_by_key(|uo| uo.max_priority_fee_per_gas);
+    assert_eq!(uos_pool, vec![uo(a, 0, 10), uo(e, 0, 60), uo(a, 1, 100)]);
+}
+
+/// `UserOperationEvent` log of the user operation included in the transaction
+fn mock_user_operation_event(
+    uo: &UserOperation,
+    uo_hash: &UserOperationHash,
+    tx_hash: H256,
+    block_number: u64,
+) -> Log {
+    Log {
+        address: ADDRESS.parse().unwrap(),
+        topics: vec![
+            UserOperationEventFilter::signature(),
+            uo_hash.0,
+            H256::from(uo.sender),
+            H256::zero(),
+        ],
+        data: encode(&[
+            Token::Uint(uo.nonce),
+            Token::Bool(true),
+            Token::Uint(1_000.into()),
+            Token::Uint(100.into()),
+        ])
+        .into(),
+        block_hash: Some(H256::random()),
+        block_number: Some(block_number.into()),
+        transaction_hash: Some(tx_hash),
+        transaction_index: Some(0.into()),
+        log_index: Some(0.into()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn get_user_operation_by_hash() {
+    let (uopool, mock) = mock_uopool();
+    let ep: Address = ADDRESS.parse().unwrap();
+    let sender = Address::random();
+    let uo = UserOperation::default().sender(sender).nonce(1.into());
+    let uo_hash = uo.hash(&ep, &1337.into());
+    // other user operation of the same sender in the same bundle
+    let uo_other = uo.clone().nonce(2.into());
+    let tx_hash = H256::random();
+    let log = mock_user_operation_event(&uo, &uo_hash, tx_hash, 2_000);
+
+    // responses are returned in the reverse order
+    mock.push(Transaction {
+        hash: tx_hash,
+        to: Some(ep),
+        input: uopool
+            .entry_point
+            .entry_point_api()
+            .handle_ops(vec![uo_other.into(), uo.clone().into()], Address::random())
+            .calldata()
+            .unwrap(),
+        ..Default::default()
+    })
+    .unwrap();
+    mock.push::<Vec<Log>, _>(vec![log.clone()]).unwrap();
+    mock.push(U64::from(2_000)).unwrap();
+
+    let uo_by_hash = uopool
+        .get_user_operation_by_hash(&uo_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(uo_by_hash.user_operation, uo);
+    assert_eq!(uo_by_hash.entry_point, ep);
+    assert_eq!(uo_by_hash.transaction_hash, tx_hash);
+    assert_eq!(Some(uo_by_hash.block_hash), log.block_hash);
+    assert_eq!(uo_by_hash.block_number, U64::from(2_000));
+
+    // not included
+    mock.push::<Vec<Log>, _>(vec![]).unwrap();
+    mock.push(U64::from(2_000)).unwrap();
+    assert!(uopool
+        .get_user_operation_by_hash(&uo_hash)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn get_user_operation_state_confirmations() {
+    let (uopool, mock) = mock_uopool();
+    let uopool = uopool.with_confirmation_blocks(3);
+    let ep: Address = ADDRESS.parse().unwrap();
+    let uo = UserOperation::default().sender(Address::random());
+    let uo_hash = uo.hash(&ep, &1337.into());
+    let tx_hash = H256::random();
+    let log = mock_user_operation_event(&uo, &uo_hash, tx_hash, 2_000);
+    let tx = Transaction {
+        hash: tx_hash,
+        to: Some(ep),
+        input: uopool
+            .entry_point
+            .entry_point_api()
+            .handle_ops(vec![uo.clone().into()], Address::random())
+            .calldata()
+            .unwrap(),
+        ..Default::default()
+    };
+    let mock_included = |latest: u64| {
+        // responses are returned in the reverse order
+        mock.push(U64::from(latest)).unwrap();
+        mock.push(tx.clone()).unwrap();
+        mock.push::<Vec<Log>, _>(vec![log.clone()]).unwrap();
+        mock.push(U64::from(latest)).unwrap();
+    };
+
+    mock_included(2_001);
+    assert_eq!(
+        uopool.get_user_operation_state(&uo_hash).await.unwrap(),
+        UserOperationState::Mined {
+            block_hash: log.block_hash.unwrap(),
+            block_number: 2_000.into(),
+            transaction_hash: tx_hash,
+            confirmations: 2,
+        }
+    );
+
+    mock_included(2_002);
+    assert_eq!(
+        uopool.get_user_operation_state(&uo_hash).await.unwrap(),
+        UserOperationState::Included {
+            block_hash: log.block_hash.unwrap(),
+            block_number: 2_000.into(),
+            transaction_hash: tx_hash,
+        }
+    );
+
+    // reorged out before it was final
+    mock.push::<Vec<Log>, _>(vec![]).unwrap();
+    mock.push(U64::from(2_002)).unwrap();
+    assert_eq!(
+        uopool.get_user_operation_state(&uo_hash).await.unwrap(),
+        UserOperationState::NotFound
+    );
+}
+
+#[tokio::test]
+async fn get_user_operation_receipt() {
+    let (uopool, mock) = mock_uopool();
+    let ep: Address = ADDRESS.parse().unwrap();
+    let uo = UserOperation::default().sender(Address::random());
+    let uo_hash = uo.hash(&ep, &1337.into());
+    let tx_hash = H256::random();
+    let event = mock_user_operation_event(&uo, &uo_hash, tx_hash, 2_000);
+    let account_log = Log {
+        address: uo.sender,
+        topics: vec![H256::random()],
+        ..event.clone()
+    };
+    let revert_reason = Log {
+        address: ep,
+        topics: vec![
+            UserOperationRevertReasonFilter::signature(),
+            uo_hash.0,
+            H256::from(uo.sender),
+        ],
+        data: encode(&[
+            Token::Uint(uo.nonce),
+            Token::Bytes(
+                [
+                    ERROR_SELECTOR.to_vec(),
+                    encode(&[Token::String("transfer failed".into())]),
+                ]
+                .concat(),
+            ),
+        ])
+        .into(),
+        ..event.clone()
+    };
+    let before_execution = Log {
+        address: ep,
+        topics: vec![BeforeExecutionFilter::signature()],
+        data: Bytes::default(),
+        ..event.clone()
+    };
+
+    // responses are returned in the reverse order
+    mock.push(TransactionReceipt {
+        transaction_hash: tx_hash,
+        logs: vec![
+            before_execution,
+            account_log.clone(),
+            revert_reason.clone(),
+            event.clone(),
+        ],
+        ..Default::default()
+    })
+    .unwrap();
+    mock.push::<Vec<Log>, _>(vec![event]).unwrap();
+    mock.push(U64::from(2_000)).unwrap();
+
+    let receipt = uopool.get_user_operation_receipt(&uo_hash).await.unwrap();
+    assert_eq!(receipt.user_operation_hash, uo_hash);
+    assert_eq!(receipt.sender, uo.sender);
+    assert_eq!(receipt.actual_gas_cost, U256::from(1_000));
+    assert_eq!(receipt.actual_gas_used, U256::from(100));
+    assert!(receipt.success);
+    assert_eq!(receipt.paymaster, None);
+    assert_eq!(receipt.logs, vec![account_log, revert_reason]);
+    assert_eq!(receipt.reason, "transfer failed");
+    assert_eq!(receipt.tx_receipt.transaction_hash, tx_hash);
+}
+
+#[tokio::test]
+async fn inclusion_events() {
+    let (mut uopool, mock) = mock_uopool();
+    let ep: Address = ADDRESS.parse().unwrap();
+    let uo = UserOperation::default().sender(Address::random());
+    let uo_hash = uopool
+        .add_user_operation(uo.clone(), Some(Default::default()))
+        .await
+        .unwrap();
+    // user operation submitted by another bundler
+    let uo_other = UserOperation::default().sender(Address::random());
+    let tx_hash = H256::random();
+    let logs = vec![
+        mock_user_operation_event(&uo, &uo_hash, tx_hash, 2_000),
+        mock_user_operation_event(&uo_other, &uo_other.hash(&ep, &1337.into()), tx_hash, 2_000),
+    ];
+    let block_hash = H256::random();
+    let mut inclusions = uopool.subscribe_inclusions();
+
+    // the same events are observed again in the next scan
+    for _ in 0..2 {
+        // responses are returned in the reverse order
+        mock.push::<Vec<Log>, _>(logs.clone()).unwrap();
+        mock.push(U64::from(2_000)).unwrap();
+        mock.push(mock_block(2_000, block_hash, H256::random()))
+            .unwrap();
+        uopool.handle_past_events().await.unwrap();
+    }
+
+    assert_eq!(
+        inclusions.try_recv().unwrap(),
+        InclusionEvent {
+            user_op_hash: uo_hash,
+            sender: uo.sender,
+            success: true,
+            actual_gas_cost: 1_000.into(),
+            tx_hash,
+        }
+    );
+    assert!(inclusions.try_recv().is_err());
+    assert!(uopool.get_all().is_empty());
+}
+
+#[tokio::test]
+async fn inclusion_confirmations() {
+    let (uopool, mock) = mock_uopool();
+    let mut uopool = uopool.with_confirmation_blocks(3);
+    let uo = UserOperation::default().sender(Address::random());
+    let uo_hash = uopool
+        .add_user_operation(uo.clone(), Some(Default::default()))
+        .await
+        .unwrap();
+    let log = mock_user_operation_event(&uo, &uo_hash, H256::random(), 2_000);
+    let (block_2001, block_2002) = (
+        mock_block(2_001, H256::random(), H256::random()),
+        mock_block(2_002, H256::random(), H256::random()),
+    );
+    let mut inclusions = uopool.subscribe_inclusions();
+
+    // included with 2 confirmations, the user operation is kept out of bundles
+    // (responses are returned in the reverse order)
+    mock.push::<Vec<Log>, _>(vec![log.clone()]).unwrap();
+    mock.push(U64::from(2_001)).unwrap();
+    mock.push(block_2001.clone()).unwrap();
+    uopool.handle_past_events().await.unwrap();
+    assert!(inclusions.try_recv().is_err());
+    assert_eq!(uopool.get_all(), vec![uo.clone()]);
+    assert!(uopool
+        .get_sorted_user_operations()
+        .await
+        .unwrap()
+        .is_empty());
+
+    // reorged out before it was final, the user operation can be bundled again
+    mock.push::<Vec<Log>, _>(vec![]).unwrap();
+    mock.push(U64::from(2_001)).unwrap();
+    mock.push(block_2001.clone()).unwrap();
+    uopool.handle_past_events().await.unwrap();
+    assert!(inclusions.try_recv().is_err());
+    assert_eq!(uopool.get_all(), vec![uo.clone()]);
+    mock.push(Block::<H256> {
+        gas_limit: 30_000_000.into(),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        uopool.get_sorted_user_operations().await.unwrap(),
+        vec![uo.clone()]
+    );
+
+    // included again with 3 confirmations, the inclusion is notified and the user operation removed
+    mock.push::<Vec<Log>, _>(vec![log]).unwrap();
+    mock.push(U64::from(2_002)).unwrap();
+    mock.push(block_2001.clone()).unwrap();
+    mock.push(Block::<H256> {
+        parent_hash: block_2001.hash.unwrap(),
+        ..block_2002
+    })
+    .unwrap();
+    uopool.handle_past_events().await.unwrap();
+    assert_eq!(inclusions.try_recv().unwrap().user_op_hash, uo_hash);
+    assert!(uopool.get_all().is_empty());
+}
+
+#[tokio::test]
+async fn revert_penalty() {
+    let (uopool, mock) = mock_uopool();
+    let mut uopool = uopool.with_revert_penalty(10);
+    uopool.reputation.init(
+        MIN_INCLUSION_RATE_DENOMINATOR,
+        THROTTLING_SLACK,
+        BAN_SLACK,
+        HOURLY_DECAY_DENOMINATOR,
+        U256::from(1),
+        U256::from(0),
+    );
+    let sender = Address::random();
+    let block_hash = H256::random();
+
+    for (nonce, status) in [
+        (0, ReputationStatus::OK),
+        (1, ReputationStatus::THROTTLED),
+        (2, ReputationStatus::THROTTLED),
+        (3, ReputationStatus::THROTTLED),
+        (4, ReputationStatus::THROTTLED),
+        (5, ReputationStatus::BANNED),
+    ] {
+        let uo = UserOperation::default().sender(sender).nonce(nonce.into());
+        let uo_hash = uopool
+            .add_user_operation(uo.clone(), Some(Default::default()))
+            .await
+            .unwrap();
+        // execution of the user operation reverted
+        let mut log = mock_user_operation_event(&uo, &uo_hash, H256::random(), 2_000);
+        log.data = encode(&[
+            Token::Uint(uo.nonce),
+            Token::Bool(false),
+            Token::Uint(1_000.into()),
+            Token::Uint(100.into()),
+        ])
+        .into();
+
+        // the same event is observed again in the next scan
+        for _ in 0..2 {
+            // responses are returned in the reverse order
+            mock.push::<Vec<Log>, _>(vec![log.clone()]).unwrap();
+            mock.push(U64::from(2_000)).unwrap();
+            mock.push(mock_block(2_000, block_hash, H256::random()))
+                .unwrap();
+            uopool.handle_past_events().await.unwrap();
+        }
+
+        assert_eq!(uopool.reputation.get_status(&sender), status);
+    }
+}
+
+fn mock_block(number: u64, hash: H256, parent_hash: H256) -> Block<H256> {
+    Block {
+        number: Some(number.into()),
+        hash: Some(hash),
+        parent_hash,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn reorg() {
+    let (mut uopool, mock) = mock_uopool();
+    let ep: Address = ADDRESS.parse().unwrap();
+    let (a9, a10, b10, b11) = (
+        H256::random(),
+        H256::random(),
+        H256::random(),
+        H256::random(),
+    );
+
+    mock.push(mock_block(10, a10, a9)).unwrap();
+    assert!(uopool.handle_reorgs().await.unwrap().is_empty());
+
+    // user operations of the orphaned bundle: not included in the new chain, included in the new
+    // chain and already back in the pool
+    let uo_readmitted = UserOperation::default().sender(Address::random());
+    let uo_included = UserOperation::default().sender(Address::random());
+    let uo_pooled = UserOperation::default().sender(Address::random());
+    uopool
+        .add_user_operation(uo_pooled.clone(), Some(Default::default()))
+        .await
+        .unwrap();
+    let bundle_tx = Transaction {
+        to: Some(ep),
+        input: uopool
+            .entry_point
+            .entry_point_api()
+            .handle_ops(
+                vec![
+                    uo_readmitted.clone().into(),
+                    uo_included.clone().into(),
+                    uo_pooled.clone().into(),
+                ],
+                Address::random(),
+            )
+            .calldata()
+            .unwrap(),
+        ..Default::default()
+    };
+
+    // responses are returned in the reverse order
+    // on-chain nonces
+    mock.push::<Bytes, _>(Bytes::from(U256::from(1).encode()))
+        .unwrap();
+    mock.push::<Bytes, _>(Bytes::from(U256::zero().encode()))
+        .unwrap();
+    mock.push(Block::<Transaction> {
+        number: Some(10.into()),
+        hash: Some(a10),
+        parent_hash: a9,
+        transactions: vec![
+            Transaction {
+                to: Some(Address::random()),
+                ..Default::default()
+            },
+            bundle_tx,
+        ],
+        ..Default::default()
+    })
+    .unwrap();
+    mock.push(mock_block(10, b10, a9)).unwrap();
+    mock.push(mock_block(11, b11, b10)).unwrap();
+
+    assert_eq!(
+        uopool.handle_reorgs().await.unwrap(),
+        vec![uo_readmitted.hash(&ep, &1337.into())]
+    );
+    assert_eq!(uopool.get_all(), vec![uo_pooled, uo_readmitted]);
+
+    // no reorg
+    mock.push(mock_block(11, b11, b10)).unwrap();
+    assert!(uopool.handle_reorgs().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn load_from_db() {
+    let dir = TempDir::new("test-uopool-db").unwrap().into_path();
+    let ep: Address = ADDRESS.parse().unwrap();
+    let chain_id = U256::from(1337);
+    let (sender_1, sender_2) = (Address::random(), Address::random());
+    let sender_3 = Address::random();
+    let uo_stale = UserOperation::default().sender(sender_1).nonce(0.into());
+    let uo_1 = UserOperation::default().sender(sender_1).nonce(1.into());
+    let uo_2 = UserOperation::default().sender(sender_2).nonce(2.into());
+    let uo_invalid = UserOperation::default()
+        .sender(sender_3)
+        .nonce(1.into())
+        .signature(Bytes::from_static(INVALID_SIGNATURE));
+    let uo_1_expires_at = 12345;
+
+    {
+        let mut mempool: DatabaseMempool<WriteMap> = DatabaseMempool::new(dir.clone()).unwrap();
+        mempool.create_tables().unwrap();
+        for uo in [&uo_stale, &uo_1, &uo_2, &uo_invalid] {
+            mempool.add(uo.clone(), &ep, &chain_id).unwrap();
+        }
+        mempool
+            .set_expires_at(&uo_1.hash(&ep, &chain_id), uo_1_expires_at)
+            .unwrap();
+    }
+
+    // restart
+    let mempool: DatabaseMempool<WriteMap> = DatabaseMempool::new(dir).unwrap();
+    mempool.create_tables().unwrap();
+    let (eth_client, mock) = Provider::mocked();
+    let eth_client = Arc::new(eth_client);
+    let mut uopool = UoPool::new(
+        EntryPoint::new(eth_client.clone(), ep),
+        MockValidator,
+        Box::new(mempool),
+        Box::<MemoryReputation>::default(),
+        eth_client,
+        U256::from(10_000_000),
+        Chain::from(1337_u64),
+    );
+
+    // next nonce of all senders is 1
+    for _ in 0..4 {
+        mock.push::<Bytes, _>(Bytes::from(U256::from(1).encode()))
+            .unwrap();
+    }
+    assert_eq!(uopool.load_from_db().await.unwrap(), 2);
+
+    let mut uos = uopool.get_all();
+    uos.sort_by_key(|uo| uo.sender == sender_2);
+    assert_eq!(uos, vec![uo_1.clone(), uo_2.clone()]);
+    assert_eq!(
+        uopool.get_user_operation_status(&uo_1.hash(&ep, &chain_id)),
+        Some(UserOperationStatus::Pending)
+    );
+    for uo in [&uo_stale, &uo_invalid] {
+        assert_eq!(
+            uopool.get_user_operation_status(&uo.hash(&ep, &chain_id)),
+            None
+        );
+    }
+
+    // the persisted expiry is kept, the user operations without one are tracked again
+    assert_eq!(
+        uopool.mempool.get_expires_at(&uo_1.hash(&ep, &chain_id)),
+        Some(uo_1_expires_at)
+    );
+    assert!(uopool
+        .mempool
+        .get_expires_at(&uo_2.hash(&ep, &chain_id))
+        .is_some());
+    assert_eq!(uopool.lifecycle.expire(), vec![uo_1.hash(&ep, &chain_id)]);
+}
+
+/// Memory mempool failing to add user operations
+#[derive(Debug, Default)]
+struct FailingAddMempool(MemoryMempool);
+
+impl Mempool for FailingAddMempool {
+    type UserOperations = VecUo;
+    type CodeHashes = VecCh;
+    type Error = anyhow::Error;
+    fn add(
+        &mut self,
+        _uo: UserOperation,
+        _ep: &Address,
+        _chain_id: &U256,
+    ) -> anyhow::Result<UserOperationHash> {
+        Err(format_err!("add failed"))
+    }
+    fn get(&self, uo_hash: &UserOperationHash) -> anyhow::Result<Option<UserOperation>> {
+        self.0.get(uo_hash)
+    }
+    fn get_all_by_sender(&self, addr: &Address) -> VecUo {
+        self.0.get_all_by_sender(addr)
+    }
+    fn get_number_by_sender(&self, addr: &Address) -> usize {
+        self.0.get_number_by_sender(addr)
+    }
+    fn has_code_hashes(&self, uo_hash: &UserOperationHash) -> anyhow::Result<bool> {
+        self.0.has_code_hashes(uo_hash)
+    }
+    fn set_code_hashes(
+        &mut self,
+        uo_hash: &UserOperationHash,
+        hashes: &VecCh,
+    ) -> anyhow::Result<()> {
+        self.0.set_code_hashes(uo_hash, hashes)
+    }
+    fn get_code_hashes(&self, uo_hash: &UserOperationHash) -> VecCh {
+        self.0.get_code_hashes(uo_hash)
+    }
+    fn set_expires_at(
+        &mut self,
+        uo_hash: &UserOperationHash,
+        expires_at: u64,
+    ) -> anyhow::Result<()> {
+        self.0.set_expires_at(uo_hash, expires_at)
+    }
+    fn get_expires_at(&self, uo_hash: &UserOperationHash) -> Option<u64> {
+        self.0.get_expires_at(uo_hash)
+    }
+    fn remove(&mut self, uo_hash: &UserOperationHash) -> anyhow::Result<()> {
+        self.0.remove(uo_hash)
+    }
+    fn get_sorted(&self) -> anyhow::Result<VecUo> {
+        self.0.get_sorted()
+    }
+    fn get_all(&self) -> VecUo {
+        self.0.get_all()
+    }
+    fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+#[tokio::test]
+async fn load_from_db_add_failure() {
+    let ep: Address = ADDRESS.parse().unwrap();
+    let chain_id = U256::from(1337);
+    let uo_1 = UserOperation::default()
+        .sender(Address::random())
+        .nonce(1.into());
+    let uo_2 = UserOperation::default()
+        .sender(Address::random())
+        .nonce(1.into());
+    let uo_code_hashes = vec![CodeHash {
+        address: Address::random(),
+        hash: H256::random(),
+    }];
+
+    let mut mempool = FailingAddMempool::default();
+    for uo in [&uo_1, &uo_2] {
+        mempool.0.add(uo.clone(), &ep, &chain_id).unwrap();
+    }
+    mempool
+        .set_code_hashes(&uo_1.hash(&ep, &chain_id), &uo_code_hashes)
+        .unwrap();
+
+    let (eth_client, mock) = Provider::mocked();
+    let eth_client = Arc::new(eth_client);
+    let mut uopool = UoPool::new(
+        EntryPoint::new(eth_client.clone(), ep),
+        MockValidator,
+        Box::new(mempool),
+        Box::<MemoryReputation>::default(),
+        eth_client,
+        U256::from(10_000_000),
+        Chain::from(1337_u64),
+    );
+
+    // the stored user operations are restored without being added again, so none is lost
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::from(1).encode()))
+            .unwrap();
+    }
+    assert_eq!(uopool.load_from_db().await.unwrap(), 2);
+    assert_eq!(uopool.get_all(), vec![uo_1.clone(), uo_2.clone()]);
+    assert_eq!(
+        uopool.mempool.get_code_hashes(&uo_1.hash(&ep, &chain_id)),
+        uo_code_hashes
+    );
+}
+
+#[tokio::test]
+async fn shutdown_and_restore() {
+    let dir = TempDir::new("test-uopool-shutdown").unwrap().into_path();
+    let (mempool_dir, reputation_dir) = (dir.join("mempool"), dir.join("reputation"));
+    let ep: Address = ADDRESS.parse().unwrap();
+    let chain_id = U256::from(1337);
+    let uo_unsent = UserOperation::default()
+        .sender(Address::random())
+        .nonce(1.into());
+    let uo_submitted = UserOperation::default()
+        .sender(Address::random())
+        .nonce(1.into());
+    let (unsent_hash, submitted_hash) = (
+        uo_unsent.hash(&ep, &chain_id),
+        uo_submitted.hash(&ep, &chain_id),
+    );
+    let entity = Address::random();
+
+    let new_uopool = || {
+        let mempool: DatabaseMempool<WriteMap> = DatabaseMempool::new(mempool_dir.clone()).unwrap();
+        mempool.create_tables().unwrap();
+        let reputation_db: DatabaseReputation<WriteMap> =
+            DatabaseReputation::new(reputation_dir.clone()).unwrap();
+        reputation_db.create_tables().unwrap();
+        let (eth_client, mock) = Provider::mocked();
+        let eth_client = Arc::new(eth_client);
+        let uopool = UoPool::new(
+            EntryPoint::new(eth_client.clone(), ep),
+            MockValidator,
+            Box::new(mempool),
+            Box::<MemoryReputation>::default(),
+            eth_client,
+            U256::from(10_000_000),
+            Chain::from(1337_u64),
+        )
+        .with_reputation_db(Box::new(reputation_db));
+        (uopool, mock)
+    };
+
+    {
+        let (mut uopool, mock) = new_uopool();
+        for (uo, uo_hash) in [(&uo_unsent, unsent_hash), (&uo_submitted, submitted_hash)] {
+            uopool.mempool.add(uo.clone(), &ep, &chain_id).unwrap();
+            uopool.lifecycle.track(&uo_hash, None);
+        }
+
+        // simulation of the bundle and on-chain nonces
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+        for _ in 0..2 {
+            mock.push::<Bytes, _>(Bytes::from(U256::from(1).encode()))
+                .unwrap();
+        }
+        uopool
+            .bundle_user_operations(
+                vec![uo_unsent.clone(), uo_submitted.clone()],
+                SubmissionMode::DropInvalid,
+            )
+            .await
+            .unwrap();
+        uopool.submit_user_operations(&[submitted_hash], H256::random());
+        for _ in 0..3 {
+            uopool.reputation.increment_seen(&entity);
+        }
+
+        // only the user operation of the unsent bundle is released
+        assert_eq!(uopool.shutdown(), 1);
+        assert!(uopool.get_bundled_valid_until(&unsent_hash).is_none());
+        assert!(uopool.get_bundled_valid_until(&submitted_hash).is_some());
+    }
+
+    // restart
+    let (mut uopool, mock) = new_uopool();
+    assert_eq!(uopool.load_reputation_from_db().unwrap(), 1);
+    assert_eq!(uopool.reputation.get(&entity).uo_seen, 3);
+
+    // next nonce of both senders is 1
+    for _ in 0..2 {
+        mock.push::<Bytes, _>(Bytes::from(U256::from(1).encode()))
+            .unwrap();
+    }
+    assert_eq!(uopool.load_from_db().await.unwrap(), 2);
+    assert_eq!(
+        uopool.get_user_operation_status(&unsent_hash),
+        Some(UserOperationStatus::Pending)
+    );
+}